@@ -0,0 +1,514 @@
+//! Semantic JSON equality
+//!
+//! Compares two JSON documents by value rather than by text: key order and
+//! formatting never matter, and options relax array order, string
+//! whitespace, and floating point precision. A lighter-weight sibling of a
+//! full structural diff that stops after a handful of differences.
+
+use crate::semantic::push_pointer_token;
+use crate::types::FormatError;
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+use std::collections::{HashMap, VecDeque};
+
+/// Options controlling what counts as "equal".
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct EqualityOptions {
+    /// Treat arrays as multisets (element order is ignored)
+    pub ignore_array_order: bool,
+    /// Compare strings with surrounding whitespace trimmed and inner runs collapsed
+    pub ignore_whitespace: bool,
+    /// Maximum absolute difference for non-integer numbers to compare equal
+    pub float_epsilon: f64,
+    /// Stop after reporting this many differences
+    pub max_differences: usize,
+}
+
+impl Default for EqualityOptions {
+    fn default() -> Self {
+        Self {
+            ignore_array_order: false,
+            ignore_whitespace: false,
+            float_epsilon: 0.0,
+            max_differences: 5,
+        }
+    }
+}
+
+/// A single difference between the two documents.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Difference {
+    /// JSON Pointer to the differing value (empty string for the root)
+    pub path: String,
+    pub message: String,
+}
+
+/// Outcome of a semantic equality comparison.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct EqualityResult {
+    pub equal: bool,
+    /// The first differences found, at most `max_differences` entries
+    pub differences: Vec<Difference>,
+}
+
+/// Compare two JSON documents for semantic equality.
+///
+/// # Arguments
+/// * `input_a` - The first JSON string
+/// * `input_b` - The second JSON string
+/// * `options` - Comparison options
+///
+/// # Returns
+/// * `Ok(EqualityResult)` - Whether the documents are equal, plus the first differing paths
+/// * `Err(FormatError)` - If either document is invalid JSON (message names which one)
+pub fn json_equal(
+    input_a: &str,
+    input_b: &str,
+    options: &EqualityOptions,
+) -> Result<EqualityResult, FormatError> {
+    let a = parse_document(input_a, "first")?;
+    let b = parse_document(input_b, "second")?;
+
+    let mut differences = Vec::new();
+    let mut path = String::new();
+    compare_values(&a, &b, options, &mut path, &mut differences);
+
+    Ok(EqualityResult {
+        equal: differences.is_empty(),
+        differences,
+    })
+}
+
+fn parse_document(input: &str, which: &str) -> Result<Value, FormatError> {
+    serde_json::from_str(input).map_err(|e| {
+        FormatError::new(
+            format!("Invalid {} document: {}", which, e),
+            e.line(),
+            e.column(),
+        )
+    })
+}
+
+/// Recursively compare values, appending differences until the limit is hit.
+fn compare_values(
+    a: &Value,
+    b: &Value,
+    options: &EqualityOptions,
+    path: &mut String,
+    out: &mut Vec<Difference>,
+) {
+    if out.len() >= difference_limit(options) {
+        return;
+    }
+
+    match (a, b) {
+        (Value::Object(ma), Value::Object(mb)) => {
+            for (key, va) in ma {
+                let len = path.len();
+                push_pointer_token(path, key);
+                match mb.get(key) {
+                    Some(vb) => compare_values(va, vb, options, path, out),
+                    None => push_difference(out, options, path, "key missing from second document"),
+                }
+                path.truncate(len);
+            }
+            for key in mb.keys().filter(|k| !ma.contains_key(*k)) {
+                let len = path.len();
+                push_pointer_token(path, key);
+                push_difference(out, options, path, "key missing from first document");
+                path.truncate(len);
+            }
+        }
+        (Value::Array(aa), Value::Array(ab)) => {
+            if options.ignore_array_order {
+                compare_unordered(aa, ab, options, path, out);
+            } else {
+                for (i, (va, vb)) in aa.iter().zip(ab).enumerate() {
+                    let len = path.len();
+                    push_pointer_token(path, &i.to_string());
+                    compare_values(va, vb, options, path, out);
+                    path.truncate(len);
+                }
+                if aa.len() != ab.len() {
+                    let message = format!("array length differs ({} vs {})", aa.len(), ab.len());
+                    push_difference(out, options, path, &message);
+                }
+            }
+        }
+        (Value::String(sa), Value::String(sb)) => {
+            let same = if options.ignore_whitespace {
+                sa.split_whitespace().eq(sb.split_whitespace())
+            } else {
+                sa == sb
+            };
+            if !same {
+                push_difference(out, options, path, "string values differ");
+            }
+        }
+        (Value::Number(na), Value::Number(nb)) => {
+            if !numbers_equal(na, nb, options.float_epsilon) {
+                let message = format!("numbers differ ({} vs {})", na, nb);
+                push_difference(out, options, path, &message);
+            }
+        }
+        (Value::Bool(ba), Value::Bool(bb)) if ba == bb => {}
+        (Value::Null, Value::Null) => {}
+        _ => {
+            let message = format!("values differ ({} vs {})", type_name(a), type_name(b));
+            push_difference(out, options, path, &message);
+        }
+    }
+}
+
+/// Match array elements as a multiset, reporting unmatched elements of either side.
+fn compare_unordered(
+    aa: &[Value],
+    ab: &[Value],
+    options: &EqualityOptions,
+    path: &mut String,
+    out: &mut Vec<Difference>,
+) {
+    let matched = match_elements(aa, ab, options);
+    let mut used = vec![false; ab.len()];
+    for &j in matched.iter().flatten() {
+        used[j] = true;
+    }
+    for (i, _) in matched.iter().enumerate().filter(|(_, m)| m.is_none()) {
+        let len = path.len();
+        push_pointer_token(path, &i.to_string());
+        push_difference(out, options, path, "no matching element in second document");
+        path.truncate(len);
+    }
+    for (j, _) in used.iter().enumerate().filter(|(_, u)| !**u) {
+        let len = path.len();
+        push_pointer_token(path, &j.to_string());
+        push_difference(out, options, path, "no matching element in first document");
+        path.truncate(len);
+    }
+}
+
+/// Pair elements of `aa` with equal elements of `ab`, matching as many as
+/// possible; the result holds the `ab` index matched to each `aa` element.
+///
+/// Elements are first bucketed by [`match_key`], so only elements that
+/// can be equal are compared. Without a float epsilon, equality is
+/// transitive and taking the first equal element in a bucket is optimal.
+/// With one it is not: at 0.1, `1.0` equals both `0.95` and `1.05`, which
+/// differ from each other, so buckets are matched with augmenting paths.
+fn match_elements(aa: &[Value], ab: &[Value], options: &EqualityOptions) -> Vec<Option<usize>> {
+    let fuzzy = options.float_epsilon != 0.0;
+    let mut buckets: HashMap<String, (Vec<usize>, Vec<usize>)> = HashMap::new();
+    for (i, value) in aa.iter().enumerate() {
+        buckets
+            .entry(match_key(value, options, fuzzy))
+            .or_default()
+            .0
+            .push(i);
+    }
+    for (j, value) in ab.iter().enumerate() {
+        buckets
+            .entry(match_key(value, options, fuzzy))
+            .or_default()
+            .1
+            .push(j);
+    }
+
+    let mut matched = vec![None; aa.len()];
+    for (left, right) in buckets.into_values() {
+        if right.is_empty() {
+            continue;
+        }
+        if fuzzy {
+            let pairs = max_matching(left.len(), right.len(), |l, r| {
+                values_equal(&aa[left[l]], &ab[right[r]], options)
+            });
+            for (l, r) in pairs.into_iter().enumerate() {
+                matched[left[l]] = r.map(|r| right[r]);
+            }
+        } else {
+            let mut used = vec![false; right.len()];
+            for &i in &left {
+                let found = (0..right.len())
+                    .find(|&r| !used[r] && values_equal(&aa[i], &ab[right[r]], options));
+                if let Some(r) = found {
+                    used[r] = true;
+                    matched[i] = Some(right[r]);
+                }
+            }
+        }
+    }
+    matched
+}
+
+/// Maximum matching of a bipartite graph, by breadth-first augmenting
+/// paths; the result holds the right node matched to each left node.
+fn max_matching(
+    left: usize,
+    right: usize,
+    edge: impl Fn(usize, usize) -> bool,
+) -> Vec<Option<usize>> {
+    let adjacent: Vec<Vec<usize>> = (0..left)
+        .map(|l| (0..right).filter(|&r| edge(l, r)).collect())
+        .collect();
+    let mut match_left: Vec<Option<usize>> = vec![None; left];
+    let mut match_right: Vec<Option<usize>> = vec![None; right];
+    for start in 0..left {
+        // Left node each right node was reached from
+        let mut reached_from: Vec<Option<usize>> = vec![None; right];
+        let mut queue = VecDeque::from([start]);
+        let mut free = None;
+        while let (Some(l), None) = (queue.pop_front(), free) {
+            for &r in &adjacent[l] {
+                if reached_from[r].is_some() {
+                    continue;
+                }
+                reached_from[r] = Some(l);
+                match match_right[r] {
+                    Some(next) => queue.push_back(next),
+                    None => {
+                        free = Some(r);
+                        break;
+                    }
+                }
+            }
+        }
+        // Flip the path back to `start`
+        let mut r = free;
+        while let Some(current) = r {
+            let l = reached_from[current].unwrap_or(start);
+            r = match_left[l];
+            match_left[l] = Some(current);
+            match_right[current] = Some(l);
+        }
+    }
+    match_left
+}
+
+/// Bucket key for unordered matching: values that compare equal always
+/// get the same key. Numbers are keyed by their `f64` value, or not at
+/// all when `fuzzy`, since an epsilon lets different values match.
+fn match_key(value: &Value, options: &EqualityOptions, fuzzy: bool) -> String {
+    let mut key = String::new();
+    push_match_key(&mut key, value, options, fuzzy);
+    key
+}
+
+fn push_match_key(key: &mut String, value: &Value, options: &EqualityOptions, fuzzy: bool) {
+    match value {
+        Value::Null => key.push('n'),
+        Value::Bool(b) => key.push(if *b { 't' } else { 'f' }),
+        Value::Number(_) if fuzzy => key.push('#'),
+        Value::Number(n) => {
+            // `0.0 == -0.0`, so both get the key of `0`
+            let x = n.as_f64().unwrap_or(0.0) + 0.0;
+            key.push_str(&format!("#{}", x.to_bits()));
+        }
+        Value::String(s) if options.ignore_whitespace => key.push_str(
+            &Value::String(s.split_whitespace().collect::<Vec<_>>().join(" ")).to_string(),
+        ),
+        Value::String(_) => key.push_str(&value.to_string()),
+        Value::Array(items) => {
+            let mut keys: Vec<String> =
+                items.iter().map(|v| match_key(v, options, fuzzy)).collect();
+            if options.ignore_array_order {
+                keys.sort_unstable();
+            }
+            key.push('[');
+            key.push_str(&keys.join(","));
+            key.push(']');
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<String> = map
+                .iter()
+                .map(|(k, v)| {
+                    format!(
+                        "{}:{}",
+                        Value::String(k.clone()),
+                        match_key(v, options, fuzzy)
+                    )
+                })
+                .collect();
+            entries.sort_unstable();
+            key.push('{');
+            key.push_str(&entries.join(","));
+            key.push('}');
+        }
+    }
+}
+
+/// Equality check without collecting differences.
+fn values_equal(a: &Value, b: &Value, options: &EqualityOptions) -> bool {
+    let mut probe = Vec::new();
+    let mut path = String::new();
+    let probe_options = EqualityOptions {
+        max_differences: 1,
+        ..options.clone()
+    };
+    compare_values(a, b, &probe_options, &mut path, &mut probe);
+    probe.is_empty()
+}
+
+fn numbers_equal(a: &Number, b: &Number, epsilon: f64) -> bool {
+    if let (Some(x), Some(y)) = (a.as_i64(), b.as_i64()) {
+        return x == y;
+    }
+    if let (Some(x), Some(y)) = (a.as_u64(), b.as_u64()) {
+        return x == y;
+    }
+    match (a.as_f64(), b.as_f64()) {
+        (Some(x), Some(y)) => x == y || (x - y).abs() <= epsilon,
+        _ => false,
+    }
+}
+
+fn push_difference(
+    out: &mut Vec<Difference>,
+    options: &EqualityOptions,
+    path: &str,
+    message: &str,
+) {
+    if out.len() < difference_limit(options) {
+        out.push(Difference {
+            path: path.to_string(),
+            message: message.to_string(),
+        });
+    }
+}
+
+/// A limit of zero would report every document as equal, so at least one
+/// difference is always recorded.
+fn difference_limit(options: &EqualityOptions) -> usize {
+    options.max_differences.max(1)
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_order_and_whitespace_ignored() {
+        let a = r#"{"a": 1, "b": [1, 2]}"#;
+        let b = r#"{"b":[1,2],"a":1}"#;
+        let result = json_equal(a, b, &EqualityOptions::default()).unwrap();
+        assert!(result.equal);
+        assert!(result.differences.is_empty());
+    }
+
+    #[test]
+    fn test_reports_differing_paths() {
+        let a = r#"{"a": 1, "b": {"c": "x"}, "d": true}"#;
+        let b = r#"{"a": 2, "b": {"c": "y"}, "e": true}"#;
+        let result = json_equal(a, b, &EqualityOptions::default()).unwrap();
+        assert!(!result.equal);
+        let paths: Vec<&str> = result.differences.iter().map(|d| d.path.as_str()).collect();
+        assert_eq!(paths, vec!["/a", "/b/c", "/d", "/e"]);
+    }
+
+    #[test]
+    fn test_array_order_option() {
+        let a = r#"[1, 2, {"x": 3}]"#;
+        let b = r#"[{"x": 3}, 2, 1]"#;
+        assert!(!json_equal(a, b, &EqualityOptions::default()).unwrap().equal);
+        let options = EqualityOptions {
+            ignore_array_order: true,
+            ..Default::default()
+        };
+        assert!(json_equal(a, b, &options).unwrap().equal);
+        assert!(
+            !json_equal("[1, 1, 2]", "[1, 2, 2]", &options)
+                .unwrap()
+                .equal
+        );
+    }
+
+    #[test]
+    fn test_float_epsilon_and_whitespace() {
+        let options = EqualityOptions {
+            float_epsilon: 0.001,
+            ignore_whitespace: true,
+            ..Default::default()
+        };
+        assert!(
+            json_equal("[0.1, \" a  b \"]", "[0.1004, \"a b\"]", &options)
+                .unwrap()
+                .equal
+        );
+        assert!(!json_equal("[0.1]", "[0.2]", &options).unwrap().equal);
+        assert!(
+            !json_equal("[0.1]", "[0.1004]", &EqualityOptions::default())
+                .unwrap()
+                .equal
+        );
+    }
+
+    #[test]
+    fn test_unordered_matching() {
+        let options = EqualityOptions {
+            ignore_array_order: true,
+            ..Default::default()
+        };
+        let a = r#"[{"a": [1, 2]}, "x", 1, 0.0, [null, true]]"#;
+        let b = r#"[-0.0, 1.0, "x", [true, null], {"a": [2, 1]}]"#;
+        assert!(json_equal(a, b, &options).unwrap().equal);
+        let result = json_equal(r#"["x", 2, "y"]"#, r#"[2, "z", "x"]"#, &options).unwrap();
+        let paths: Vec<&str> = result.differences.iter().map(|d| d.path.as_str()).collect();
+        assert_eq!(paths, ["/2", "/1"]);
+    }
+
+    #[test]
+    fn test_unordered_matching_with_epsilon() {
+        let options = EqualityOptions {
+            ignore_array_order: true,
+            float_epsilon: 0.1,
+            ..Default::default()
+        };
+        // 1.0 matches either element; only pairing it with 0.95 leaves 1.05 for 1.1
+        assert!(
+            json_equal("[1.0, 1.1]", "[1.05, 0.95]", &options)
+                .unwrap()
+                .equal
+        );
+        assert!(
+            json_equal(
+                r#"[{"v": 1.0}, {"v": 1.1}]"#,
+                r#"[{"v": 1.05}, {"v": 0.95}]"#,
+                &options
+            )
+            .unwrap()
+            .equal
+        );
+        assert!(
+            !json_equal("[1.0, 1.3]", "[1.05, 0.95]", &options)
+                .unwrap()
+                .equal
+        );
+    }
+
+    #[test]
+    fn test_max_differences_limit() {
+        let options = EqualityOptions {
+            max_differences: 2,
+            ..Default::default()
+        };
+        let result = json_equal("[1, 2, 3, 4]", "[5, 6, 7, 8]", &options).unwrap();
+        assert_eq!(result.differences.len(), 2);
+    }
+
+    #[test]
+    fn test_invalid_input_names_document() {
+        let err = json_equal("{}", "{bad}", &EqualityOptions::default()).unwrap_err();
+        assert!(err.message.contains("second"));
+    }
+}
@@ -0,0 +1,413 @@
+//! Highlighted line diffs
+//!
+//! Compares two versions of a JSON or XML document line by line and renders
+//! them as HTML, either as two aligned columns or as a unified view, with
+//! added, removed, and changed lines marked. Each side is highlighted with
+//! the regular highlighter for its language, so the diff uses the same
+//! colors as the editor. Differences are found on text, so reformatting a
+//! document shows up as changed lines; format both versions the same way
+//! first to compare content.
+
+use crate::highlight::{HighlightOptions, HighlightTheme, Language};
+use crate::highlighter::{highlight_json_chunk, JsonHighlightState};
+use crate::xml_highlighter::{highlight_xml_chunk, XmlHighlightState};
+use serde::{Deserialize, Serialize};
+
+/// Largest table, in cells, for the line alignment; differing regions
+/// larger than this are shown as all removed followed by all added.
+const MAX_ALIGNMENT_CELLS: usize = 4_000_000;
+
+/// How a diff is laid out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DiffView {
+    /// Old and new versions in two aligned columns
+    #[default]
+    SideBySide,
+    /// One column, removed lines before the lines that replace them
+    Unified,
+}
+
+/// Options for [`highlight_diff`].
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DiffOptions {
+    pub view: DiffView,
+    /// Syntax colors for both versions
+    pub theme: HighlightTheme,
+}
+
+/// A rendered diff and its line counts.
+///
+/// In the side-by-side view a removed line facing an added line is one
+/// changed line; the unified view counts the same way.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct HighlightedDiff {
+    pub html: String,
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+}
+
+/// How a row of the diff differs between the versions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RowKind {
+    Equal,
+    Added,
+    Removed,
+    Changed,
+}
+
+impl RowKind {
+    fn class_name(self) -> &'static str {
+        match self {
+            RowKind::Equal => "diff-equal",
+            RowKind::Added => "diff-added",
+            RowKind::Removed => "diff-removed",
+            RowKind::Changed => "diff-changed",
+        }
+    }
+
+    fn style(self) -> &'static str {
+        match self {
+            RowKind::Equal => "",
+            RowKind::Added => " style=\"background:rgba(46,160,67,0.2)\"",
+            RowKind::Removed => " style=\"background:rgba(248,81,73,0.2)\"",
+            RowKind::Changed => " style=\"background:rgba(210,153,34,0.2)\"",
+        }
+    }
+}
+
+/// A row of the side-by-side view: a line index in each version.
+type Row = (RowKind, Option<usize>, Option<usize>);
+
+/// Highlight the differences between two versions of a document.
+///
+/// Lines are compared with trailing carriage returns ignored. Each line
+/// keeps its `<span class="line" data-line="N">` wrapper from the
+/// highlighter, numbered within its own version.
+///
+/// # Arguments
+/// * `old` - The original document
+/// * `new` - The changed document
+/// * `language` - Whether both versions are JSON or XML
+/// * `options` - Layout and colors
+///
+/// # Returns
+/// * The diff as an HTML `<table class="hl-diff ...">`, rows classed
+///   `diff-equal`, `diff-added`, `diff-removed`, or `diff-changed`, with
+///   counts of the lines that differ
+pub fn highlight_diff(
+    old: &str,
+    new: &str,
+    language: Language,
+    options: &DiffOptions,
+) -> HighlightedDiff {
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+    let old_html = highlighted_lines(old, language, &options.theme);
+    let new_html = highlighted_lines(new, language, &options.theme);
+    let rows = pair_rows(&align(&old_lines, &new_lines));
+
+    let count = |kind| rows.iter().filter(|row| row.0 == kind).count();
+    let mut diff = HighlightedDiff {
+        html: String::new(),
+        added: count(RowKind::Added),
+        removed: count(RowKind::Removed),
+        changed: count(RowKind::Changed),
+    };
+    let out = &mut diff.html;
+    match options.view {
+        DiffView::SideBySide => {
+            out.push_str("<table class=\"hl-diff hl-diff-split\" style=\"border-collapse:collapse;width:100%;\">");
+            out.push_str("<tbody>");
+            for &(kind, old_index, new_index) in &rows {
+                push_row_start(out, kind);
+                push_cell(out, "diff-old", line_html(&old_html, old_index));
+                push_cell(out, "diff-new", line_html(&new_html, new_index));
+                out.push_str("</tr>");
+            }
+        }
+        DiffView::Unified => {
+            out.push_str("<table class=\"hl-diff hl-diff-unified\" style=\"border-collapse:collapse;width:100%;\">");
+            out.push_str("<tbody>");
+            let mut pending_added = Vec::new();
+            for &(kind, old_index, new_index) in &rows {
+                // Replacement lines follow the whole run of lines they replace
+                if matches!(kind, RowKind::Equal) {
+                    push_unified_lines(out, &mut pending_added, &new_html);
+                }
+                match kind {
+                    RowKind::Equal => {
+                        push_unified_line(out, kind, " ", line_html(&new_html, new_index))
+                    }
+                    RowKind::Removed | RowKind::Changed => {
+                        push_unified_line(out, kind, "-", line_html(&old_html, old_index));
+                        pending_added.extend(new_index.map(|i| (kind, i)));
+                    }
+                    RowKind::Added => pending_added.extend(new_index.map(|i| (kind, i))),
+                }
+            }
+            push_unified_lines(out, &mut pending_added, &new_html);
+        }
+    }
+    out.push_str("</tbody></table>");
+    diff
+}
+
+/// Lines of a document, without a final empty line after a trailing line break.
+fn split_lines(input: &str) -> Vec<&str> {
+    let mut lines: Vec<&str> = input
+        .split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .collect();
+    if input.ends_with('\n') {
+        lines.pop();
+    }
+    lines
+}
+
+/// Highlighted HTML for each line of a document, each a self-contained line span.
+fn highlighted_lines(input: &str, language: Language, theme: &HighlightTheme) -> Vec<String> {
+    let options = HighlightOptions {
+        theme: theme.clone(),
+        line_numbers: true,
+        ..Default::default()
+    };
+    // A single chunk has no <pre> wrapper, and line numbering closes every span at a line break
+    let html = match language {
+        Language::Json => {
+            highlight_json_chunk(input, &JsonHighlightState::default(), &options).html
+        }
+        Language::Xml => highlight_xml_chunk(input, &XmlHighlightState::default(), &options).html,
+    };
+    let mut lines: Vec<String> = html.split('\n').map(str::to_string).collect();
+    if input.ends_with('\n') {
+        lines.pop();
+    }
+    lines
+}
+
+/// Line-by-line edit script from `old` to `new`: `(Some(i), Some(j))` for a
+/// kept line, and `(Some(i), None)` / `(None, Some(j))` for removed and added ones.
+fn align(old: &[&str], new: &[&str]) -> Vec<(Option<usize>, Option<usize>)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let mut script: Vec<(Option<usize>, Option<usize>)> =
+        (0..prefix).map(|i| (Some(i), Some(i))).collect();
+    let (n, m) = (old_middle.len(), new_middle.len());
+    if n.saturating_mul(m) > MAX_ALIGNMENT_CELLS {
+        script.extend((0..n).map(|i| (Some(prefix + i), None)));
+        script.extend((0..m).map(|j| (None, Some(prefix + j))));
+    } else {
+        // Longest common subsequence of the differing region, from the end
+        // so the script can be read forwards; one side has at most 2000
+        // lines here, which fits u16
+        let width = m + 1;
+        let mut table = vec![0u16; (n + 1) * width];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                table[i * width + j] = if old_middle[i] == new_middle[j] {
+                    table[(i + 1) * width + j + 1] + 1
+                } else {
+                    table[(i + 1) * width + j].max(table[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && old_middle[i] == new_middle[j] {
+                script.push((Some(prefix + i), Some(prefix + j)));
+                i += 1;
+                j += 1;
+            } else if j < m && (i == n || table[i * width + j + 1] >= table[(i + 1) * width + j]) {
+                script.push((None, Some(prefix + j)));
+                j += 1;
+            } else {
+                script.push((Some(prefix + i), None));
+                i += 1;
+            }
+        }
+        // Removals read more naturally before the additions that replace them
+        sort_runs(&mut script);
+    }
+    let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+    script.extend((0..suffix).map(|k| (Some(old_end + k), Some(new_end + k))));
+    script
+}
+
+/// Put removed lines before added lines within each run of differences.
+fn sort_runs(script: &mut [(Option<usize>, Option<usize>)]) {
+    for run in script.split_mut(|(a, b)| a.is_some() && b.is_some()) {
+        run.sort_by_key(|(a, _)| a.is_none());
+    }
+}
+
+/// Rows for the edit script, a removed line facing the added line at the
+/// same position in its run of differences being one changed row.
+fn pair_rows(script: &[(Option<usize>, Option<usize>)]) -> Vec<Row> {
+    let mut rows = Vec::with_capacity(script.len());
+    for run in script.split_inclusive(|(a, b)| a.is_some() && b.is_some()) {
+        let (kept, changes) = match run.split_last() {
+            Some((&(Some(i), Some(j)), changes)) => (Some((i, j)), changes),
+            _ => (None, run),
+        };
+        let removed: Vec<usize> = changes
+            .iter()
+            .filter_map(|&(a, b)| a.filter(|_| b.is_none()))
+            .collect();
+        let added: Vec<usize> = changes
+            .iter()
+            .filter_map(|&(a, b)| b.filter(|_| a.is_none()))
+            .collect();
+        for k in 0..removed.len().max(added.len()) {
+            let kind = match (removed.get(k), added.get(k)) {
+                (Some(_), Some(_)) => RowKind::Changed,
+                (Some(_), None) => RowKind::Removed,
+                _ => RowKind::Added,
+            };
+            rows.push((kind, removed.get(k).copied(), added.get(k).copied()));
+        }
+        if let Some((i, j)) = kept {
+            rows.push((RowKind::Equal, Some(i), Some(j)));
+        }
+    }
+    rows
+}
+
+fn line_html(lines: &[String], index: Option<usize>) -> &str {
+    index.and_then(|i| lines.get(i)).map_or("", String::as_str)
+}
+
+fn push_row_start(out: &mut String, kind: RowKind) {
+    out.push_str("<tr class=\"");
+    out.push_str(kind.class_name());
+    out.push('"');
+    out.push_str(kind.style());
+    out.push('>');
+}
+
+fn push_cell(out: &mut String, class: &str, html: &str) {
+    out.push_str("<td class=\"");
+    out.push_str(class);
+    out.push_str("\" style=\"white-space:pre;vertical-align:top;\">");
+    out.push_str(html);
+    out.push_str("</td>");
+}
+
+fn push_unified_line(out: &mut String, kind: RowKind, sign: &str, html: &str) {
+    push_row_start(out, kind);
+    push_cell(out, "diff-sign", sign);
+    push_cell(out, "diff-line", html);
+    out.push_str("</tr>");
+}
+
+/// Write the added side of a run of differences.
+fn push_unified_lines(out: &mut String, pending: &mut Vec<(RowKind, usize)>, new_html: &[String]) {
+    for (kind, index) in pending.drain(..) {
+        push_unified_line(out, kind, "+", line_html(new_html, Some(index)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(diff: &str) -> Vec<&str> {
+        diff.match_indices("<tr class=\"")
+            .map(|(i, _)| &diff[i + 11..i + diff[i + 11..].find('"').unwrap() + 11])
+            .collect()
+    }
+
+    #[test]
+    fn test_align() {
+        let old = ["a", "b", "c", "d"];
+        let new = ["a", "x", "c", "d", "e"];
+        assert_eq!(
+            align(&old, &new),
+            vec![
+                (Some(0), Some(0)),
+                (Some(1), None),
+                (None, Some(1)),
+                (Some(2), Some(2)),
+                (Some(3), Some(3)),
+                (None, Some(4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_side_by_side() {
+        let old = "{\n  \"a\": 1,\n  \"b\": 2\n}\n";
+        let new = "{\n  \"a\": 1,\n  \"b\": 3,\n  \"c\": 4\n}\n";
+        let diff = highlight_diff(old, new, Language::Json, &DiffOptions::default());
+        assert_eq!((diff.added, diff.removed, diff.changed), (1, 0, 1));
+        assert_eq!(
+            kinds(&diff.html),
+            [
+                "diff-equal",
+                "diff-equal",
+                "diff-changed",
+                "diff-added",
+                "diff-equal"
+            ]
+        );
+        assert!(diff
+            .html
+            .starts_with("<table class=\"hl-diff hl-diff-split\""));
+        // Both sides keep their syntax colors and line numbers
+        assert!(diff.html.contains(
+            "<span class=\"line\" data-line=\"4\">  <span style=\"color:#9cdcfe\">\"c\"</span>"
+        ));
+        assert!(diff.html.contains(concat!(
+            "<tr class=\"diff-added\" style=\"background:rgba(46,160,67,0.2)\">",
+            "<td class=\"diff-old\" style=\"white-space:pre;vertical-align:top;\"></td>"
+        )));
+    }
+
+    #[test]
+    fn test_unified() {
+        let old = "<a>\n<b/>\n<c/>\n</a>";
+        let new = "<a>\n<x/>\n</a>";
+        let options = DiffOptions {
+            view: DiffView::Unified,
+            ..Default::default()
+        };
+        let diff = highlight_diff(old, new, Language::Xml, &options);
+        assert_eq!((diff.added, diff.removed, diff.changed), (0, 1, 1));
+        // Both removed lines come before the line replacing them
+        assert_eq!(
+            kinds(&diff.html),
+            [
+                "diff-equal",
+                "diff-changed",
+                "diff-removed",
+                "diff-changed",
+                "diff-equal"
+            ]
+        );
+        let signs: Vec<&str> = diff
+            .html
+            .match_indices("vertical-align:top;\">")
+            .map(|(i, _)| &diff.html[i + 21..i + 22])
+            .step_by(2)
+            .collect();
+        assert_eq!(signs, [" ", "-", "-", "+", " "]);
+    }
+
+    #[test]
+    fn test_identical_and_empty() {
+        let diff = highlight_diff("[1]\r\n", "[1]\n", Language::Json, &DiffOptions::default());
+        assert_eq!((diff.added, diff.removed, diff.changed), (0, 0, 0));
+        let diff = highlight_diff("", "[1]", Language::Json, &DiffOptions::default());
+        assert_eq!(kinds(&diff.html), ["diff-changed"]);
+    }
+}
@@ -0,0 +1,398 @@
+//! Encoding problem detection
+//!
+//! Pre-validation checks for byte-level problems that commonly surface as
+//! confusing "invalid JSON" errors: byte order marks, invalid UTF-8, lone
+//! UTF-16 surrogates in `\u` escapes, and mixed line endings. Also decodes
+//! XML bytes in the encoding their BOM or declaration names.
+
+use crate::types::FormatError;
+use serde::Serialize;
+use std::borrow::Cow;
+
+/// Stop reporting after this many issues; a UTF-16 file read as UTF-8
+/// would otherwise produce one issue per character.
+const MAX_ISSUES: usize = 100;
+
+/// Category of an encoding issue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EncodingIssueKind {
+    /// A byte order mark at the start of the input
+    Bom,
+    /// A byte sequence that is not valid UTF-8
+    InvalidUtf8,
+    /// A `\uD800`-`\uDFFF` escape without its matching half
+    LoneSurrogate,
+    /// More than one line ending style (LF, CRLF, CR) in the same input
+    MixedLineEndings,
+}
+
+/// A single encoding problem and where it occurs.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct EncodingIssue {
+    pub kind: EncodingIssueKind,
+    /// Byte offset from the start of the input
+    pub offset: usize,
+    pub message: String,
+}
+
+impl EncodingIssue {
+    fn new(kind: EncodingIssueKind, offset: usize, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            offset,
+            message: message.into(),
+        }
+    }
+}
+
+/// Detect encoding problems in raw input bytes.
+///
+/// # Arguments
+/// * `input` - The raw bytes of the document
+///
+/// # Returns
+/// * Issues ordered by kind, then offset (empty if the input is clean)
+pub fn detect_encoding_issues(input: &[u8]) -> Vec<EncodingIssue> {
+    let mut issues = Vec::new();
+    check_bom(input, &mut issues);
+    check_utf8(input, &mut issues);
+    check_surrogates(input, &mut issues);
+    check_line_endings(input, &mut issues);
+    issues.truncate(MAX_ISSUES);
+    issues
+}
+
+/// Decode raw XML bytes to UTF-8 text.
+///
+/// The encoding comes from a byte order mark, the `<?xml` pattern of a
+/// UTF-16 document without one, or the declaration's `encoding` attribute,
+/// defaulting to UTF-8. Supported encodings are UTF-8, UTF-16LE/BE, and
+/// ISO-8859-1 (Latin-1, which also covers US-ASCII). A UTF-8 BOM is
+/// dropped; the declaration is left as written.
+///
+/// # Arguments
+/// * `input` - The raw bytes of the document
+///
+/// # Returns
+/// * `Ok(Cow<str>)` - The text, borrowed when the input is already UTF-8
+/// * `Err(FormatError)` - If the encoding is unsupported or the bytes are
+///   invalid for it
+pub fn decode_xml(input: &[u8]) -> Result<Cow<'_, str>, FormatError> {
+    if input.starts_with(&[0xFF, 0xFE, 0x00, 0x00]) || input.starts_with(&[0x00, 0x00, 0xFE, 0xFF])
+    {
+        return Err(FormatError::new("Unsupported encoding: UTF-32", 1, 1));
+    }
+    if let Some(rest) = input.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        return decode_utf8(rest);
+    }
+    if let Some(rest) = input.strip_prefix(&[0xFF, 0xFE]) {
+        return decode_utf16(rest, u16::from_le_bytes);
+    }
+    if let Some(rest) = input.strip_prefix(&[0xFE, 0xFF]) {
+        return decode_utf16(rest, u16::from_be_bytes);
+    }
+    if input.starts_with(b"<\0?\0") {
+        return decode_utf16(input, u16::from_le_bytes);
+    }
+    if input.starts_with(b"\0<\0?") {
+        return decode_utf16(input, u16::from_be_bytes);
+    }
+
+    let declared = declared_encoding(input).map(|e| e.to_ascii_lowercase());
+    match declared.as_deref() {
+        // A UTF-16 label on 8-bit text means the bytes were already transcoded
+        None | Some("utf-8" | "utf8" | "utf-16" | "utf16") => decode_utf8(input),
+        Some("iso-8859-1" | "iso_8859-1" | "latin1" | "latin-1" | "l1" | "us-ascii" | "ascii") => {
+            Ok(Cow::Owned(input.iter().map(|&b| char::from(b)).collect()))
+        }
+        Some(other) => Err(FormatError::new(
+            format!("Unsupported encoding: {}", other),
+            1,
+            1,
+        )),
+    }
+}
+
+/// The `encoding` value of a leading XML declaration, if any.
+fn declared_encoding(input: &[u8]) -> Option<&str> {
+    let end = input.windows(2).position(|w| w == b"?>")?;
+    let decl =
+        std::str::from_utf8(input.strip_prefix(b"<?xml")?.get(..end.checked_sub(5)?)?).ok()?;
+    let rest = decl[decl.find("encoding")? + "encoding".len()..].trim_start();
+    let rest = rest.strip_prefix('=')?.trim_start();
+    let quote = rest.chars().next().filter(|&c| c == '"' || c == '\'')?;
+    let value = &rest[1..];
+    value.find(quote).map(|end| &value[..end])
+}
+
+fn decode_utf8(input: &[u8]) -> Result<Cow<'_, str>, FormatError> {
+    std::str::from_utf8(input).map(Cow::Borrowed).map_err(|e| {
+        let before = String::from_utf8_lossy(&input[..e.valid_up_to()]);
+        FormatError::at_offset(
+            format!("Invalid UTF-8 byte 0x{:02X}", input[e.valid_up_to()]),
+            &before,
+            before.len(),
+        )
+    })
+}
+
+fn decode_utf16(input: &[u8], unit: fn([u8; 2]) -> u16) -> Result<Cow<'_, str>, FormatError> {
+    if !input.len().is_multiple_of(2) {
+        return Err(FormatError::new(
+            "Truncated UTF-16 input: odd number of bytes",
+            1,
+            1,
+        ));
+    }
+    let units = input.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    let mut out = String::with_capacity(input.len() / 2);
+    for c in char::decode_utf16(units) {
+        match c {
+            Ok(c) => out.push(c),
+            Err(e) => {
+                let message = format!(
+                    "Invalid UTF-16: lone surrogate 0x{:04X}",
+                    e.unpaired_surrogate()
+                );
+                return Err(FormatError::at_offset(message, &out, out.len()));
+            }
+        }
+    }
+    Ok(Cow::Owned(out))
+}
+
+fn check_bom(input: &[u8], issues: &mut Vec<EncodingIssue>) {
+    let name = if input.starts_with(&[0xFF, 0xFE, 0x00, 0x00]) {
+        "UTF-32LE"
+    } else if input.starts_with(&[0x00, 0x00, 0xFE, 0xFF]) {
+        "UTF-32BE"
+    } else if input.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        "UTF-8"
+    } else if input.starts_with(&[0xFF, 0xFE]) {
+        "UTF-16LE"
+    } else if input.starts_with(&[0xFE, 0xFF]) {
+        "UTF-16BE"
+    } else {
+        return;
+    };
+    issues.push(EncodingIssue::new(
+        EncodingIssueKind::Bom,
+        0,
+        format!("{} byte order mark at start of input", name),
+    ));
+}
+
+fn check_utf8(input: &[u8], issues: &mut Vec<EncodingIssue>) {
+    let mut offset = 0;
+    while offset < input.len() && issues.len() < MAX_ISSUES {
+        match std::str::from_utf8(&input[offset..]) {
+            Ok(_) => break,
+            Err(e) => {
+                let at = offset + e.valid_up_to();
+                let bad_len = e.error_len().unwrap_or(input.len() - at);
+                let message = match e.error_len() {
+                    Some(_) => format!("Invalid UTF-8 byte 0x{:02X}", input[at]),
+                    None => "Truncated UTF-8 sequence at end of input".to_string(),
+                };
+                issues.push(EncodingIssue::new(
+                    EncodingIssueKind::InvalidUtf8,
+                    at,
+                    message,
+                ));
+                offset = at + bad_len;
+            }
+        }
+    }
+}
+
+/// Scan `\uXXXX` escapes in JSON string literals for unpaired surrogate halves.
+///
+/// Backslashes outside string literals are not escapes and are skipped.
+fn check_surrogates(input: &[u8], issues: &mut Vec<EncodingIssue>) {
+    let mut in_string = false;
+    let mut i = 0;
+    while i < input.len() && issues.len() < MAX_ISSUES {
+        match input[i] {
+            b'\\' if in_string => {}
+            c => {
+                in_string ^= c == b'"';
+                i += 1;
+                continue;
+            }
+        }
+        let Some(unit) = read_u_escape(input, i) else {
+            // Skip the escaped character so `\\u` is not read as an escape
+            i += 2;
+            continue;
+        };
+        match unit {
+            0xD800..=0xDBFF => {
+                let paired = matches!(read_u_escape(input, i + 6), Some(0xDC00..=0xDFFF));
+                if paired {
+                    i += 12;
+                    continue;
+                }
+                issues.push(EncodingIssue::new(
+                    EncodingIssueKind::LoneSurrogate,
+                    i,
+                    format!(
+                        "High surrogate \\u{:04X} without a following low surrogate",
+                        unit
+                    ),
+                ));
+            }
+            0xDC00..=0xDFFF => {
+                issues.push(EncodingIssue::new(
+                    EncodingIssueKind::LoneSurrogate,
+                    i,
+                    format!(
+                        "Low surrogate \\u{:04X} without a preceding high surrogate",
+                        unit
+                    ),
+                ));
+            }
+            _ => {}
+        }
+        i += 6;
+    }
+}
+
+/// Read the code unit of a `\uXXXX` escape starting at `start`.
+fn read_u_escape(input: &[u8], start: usize) -> Option<u16> {
+    let escape = input.get(start..start + 6)?;
+    if escape[0] != b'\\' || escape[1] != b'u' {
+        return None;
+    }
+    let hex = std::str::from_utf8(&escape[2..]).ok()?;
+    u16::from_str_radix(hex, 16).ok()
+}
+
+fn check_line_endings(input: &[u8], issues: &mut Vec<EncodingIssue>) {
+    // (count, first offset) for LF, CRLF, CR
+    let mut styles = [(0usize, 0usize); 3];
+    let mut i = 0;
+    while i < input.len() {
+        let style = match input[i] {
+            b'\n' => Some(0),
+            b'\r' if input.get(i + 1) == Some(&b'\n') => Some(1),
+            b'\r' => Some(2),
+            _ => None,
+        };
+        if let Some(s) = style {
+            if styles[s].0 == 0 {
+                styles[s].1 = i;
+            }
+            styles[s].0 += 1;
+            i += if s == 1 { 2 } else { 1 };
+        } else {
+            i += 1;
+        }
+    }
+
+    let used = styles.iter().filter(|(count, _)| *count > 0).count();
+    if used < 2 {
+        return;
+    }
+    // Point at the first occurrence of the least common style
+    let (minority, _) = styles
+        .iter()
+        .enumerate()
+        .filter(|(_, (count, _))| *count > 0)
+        .min_by_key(|(_, (count, _))| *count)
+        .expect("at least two styles are in use");
+    issues.push(EncodingIssue::new(
+        EncodingIssueKind::MixedLineEndings,
+        styles[minority].1,
+        format!(
+            "Mixed line endings: {} LF, {} CRLF, {} CR",
+            styles[0].0, styles[1].0, styles[2].0
+        ),
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_input() {
+        assert!(detect_encoding_issues(r#"{"key": "café"}"#.as_bytes()).is_empty());
+        assert!(detect_encoding_issues("{\n\"a\": \"é\"\n}".as_bytes()).is_empty());
+    }
+
+    #[test]
+    fn test_bom_detection() {
+        let issues = detect_encoding_issues(b"\xEF\xBB\xBF{}");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].kind, EncodingIssueKind::Bom);
+        assert!(issues[0].message.contains("UTF-8"));
+
+        let issues = detect_encoding_issues(b"\xFF\xFE{\x00}\x00");
+        assert!(issues[0].message.contains("UTF-16LE"));
+    }
+
+    #[test]
+    fn test_invalid_utf8_offsets() {
+        let issues = detect_encoding_issues(b"{\"a\": \"\xC3\x28\", \"b\": \"\xFF\"}");
+        let offsets: Vec<usize> = issues
+            .iter()
+            .filter(|i| i.kind == EncodingIssueKind::InvalidUtf8)
+            .map(|i| i.offset)
+            .collect();
+        assert_eq!(offsets, vec![7, 18]);
+    }
+
+    #[test]
+    fn test_lone_surrogates() {
+        let issues = detect_encoding_issues(br#"["\uD83D\uDE00", "\uD83D", "\uDE00", "\\uD800"]"#);
+        let offsets: Vec<usize> = issues.iter().map(|i| i.offset).collect();
+        assert_eq!(offsets, vec![18, 28]);
+        assert!(issues
+            .iter()
+            .all(|i| i.kind == EncodingIssueKind::LoneSurrogate));
+        // Only escapes inside string literals count
+        assert!(detect_encoding_issues(br#"{"a\"": 1} \uD800 ["\\", "x"]"#).is_empty());
+        assert_eq!(detect_encoding_issues(br#"["\"", "\uDC00"]"#)[0].offset, 8);
+    }
+
+    #[test]
+    fn test_mixed_line_endings() {
+        let issues = detect_encoding_issues(b"{\n\"a\": 1,\r\n\"b\": 2\n}");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].kind, EncodingIssueKind::MixedLineEndings);
+        assert_eq!(issues[0].offset, 9);
+        assert!(issues[0].message.contains("2 LF, 1 CRLF"));
+    }
+
+    #[test]
+    fn test_decode_xml() {
+        assert_eq!(decode_xml("<a>é</a>".as_bytes()).unwrap(), "<a>é</a>");
+        assert_eq!(decode_xml(b"\xEF\xBB\xBF<a/>").unwrap(), "<a/>");
+
+        let utf16: Vec<u8> = "<?xml version=\"1.0\" encoding=\"UTF-16\"?><a>é</a>"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let text = decode_xml(&utf16).unwrap();
+        assert!(text.ends_with("<a>é</a>"));
+        let with_bom: Vec<u8> = [0xFE, 0xFF]
+            .into_iter()
+            .chain("<a>é</a>".encode_utf16().flat_map(u16::to_be_bytes))
+            .collect();
+        assert_eq!(decode_xml(&with_bom).unwrap(), "<a>é</a>");
+
+        assert_eq!(
+            decode_xml(b"<?xml version='1.0' encoding='ISO-8859-1'?><a>\xE9</a>").unwrap(),
+            "<?xml version='1.0' encoding='ISO-8859-1'?><a>é</a>"
+        );
+    }
+
+    #[test]
+    fn test_decode_xml_errors() {
+        let err = decode_xml(b"<a>\n\xFF</a>").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 1));
+        assert!(decode_xml(b"<?xml version=\"1.0\" encoding=\"Shift_JIS\"?><a/>").is_err());
+        assert!(decode_xml(b"\xFF\xFE<\x00a").is_err());
+    }
+}
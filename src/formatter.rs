@@ -44,6 +44,14 @@ pub fn format_json(input: &str, indent: IndentStyle) -> Result<String, FormatErr
     Ok(output)
 }
 
+/// Format an already-parsed JSON value with the specified indentation style.
+pub(crate) fn format_value_to_string(value: &Value, indent: IndentStyle) -> String {
+    let indent_str = indent.as_str();
+    let mut output = String::new();
+    format_value(value, &indent_str, 0, &mut output);
+    output
+}
+
 /// Recursively format a JSON value with proper indentation.
 fn format_value(value: &Value, indent_str: &str, depth: usize, output: &mut String) {
     match value {
@@ -0,0 +1,1621 @@
+//! Highlighter configuration shared by the JSON and XML highlighters
+//!
+//! A [`HighlightTheme`] holds one color per token type for each language.
+//! Three built-in themes are provided: `dark` (the VS Code dark palette the
+//! highlighters have always used), `light`, for light backgrounds, and
+//! `highContrast`, for black backgrounds and low vision.
+//! From JavaScript a theme is either a built-in name or an object with
+//! per-token overrides on top of a base theme.
+//!
+//! Both highlighters are split into a scanner that reports [`Token`]s and
+//! the rendering here, so hosts can also take the raw token stream. Output
+//! is HTML with inline colors by default; see [`OutputTarget`] for HTML
+//! with classes, ANSI terminal colors, and plain text.
+
+use crate::types::FormatError;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Kind of a highlighted token, for both JSON and XML.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TokenKind {
+    // JSON
+    Key,
+    String,
+    Number,
+    Boolean,
+    Null,
+    Punctuation,
+    // XML
+    Tag,
+    AttrName,
+    /// `xmlns` and `xmlns:prefix` attribute names
+    Namespace,
+    AttrValue,
+    Text,
+    Comment,
+    Cdata,
+    Declaration,
+    Entity,
+    // Both
+    Bracket,
+}
+
+impl TokenKind {
+    /// CSS class used for the kind by [`OutputTarget::HtmlClasses`].
+    pub fn class_name(self) -> &'static str {
+        match self {
+            TokenKind::Key => "hl-key",
+            TokenKind::String => "hl-string",
+            TokenKind::Number => "hl-number",
+            TokenKind::Boolean => "hl-boolean",
+            TokenKind::Null => "hl-null",
+            TokenKind::Punctuation => "hl-punctuation",
+            TokenKind::Tag => "hl-tag",
+            TokenKind::AttrName => "hl-attr-name",
+            TokenKind::Namespace => "hl-namespace",
+            TokenKind::AttrValue => "hl-attr-value",
+            TokenKind::Text => "hl-text",
+            TokenKind::Comment => "hl-comment",
+            TokenKind::Cdata => "hl-cdata",
+            TokenKind::Declaration => "hl-declaration",
+            TokenKind::Entity => "hl-entity",
+            TokenKind::Bracket => "hl-bracket",
+        }
+    }
+}
+
+/// Language being highlighted, which picks the theme's colors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Language {
+    Json,
+    Xml,
+}
+
+impl Language {
+    fn class_name(self) -> &'static str {
+        match self {
+            Language::Json => "hl-json",
+            Language::Xml => "hl-xml",
+        }
+    }
+}
+
+/// A highlighted token.
+///
+/// `start` and `end` are byte offsets into the input; whitespace and other
+/// characters between tokens are not reported. `line` and `column` are
+/// 1-based, with the column counted in characters.
+///
+/// `depth` is the nesting level: 0 at the top, one more inside each JSON
+/// object or array or XML element. Brackets and tags count at the level
+/// they open or close from, so a pair has the same depth.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+    pub depth: usize,
+}
+
+/// What a fold region spans.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FoldKind {
+    Object,
+    Array,
+    Element,
+    Comment,
+    Cdata,
+}
+
+/// A region the page can fold, spanning at least two lines.
+///
+/// Lines are 1-based; `summary` is a short placeholder for the folded text.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FoldRegion {
+    pub start_line: usize,
+    pub end_line: usize,
+    pub kind: FoldKind,
+    pub summary: String,
+}
+
+/// Sort regions by start line, outer regions first.
+///
+/// Regions are collected as they close, inner before outer, so equal
+/// ranges are reversed first to put the outer one first.
+pub(crate) fn sort_regions(regions: &mut [FoldRegion]) {
+    regions.reverse();
+    regions.sort_by_key(|r| (r.start_line, std::cmp::Reverse(r.end_line)));
+}
+
+/// An opening bracket or start tag and the token that closes it.
+///
+/// Ranges are `[start, end)` byte offsets into the input, covering the
+/// bracket or the whole tag from `<` to `>`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct MatchingPair {
+    pub open: (usize, usize),
+    pub close: (usize, usize),
+}
+
+/// Colors for JSON tokens.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JsonColors {
+    pub key: String,
+    pub string: String,
+    pub number: String,
+    pub boolean: String,
+    pub null: String,
+    /// `{ } [ ]`
+    pub bracket: String,
+    /// `:` and `,`
+    pub punctuation: String,
+    /// Key colors by nesting depth for [`HighlightOptions::depth_key_colors`],
+    /// starting with the keys of the top-level object and cycling
+    pub key_depths: Vec<String>,
+}
+
+impl JsonColors {
+    /// Color for a token kind; kinds JSON never produces get `punctuation`.
+    pub fn color(&self, kind: TokenKind) -> &str {
+        match kind {
+            TokenKind::Key => &self.key,
+            TokenKind::String => &self.string,
+            TokenKind::Number => &self.number,
+            TokenKind::Boolean => &self.boolean,
+            TokenKind::Null => &self.null,
+            TokenKind::Bracket => &self.bracket,
+            _ => &self.punctuation,
+        }
+    }
+}
+
+/// Colors for XML tokens.
+#[derive(Clone, Debug, PartialEq)]
+pub struct XmlColors {
+    pub tag: String,
+    pub attr_name: String,
+    /// Namespace declarations, `xmlns` and `xmlns:prefix`
+    pub namespace: String,
+    pub attr_value: String,
+    pub text: String,
+    pub comment: String,
+    pub cdata: String,
+    /// XML declaration, processing instructions, and DOCTYPE
+    pub declaration: String,
+    /// `<`, `>`, `</`, and `/>`
+    pub bracket: String,
+    pub entity: String,
+}
+
+impl XmlColors {
+    /// Color for a token kind; kinds XML never produces get `text`.
+    pub fn color(&self, kind: TokenKind) -> &str {
+        match kind {
+            TokenKind::Tag => &self.tag,
+            TokenKind::AttrName => &self.attr_name,
+            TokenKind::Namespace => &self.namespace,
+            TokenKind::AttrValue => &self.attr_value,
+            TokenKind::Comment => &self.comment,
+            TokenKind::Cdata => &self.cdata,
+            TokenKind::Declaration => &self.declaration,
+            TokenKind::Bracket => &self.bracket,
+            TokenKind::Entity => &self.entity,
+            _ => &self.text,
+        }
+    }
+}
+
+/// Per-token colors for the highlighters.
+///
+/// Colors are written into `style` attributes as given; [`HighlightTheme::validate`]
+/// rejects values that could break out of the attribute.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "ThemeSpec")]
+pub struct HighlightTheme {
+    pub json: JsonColors,
+    pub xml: XmlColors,
+}
+
+impl HighlightTheme {
+    /// VS Code dark inspired palette, the default.
+    pub fn dark() -> Self {
+        HighlightTheme {
+            json: JsonColors {
+                key: "#9cdcfe".into(),
+                string: "#ce9178".into(),
+                number: "#b5cea8".into(),
+                boolean: "#569cd6".into(),
+                null: "#569cd6".into(),
+                bracket: "#ffd700".into(),
+                punctuation: "#d4d4d4".into(),
+                key_depths: palette(&["#9cdcfe", "#4ec9b0", "#dcdcaa", "#c586c0", "#d7ba7d"]),
+            },
+            xml: XmlColors {
+                tag: "#569cd6".into(),
+                attr_name: "#9cdcfe".into(),
+                namespace: "#4ec9b0".into(),
+                attr_value: "#ce9178".into(),
+                text: "#d4d4d4".into(),
+                comment: "#6a9955".into(),
+                cdata: "#dcdcaa".into(),
+                declaration: "#c586c0".into(),
+                bracket: "#808080".into(),
+                entity: "#d7ba7d".into(),
+            },
+        }
+    }
+
+    /// VS Code light inspired palette.
+    pub fn light() -> Self {
+        HighlightTheme {
+            json: JsonColors {
+                key: "#0451a5".into(),
+                string: "#a31515".into(),
+                number: "#098658".into(),
+                boolean: "#0000ff".into(),
+                null: "#0000ff".into(),
+                bracket: "#795e26".into(),
+                punctuation: "#383a42".into(),
+                key_depths: palette(&["#0451a5", "#267f99", "#795e26", "#af00db", "#b05a00"]),
+            },
+            xml: XmlColors {
+                tag: "#800000".into(),
+                attr_name: "#e50000".into(),
+                namespace: "#267f99".into(),
+                attr_value: "#0000ff".into(),
+                text: "#383a42".into(),
+                comment: "#008000".into(),
+                cdata: "#795e26".into(),
+                declaration: "#af00db".into(),
+                bracket: "#800000".into(),
+                entity: "#b05a00".into(),
+            },
+        }
+    }
+
+    /// High contrast palette on black, with every color at least 7:1
+    /// against the background.
+    pub fn high_contrast() -> Self {
+        HighlightTheme {
+            json: JsonColors {
+                key: "#79c0ff".into(),
+                string: "#ffa657".into(),
+                number: "#7ee787".into(),
+                boolean: "#ff7bea".into(),
+                null: "#ff7bea".into(),
+                bracket: "#ffff00".into(),
+                punctuation: "#ffffff".into(),
+                key_depths: palette(&["#79c0ff", "#56d4dd", "#d2a8ff", "#ffd700"]),
+            },
+            xml: XmlColors {
+                tag: "#79c0ff".into(),
+                attr_name: "#d2a8ff".into(),
+                namespace: "#56d4dd".into(),
+                attr_value: "#ffa657".into(),
+                text: "#ffffff".into(),
+                comment: "#7ee787".into(),
+                cdata: "#ffff00".into(),
+                declaration: "#ff7bea".into(),
+                bracket: "#ffffff".into(),
+                entity: "#ffd700".into(),
+            },
+        }
+    }
+
+    /// Look up a built-in theme by name (`"dark"`, `"light"`, or `"highContrast"`).
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            "highContrast" => Some(Self::high_contrast()),
+            _ => None,
+        }
+    }
+
+    /// Check that every color is safe to embed in a `style` attribute.
+    ///
+    /// Accepts hex colors, color names, and functional notations such as
+    /// `rgb(0, 0, 0)`; anything with quotes, semicolons, or markup is rejected.
+    pub fn validate(&self) -> Result<(), String> {
+        let json = &self.json;
+        let xml = &self.xml;
+        let colors = [
+            &json.key,
+            &json.string,
+            &json.number,
+            &json.boolean,
+            &json.null,
+            &json.bracket,
+            &json.punctuation,
+            &xml.tag,
+            &xml.attr_name,
+            &xml.namespace,
+            &xml.attr_value,
+            &xml.text,
+            &xml.comment,
+            &xml.cdata,
+            &xml.declaration,
+            &xml.bracket,
+            &xml.entity,
+        ];
+        match colors
+            .into_iter()
+            .chain(&json.key_depths)
+            .find(|c| !is_safe_color(c))
+        {
+            Some(color) => Err(format!("Invalid color '{}'", color)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl HighlightTheme {
+    /// Style sheet giving [`OutputTarget::HtmlClasses`] output this theme's colors.
+    ///
+    /// Rules are scoped by the `hl-json` and `hl-xml` classes on the `<pre>`;
+    /// error markers get a wavy underline in either language.
+    pub fn css(&self) -> String {
+        const JSON: &[TokenKind] = &[
+            TokenKind::Key,
+            TokenKind::String,
+            TokenKind::Number,
+            TokenKind::Boolean,
+            TokenKind::Null,
+            TokenKind::Bracket,
+            TokenKind::Punctuation,
+        ];
+        const XML: &[TokenKind] = &[
+            TokenKind::Tag,
+            TokenKind::AttrName,
+            TokenKind::Namespace,
+            TokenKind::AttrValue,
+            TokenKind::Text,
+            TokenKind::Comment,
+            TokenKind::Cdata,
+            TokenKind::Declaration,
+            TokenKind::Bracket,
+            TokenKind::Entity,
+        ];
+        let mut css = String::new();
+        for (language, kinds) in [(Language::Json, JSON), (Language::Xml, XML)] {
+            for &kind in kinds {
+                css.push_str(&format!(
+                    ".{} .{} {{ color: {}; }}\n",
+                    language.class_name(),
+                    kind.class_name(),
+                    self.color(language, kind)
+                ));
+            }
+        }
+        for (level, color) in self.json.key_depths.iter().enumerate() {
+            css.push_str(&format!(
+                ".hl-json .hl-key.hl-key-{} {{ color: {}; }}\n",
+                level, color
+            ));
+        }
+        css.push_str(".hl .hl-error { text-decoration: underline wavy #f14c4c; }\n");
+        css
+    }
+
+    pub(crate) fn color(&self, language: Language, kind: TokenKind) -> &str {
+        match language {
+            Language::Json => self.json.color(kind),
+            Language::Xml => self.xml.color(kind),
+        }
+    }
+}
+
+fn palette(colors: &[&str]) -> Vec<String> {
+    colors.iter().map(|c| c.to_string()).collect()
+}
+
+impl Default for HighlightTheme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+/// Options shared by the highlighters.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct HighlightOptions {
+    /// `"dark"`, `"light"`, `"highContrast"`, or `{ base?, json?: {...}, xml?: {...} }`
+    /// from JavaScript
+    pub theme: HighlightTheme,
+    /// Wrap each line in `<span class="line" data-line="N">`, numbered from 1,
+    /// so the page can show a gutter or scroll to and mark a line
+    pub line_numbers: bool,
+    /// Also give each HTML line span an `id="L<N>"` anchor, so links like
+    /// `#L42` and "go to line" land on the line; implies the line spans
+    pub line_anchors: bool,
+    /// Text to mark with `<mark>` on top of the syntax colors
+    pub search: SearchOptions,
+    /// What the highlighters produce
+    pub output: OutputTarget,
+    /// Highlight at most this many bytes of a document, ending at a line
+    /// break where possible, and append a notice saying how much was left
+    /// out. Chunks are never cut.
+    pub max_bytes: Option<usize>,
+    /// Show JSON objects and arrays with more than this many keys or items
+    /// as a one-line placeholder such as `{…} 1,024 keys`, carrying the
+    /// byte range of the whole value for expanding it on demand. Only
+    /// whole documents are collapsed, not chunks.
+    pub collapse_above: Option<usize>,
+    /// Give each JSON key, value, and opening bracket span a `data-path`
+    /// attribute with its JSON Pointer, such as `/items/0/name`. Only whole
+    /// documents get paths, not chunks.
+    pub data_paths: bool,
+    /// Validate the document and mark the text at each syntax error, as
+    /// `highlight_*_with_errors` do. Everything else is still colored as
+    /// well as the tokenizer can. Only whole documents are checked, not
+    /// chunks.
+    pub mark_errors: bool,
+    /// Color JSON object keys by how deeply they are nested, cycling
+    /// through the theme's [`JsonColors::key_depths`]. Only whole documents
+    /// get depth colors, not chunks.
+    pub depth_key_colors: bool,
+}
+
+/// Output format of the highlighters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OutputTarget {
+    /// HTML with `style="color:..."` on each token
+    #[default]
+    HtmlInline,
+    /// HTML with `hl-*` classes on each token (see [`HighlightTheme::css`])
+    HtmlClasses,
+    /// Text with 24-bit ANSI color escapes, for terminals; control
+    /// characters in the input are shown as `^X` so they cannot act on
+    /// the terminal, and search matches are shown in reverse video
+    Ansi,
+    /// The text as is, with only a line number gutter if requested
+    Plain,
+}
+
+impl OutputTarget {
+    fn is_html(self) -> bool {
+        matches!(self, OutputTarget::HtmlInline | OutputTarget::HtmlClasses)
+    }
+}
+
+/// Text to mark in highlighted output, for find-in-document.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SearchOptions {
+    /// Text to find; empty finds nothing
+    pub query: String,
+    /// Match case exactly instead of ignoring it
+    pub case_sensitive: bool,
+    /// Further `[start, end)` byte ranges to mark, such as regular
+    /// expression matches found by the caller
+    pub ranges: Vec<(usize, usize)>,
+}
+
+/// Matches found for a search, as returned to JavaScript.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SearchMatches {
+    pub count: usize,
+    pub ranges: Vec<(usize, usize)>,
+}
+
+/// Find the ranges to mark for a search.
+///
+/// Occurrences of the query do not overlap. Ranges from the query and
+/// from `search.ranges` are sorted and merged where they overlap;
+/// caller ranges are clamped to the input and widened to character
+/// boundaries.
+///
+/// # Arguments
+/// * `input` - The text to search
+/// * `search` - Query and extra ranges
+///
+/// # Returns
+/// * Sorted, disjoint `[start, end)` byte ranges; its length is the match count
+pub fn find_matches(input: &str, search: &SearchOptions) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = search
+        .ranges
+        .iter()
+        .map(|&(start, end)| {
+            (
+                floor_char_boundary(input, start),
+                ceil_char_boundary(input, end),
+            )
+        })
+        .filter(|(start, end)| start < end)
+        .collect();
+
+    if !search.query.is_empty() {
+        let mut start = 0;
+        while start < input.len() {
+            match match_len(&input[start..], &search.query, search.case_sensitive) {
+                Some(len) => {
+                    ranges.push((start, start + len));
+                    start += len;
+                }
+                None => start += input[start..].chars().next().map_or(1, char::len_utf8),
+            }
+        }
+    }
+
+    ranges.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start < last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// Byte length of the match of `query` at the start of `text`, if any.
+fn match_len(text: &str, query: &str, case_sensitive: bool) -> Option<usize> {
+    if case_sensitive {
+        return text.starts_with(query).then_some(query.len());
+    }
+    let mut chars = text.char_indices();
+    for q in query.chars() {
+        let (_, c) = chars.next()?;
+        if c != q && !c.to_lowercase().eq(q.to_lowercase()) {
+            return None;
+        }
+    }
+    Some(chars.next().map_or(text.len(), |(i, _)| i))
+}
+
+fn floor_char_boundary(input: &str, offset: usize) -> usize {
+    let mut offset = offset.min(input.len());
+    while !input.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+fn ceil_char_boundary(input: &str, offset: usize) -> usize {
+    let mut offset = offset.min(input.len());
+    while !input.is_char_boundary(offset) {
+        offset += 1;
+    }
+    offset
+}
+
+/// Collects scanner output into [`Token`]s with line and column numbers.
+pub(crate) struct TokenCollector<'a> {
+    input: &'a str,
+    tokens: Vec<Token>,
+    // Position reached so far, advanced as tokens arrive in order
+    offset: usize,
+    line: usize,
+    column: usize,
+    depth: usize,
+}
+
+impl<'a> TokenCollector<'a> {
+    pub(crate) fn new(input: &'a str) -> Self {
+        TokenCollector {
+            input,
+            tokens: Vec::new(),
+            offset: 0,
+            line: 1,
+            column: 1,
+            depth: 0,
+        }
+    }
+
+    /// Tokens from here on are one level deeper.
+    pub(crate) fn enter(&mut self) {
+        self.depth += 1;
+    }
+
+    /// Tokens from here on are one level shallower; stray closers stay at 0.
+    pub(crate) fn leave(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+    }
+
+    pub(crate) fn push(&mut self, kind: TokenKind, start: usize, end: usize) {
+        for c in self.input[self.offset..start].chars() {
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+        self.offset = start;
+        self.tokens.push(Token {
+            kind,
+            start,
+            end,
+            line: self.line,
+            column: self.column,
+            depth: self.depth,
+        });
+    }
+
+    pub(crate) fn finish(self) -> Vec<Token> {
+        self.tokens
+    }
+}
+
+/// Writes scanner output in the requested [`OutputTarget`].
+///
+/// Everything is written in one pass into a single buffer: search marks
+/// and error markers are split at token boundaries, and with line numbers each line is
+/// ended and the next started as line breaks are written, reopening any
+/// token color or mark that continues.
+pub(crate) struct Output<'a> {
+    input: &'a str,
+    options: &'a HighlightOptions,
+    language: Language,
+    target: OutputTarget,
+    // Bytes after `input` left out because of `max_bytes`
+    omitted: usize,
+    out: String,
+    // End of the last token; input between tokens is written uncolored
+    offset: usize,
+    // Whether this is a whole document, wrapped in `<pre>` for HTML
+    document: bool,
+    // Search matches and error markers, and the first one not yet written past
+    marks: Vec<(usize, usize, Mark)>,
+    next_mark: usize,
+    // Messages for `Mark::Error`
+    errors: Vec<String>,
+    // Whether lines are wrapped in line spans or prefixed with a gutter
+    wrap_lines: bool,
+    // Current line number, and where its line start is in `out`
+    line: usize,
+    line_start: usize,
+    // JSON Pointer written on the current token's span
+    path: Option<String>,
+    // Index into the theme's key depth colors for the current key
+    key_level: Option<usize>,
+    // Whether a token color is open in `out`, and which mark
+    span_open: bool,
+    mark_open: Option<Mark>,
+}
+
+/// What a marked range of the output is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mark {
+    Search,
+    /// Index into `Output::errors`
+    Error(usize),
+}
+
+impl<'a> Output<'a> {
+    /// Output for a whole document, wrapped in `<pre>` for HTML.
+    ///
+    /// The document may be cut to `max_bytes`; scan [`Output::input`] rather
+    /// than the whole document.
+    pub(crate) fn new(input: &'a str, options: &'a HighlightOptions, language: Language) -> Self {
+        let shown = match options.max_bytes {
+            Some(max) if input.len() > max => {
+                let cut = floor_char_boundary(input, max);
+                input[..cut]
+                    .rfind('\n')
+                    .map_or(&input[..cut], |i| &input[..i + 1])
+            }
+            _ => input,
+        };
+        let mut output = Self::build(shown, options, language, 0, true);
+        output.omitted = input.len() - shown.len();
+        output.start_line();
+        output
+    }
+
+    /// Color the next key as nested at `depth`, if depth key colors are on.
+    pub(crate) fn set_key_depth(&mut self, depth: usize) {
+        let levels = self.options.theme.json.key_depths.len();
+        self.key_level =
+            (self.options.depth_key_colors && levels > 0).then(|| depth.saturating_sub(1) % levels);
+    }
+
+    /// Push a token whose span carries `data-path="path"` in HTML output.
+    pub(crate) fn push_with_path(
+        &mut self,
+        start: usize,
+        end: usize,
+        kind: TokenKind,
+        path: String,
+    ) {
+        self.path = Some(path);
+        self.push(start, end, kind);
+        self.path = None;
+    }
+
+    /// Write a placeholder in place of `start..end`, an object or array of
+    /// `count` keys or items, described by `summary`.
+    ///
+    /// Line numbers after the placeholder stay those of the document.
+    pub(crate) fn push_collapsed(
+        &mut self,
+        start: usize,
+        end: usize,
+        object: bool,
+        count: usize,
+        summary: &str,
+    ) {
+        self.push_text(self.offset, start, None);
+        self.close_span();
+        while self
+            .marks
+            .get(self.next_mark)
+            .is_some_and(|&(_, mark_end, _)| mark_end <= end)
+        {
+            self.next_mark += 1;
+        }
+        let kind = if object { "object" } else { "array" };
+        match self.target {
+            OutputTarget::HtmlInline | OutputTarget::HtmlClasses => {
+                self.out.push_str(&format!(
+                    "<span class=\"hl-collapsed\" data-kind=\"{}\" data-start=\"{}\" data-end=\"{}\" data-count=\"{}\"",
+                    kind, start, end, count
+                ));
+                if self.target == OutputTarget::HtmlInline {
+                    self.out
+                        .push_str(" style=\"font-style:italic;opacity:0.7;cursor:pointer;\"");
+                }
+                self.out.push('>');
+                push_escaped(&mut self.out, summary);
+                self.out.push_str("</span>");
+            }
+            OutputTarget::Ansi => self.out.push_str(&format!("\x1b[2m{}\x1b[22m", summary)),
+            OutputTarget::Plain => self.out.push_str(summary),
+        }
+        self.line += self.input[start..end].matches('\n').count();
+        self.offset = end;
+    }
+
+    /// Mark the text at each error, which replaces any search match there.
+    pub(crate) fn mark_errors(&mut self, errors: &[FormatError]) {
+        for error in errors {
+            let (start, end) = error_range(self.input, error.line, error.column);
+            self.mark_error(start, end, &error.message);
+        }
+    }
+
+    /// Mark `start..end` as an error with `message`, unless another error
+    /// is marked there already.
+    pub(crate) fn mark_error(&mut self, start: usize, end: usize, message: &str) {
+        self.marks
+            .retain(|&(s, e, mark)| mark != Mark::Search || e <= start || s >= end);
+        if start < end && !self.marks.iter().any(|&(s, e, _)| s < end && e > start) {
+            self.marks
+                .push((start, end, Mark::Error(self.errors.len())));
+            self.errors.push(message.to_string());
+            self.marks.sort_by_key(|&(start, _, _)| start);
+        }
+    }
+
+    /// The part of the document being highlighted.
+    pub(crate) fn input(&self) -> &'a str {
+        self.input
+    }
+
+    /// Output for a chunk of a document, without the `<pre>` wrapper; lines
+    /// are numbered after `lines_before`.
+    pub(crate) fn fragment(
+        input: &'a str,
+        options: &'a HighlightOptions,
+        language: Language,
+        lines_before: usize,
+    ) -> Self {
+        let mut output = Self::build(input, options, language, lines_before, false);
+        output.start_line();
+        output
+    }
+
+    fn build(
+        input: &'a str,
+        options: &'a HighlightOptions,
+        language: Language,
+        lines_before: usize,
+        document: bool,
+    ) -> Self {
+        let target = options.output;
+        let mut out = String::with_capacity(input.len() * 3);
+        if document {
+            match target {
+                OutputTarget::HtmlInline => {
+                    out.push_str("<pre style=\"margin:0;font-family:inherit;\">")
+                }
+                OutputTarget::HtmlClasses => {
+                    out.push_str("<pre class=\"hl ");
+                    out.push_str(language.class_name());
+                    out.push_str("\">");
+                }
+                OutputTarget::Ansi | OutputTarget::Plain => {}
+            }
+        }
+        Output {
+            input,
+            options,
+            language,
+            target,
+            omitted: 0,
+            out,
+            offset: 0,
+            document,
+            marks: find_matches(input, &options.search)
+                .into_iter()
+                .map(|(start, end)| (start, end, Mark::Search))
+                .collect(),
+            next_mark: 0,
+            errors: Vec::new(),
+            wrap_lines: options.line_numbers || (options.line_anchors && target.is_html()),
+            line: lines_before + 1,
+            line_start: 0,
+            path: None,
+            key_level: None,
+            span_open: false,
+            mark_open: None,
+        }
+    }
+
+    pub(crate) fn push(&mut self, start: usize, end: usize, kind: TokenKind) {
+        self.push_text(self.offset, start, None);
+        self.push_text(start, end, Some(kind));
+        self.close_span();
+        self.offset = end;
+    }
+
+    /// Write input as a token of `kind`, splitting it at search matches.
+    fn push_text(&mut self, start: usize, end: usize, kind: Option<TokenKind>) {
+        let input = self.input;
+        let mut pos = start;
+        while pos < end {
+            let (stop, mark) = match self.marks.get(self.next_mark) {
+                Some(&(mark_start, mark_end, mark)) if mark_start <= pos => {
+                    if mark_end <= end {
+                        self.next_mark += 1;
+                    }
+                    (mark_end.min(end), Some(mark))
+                }
+                Some(&(mark_start, _, _)) => (mark_start.min(end), None),
+                None => (end, None),
+            };
+            self.write(&input[pos..stop], kind, mark);
+            pos = stop;
+        }
+    }
+
+    /// Write text, opening the token color and mark before the first
+    /// visible character and breaking lines when numbering them.
+    fn write(&mut self, text: &str, kind: Option<TokenKind>, mark: Option<Mark>) {
+        let mut rest = text;
+        loop {
+            let (piece, more) = match rest.find('\n').filter(|_| self.wrap_lines) {
+                Some(i) => (&rest[..i], Some(&rest[i + 1..])),
+                None => (rest, None),
+            };
+            if !piece.is_empty() {
+                if let (Some(kind), false) = (kind, self.span_open) {
+                    self.open_span(kind);
+                }
+                if let (Some(mark), None) = (mark, self.mark_open) {
+                    self.open_mark(mark);
+                }
+                match self.target {
+                    OutputTarget::HtmlInline | OutputTarget::HtmlClasses => {
+                        push_escaped(&mut self.out, piece)
+                    }
+                    OutputTarget::Ansi => push_terminal_safe(&mut self.out, piece),
+                    OutputTarget::Plain => self.out.push_str(piece),
+                }
+            }
+            let Some(more) = more else {
+                break;
+            };
+            self.end_line();
+            self.out.push('\n');
+            self.line += 1;
+            self.start_line();
+            rest = more;
+        }
+        self.close_mark();
+    }
+
+    fn open_span(&mut self, kind: TokenKind) {
+        let level = self.key_level.filter(|_| kind == TokenKind::Key);
+        let color = match level {
+            Some(level) => &self.options.theme.json.key_depths[level],
+            None => self.options.theme.color(self.language, kind),
+        };
+        match self.target {
+            OutputTarget::HtmlInline => {
+                self.out.push_str("<span style=\"color:");
+                self.out.push_str(color);
+                self.out.push('"');
+                self.push_path_attribute();
+                self.out.push('>');
+            }
+            OutputTarget::HtmlClasses => {
+                self.out.push_str("<span class=\"");
+                self.out.push_str(kind.class_name());
+                if let Some(level) = level {
+                    self.out.push_str(&format!(" hl-key-{}", level));
+                }
+                self.out.push('"');
+                self.push_path_attribute();
+                self.out.push('>');
+            }
+            OutputTarget::Ansi => match parse_hex_color(color) {
+                Some((r, g, b)) => self.out.push_str(&format!("\x1b[38;2;{};{};{}m", r, g, b)),
+                // Color names and functions have no terminal equivalent here
+                None => return,
+            },
+            OutputTarget::Plain => return,
+        }
+        self.span_open = true;
+    }
+
+    fn push_path_attribute(&mut self) {
+        if let Some(path) = &self.path {
+            self.out.push_str(" data-path=\"");
+            push_escaped_attribute(&mut self.out, path);
+            self.out.push('"');
+        }
+    }
+
+    fn close_span(&mut self) {
+        if self.span_open {
+            self.out.push_str(if self.target.is_html() {
+                "</span>"
+            } else {
+                "\x1b[39m"
+            });
+            self.span_open = false;
+        }
+    }
+
+    fn open_mark(&mut self, mark: Mark) {
+        match (self.target, mark) {
+            (OutputTarget::Plain, _) => return,
+            (OutputTarget::HtmlInline | OutputTarget::HtmlClasses, Mark::Search) => {
+                self.out.push_str("<mark>")
+            }
+            (OutputTarget::Ansi, Mark::Search) => self.out.push_str("\x1b[7m"),
+            (OutputTarget::HtmlInline | OutputTarget::HtmlClasses, Mark::Error(index)) => {
+                self.out.push_str("<span class=\"hl-error\" title=\"");
+                push_escaped_attribute(&mut self.out, &self.errors[index]);
+                self.out.push('"');
+                if self.target == OutputTarget::HtmlInline {
+                    self.out
+                        .push_str(" style=\"text-decoration:underline wavy #f14c4c;\"");
+                }
+                self.out.push('>');
+            }
+            (OutputTarget::Ansi, Mark::Error(_)) => self.out.push_str("\x1b[4:3m"),
+        }
+        self.mark_open = Some(mark);
+    }
+
+    fn close_mark(&mut self) {
+        if let Some(mark) = self.mark_open.take() {
+            self.out.push_str(match (self.target.is_html(), mark) {
+                (true, Mark::Search) => "</mark>",
+                (true, Mark::Error(_)) => "</span>",
+                (false, Mark::Search) => "\x1b[27m",
+                (false, Mark::Error(_)) => "\x1b[24m",
+            });
+        }
+    }
+
+    fn start_line(&mut self) {
+        if !self.wrap_lines {
+            return;
+        }
+        self.line_start = self.out.len();
+        match self.target {
+            OutputTarget::HtmlInline | OutputTarget::HtmlClasses => {
+                self.out.push_str("<span class=\"line\"");
+                if self.options.line_anchors {
+                    self.out.push_str(" id=\"L");
+                    self.out.push_str(&self.line.to_string());
+                    self.out.push('"');
+                }
+                self.out.push_str(" data-line=\"");
+                self.out.push_str(&self.line.to_string());
+                self.out.push_str("\">");
+            }
+            OutputTarget::Ansi => self
+                .out
+                .push_str(&format!("\x1b[2m{:>5} |\x1b[22m ", self.line)),
+            OutputTarget::Plain => self.out.push_str(&format!("{:>5} | ", self.line)),
+        }
+    }
+
+    /// End the current line, closing anything open in it.
+    fn end_line(&mut self) {
+        self.close_mark();
+        self.close_span();
+        if self.target.is_html() {
+            self.out.push_str("</span>");
+        }
+    }
+
+    pub(crate) fn finish(mut self) -> String {
+        self.push_text(self.offset, self.input.len(), None);
+        if self.wrap_lines {
+            if !self.document && self.input.ends_with('\n') {
+                // A chunk ending in a line break ends that line; the next chunk starts the next one
+                self.out.truncate(self.line_start);
+            } else {
+                self.end_line();
+            }
+        }
+        if self.omitted > 0 {
+            self.push_truncation_notice();
+        }
+        if self.document && self.target.is_html() {
+            self.out.push_str("</pre>");
+        }
+        self.out
+    }
+
+    fn push_truncation_notice(&mut self) {
+        if !self.input.is_empty() && !self.input.ends_with('\n') {
+            self.out.push('\n');
+        }
+        let notice = format!(
+            "… {} more bytes not highlighted (showing the first {} of {})",
+            self.omitted,
+            self.input.len(),
+            self.input.len() + self.omitted
+        );
+        match self.target {
+            OutputTarget::HtmlInline => {
+                self.out.push_str(
+                    "<span class=\"hl-truncated\" style=\"font-style:italic;opacity:0.7\">",
+                );
+                push_escaped(&mut self.out, &notice);
+                self.out.push_str("</span>");
+            }
+            OutputTarget::HtmlClasses => {
+                self.out.push_str("<span class=\"hl-truncated\">");
+                push_escaped(&mut self.out, &notice);
+                self.out.push_str("</span>");
+            }
+            OutputTarget::Ansi => self.out.push_str(&format!("\x1b[2m{}\x1b[22m", notice)),
+            OutputTarget::Plain => self.out.push_str(&notice),
+        }
+    }
+}
+
+/// Split a document after every `lines` line breaks, for segmented output.
+///
+/// Each segment comes with `options` adjusted to it: explicit search ranges
+/// are clipped to the segment and made relative to its start. Search
+/// queries are matched within each segment.
+pub(crate) fn split_segments<'a>(
+    input: &'a str,
+    lines: usize,
+    options: &HighlightOptions,
+) -> Vec<(&'a str, HighlightOptions)> {
+    let lines = lines.max(1);
+    let mut segments = Vec::new();
+    let mut start = 0;
+    while start < input.len() {
+        let end = input[start..]
+            .match_indices('\n')
+            .nth(lines - 1)
+            .map_or(input.len(), |(i, _)| start + i + 1);
+        let mut segment_options = options.clone();
+        segment_options.search.ranges = options
+            .search
+            .ranges
+            .iter()
+            .filter_map(|&(s, e)| {
+                let (s, e) = (s.max(start), e.min(end));
+                (s < e).then(|| (s - start, e - start))
+            })
+            .collect();
+        segments.push((&input[start..end], segment_options));
+        start = end;
+    }
+    segments
+}
+
+/// A formatted document and its highlighted output.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FormattedHighlight {
+    pub formatted: String,
+    pub html: String,
+}
+
+/// Highlighted output for a document, with its validation errors marked.
+///
+/// The text at each error is wrapped in `<span class="hl-error">` with the
+/// message as its `title`, underlined in red for [`OutputTarget::HtmlInline`]
+/// and with a curly underline for [`OutputTarget::Ansi`].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HighlightedValidation {
+    pub html: String,
+    pub is_valid: bool,
+    pub errors: Vec<FormatError>,
+}
+
+/// A highlighted chunk of a document and the state to highlight the next chunk from.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct HighlightedChunk<S> {
+    pub html: String,
+    pub state: S,
+}
+
+fn push_escaped(out: &mut String, text: &str) {
+    let mut rest = text;
+    while let Some(i) = rest.find(['<', '>', '&']) {
+        out.push_str(&rest[..i]);
+        out.push_str(match rest.as_bytes()[i] {
+            b'<' => "&lt;",
+            b'>' => "&gt;",
+            _ => "&amp;",
+        });
+        rest = &rest[i + 1..];
+    }
+    out.push_str(rest);
+}
+
+fn push_escaped_attribute(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// Byte range to underline for an error at a 1-based line and column:
+/// the run of non-whitespace text there, or the last character before it
+/// when the error is at whitespace or the end of the input.
+fn error_range(input: &str, line: usize, column: usize) -> (usize, usize) {
+    let line_start = match line {
+        0 | 1 => 0,
+        _ => input
+            .match_indices('\n')
+            .nth(line - 2)
+            .map_or(input.len(), |(i, _)| i + 1),
+    };
+    let line_end = input[line_start..]
+        .find('\n')
+        .map_or(input.len(), |i| line_start + i);
+    let start = input[line_start..line_end]
+        .char_indices()
+        .nth(column.saturating_sub(1))
+        .map_or(line_end, |(i, _)| line_start + i);
+    let end = input[start..line_end]
+        .find(char::is_whitespace)
+        .map_or(line_end, |i| start + i);
+    if start < end {
+        return (start, end);
+    }
+    match input[..start].trim_end().chars().next_back() {
+        Some(c) => {
+            let end = input[..start].trim_end().len();
+            (end - c.len_utf8(), end)
+        }
+        None => (start, start),
+    }
+}
+
+/// Write text for a terminal, showing control characters other than tab
+/// and line breaks in caret notation (`^[` for escape).
+fn push_terminal_safe(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '\t' | '\n' | '\r' => out.push(c),
+            '\u{7f}' => out.push_str("^?"),
+            c if c.is_ascii_control() => {
+                out.push('^');
+                out.push((c as u8 + 0x40) as char);
+            }
+            // C1 controls can also start terminal sequences
+            '\u{80}'..='\u{9f}' => out.push('\u{fffd}'),
+            c => out.push(c),
+        }
+    }
+}
+
+/// `#rgb` or `#rrggbb` as RGB components.
+fn parse_hex_color(color: &str) -> Option<(u8, u8, u8)> {
+    let hex = color.strip_prefix('#')?;
+    let digit = |i: usize, len: usize| u8::from_str_radix(hex.get(i..i + len)?, 16).ok();
+    match hex.len() {
+        3 => Some((digit(0, 1)? * 17, digit(1, 1)? * 17, digit(2, 1)? * 17)),
+        6 => Some((digit(0, 2)?, digit(2, 2)?, digit(4, 2)?)),
+        _ => None,
+    }
+}
+
+fn is_safe_color(color: &str) -> bool {
+    !color.is_empty()
+        && color.len() <= 64
+        && color.chars().all(|c| {
+            c.is_ascii_alphanumeric() || matches!(c, '#' | '(' | ')' | ',' | '.' | '%' | ' ' | '-')
+        })
+}
+
+/// A theme as written in options: a built-in name, or overrides on a base theme.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ThemeSpec {
+    Named(String),
+    Custom {
+        #[serde(default)]
+        base: Option<String>,
+        #[serde(default)]
+        json: BTreeMap<String, String>,
+        #[serde(default)]
+        xml: BTreeMap<String, String>,
+    },
+}
+
+impl TryFrom<ThemeSpec> for HighlightTheme {
+    type Error = String;
+
+    fn try_from(spec: ThemeSpec) -> Result<Self, Self::Error> {
+        let named = |name: &str| {
+            HighlightTheme::named(name).ok_or_else(|| format!("Unknown theme '{}'", name))
+        };
+        let theme = match spec {
+            ThemeSpec::Named(name) => named(&name)?,
+            ThemeSpec::Custom { base, json, xml } => {
+                let mut theme = named(base.as_deref().unwrap_or("dark"))?;
+                for (token, color) in json {
+                    let slot = match token.as_str() {
+                        "key" => &mut theme.json.key,
+                        "string" => &mut theme.json.string,
+                        "number" => &mut theme.json.number,
+                        "boolean" => &mut theme.json.boolean,
+                        "null" => &mut theme.json.null,
+                        "bracket" => &mut theme.json.bracket,
+                        "punctuation" => &mut theme.json.punctuation,
+                        _ => return Err(format!("Unknown JSON token type '{}'", token)),
+                    };
+                    *slot = color;
+                }
+                for (token, color) in xml {
+                    let slot = match token.as_str() {
+                        "tag" => &mut theme.xml.tag,
+                        "attrName" => &mut theme.xml.attr_name,
+                        "namespace" => &mut theme.xml.namespace,
+                        "attrValue" => &mut theme.xml.attr_value,
+                        "text" => &mut theme.xml.text,
+                        "comment" => &mut theme.xml.comment,
+                        "cdata" => &mut theme.xml.cdata,
+                        "declaration" => &mut theme.xml.declaration,
+                        "bracket" => &mut theme.xml.bracket,
+                        "entity" => &mut theme.xml.entity,
+                        _ => return Err(format!("Unknown XML token type '{}'", token)),
+                    };
+                    *slot = color;
+                }
+                theme
+            }
+        };
+        theme.validate()?;
+        Ok(theme)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_named_themes() {
+        let options: HighlightOptions = serde_json::from_str(r#"{"theme":"light"}"#).unwrap();
+        assert_eq!(options.theme, HighlightTheme::light());
+        let options: HighlightOptions =
+            serde_json::from_str(r#"{"theme":"highContrast"}"#).unwrap();
+        assert_eq!(options.theme, HighlightTheme::high_contrast());
+        assert!(HighlightTheme::high_contrast().validate().is_ok());
+        assert_eq!(HighlightOptions::default().theme, HighlightTheme::dark());
+        assert!(serde_json::from_str::<HighlightOptions>(r#"{"theme":"solarized"}"#).is_err());
+    }
+
+    #[test]
+    fn test_custom_overrides() {
+        let options: HighlightOptions = serde_json::from_str(
+            r##"{"theme":{"base":"light","json":{"key":"#112233"},"xml":{"attrName":"red"}}}"##,
+        )
+        .unwrap();
+        assert_eq!(options.theme.json.key, "#112233");
+        assert_eq!(
+            options.theme.json.string,
+            HighlightTheme::light().json.string
+        );
+        assert_eq!(options.theme.xml.attr_name, "red");
+        assert!(
+            serde_json::from_str::<HighlightOptions>(r#"{"theme":{"json":{"keys":"red"}}}"#)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_line_spans() {
+        let options = HighlightOptions {
+            line_numbers: true,
+            output: OutputTarget::HtmlClasses,
+            ..Default::default()
+        };
+        let mut output = Output::fragment("x\n1\n\n2 ", &options, Language::Json, 0);
+        output.push(0, 1, TokenKind::Key);
+        output.push(2, 6, TokenKind::Number);
+        assert_eq!(
+            output.finish(),
+            concat!(
+                "<span class=\"line\" data-line=\"1\"><span class=\"hl-key\">x</span></span>\n",
+                "<span class=\"line\" data-line=\"2\"><span class=\"hl-number\">1</span></span>\n",
+                "<span class=\"line\" data-line=\"3\"></span>\n",
+                "<span class=\"line\" data-line=\"4\"><span class=\"hl-number\">2</span> </span>"
+            )
+        );
+        let mut output = Output::fragment("a\nb\n", &options, Language::Json, 6);
+        output.push(2, 4, TokenKind::Null);
+        assert_eq!(
+            output.finish(),
+            "<span class=\"line\" data-line=\"7\">a</span>\n<span class=\"line\" data-line=\"8\"><span class=\"hl-null\">b</span></span>\n"
+        );
+    }
+
+    #[test]
+    fn test_line_anchors() {
+        let options = HighlightOptions {
+            line_anchors: true,
+            ..Default::default()
+        };
+        let output = Output::fragment("a\nb", &options, Language::Json, 9);
+        assert_eq!(
+            output.finish(),
+            "<span class=\"line\" id=\"L10\" data-line=\"10\">a</span>\n<span class=\"line\" id=\"L11\" data-line=\"11\">b</span>"
+        );
+        // Text output has no anchors and needs no gutter
+        let options = HighlightOptions {
+            line_anchors: true,
+            output: OutputTarget::Plain,
+            ..Default::default()
+        };
+        assert_eq!(
+            Output::fragment("a\nb", &options, Language::Json, 0).finish(),
+            "a\nb"
+        );
+    }
+
+    #[test]
+    fn test_find_matches() {
+        let search = SearchOptions {
+            query: "ab".into(),
+            ..Default::default()
+        };
+        assert_eq!(find_matches("xAbabab", &search), [(1, 3), (3, 5), (5, 7)]);
+        assert_eq!(find_matches("aB-ab", &search), [(0, 2), (3, 5)]);
+        let search = SearchOptions {
+            query: "\u{c9}t\u{e9}".into(),
+            case_sensitive: false,
+            ranges: vec![(0, 1), (9, 100)],
+        };
+        // "été" matches "ÉTÉ"; ranges are clamped and widened to character boundaries
+        assert_eq!(
+            find_matches("x \u{e9}T\u{c9} \u{e9}", &search),
+            [(0, 1), (2, 7), (8, 10)]
+        );
+        let search = SearchOptions {
+            query: "A".into(),
+            case_sensitive: true,
+            ..Default::default()
+        };
+        assert!(find_matches("aaa", &search).is_empty());
+    }
+
+    #[test]
+    fn test_marks_nest_in_lines() {
+        let options = HighlightOptions {
+            line_numbers: true,
+            search: SearchOptions {
+                query: "b\nc".into(),
+                ..Default::default()
+            },
+            output: OutputTarget::HtmlClasses,
+            ..Default::default()
+        };
+        let mut output = Output::fragment("ab\ncd", &options, Language::Xml, 0);
+        output.push(0, 5, TokenKind::Tag);
+        assert_eq!(
+            output.finish(),
+            concat!(
+                "<span class=\"line\" data-line=\"1\"><span class=\"hl-tag\">a<mark>b</mark></span></span>\n",
+                "<span class=\"line\" data-line=\"2\"><span class=\"hl-tag\"><mark>c</mark>d</span></span>"
+            )
+        );
+    }
+
+    #[test]
+    fn test_text_targets() {
+        let input = "ab\x1b[2J\ncd";
+        let mut options = HighlightOptions {
+            line_numbers: true,
+            output: OutputTarget::Ansi,
+            search: SearchOptions {
+                query: "d".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut output = Output::new(input, &options, Language::Json);
+        output.push(0, 2, TokenKind::Key);
+        output.push(7, 9, TokenKind::Boolean);
+        assert_eq!(
+            output.finish(),
+            concat!(
+                "\x1b[2m    1 |\x1b[22m \x1b[38;2;156;220;254mab\x1b[39m^[[2J\n",
+                "\x1b[2m    2 |\x1b[22m \x1b[38;2;86;156;214mc\x1b[7md\x1b[27m\x1b[39m"
+            )
+        );
+
+        options.output = OutputTarget::Plain;
+        let mut output = Output::new(input, &options, Language::Json);
+        output.push(0, 2, TokenKind::Key);
+        assert_eq!(output.finish(), "    1 | ab\x1b[2J\n    2 | cd");
+    }
+
+    #[test]
+    fn test_max_bytes() {
+        let options = HighlightOptions {
+            max_bytes: Some(9),
+            output: OutputTarget::Plain,
+            ..Default::default()
+        };
+        // Cut at the last line break within the limit
+        let output = Output::new("abc\ndef\nghi\n", &options, Language::Xml);
+        assert_eq!(output.input(), "abc\ndef\n");
+        assert_eq!(
+            output.finish(),
+            "abc\ndef\n… 4 more bytes not highlighted (showing the first 8 of 12)"
+        );
+        // Without a line break, at a character boundary
+        let output = Output::new("ab\u{e9}\u{e9}\u{e9}\u{e9}", &options, Language::Xml);
+        assert_eq!(output.input(), "ab\u{e9}\u{e9}\u{e9}");
+        // Within the limit nothing is added
+        let output = Output::new("abc", &options, Language::Xml);
+        assert_eq!(output.finish(), "abc");
+    }
+
+    #[test]
+    fn test_error_marks() {
+        let options = HighlightOptions {
+            output: OutputTarget::HtmlClasses,
+            search: SearchOptions {
+                query: "b".to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut output = Output::fragment("a b\nxyz \"q\"", &options, Language::Json, 0);
+        output.mark_errors(&[
+            FormatError::new("bad \"x\"", 2, 2),
+            FormatError::new("end", 3, 1),
+        ]);
+        output.push(0, 1, TokenKind::Key);
+        output.push(8, 11, TokenKind::String);
+        assert_eq!(
+            output.finish(),
+            "<span class=\"hl-key\">a</span> <mark>b</mark>\nx\
+             <span class=\"hl-error\" title=\"bad &quot;x&quot;\">yz</span> \
+             <span class=\"hl-string\">\"q<span class=\"hl-error\" title=\"end\">\"</span></span>"
+                .replace("\\\n", "")
+        );
+    }
+
+    #[test]
+    fn test_error_range() {
+        let input = "{\n  \"a\": tru\n}";
+        assert_eq!(error_range(input, 2, 8), (9, 12));
+        // At the end of the input, the last character
+        assert_eq!(error_range(input, 3, 2), (13, 14));
+        assert_eq!(error_range("", 1, 1), (0, 0));
+    }
+
+    #[test]
+    fn test_split_segments() {
+        let options = HighlightOptions {
+            search: SearchOptions {
+                ranges: vec![(1, 5)],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let segments = split_segments("a\nb\nc\nd", 2, &options);
+        let texts: Vec<_> = segments.iter().map(|(text, _)| *text).collect();
+        assert_eq!(texts, ["a\nb\n", "c\nd"]);
+        assert_eq!(segments[0].1.search.ranges, [(1, 4)]);
+        assert_eq!(segments[1].1.search.ranges, [(0, 1)]);
+        assert!(split_segments("", 2, &options).is_empty());
+    }
+
+    #[test]
+    fn test_collapsed() {
+        let options = HighlightOptions {
+            line_numbers: true,
+            output: OutputTarget::HtmlClasses,
+            ..Default::default()
+        };
+        let mut output = Output::fragment("[\n[1,\n2],\n3]", &options, Language::Json, 0);
+        output.push(0, 1, TokenKind::Bracket);
+        output.push_collapsed(2, 8, false, 2, "[…] 2 items");
+        output.push(10, 11, TokenKind::Number);
+        assert_eq!(
+            output.finish(),
+            concat!(
+                "<span class=\"line\" data-line=\"1\"><span class=\"hl-bracket\">[</span></span>\n",
+                "<span class=\"line\" data-line=\"2\"><span class=\"hl-collapsed\" data-kind=\"array\" ",
+                "data-start=\"2\" data-end=\"8\" data-count=\"2\">[…] 2 items</span>,</span>\n",
+                "<span class=\"line\" data-line=\"4\"><span class=\"hl-number\">3</span>]</span>"
+            )
+        );
+    }
+
+    #[test]
+    fn test_theme_css() {
+        let css = HighlightTheme::light().css();
+        assert!(css.contains(".hl-json .hl-key { color: #0451a5; }\n"));
+        assert!(css.contains(".hl-xml .hl-attr-name { color: #e50000; }\n"));
+        assert_eq!(parse_hex_color("#fa0"), Some((255, 170, 0)));
+        assert_eq!(parse_hex_color("red"), None);
+    }
+
+    #[test]
+    fn test_token_depth() {
+        let mut tokens = TokenCollector::new("[[1]]]");
+        for (start, kind) in [
+            (0, "open"),
+            (1, "open"),
+            (2, ""),
+            (3, "close"),
+            (4, "close"),
+            (5, "close"),
+        ] {
+            if kind == "close" {
+                tokens.leave();
+            }
+            tokens.push(TokenKind::Bracket, start, start + 1);
+            if kind == "open" {
+                tokens.enter();
+            }
+        }
+        let depths: Vec<usize> = tokens.finish().iter().map(|t| t.depth).collect();
+        assert_eq!(depths, [0, 1, 2, 1, 0, 0]);
+    }
+
+    #[test]
+    fn test_token_positions() {
+        let mut tokens = TokenCollector::new("a\n\u{e9}b\nc");
+        tokens.push(TokenKind::Text, 0, 1);
+        tokens.push(TokenKind::Text, 4, 5);
+        tokens.push(TokenKind::Text, 6, 7);
+        let positions: Vec<(usize, usize)> =
+            tokens.finish().iter().map(|t| (t.line, t.column)).collect();
+        assert_eq!(positions, [(1, 1), (2, 2), (3, 1)]);
+    }
+
+    #[test]
+    fn test_rejects_unsafe_colors() {
+        let input = r#"{"theme":{"json":{"key":"red\"><script>"}}}"#;
+        assert!(serde_json::from_str::<HighlightOptions>(input).is_err());
+        let input = r#"{"theme":{"xml":{"tag":"red;background:url(x)"}}}"#;
+        assert!(serde_json::from_str::<HighlightOptions>(input).is_err());
+        assert!(HighlightTheme::light().validate().is_ok());
+    }
+}
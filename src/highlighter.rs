@@ -3,15 +3,29 @@
 //! Provides syntax highlighting for JSON using a simple state machine parser.
 //! Avoids syntect's binary serialization which has WASM compatibility issues.
 
-/// Color palette (VS Code dark theme inspired)
-mod colors {
-    pub const STRING: &str = "#ce9178";      // Orange-ish for strings
-    pub const KEY: &str = "#9cdcfe";         // Light blue for keys
-    pub const NUMBER: &str = "#b5cea8";      // Light green for numbers
-    pub const BOOLEAN: &str = "#569cd6";     // Blue for booleans
-    pub const NULL: &str = "#569cd6";        // Blue for null
-    pub const BRACKET: &str = "#ffd700";     // Gold for brackets
-    pub const PUNCTUATION: &str = "#d4d4d4"; // Gray for colons, commas
+use crate::formatter::format_json;
+use crate::highlight::{
+    sort_regions, split_segments, FoldKind, FoldRegion, FormattedHighlight, HighlightOptions,
+    HighlightedChunk, HighlightedValidation, Language, MatchingPair, Output, Token, TokenCollector,
+    TokenKind,
+};
+use crate::semantic::push_pointer_token;
+use crate::types::{FormatError, IndentStyle};
+use crate::validator::validate_json;
+use serde::{Deserialize, Serialize};
+
+/// Where JSON highlighting stopped, to continue with the next chunk.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct JsonHighlightState {
+    /// Lines highlighted before the next chunk
+    pub lines: usize,
+    /// Open brackets, innermost last
+    pub brackets: String,
+    /// Whether the next string is an object key
+    pub expect_key: bool,
+    /// Whether the previous chunk ended inside a string
+    pub in_string: bool,
 }
 
 /// Highlights JSON string and returns HTML with inline styles.
@@ -23,257 +37,707 @@ mod colors {
 /// * HTML string with inline styles for syntax highlighting
 /// * Empty string if input is empty
 pub fn highlight_json(input: &str) -> String {
+    highlight_json_with_options(input, &HighlightOptions::default())
+}
+
+/// Highlights JSON with the given theme and options.
+///
+/// # Arguments
+/// * `input` - The JSON string to highlight
+/// * `options` - Theme and output options
+///
+/// # Returns
+/// * Highlighted output in the requested target, HTML with inline styles by default
+/// * Empty string if input is empty
+pub fn highlight_json_with_options(input: &str, options: &HighlightOptions) -> String {
     if input.is_empty() {
         return String::new();
     }
+    let mut output = Output::new(input, options, Language::Json);
+    if options.mark_errors {
+        output.mark_errors(&validate_json(input).error.into_iter().collect::<Vec<_>>());
+    }
+    let input = output.input();
+    let collapsed = options
+        .collapse_above
+        .map_or_else(Vec::new, |limit| collapsed_containers(input, limit));
+    let mut next = 0;
+    let mut paths = PathTracker::default();
+    let mut depth = 0usize;
+    scan_json(
+        input,
+        &mut JsonHighlightState::default(),
+        |kind, start, end| {
+            let path = if options.data_paths {
+                paths.token(kind, &input[start..end])
+            } else {
+                None
+            };
+            match (kind, &input[start..end]) {
+                (TokenKind::Bracket, "{" | "[") => depth += 1,
+                (TokenKind::Bracket, _) => depth = depth.saturating_sub(1),
+                (TokenKind::Key, _) => output.set_key_depth(depth),
+                _ => {}
+            }
+            while collapsed.get(next).is_some_and(|c| start >= c.end) {
+                next += 1;
+            }
+            match (collapsed.get(next), path) {
+                (Some(c), _) if start == c.start => output.push_collapsed(
+                    c.start,
+                    c.end,
+                    c.object,
+                    c.count,
+                    &container_summary(c.object, c.count),
+                ),
+                (Some(c), _) if start > c.start => {}
+                (_, Some(path)) => output.push_with_path(start, end, kind, path),
+                (_, None) => output.push(start, end, kind),
+            }
+        },
+    );
+    output.finish()
+}
 
-    let mut output = String::with_capacity(input.len() * 3);
-    output.push_str("<pre style=\"margin:0;font-family:inherit;\">");
+/// Highlights JSON Lines (NDJSON), one JSON value per line.
+///
+/// Each line is validated and highlighted on its own, so an unterminated
+/// string or stray bracket on one line does not change the colors of the
+/// lines after it. The text of each invalid line is marked like the errors
+/// of [`highlight_json_with_errors`]. Blank lines are allowed.
+///
+/// # Arguments
+/// * `input` - The JSON Lines text to highlight
+/// * `options` - Theme and output options
+///
+/// # Returns
+/// * The highlighted output, whether every line is valid, and one error per
+///   invalid line, positioned in the whole document
+pub fn highlight_ndjson(input: &str, options: &HighlightOptions) -> HighlightedValidation {
+    let mut output = Output::new(input, options, Language::Json);
+    let shown = output.input();
+    let mut errors = Vec::new();
+    let mut offset = 0;
+    for (index, line) in input.split_inclusive('\n').enumerate() {
+        let content = line.trim_end_matches(['\n', '\r']);
+        if !content.trim().is_empty() {
+            if let Some(error) = validate_json(content).error {
+                let start = offset + (content.len() - content.trim_start().len());
+                let end = offset + content.trim_end().len();
+                output.mark_error(start.min(shown.len()), end.min(shown.len()), &error.message);
+                errors.push(FormatError::new(error.message, index + 1, error.column));
+            }
+        }
+        if offset < shown.len() {
+            let visible = &shown[offset..(offset + content.len()).min(shown.len())];
+            scan_json(
+                visible,
+                &mut JsonHighlightState::default(),
+                |kind, start, end| output.push(offset + start, offset + end, kind),
+            );
+        }
+        offset += line.len();
+    }
+    HighlightedValidation {
+        html: if input.is_empty() {
+            String::new()
+        } else {
+            output.finish()
+        },
+        is_valid: errors.is_empty(),
+        errors,
+    }
+}
 
-    let chars: Vec<char> = input.chars().collect();
-    let len = chars.len();
+/// Formats JSON and highlights the result in one call.
+///
+/// The input is parsed once, by the formatter; the formatted text is then
+/// highlighted without leaving Rust, so a page that shows both does not
+/// pass a large document across the JavaScript boundary twice.
+///
+/// # Arguments
+/// * `input` - The JSON string to format
+/// * `indent` - Indentation for the formatted JSON
+/// * `options` - Theme and output options for the highlighting
+///
+/// # Returns
+/// * `Ok(FormattedHighlight)` - The formatted text and its highlighted output
+/// * `Err(FormatError)` - If the input cannot be formatted
+pub fn format_and_highlight_json(
+    input: &str,
+    indent: IndentStyle,
+    options: &HighlightOptions,
+) -> Result<FormattedHighlight, FormatError> {
+    let formatted = format_json(input, indent)?;
+    let html = highlight_json_with_options(&formatted, options);
+    Ok(FormattedHighlight { formatted, html })
+}
+
+/// Validates JSON and highlights it with the errors marked in place.
+///
+/// Marks the first error, where the parser stopped. The text at an
+/// error is wrapped in `<span class="hl-error">` with the message as its
+/// tooltip, so problems show up in context rather than only as a message.
+///
+/// # Arguments
+/// * `input` - The JSON string to validate and highlight
+/// * `options` - Theme and output options
+///
+/// # Returns
+/// * The highlighted output, whether the input is valid, and its errors
+pub fn highlight_json_with_errors(
+    input: &str,
+    options: &HighlightOptions,
+) -> HighlightedValidation {
+    let result = validate_json(input);
+    let errors: Vec<_> = result.error.into_iter().collect();
+    let html = if input.is_empty() {
+        String::new()
+    } else {
+        let mut output = Output::new(input, options, Language::Json);
+        output.mark_errors(&errors);
+        scan_json(
+            output.input(),
+            &mut JsonHighlightState::default(),
+            |kind, start, end| output.push(start, end, kind),
+        );
+        output.finish()
+    };
+    HighlightedValidation {
+        html,
+        is_valid: result.is_valid,
+        errors,
+    }
+}
+
+/// Highlights one chunk of a larger JSON document.
+///
+/// Feed the chunks in order, each with the state returned for the one
+/// before (the default state for the first). Chunks should end at line
+/// breaks; tokens other than strings are not carried across chunks. HTML
+/// output has no `<pre>` wrapper, and with `line_numbers` the line spans
+/// continue the numbering of earlier chunks.
+///
+/// # Arguments
+/// * `chunk` - The next part of the JSON document
+/// * `state` - State returned for the previous chunk
+/// * `options` - Theme and output options
+///
+/// # Returns
+/// * The chunk's output and the state to continue from
+pub fn highlight_json_chunk(
+    chunk: &str,
+    state: &JsonHighlightState,
+    options: &HighlightOptions,
+) -> HighlightedChunk<JsonHighlightState> {
+    let mut state = state.clone();
+    let mut output = Output::fragment(chunk, options, Language::Json, state.lines);
+    scan_json(chunk, &mut state, |kind, start, end| {
+        output.push(start, end, kind)
+    });
+    let html = output.finish();
+    state.lines += chunk.matches('\n').count();
+    HighlightedChunk { html, state }
+}
+
+/// Highlights a JSON document as separate segments of `lines_per_segment`
+/// lines each.
+///
+/// Segments are highlighted as consecutive chunks (see
+/// [`highlight_json_chunk`]), so constructs spanning segments are colored as
+/// in the whole document and line numbers continue across them. The host
+/// can insert them into the page one at a time instead of building a
+/// single huge string.
+///
+/// # Arguments
+/// * `input` - The JSON string to highlight
+/// * `lines_per_segment` - Lines in each segment; at least one
+/// * `options` - Theme and output options
+///
+/// # Returns
+/// * One output string per segment, without a `<pre>` wrapper; none for empty input
+pub fn highlight_json_segments(
+    input: &str,
+    lines_per_segment: usize,
+    options: &HighlightOptions,
+) -> Vec<String> {
+    let mut state = JsonHighlightState::default();
+    split_segments(input, lines_per_segment, options)
+        .into_iter()
+        .map(|(segment, options)| {
+            let chunk = highlight_json_chunk(segment, &state, &options);
+            state = chunk.state;
+            chunk.html
+        })
+        .collect()
+}
+
+/// Split JSON into highlighting tokens.
+///
+/// Never fails: invalid input is tokenized as far as it goes, and
+/// characters that start no token are skipped. Each token has its
+/// nesting depth, brackets at the depth of the value they belong to.
+///
+/// # Arguments
+/// * `input` - The JSON string to tokenize
+///
+/// # Returns
+/// * Tokens in input order
+pub fn tokenize_json(input: &str) -> Vec<Token> {
+    let mut tokens = TokenCollector::new(input);
+    let bytes = input.as_bytes();
+    scan_json(
+        input,
+        &mut JsonHighlightState::default(),
+        |kind, start, end| {
+            let bracket = if kind == TokenKind::Bracket {
+                bytes[start]
+            } else {
+                0
+            };
+            if matches!(bracket, b'}' | b']') {
+                tokens.leave();
+            }
+            tokens.push(kind, start, end);
+            if matches!(bracket, b'{' | b'[') {
+                tokens.enter();
+            }
+        },
+    );
+    tokens.finish()
+}
+
+/// Find the objects and arrays that span several lines, for code folding.
+///
+/// Works on invalid JSON as far as brackets match up.
+///
+/// # Arguments
+/// * `input` - The JSON string to scan
+///
+/// # Returns
+/// * Regions sorted by start line, with summaries like `{…} 24 keys`
+pub fn json_fold_regions(input: &str) -> Vec<FoldRegion> {
+    let mut regions = Vec::new();
+    // Open brackets: start line, whether an object, and keys or items so far
+    let mut open: Vec<(usize, bool, usize)> = Vec::new();
+    let count_item = |open: &mut Vec<(usize, bool, usize)>| {
+        if let Some((_, false, items)) = open.last_mut() {
+            *items += 1;
+        }
+    };
+    for token in tokenize_json(input) {
+        match (token.kind, &input[token.start..token.end]) {
+            (TokenKind::Bracket, bracket @ ("{" | "[")) => {
+                count_item(&mut open);
+                open.push((token.line, bracket == "{", 0));
+            }
+            (TokenKind::Bracket, _) => {
+                let Some((start_line, object, count)) = open.pop() else {
+                    continue;
+                };
+                if token.line > start_line {
+                    regions.push(FoldRegion {
+                        start_line,
+                        end_line: token.line,
+                        kind: if object {
+                            FoldKind::Object
+                        } else {
+                            FoldKind::Array
+                        },
+                        summary: container_summary(object, count),
+                    });
+                }
+            }
+            (TokenKind::Key, _) => {
+                if let Some((_, true, keys)) = open.last_mut() {
+                    *keys += 1;
+                }
+            }
+            (TokenKind::String | TokenKind::Number | TokenKind::Boolean | TokenKind::Null, _) => {
+                count_item(&mut open)
+            }
+            _ => {}
+        }
+    }
+    sort_regions(&mut regions);
+    regions
+}
+
+/// Follows the JSON Pointer of each token while scanning.
+#[derive(Default)]
+struct PathTracker {
+    frames: Vec<PathFrame>,
+}
+
+/// An open object with its current key, or an open array with the index
+/// of its current item.
+enum PathFrame {
+    Object(Option<String>),
+    Array(Option<usize>),
+}
+
+impl PathTracker {
+    /// Update for a token and return its pointer, for keys, values, and
+    /// opening brackets.
+    fn token(&mut self, kind: TokenKind, text: &str) -> Option<String> {
+        match (kind, text) {
+            (TokenKind::Key, _) => {
+                if let Some(PathFrame::Object(key)) = self.frames.last_mut() {
+                    // Keys are JSON strings; an unterminated one is used as written
+                    let decoded = serde_json::from_str(text)
+                        .unwrap_or_else(|_| text.trim_matches('"').to_string());
+                    *key = Some(decoded);
+                }
+                Some(self.pointer())
+            }
+            (TokenKind::Bracket, "{" | "[") => {
+                self.next_item();
+                let pointer = self.pointer();
+                self.frames.push(if text == "{" {
+                    PathFrame::Object(None)
+                } else {
+                    PathFrame::Array(None)
+                });
+                Some(pointer)
+            }
+            (TokenKind::Bracket, _) => {
+                self.frames.pop();
+                None
+            }
+            (TokenKind::String | TokenKind::Number | TokenKind::Boolean | TokenKind::Null, _) => {
+                self.next_item();
+                Some(self.pointer())
+            }
+            _ => None,
+        }
+    }
+
+    fn next_item(&mut self) {
+        if let Some(PathFrame::Array(index)) = self.frames.last_mut() {
+            *index = Some(index.map_or(0, |i| i + 1));
+        }
+    }
+
+    fn pointer(&self) -> String {
+        let mut pointer = String::new();
+        for frame in &self.frames {
+            match frame {
+                PathFrame::Object(Some(key)) => push_pointer_token(&mut pointer, key),
+                PathFrame::Array(Some(index)) => {
+                    push_pointer_token(&mut pointer, &index.to_string())
+                }
+                _ => {}
+            }
+        }
+        pointer
+    }
+}
+
+/// An object or array closed in the input.
+struct Container {
+    start: usize,
+    end: usize,
+    object: bool,
+    /// Keys of an object or items of an array
+    count: usize,
+}
+
+/// The outermost objects and arrays with more than `limit` keys or items,
+/// in input order.
+fn collapsed_containers(input: &str, limit: usize) -> Vec<Container> {
+    let mut found: Vec<Container> = Vec::new();
+    // Open brackets: start, whether an object, and keys or items so far
+    let mut open: Vec<(usize, bool, usize)> = Vec::new();
+    let bytes = input.as_bytes();
+    scan_json(
+        input,
+        &mut JsonHighlightState::default(),
+        |kind, start, end| {
+            let count_item = |open: &mut Vec<(usize, bool, usize)>| {
+                if let Some((_, false, items)) = open.last_mut() {
+                    *items += 1;
+                }
+            };
+            match (kind, bytes[start]) {
+                (TokenKind::Bracket, bracket @ (b'{' | b'[')) => {
+                    count_item(&mut open);
+                    open.push((start, bracket == b'{', 0));
+                }
+                (TokenKind::Bracket, _) => {
+                    if let Some((start, object, count)) =
+                        open.pop().filter(|&(_, _, count)| count > limit)
+                    {
+                        // Inner containers close first; an enclosing one replaces them
+                        found.retain(|c| c.start < start);
+                        found.push(Container {
+                            start,
+                            end,
+                            object,
+                            count,
+                        });
+                    }
+                }
+                (TokenKind::Key, _) => {
+                    if let Some((_, true, keys)) = open.last_mut() {
+                        *keys += 1;
+                    }
+                }
+                (
+                    TokenKind::String | TokenKind::Number | TokenKind::Boolean | TokenKind::Null,
+                    _,
+                ) => count_item(&mut open),
+                _ => {}
+            }
+        },
+    );
+    found
+}
+
+/// Short description of an object or array, like `{…} 1,024 keys`.
+fn container_summary(object: bool, count: usize) -> String {
+    let digits = count.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    match (object, count) {
+        (true, 1) => "{…} 1 key".to_string(),
+        (true, _) => format!("{{…}} {} keys", grouped),
+        (false, 1) => "[…] 1 item".to_string(),
+        (false, _) => format!("[…] {} items", grouped),
+    }
+}
+
+/// Pair each opening bracket with the bracket that closes it, so the page
+/// can highlight the match of the bracket under the cursor.
+///
+/// Brackets inside strings are ignored. A closing bracket of the wrong kind
+/// still closes the innermost open bracket but is not reported as a match.
+///
+/// # Arguments
+/// * `input` - The JSON string to scan
+///
+/// # Returns
+/// * Matched pairs, sorted by the position of the opening bracket
+pub fn json_matching_pairs(input: &str) -> Vec<MatchingPair> {
+    let mut pairs = Vec::new();
+    let mut open: Vec<(&str, usize)> = Vec::new();
+    for token in tokenize_json(input) {
+        if token.kind != TokenKind::Bracket {
+            continue;
+        }
+        match &input[token.start..token.end] {
+            bracket @ ("{" | "[") => open.push((bracket, token.start)),
+            close => {
+                let Some((bracket, start)) = open.pop() else {
+                    continue;
+                };
+                if (bracket, close) == ("{", "}") || (bracket, close) == ("[", "]") {
+                    pairs.push(MatchingPair {
+                        open: (start, start + 1),
+                        close: (token.start, token.end),
+                    });
+                }
+            }
+        }
+    }
+    pairs.sort_by_key(|pair| pair.open.0);
+    pairs
+}
+
+/// Run the highlighting state machine, reporting each token's kind and byte range.
+///
+/// Starts from `state` and leaves it describing where the input ended.
+fn scan_json(
+    input: &str,
+    state: &mut JsonHighlightState,
+    mut emit: impl FnMut(TokenKind, usize, usize),
+) {
+    let bytes = input.as_bytes();
+    let len = bytes.len();
     let mut i = 0;
 
     // Track if we're expecting a key (after { or ,)
-    let mut expect_key = false;
-    let mut brace_stack: Vec<char> = Vec::new();
+    let expect_key = &mut state.expect_key;
+    let brace_stack = &mut state.brackets;
 
-    while i < len {
-        let c = chars[i];
+    // Finish a string left open by the previous chunk
+    if state.in_string {
+        let (end, closed) = string_rest(bytes, 0);
+        emit(
+            if *expect_key {
+                TokenKind::Key
+            } else {
+                TokenKind::String
+            },
+            0,
+            end,
+        );
+        if !closed {
+            return;
+        }
+        state.in_string = false;
+        *expect_key = false;
+        i = end;
+    }
 
-        match c {
+    while i < len {
+        match bytes[i] {
             // Whitespace - preserve as-is
-            ' ' | '\t' | '\n' | '\r' => {
-                output.push(c);
-                i += 1;
-            }
+            b' ' | b'\t' | b'\n' | b'\r' => i += 1,
 
             // Object start
-            '{' => {
-                push_colored(&mut output, "{", colors::BRACKET);
+            b'{' => {
+                emit(TokenKind::Bracket, i, i + 1);
                 brace_stack.push('{');
-                expect_key = true;
-                i += 1;
-            }
-
-            // Object end
-            '}' => {
-                push_colored(&mut output, "}", colors::BRACKET);
-                brace_stack.pop();
-                expect_key = false;
+                *expect_key = true;
                 i += 1;
             }
 
             // Array start
-            '[' => {
-                push_colored(&mut output, "[", colors::BRACKET);
+            b'[' => {
+                emit(TokenKind::Bracket, i, i + 1);
                 brace_stack.push('[');
-                expect_key = false;
+                *expect_key = false;
                 i += 1;
             }
 
-            // Array end
-            ']' => {
-                push_colored(&mut output, "]", colors::BRACKET);
+            // Object or array end
+            b'}' | b']' => {
+                emit(TokenKind::Bracket, i, i + 1);
                 brace_stack.pop();
-                expect_key = false;
+                *expect_key = false;
                 i += 1;
             }
 
             // Colon (key-value separator)
-            ':' => {
-                push_colored(&mut output, ":", colors::PUNCTUATION);
-                expect_key = false;
+            b':' => {
+                emit(TokenKind::Punctuation, i, i + 1);
+                *expect_key = false;
                 i += 1;
             }
 
             // Comma
-            ',' => {
-                push_colored(&mut output, ",", colors::PUNCTUATION);
+            b',' => {
+                emit(TokenKind::Punctuation, i, i + 1);
                 // After comma in object, expect key; in array, expect value
-                expect_key = brace_stack.last() == Some(&'{');
+                *expect_key = brace_stack.ends_with('{');
                 i += 1;
             }
 
             // String (could be key or value)
-            '"' => {
-                let (string_content, end_pos) = parse_string(&chars, i);
-                let color = if expect_key { colors::KEY } else { colors::STRING };
-                push_colored(&mut output, &string_content, color);
-                expect_key = false;
-                i = end_pos;
+            b'"' => {
+                let (end, closed) = string_rest(bytes, i + 1);
+                emit(
+                    if *expect_key {
+                        TokenKind::Key
+                    } else {
+                        TokenKind::String
+                    },
+                    i,
+                    end,
+                );
+                if !closed {
+                    // Keep `expect_key` to color the rest of the string in the next chunk
+                    state.in_string = true;
+                    return;
+                }
+                *expect_key = false;
+                i = end;
             }
 
             // Number
-            '-' | '0'..='9' => {
-                let (num_str, end_pos) = parse_number(&chars, i);
-                push_colored(&mut output, &num_str, colors::NUMBER);
-                expect_key = false;
-                i = end_pos;
+            b'-' | b'0'..=b'9' => {
+                let end = number_end(bytes, i);
+                emit(TokenKind::Number, i, end);
+                *expect_key = false;
+                i = end;
             }
 
-            // true
-            't' if matches_keyword(&chars, i, "true") => {
-                push_colored(&mut output, "true", colors::BOOLEAN);
-                expect_key = false;
-                i += 4;
-            }
-
-            // false
-            'f' if matches_keyword(&chars, i, "false") => {
-                push_colored(&mut output, "false", colors::BOOLEAN);
-                expect_key = false;
-                i += 5;
+            // true / false
+            b't' | b'f' if keyword_at(input, i, "true") || keyword_at(input, i, "false") => {
+                let end = i + if bytes[i] == b't' { 4 } else { 5 };
+                emit(TokenKind::Boolean, i, end);
+                *expect_key = false;
+                i = end;
             }
 
             // null
-            'n' if matches_keyword(&chars, i, "null") => {
-                push_colored(&mut output, "null", colors::NULL);
-                expect_key = false;
+            b'n' if keyword_at(input, i, "null") => {
+                emit(TokenKind::Null, i, i + 4);
+                *expect_key = false;
                 i += 4;
             }
 
-            // Unknown character - just escape and output
-            _ => {
-                push_escaped(&mut output, c);
-                i += 1;
-            }
+            // Unknown character - left uncolored
+            _ => i += input[i..].chars().next().map_or(1, char::len_utf8),
         }
     }
-
-    output.push_str("</pre>");
-    output
 }
 
-/// Parse a JSON string starting at position i, returns (string_with_quotes, end_position)
-fn parse_string(chars: &[char], start: usize) -> (String, usize) {
-    let mut result = String::new();
-    result.push('"');
-
-    let mut i = start + 1; // Skip opening quote
-    let len = chars.len();
-
-    while i < len {
-        let c = chars[i];
-        match c {
-            '"' => {
-                result.push('"');
-                return (result, i + 1);
-            }
-            '\\' if i + 1 < len => {
-                result.push('\\');
-                result.push(chars[i + 1]);
-                i += 2;
-            }
-            '<' => {
-                result.push_str("&lt;");
-                i += 1;
-            }
-            '>' => {
-                result.push_str("&gt;");
-                i += 1;
-            }
-            '&' => {
-                result.push_str("&amp;");
-                i += 1;
-            }
-            _ => {
-                result.push(c);
-                i += 1;
-            }
+/// End of a JSON string whose content starts at `start`: just past the
+/// closing quote and `true`, or the end of input and `false` if unterminated
+fn string_rest(bytes: &[u8], start: usize) -> (usize, bool) {
+    let mut i = start;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => return (i + 1, true),
+            b'\\' => i += 2,
+            _ => i += 1,
         }
     }
-
-    // Unterminated string
-    (result, i)
+    (bytes.len(), false)
 }
 
-/// Parse a JSON number starting at position i, returns (number_string, end_position)
-fn parse_number(chars: &[char], start: usize) -> (String, usize) {
-    let mut result = String::new();
-    let mut i = start;
-    let len = chars.len();
-
-    // Optional minus
-    if i < len && chars[i] == '-' {
-        result.push('-');
-        i += 1;
-    }
+/// End of the JSON number starting at `start`
+fn number_end(bytes: &[u8], start: usize) -> usize {
+    let digits = |mut i: usize| {
+        while i < bytes.len() && bytes[i].is_ascii_digit() {
+            i += 1;
+        }
+        i
+    };
 
-    // Integer part
-    while i < len && chars[i].is_ascii_digit() {
-        result.push(chars[i]);
+    // Optional minus, then integer part
+    let mut i = start;
+    if bytes[i] == b'-' {
         i += 1;
     }
+    i = digits(i);
 
     // Decimal part
-    if i < len && chars[i] == '.' {
-        result.push('.');
-        i += 1;
-        while i < len && chars[i].is_ascii_digit() {
-            result.push(chars[i]);
-            i += 1;
-        }
+    if i < bytes.len() && bytes[i] == b'.' {
+        i = digits(i + 1);
     }
 
     // Exponent part
-    if i < len && (chars[i] == 'e' || chars[i] == 'E') {
-        result.push(chars[i]);
+    if i < bytes.len() && (bytes[i] == b'e' || bytes[i] == b'E') {
         i += 1;
-        if i < len && (chars[i] == '+' || chars[i] == '-') {
-            result.push(chars[i]);
-            i += 1;
-        }
-        while i < len && chars[i].is_ascii_digit() {
-            result.push(chars[i]);
+        if i < bytes.len() && (bytes[i] == b'+' || bytes[i] == b'-') {
             i += 1;
         }
+        i = digits(i);
     }
-
-    (result, i)
+    i
 }
 
 /// Check if a keyword matches at position i
-fn matches_keyword(chars: &[char], start: usize, keyword: &str) -> bool {
-    let kw_chars: Vec<char> = keyword.chars().collect();
-    if start + kw_chars.len() > chars.len() {
-        return false;
-    }
-    for (j, kc) in kw_chars.iter().enumerate() {
-        if chars[start + j] != *kc {
-            return false;
-        }
-    }
+fn keyword_at(input: &str, start: usize, keyword: &str) -> bool {
     // Make sure keyword ends (not followed by alphanumeric)
-    let end_pos = start + kw_chars.len();
-    if end_pos < chars.len() && chars[end_pos].is_alphanumeric() {
-        return false;
-    }
-    true
-}
-
-/// Push colored HTML span
-fn push_colored(output: &mut String, text: &str, color: &str) {
-    output.push_str("<span style=\"color:");
-    output.push_str(color);
-    output.push_str("\">");
-    output.push_str(text);
-    output.push_str("</span>");
-}
-
-/// Push escaped character
-fn push_escaped(output: &mut String, c: char) {
-    match c {
-        '<' => output.push_str("&lt;"),
-        '>' => output.push_str("&gt;"),
-        '&' => output.push_str("&amp;"),
-        _ => output.push(c),
-    }
+    input[start..].starts_with(keyword)
+        && !input[start + keyword.len()..]
+            .chars()
+            .next()
+            .is_some_and(char::is_alphanumeric)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::highlight::{HighlightTheme, OutputTarget, SearchOptions};
 
     #[test]
     fn test_highlight_empty_input() {
@@ -318,10 +782,11 @@ mod tests {
     fn test_highlight_key_vs_value_colors() {
         let input = r#"{"myKey": "myValue"}"#;
         let result = highlight_json(input);
+        let colors = HighlightTheme::dark().json;
         // Key should have KEY color
-        assert!(result.contains(&format!("color:{}", colors::KEY)));
+        assert!(result.contains(&format!("color:{}", colors.key)));
         // Value should have STRING color
-        assert!(result.contains(&format!("color:{}", colors::STRING)));
+        assert!(result.contains(&format!("color:{}", colors.string)));
     }
 
     #[test]
@@ -331,4 +796,332 @@ mod tests {
         assert!(result.contains("&lt;script&gt;"));
         assert!(!result.contains("<script>"));
     }
+
+    #[test]
+    fn test_highlight_with_theme() {
+        let options = HighlightOptions {
+            theme: HighlightTheme::light(),
+            ..Default::default()
+        };
+        let result = highlight_json_with_options(r#"{"a": null}"#, &options);
+        assert!(result.contains(&format!("color:{}\">\"a\"", options.theme.json.key)));
+        assert!(result.contains(&format!("color:{}\">null", options.theme.json.null)));
+        assert!(!result.contains(&HighlightTheme::dark().json.bracket));
+    }
+
+    #[test]
+    fn test_tokenize_json() {
+        let input = "{\"a\": [1.5e3, true],\n \"b\": \"\\\"x\" nul}";
+        let tokens = tokenize_json(input);
+        let kinds: Vec<(TokenKind, &str)> = tokens
+            .iter()
+            .map(|t| (t.kind, &input[t.start..t.end]))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (TokenKind::Bracket, "{"),
+                (TokenKind::Key, "\"a\""),
+                (TokenKind::Punctuation, ":"),
+                (TokenKind::Bracket, "["),
+                (TokenKind::Number, "1.5e3"),
+                (TokenKind::Punctuation, ","),
+                (TokenKind::Boolean, "true"),
+                (TokenKind::Bracket, "]"),
+                (TokenKind::Punctuation, ","),
+                (TokenKind::Key, "\"b\""),
+                (TokenKind::Punctuation, ":"),
+                (TokenKind::String, "\"\\\"x\""),
+                (TokenKind::Bracket, "}"),
+            ]
+        );
+        assert_eq!((tokens[9].line, tokens[9].column), (2, 2));
+        let depths: Vec<usize> = tokens.iter().map(|t| t.depth).collect();
+        assert_eq!(depths, [0, 1, 1, 1, 2, 2, 2, 1, 1, 1, 1, 1, 0]);
+    }
+
+    #[test]
+    fn test_search_marks() {
+        let options = HighlightOptions {
+            search: SearchOptions {
+                query: "<b".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let result = highlight_json_with_options(r#"{"a<b": "<B"}"#, &options);
+        assert_eq!(result.matches("<mark>&lt;").count(), 2);
+        assert!(result.contains(&format!(
+            "color:{}\">\"a<mark>&lt;b</mark>\"</span>",
+            options.theme.json.key
+        )));
+    }
+
+    #[test]
+    fn test_highlight_ndjson() {
+        let input = "{\"a\": \"x}\n\n[1, 2]\r\n  nope \n{\"b\": true}";
+        let options = HighlightOptions {
+            output: OutputTarget::HtmlClasses,
+            ..Default::default()
+        };
+        let result = highlight_ndjson(input, &options);
+        assert!(!result.is_valid);
+        let lines: Vec<usize> = result.errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, [1, 4]);
+        // The unterminated string does not run into the following lines
+        assert!(result
+            .html
+            .contains("<span class=\"hl-bracket\">[</span><span class=\"hl-number\">1</span>"));
+        assert!(result.html.contains("<span class=\"hl-key\">\"b\"</span>"));
+        assert!(result.html.contains(&format!(
+            "<span class=\"hl-error\" title=\"{}\">nope</span> \n",
+            result.errors[1].message
+        )));
+
+        let result = highlight_ndjson("1\n\"two\"\n", &options);
+        assert!(result.is_valid && result.errors.is_empty());
+        assert!(highlight_ndjson("", &options).html.is_empty());
+    }
+
+    #[test]
+    fn test_format_and_highlight() {
+        let options = HighlightOptions::default();
+        let result =
+            format_and_highlight_json(r#"{"a":[1]}"#, IndentStyle::Spaces(2), &options).unwrap();
+        assert_eq!(
+            result.formatted,
+            format_json(r#"{"a":[1]}"#, IndentStyle::Spaces(2)).unwrap()
+        );
+        assert_eq!(
+            result.html,
+            highlight_json_with_options(&result.formatted, &options)
+        );
+        assert!(format_and_highlight_json("{", IndentStyle::Spaces(2), &options).is_err());
+    }
+
+    #[test]
+    fn test_highlight_with_errors() {
+        let input = "{\n  \"a\": tru\n}";
+        let result = highlight_json_with_errors(input, &HighlightOptions::default());
+        assert!(!result.is_valid);
+        assert_eq!(result.errors.len(), 1);
+        let error = &result.errors[0];
+        assert!(result.html.contains(&format!(
+            "<span class=\"hl-error\" title=\"{}\"",
+            error.message
+        )));
+
+        let result = highlight_json_with_errors("[1]", &HighlightOptions::default());
+        assert!(result.is_valid && result.errors.is_empty());
+        assert_eq!(result.html, highlight_json("[1]"));
+    }
+
+    #[test]
+    fn test_mark_errors_option() {
+        let options = HighlightOptions {
+            output: OutputTarget::HtmlClasses,
+            mark_errors: true,
+            ..Default::default()
+        };
+        let input = "{\"a\": [1, 2}, \"b\": null}";
+        let html = highlight_json_with_options(input, &options);
+        assert_eq!(html, highlight_json_with_errors(input, &options).html);
+        assert!(html.contains("hl-error"));
+        // The tokens around the error keep their colors
+        assert!(html.contains("<span class=\"hl-key\">\"a\"</span>"));
+        assert!(html.contains("<span class=\"hl-null\">null</span>"));
+
+        let plain = HighlightOptions {
+            output: OutputTarget::HtmlClasses,
+            ..Default::default()
+        };
+        assert_eq!(
+            highlight_json_with_options("[1]", &options),
+            highlight_json_with_options("[1]", &plain)
+        );
+    }
+
+    #[test]
+    fn test_depth_key_colors() {
+        let input = r#"{"a": {"b": [{"c": 1}]}, "d": 2}"#;
+        let options = HighlightOptions {
+            output: OutputTarget::HtmlClasses,
+            depth_key_colors: true,
+            ..Default::default()
+        };
+        let html = highlight_json_with_options(input, &options);
+        for (key, level) in [("a", 0), ("b", 1), ("c", 3), ("d", 0)] {
+            assert!(html.contains(&format!(
+                "<span class=\"hl-key hl-key-{}\">\"{}\"</span>",
+                level, key
+            )));
+        }
+
+        let options = HighlightOptions {
+            depth_key_colors: true,
+            ..Default::default()
+        };
+        let html = highlight_json_with_options(input, &options);
+        let colors = &HighlightTheme::dark().json.key_depths;
+        assert!(html.contains(&format!("<span style=\"color:{}\">\"b\"</span>", colors[1])));
+        assert!(html.contains(&format!("<span style=\"color:{}\">\"d\"</span>", colors[0])));
+        // Strings and other tokens keep their colors
+        assert!(!highlight_json_with_options(r#"["x"]"#, &options).contains(&colors[0]));
+    }
+
+    #[test]
+    fn test_matching_pairs() {
+        let input = r#"{"a": [1, "]"], "b": {}}"#;
+        let pairs: Vec<_> = json_matching_pairs(input)
+            .iter()
+            .map(|p| (p.open.0, p.close.0))
+            .collect();
+        assert_eq!(pairs, [(0, 23), (6, 13), (21, 22)]);
+        // Mismatched and unclosed brackets are left out
+        assert_eq!(json_matching_pairs("[1}"), []);
+        assert_eq!(json_matching_pairs("[[]").len(), 1);
+    }
+
+    #[test]
+    fn test_fold_regions() {
+        let input = "{\n  \"a\": [1, {\"x\": 2},\n    [3]],\n  \"b\": {\"c\": [\n  ]}\n}";
+        let regions: Vec<(usize, usize, FoldKind, String)> = json_fold_regions(input)
+            .into_iter()
+            .map(|r| (r.start_line, r.end_line, r.kind, r.summary))
+            .collect();
+        assert_eq!(
+            regions,
+            [
+                (1, 6, FoldKind::Object, "{…} 2 keys".to_string()),
+                (2, 3, FoldKind::Array, "[…] 3 items".to_string()),
+                (4, 5, FoldKind::Object, "{…} 1 key".to_string()),
+                (4, 5, FoldKind::Array, "[…] 0 items".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_collapse_above() {
+        let input = "{\"big\": [1, 2, [3, 4, 5]], \"small\": {\"a\": 1}}";
+        let options = HighlightOptions {
+            collapse_above: Some(2),
+            output: OutputTarget::Plain,
+            ..Default::default()
+        };
+        // The outer array has 3 items, so its long inner array is folded into it
+        assert_eq!(
+            highlight_json_with_options(input, &options),
+            "{\"big\": […] 3 items, \"small\": {\"a\": 1}}"
+        );
+        let html = highlight_json_with_options(
+            input,
+            &HighlightOptions {
+                collapse_above: Some(2),
+                ..Default::default()
+            },
+        );
+        assert!(
+            html.contains("data-kind=\"array\" data-start=\"8\" data-end=\"25\" data-count=\"3\"")
+        );
+        assert_eq!(&input[8..25], "[1, 2, [3, 4, 5]]");
+    }
+
+    #[test]
+    fn test_data_paths() {
+        let input = r#"{"a/b": [1, {"c~": null}], "d": "x"}"#;
+        let options = HighlightOptions {
+            data_paths: true,
+            output: OutputTarget::HtmlClasses,
+            ..Default::default()
+        };
+        let result = highlight_json_with_options(input, &options);
+        let paths: Vec<&str> = result
+            .split(" data-path=\"")
+            .skip(1)
+            .map(|rest| &rest[..rest.find('"').unwrap()])
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "",
+                "/a~1b",
+                "/a~1b",
+                "/a~1b/0",
+                "/a~1b/1",
+                "/a~1b/1/c~0",
+                "/a~1b/1/c~0",
+                "/d",
+                "/d"
+            ]
+        );
+        assert!(result.contains("<span class=\"hl-number\" data-path=\"/a~1b/0\">1</span>"));
+        assert!(
+            !highlight_json_with_options(input, &HighlightOptions::default()).contains("data-path")
+        );
+    }
+
+    #[test]
+    fn test_container_summary() {
+        assert_eq!(container_summary(true, 1), "{…} 1 key");
+        assert_eq!(container_summary(true, 1024), "{…} 1,024 keys");
+        assert_eq!(container_summary(false, 999), "[…] 999 items");
+        assert_eq!(container_summary(false, 1234567), "[…] 1,234,567 items");
+    }
+
+    #[test]
+    fn test_line_anchors() {
+        let options = HighlightOptions {
+            line_anchors: true,
+            ..Default::default()
+        };
+        let result = highlight_json_with_options("{\n  \"a\": 1\n}", &options);
+        assert!(result.contains("<span class=\"line\" id=\"L2\" data-line=\"2\">  <span"));
+        assert_eq!(result.matches(" id=\"L").count(), 3);
+    }
+
+    #[test]
+    fn test_segments() {
+        let input = "{\n  \"a\": \"x\",\n  \"b\": [1,\n    2]\n}\n";
+        let options = HighlightOptions {
+            line_numbers: true,
+            ..Default::default()
+        };
+        let segments = highlight_json_segments(input, 2, &options);
+        assert_eq!(segments.len(), 3);
+        assert!(segments[2].starts_with("<span class=\"line\" data-line=\"5\">"));
+        // Together the segments are the whole document without its wrapper
+        // and the empty line after the final line break
+        let whole = highlight_json_with_options(input, &options);
+        assert_eq!(
+            format!(
+                "<pre style=\"margin:0;font-family:inherit;\">{}</pre>",
+                segments.concat()
+            ),
+            whole.replace("<span class=\"line\" data-line=\"6\"></span>", "")
+        );
+        assert!(highlight_json_segments("", 10, &options).is_empty());
+    }
+
+    #[test]
+    fn test_chunks_continue_state() {
+        let options = HighlightOptions::default();
+        let colors = &options.theme.json;
+        let first = highlight_json_chunk("{\"a\": [1,\n", &JsonHighlightState::default(), &options);
+        assert_eq!(first.state.brackets, "{[");
+        assert_eq!(first.state.lines, 1);
+        let second = highlight_json_chunk("2], \"lo\n", &first.state, &options);
+        assert!(second.state.in_string && second.state.expect_key);
+        let third = highlight_json_chunk("ng\": 3}\n", &second.state, &options);
+        assert!(third
+            .html
+            .starts_with(&format!("<span style=\"color:{}\">ng\"", colors.key)));
+        assert!(third.html.contains(&format!("color:{}\">3", colors.number)));
+        assert_eq!(
+            third.state,
+            JsonHighlightState {
+                lines: 3,
+                ..Default::default()
+            }
+        );
+    }
 }
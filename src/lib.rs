@@ -1,22 +1,68 @@
 use wasm_bindgen::prelude::*;
 
+pub mod compare;
+pub mod diff_highlighter;
+pub mod encoding;
 pub mod formatter;
+pub mod highlight;
 pub mod highlighter;
+pub mod schema_sample;
+pub mod semantic;
 pub mod types;
 pub mod validator;
+pub mod xml_canonical;
 pub mod xml_formatter;
 pub mod xml_highlighter;
+pub mod xml_html;
+pub mod xml_namespaces;
+pub mod xml_query;
+pub mod xml_schema;
+pub mod xml_transform;
+pub mod xml_tree;
 
 #[cfg(test)]
 mod tests;
 
 // Re-export public types for convenience (Rust API)
+pub use compare::{json_equal, EqualityOptions, EqualityResult};
+pub use diff_highlighter::{highlight_diff, DiffOptions, DiffView, HighlightedDiff};
+pub use encoding::{decode_xml, detect_encoding_issues, EncodingIssue, EncodingIssueKind};
 pub use formatter::{format_json, minify_json};
-pub use highlighter::highlight_json;
-pub use types::{FormatError, IndentStyle, JsonStats, ValidationResult};
-pub use validator::validate_json;
-pub use xml_formatter::{format_xml, minify_xml};
-pub use xml_highlighter::highlight_xml;
+pub use highlight::{
+    find_matches, FoldKind, FoldRegion, FormattedHighlight, HighlightOptions, HighlightTheme,
+    HighlightedChunk, HighlightedValidation, JsonColors, Language, MatchingPair, OutputTarget,
+    SearchMatches, SearchOptions, Token, TokenKind, XmlColors,
+};
+pub use highlighter::{
+    format_and_highlight_json, highlight_json, highlight_json_chunk, highlight_json_segments,
+    highlight_json_with_errors, highlight_json_with_options, highlight_ndjson, json_fold_regions,
+    json_matching_pairs, tokenize_json, JsonHighlightState,
+};
+pub use schema_sample::generate_sample;
+pub use semantic::{detect_semantic_values, SemanticKind, SemanticReport};
+pub use types::{
+    FormatError, IndentStyle, JsonStats, ValidationResult, XmlStats, XmlValidationResult,
+    DEFAULT_MAX_INPUT_BYTES,
+};
+pub use validator::{
+    validate_json, validate_xml, validate_xml_all, validate_xml_namespaces, xml_stats,
+};
+pub use xml_canonical::{canonicalize_xml, CanonicalizationOptions};
+pub use xml_formatter::{
+    format_xml, format_xml_bytes, minify_xml, minify_xml_bytes, CdataHandling, CommentHandling,
+    EmptyElementStyle, NewlineStyle, XmlFormatOptions, XmlLimits,
+};
+pub use xml_highlighter::{
+    format_and_highlight_xml, highlight_xml, highlight_xml_chunk, highlight_xml_segments,
+    highlight_xml_with_errors, highlight_xml_with_options, tokenize_xml, xml_fold_regions,
+    xml_matching_pairs, XmlContext, XmlHighlightState,
+};
+pub use xml_html::html_to_xml;
+pub use xml_namespaces::{normalize_namespaces, NamespaceOptions};
+pub use xml_query::{xml_query, QueryMatch, QueryResult};
+pub use xml_schema::{validate_xml_schema, SchemaValidationResult, SchemaViolation};
+pub use xml_transform::{attributes_to_elements, elements_to_attributes};
+pub use xml_tree::XmlDocument;
 
 // ============================================================================
 // WASM/JavaScript API
@@ -32,16 +78,7 @@ pub fn greet() -> String {
 /// Parse indent style string into IndentStyle enum.
 /// Accepts: "spaces:2", "spaces:4", "tabs"
 fn parse_indent_style(indent: &str) -> Result<IndentStyle, JsValue> {
-    match indent {
-        "tabs" => Ok(IndentStyle::Tabs),
-        s if s.starts_with("spaces:") => {
-            let num = s.strip_prefix("spaces:")
-                .and_then(|n| n.parse::<u8>().ok())
-                .ok_or_else(|| JsValue::from_str("Invalid indent format. Use 'spaces:N' or 'tabs'"))?;
-            Ok(IndentStyle::Spaces(num))
-        }
-        _ => Err(JsValue::from_str("Invalid indent format. Use 'spaces:2', 'spaces:4', or 'tabs'")),
-    }
+    indent.parse().map_err(|e: String| JsValue::from_str(&e))
 }
 
 /// Format JSON with specified indentation.
@@ -141,38 +178,489 @@ pub fn js_highlight_json(input: &str) -> String {
     highlighter::highlight_json(input)
 }
 
+/// Highlight JSON with a chosen theme and optional line wrapping.
+///
+/// # Arguments
+/// * `input` - The JSON string to highlight
+/// * `options` - Optional object, every field optional:
+///   * `theme` - `"dark"`, `"light"`, `"highContrast"`, or
+///     `{ base?: "dark" | "light" | "highContrast", json?: { key?, string?,
+///     number?, boolean?, null?, bracket?, punctuation? } }` where each color
+///     is a CSS color such as `"#9cdcfe"`
+///   * `lineNumbers` - Wrap each line in `<span class="line" data-line="N">`
+///   * `lineAnchors` - Also give each line `id="LN"` for deep links
+///   * `search` - `{ query?: string, caseSensitive?: boolean,
+///     ranges?: [number, number][] }`; matches (see `findMatches`) are
+///     wrapped in `<mark>`
+///   * `output` - `"htmlInline"`, `"htmlClasses"`, `"ansi"`, or `"plain"`;
+///     `"htmlClasses"` uses `hl-*` classes styled by `highlightThemeCss`
+///   * `maxBytes` - Past this size only the first lines are highlighted and a
+///     notice is appended
+///   * `collapseAbove` - Objects and arrays with more keys or items become
+///     `<span class="hl-collapsed" data-start="S" data-end="E">{…} N keys</span>`
+///     placeholders, `S..E` being the value's UTF-8 byte range
+///   * `dataPaths` - Give key, value, and opening bracket spans a `data-path`
+///     attribute with their JSON Pointer
+///   * `markErrors` - Mark syntax errors as `highlightJsonWithErrors` does
+///     while still coloring the rest of a broken document
+///   * `depthKeyColors` - Color object keys by nesting depth from a rotating
+///     palette (classes `hl-key hl-key-N`)
+///
+/// # Returns
+/// * Highlighted output, HTML with inline styles unless `output` says otherwise
+/// * Throws error string if the options are invalid
+#[wasm_bindgen(js_name = "highlightJsonWithOptions")]
+pub fn js_highlight_json_with_options(input: &str, options: JsValue) -> Result<String, JsValue> {
+    let options: HighlightOptions = from_js_options(&options)?;
+    Ok(highlighter::highlight_json_with_options(input, &options))
+}
+
+/// Format JSON and highlight the formatted text in one call.
+///
+/// # Arguments
+/// * `input` - The JSON string to format
+/// * `indent` - Indent style: "spaces:2", "spaces:4", or "tabs"
+/// * `options` - Optional object as for `highlightJsonWithOptions`
+///
+/// # Returns
+/// * JSON string: `{ "formatted": string, "html": string }`
+/// * Throws error string if the input cannot be formatted or the options are invalid
+#[wasm_bindgen(js_name = "formatAndHighlightJson")]
+pub fn js_format_and_highlight_json(
+    input: &str,
+    indent: &str,
+    options: JsValue,
+) -> Result<String, JsValue> {
+    let style = parse_indent_style(indent)?;
+    let options: HighlightOptions = from_js_options(&options)?;
+    let result = highlighter::format_and_highlight_json(input, style, &options)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    to_js_json(&result)
+}
+
+/// Validate JSON and highlight it with the first syntax error marked in place.
+///
+/// # Arguments
+/// * `input` - The JSON string to check
+/// * `options` - Optional object as for `highlightJsonWithOptions`
+///
+/// # Returns
+/// * JSON string: `{ "html": string, "isValid": boolean, "errors":
+///   [{ "message": string, "line": number, "column": number }] }` where the
+///   text at each error is wrapped in `<span class="hl-error" title="message">`
+/// * Throws error string if the options are invalid
+#[wasm_bindgen(js_name = "highlightJsonWithErrors")]
+pub fn js_highlight_json_with_errors(input: &str, options: JsValue) -> Result<String, JsValue> {
+    let options: HighlightOptions = from_js_options(&options)?;
+    to_js_json(&highlighter::highlight_json_with_errors(input, &options))
+}
+
+/// Highlight JSON Lines (NDJSON), validating and coloring each line on its own.
+///
+/// # Arguments
+/// * `input` - The JSON Lines text to highlight
+/// * `options` - Optional object as for `highlightJsonWithOptions`
+///
+/// # Returns
+/// * JSON string shaped as for `highlightJsonWithErrors`, with one error per
+///   invalid line and the whole text of that line marked
+/// * Throws error string if the options are invalid
+#[wasm_bindgen(js_name = "highlightNdjson")]
+pub fn js_highlight_ndjson(input: &str, options: JsValue) -> Result<String, JsValue> {
+    let options: HighlightOptions = from_js_options(&options)?;
+    to_js_json(&highlighter::highlight_ndjson(input, &options))
+}
+
+/// Style sheet for `"htmlClasses"` highlight output.
+///
+/// # Arguments
+/// * `theme` - `"dark"`, `"light"`, `"highContrast"`, or a theme object as in
+///   the highlight options
+///
+/// # Returns
+/// * CSS rules such as `.hl-json .hl-key { color: #9cdcfe; }`
+/// * Throws error string if the theme is invalid
+#[wasm_bindgen(js_name = "highlightThemeCss")]
+pub fn js_highlight_theme_css(theme: JsValue) -> Result<String, JsValue> {
+    let theme: HighlightTheme = from_js_options(&theme)?;
+    Ok(theme.css())
+}
+
+/// Find the text a search marks, to count matches and step through them.
+///
+/// # Arguments
+/// * `input` - The text to search
+/// * `search` - Object: `{ query?: string, caseSensitive?: boolean,
+///   ranges?: [number, number][] }`, the `search` highlight option
+///
+/// # Returns
+/// * JSON string: `{ "count": number, "ranges": [[start, end]] }` with
+///   sorted UTF-8 byte ranges
+/// * Throws error string if the search object is invalid
+#[wasm_bindgen(js_name = "findMatches")]
+pub fn js_find_matches(input: &str, search: JsValue) -> Result<String, JsValue> {
+    let search: SearchOptions = from_js_options(&search)?;
+    let ranges = highlight::find_matches(input, &search);
+    to_js_json(&SearchMatches {
+        count: ranges.len(),
+        ranges,
+    })
+}
+
+/// Highlight the line differences between two versions of a document.
+///
+/// # Arguments
+/// * `old` - The original document
+/// * `new` - The changed document
+/// * `format` - `"json"` or `"xml"`
+/// * `options` - Optional object: `{ view?: "sideBySide" | "unified",
+///   theme?: "dark" | "light" | "highContrast" | object }` with the theme as in
+///   the highlight options
+///
+/// # Returns
+/// * JSON string: `{ "html": string, "added": number, "removed": number,
+///   "changed": number }` where `html` is a `<table class="hl-diff">` with
+///   rows classed `diff-equal`, `diff-added`, `diff-removed`, or `diff-changed`
+/// * Throws error string if the format or options are invalid
+#[wasm_bindgen(js_name = "highlightDiff")]
+pub fn js_highlight_diff(
+    old: &str,
+    new: &str,
+    format: &str,
+    options: JsValue,
+) -> Result<String, JsValue> {
+    let language = match format {
+        "json" => Language::Json,
+        "xml" => Language::Xml,
+        _ => {
+            return Err(JsValue::from_str(&format!(
+                "Unknown format '{}', expected \"json\" or \"xml\"",
+                format
+            )))
+        }
+    };
+    let options: DiffOptions = from_js_options(&options)?;
+    to_js_json(&diff_highlighter::highlight_diff(
+        old, new, language, &options,
+    ))
+}
+
+/// Highlight one chunk of a large JSON document, for virtual scrolling.
+///
+/// # Arguments
+/// * `chunk` - The next part of the document, ending at a line break
+/// * `state` - The `state` returned for the previous chunk; omit for the first
+/// * `options` - Optional object as for `highlightJsonWithOptions`
+///
+/// # Returns
+/// * JSON string: `{ "html": string, "state": object }` where `html` has no
+///   `<pre>` wrapper and `state` is passed back with the next chunk
+/// * Throws error string if the state or options are invalid
+#[wasm_bindgen(js_name = "highlightJsonChunk")]
+pub fn js_highlight_json_chunk(
+    chunk: &str,
+    state: JsValue,
+    options: JsValue,
+) -> Result<String, JsValue> {
+    let state: JsonHighlightState = from_js_options(&state)?;
+    let options: HighlightOptions = from_js_options(&options)?;
+    to_js_json(&highlighter::highlight_json_chunk(chunk, &state, &options))
+}
+
+/// Highlight JSON as an array of segments, for inserting a huge document
+/// into the page progressively.
+///
+/// # Arguments
+/// * `input` - The JSON string to highlight
+/// * `lines_per_segment` - Lines in each segment; at least one
+/// * `options` - Optional object as for `highlightJsonWithOptions`
+///
+/// # Returns
+/// * Array of output strings, one per segment, without a `<pre>` wrapper;
+///   line numbers continue across segments
+/// * Throws error string if the options are invalid
+#[wasm_bindgen(js_name = "highlightJsonSegments")]
+pub fn js_highlight_json_segments(
+    input: &str,
+    lines_per_segment: usize,
+    options: JsValue,
+) -> Result<Vec<String>, JsValue> {
+    let options: HighlightOptions = from_js_options(&options)?;
+    Ok(highlighter::highlight_json_segments(
+        input,
+        lines_per_segment,
+        &options,
+    ))
+}
+
+/// Find foldable objects and arrays in JSON.
+///
+/// # Arguments
+/// * `input` - The JSON string to scan
+///
+/// # Returns
+/// * JSON array string: `[{ "startLine": number, "endLine": number,
+///   "kind": "object" | "array", "summary": string }]`, sorted by start line,
+///   with summaries like `"{…} 24 keys"`
+#[wasm_bindgen(js_name = "jsonFoldRegions")]
+pub fn js_json_fold_regions(input: &str) -> Result<String, JsValue> {
+    to_js_json(&highlighter::json_fold_regions(input))
+}
+
+/// Pair each opening bracket in JSON with its closing bracket.
+///
+/// # Arguments
+/// * `input` - The JSON string to scan
+///
+/// # Returns
+/// * JSON array string: `[{ "open": [start, end], "close": [start, end] }]`
+///   with UTF-8 byte ranges, sorted by the opening position
+#[wasm_bindgen(js_name = "jsonMatchingPairs")]
+pub fn js_json_matching_pairs(input: &str) -> Result<String, JsValue> {
+    to_js_json(&highlighter::json_matching_pairs(input))
+}
+
+/// Split JSON into highlighting tokens, for editors that do their own rendering.
+///
+/// # Arguments
+/// * `input` - The JSON string to tokenize
+///
+/// # Returns
+/// * JSON array string:
+///   `[{ "kind": "key" | "string" | "number" | "boolean" | "null" | "bracket"
+///   | "punctuation", "start": number, "end": number, "line": number,
+///   "column": number, "depth": number }]` where `start`/`end` are UTF-8
+///   byte offsets, `line`/`column` are 1-based, and `depth` is the nesting
+///   level, brackets counting at the level outside them
+#[wasm_bindgen(js_name = "tokenizeJson")]
+pub fn js_tokenize_json(input: &str) -> Result<String, JsValue> {
+    to_js_json(&highlighter::tokenize_json(input))
+}
+
+/// Detect well-known string formats (dates, UUIDs, emails, IPs, URLs, base64).
+///
+/// # Arguments
+/// * `input` - The JSON string to analyze
+///
+/// # Returns
+/// * JSON string containing the report:
+///   ```json
+///   {
+///     "findings": [{ "path": string, "kind": string, "count": number }],
+///     "stringsScanned": number
+///   }
+///   ```
+/// * Throws error string if the JSON is invalid
+#[wasm_bindgen(js_name = "detectSemanticValues")]
+pub fn js_detect_semantic_values(input: &str) -> Result<String, JsValue> {
+    let report =
+        semantic::detect_semantic_values(input).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    to_js_json(&report)
+}
+
+/// Compare two JSON documents ignoring key order and formatting.
+///
+/// # Arguments
+/// * `input_a` - The first JSON string
+/// * `input_b` - The second JSON string
+/// * `options` - Optional object:
+///   `{ ignoreArrayOrder, ignoreWhitespace, floatEpsilon, maxDifferences }`
+///
+/// # Returns
+/// * JSON string: `{ "equal": boolean, "differences": [{ "path": string, "message": string }] }`
+/// * Throws error string if either document is invalid
+#[wasm_bindgen(js_name = "jsonEqual")]
+pub fn js_json_equal(input_a: &str, input_b: &str, options: JsValue) -> Result<String, JsValue> {
+    let options: compare::EqualityOptions = from_js_options(&options)?;
+    let result = compare::json_equal(input_a, input_b, &options)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    to_js_json(&result)
+}
+
+/// Generate a sample document that satisfies a JSON Schema.
+///
+/// # Arguments
+/// * `schema` - The JSON Schema string
+/// * `indent` - Indent style: "spaces:2", "spaces:4", or "tabs"
+///
+/// # Returns
+/// * Formatted sample JSON string on success
+/// * Throws error string if the schema is invalid
+#[wasm_bindgen(js_name = "generateSample")]
+pub fn js_generate_sample(schema: &str, indent: &str) -> Result<String, JsValue> {
+    let style = parse_indent_style(indent)?;
+    schema_sample::generate_sample(schema, style).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Detect encoding problems (BOMs, invalid UTF-8, lone surrogates, mixed line endings).
+///
+/// # Arguments
+/// * `input` - Raw document bytes (`Uint8Array`)
+///
+/// # Returns
+/// * JSON array string: `[{ "kind": string, "offset": number, "message": string }]`
+#[wasm_bindgen(js_name = "detectEncodingIssues")]
+pub fn js_detect_encoding_issues(input: &[u8]) -> Result<String, JsValue> {
+    to_js_json(&encoding::detect_encoding_issues(input))
+}
+
+/// Deserialize a JavaScript options object (`undefined`/`null` means defaults).
+fn from_js_options<T: serde::de::DeserializeOwned + Default>(
+    options: &JsValue,
+) -> Result<T, JsValue> {
+    if options.is_undefined() || options.is_null() {
+        return Ok(T::default());
+    }
+    let json: String = js_sys::JSON::stringify(options)?.into();
+    serde_json::from_str(&json).map_err(|e| JsValue::from_str(&format!("Invalid options: {}", e)))
+}
+
+/// Serialize a result struct to a JSON string for JavaScript.
+fn to_js_json<T: serde::Serialize>(value: &T) -> Result<String, JsValue> {
+    serde_json::to_string(value).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
 // ============================================================================
 // XML WASM Exports (Spike - Q1 Investigation)
 // ============================================================================
 
-/// Format XML with specified indentation.
+/// Format XML.
 ///
 /// # Arguments
 /// * `input` - The XML string to format
-/// * `indent` - Indent style: "spaces:2", "spaces:4", or "tabs"
+/// * `options` - Optional object:
+///   `{ indent?: "spaces:N" | "tabs", preserveMixedContent?: boolean,
+///   sortAttributes?: boolean, wrapAttributesWidth?: number,
+///   emptyElements?: "preserve" | "selfClosing" | "expanded",
+///   comments?: "preserve" | "strip", cdata?: "preserve" | "unwrap" | "wrap",
+///   stripProcessingInstructions?: boolean,
+///   newline?: "lf" | "crlf",
+///   limits?: { maxInputBytes?, maxDepth?, maxAttributes?, maxEntityExpansions? },
+///   html?: boolean }`
 ///
 /// # Returns
 /// * Formatted XML string on success
 /// * Throws error string on failure
 #[wasm_bindgen(js_name = "formatXml")]
-pub fn js_format_xml(input: &str, indent: &str) -> Result<String, JsValue> {
-    let style = parse_indent_style(indent)?;
-    xml_formatter::format_xml(input, style)
-        .map_err(|e| JsValue::from_str(&e.to_string()))
+pub fn js_format_xml(input: &str, options: JsValue) -> Result<String, JsValue> {
+    let options: XmlFormatOptions = from_js_options(&options)?;
+    xml_formatter::format_xml(input, &options).map_err(|e| JsValue::from_str(&e.to_string()))
 }
 
 /// Minify XML by removing all unnecessary whitespace.
 ///
 /// # Arguments
 /// * `input` - The XML string to minify
+/// * `options` - Optional object with the same shape as for `formatXml`;
+///   only `sortAttributes`, `emptyElements`, `comments`, `cdata`,
+///   `stripProcessingInstructions`, `limits`, and `html` apply
 ///
 /// # Returns
 /// * Minified XML string on success
 /// * Throws error string on failure
 #[wasm_bindgen(js_name = "minifyXml")]
-pub fn js_minify_xml(input: &str) -> Result<String, JsValue> {
-    xml_formatter::minify_xml(input)
-        .map_err(|e| JsValue::from_str(&e.to_string()))
+pub fn js_minify_xml(input: &str, options: JsValue) -> Result<String, JsValue> {
+    let options: XmlFormatOptions = from_js_options(&options)?;
+    xml_formatter::minify_xml(input, &options).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Format XML from raw bytes, honoring BOMs and the declared encoding
+/// (UTF-8, UTF-16LE/BE, ISO-8859-1).
+///
+/// # Arguments
+/// * `input` - Raw document bytes (`Uint8Array`)
+/// * `options` - Optional object with the same shape as for `formatXml`
+///
+/// # Returns
+/// * Formatted XML string on success
+/// * Throws error string on failure
+#[wasm_bindgen(js_name = "formatXmlBytes")]
+pub fn js_format_xml_bytes(input: &[u8], options: JsValue) -> Result<String, JsValue> {
+    let options: XmlFormatOptions = from_js_options(&options)?;
+    xml_formatter::format_xml_bytes(input, &options).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Minify XML from raw bytes, honoring BOMs and the declared encoding.
+///
+/// # Arguments
+/// * `input` - Raw document bytes (`Uint8Array`)
+/// * `options` - Optional object with the same shape as for `minifyXml`
+///
+/// # Returns
+/// * Minified XML string on success
+/// * Throws error string on failure
+#[wasm_bindgen(js_name = "minifyXmlBytes")]
+pub fn js_minify_xml_bytes(input: &[u8], options: JsValue) -> Result<String, JsValue> {
+    let options: XmlFormatOptions = from_js_options(&options)?;
+    xml_formatter::minify_xml_bytes(input, &options).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Check XML well-formedness and return statistics as JSON string.
+///
+/// # Arguments
+/// * `input` - The XML string to validate
+///
+/// # Returns
+/// * JSON string containing validation result:
+///   ```json
+///   {
+///     "isValid": boolean,
+///     "error": { "message": string, "line": number, "column": number } | null,
+///     "stats": {
+///       "elementCount": number,
+///       "attributeCount": number,
+///       "maxDepth": number,
+///       "namespaces": string[],
+///       "textBytes": number,
+///       "commentCount": number,
+///       "cdataCount": number,
+///       "elementNames": { [name: string]: number }
+///     }
+///   }
+///   ```
+#[wasm_bindgen(js_name = "validateXml")]
+pub fn js_validate_xml(input: &str) -> Result<String, JsValue> {
+    to_js_json(&validator::validate_xml(input))
+}
+
+/// Check XML namespace usage (undeclared prefixes, duplicate declarations,
+/// reserved prefixes, attribute collisions), reporting every problem.
+///
+/// # Arguments
+/// * `input` - The XML string to validate
+///
+/// # Returns
+/// * JSON string with the same shape as `validateXmlAll`
+#[wasm_bindgen(js_name = "validateXmlNamespaces")]
+pub fn js_validate_xml_namespaces(input: &str) -> Result<String, JsValue> {
+    to_js_json(&validator::validate_xml_namespaces(input))
+}
+
+/// Collect XML document statistics.
+///
+/// # Arguments
+/// * `input` - The XML string to analyze
+///
+/// # Returns
+/// * JSON string with the `stats` object described for `validateXml`
+/// * Throws error string if the XML is not well-formed
+#[wasm_bindgen(js_name = "xmlStats")]
+pub fn js_xml_stats(input: &str) -> Result<String, JsValue> {
+    let stats = validator::xml_stats(input).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    to_js_json(&stats)
+}
+
+/// Check XML well-formedness, reporting every recoverable error.
+///
+/// # Arguments
+/// * `input` - The XML string to validate
+///
+/// # Returns
+/// * JSON string with the same shape as `validateXml`, plus
+///   `"errors": [{ "message": string, "line": number, "column": number }]`
+#[wasm_bindgen(js_name = "validateXmlAll")]
+pub fn js_validate_xml_all(input: &str) -> Result<String, JsValue> {
+    to_js_json(&validator::validate_xml_all(input))
 }
 
 /// Highlight XML with syntax colors, returning HTML with inline styles.
@@ -186,3 +674,263 @@ pub fn js_minify_xml(input: &str) -> Result<String, JsValue> {
 pub fn js_highlight_xml(input: &str) -> String {
     xml_highlighter::highlight_xml(input)
 }
+
+/// Highlight XML with a chosen theme and optional line wrapping.
+///
+/// # Arguments
+/// * `input` - The XML string to highlight
+/// * `options` - Optional object:
+///   `{ theme?: "dark" | "light" | "highContrast" | { base?: "dark" | "light"
+///   | "highContrast",
+///   xml?: { tag?, attrName?, namespace?, attrValue?, text?, comment?, cdata?,
+///   declaration?, bracket?, entity? } }, lineNumbers?: boolean,
+///   lineAnchors?: boolean, search?: { query?, caseSensitive?, ranges? },
+///   output?, maxBytes? }`, as for
+///   `highlightJsonWithOptions`
+///
+/// # Returns
+/// * Highlighted output, HTML with inline styles unless `output` says otherwise
+/// * Throws error string if the options are invalid
+#[wasm_bindgen(js_name = "highlightXmlWithOptions")]
+pub fn js_highlight_xml_with_options(input: &str, options: JsValue) -> Result<String, JsValue> {
+    let options: HighlightOptions = from_js_options(&options)?;
+    Ok(xml_highlighter::highlight_xml_with_options(input, &options))
+}
+
+/// Format XML and highlight the formatted text in one call.
+///
+/// # Arguments
+/// * `input` - The XML string to format
+/// * `format_options` - Optional object as for `formatXml`
+/// * `options` - Optional object as for `highlightXmlWithOptions`
+///
+/// # Returns
+/// * JSON string: `{ "formatted": string, "html": string }`
+/// * Throws error string if the input cannot be formatted or the options are invalid
+#[wasm_bindgen(js_name = "formatAndHighlightXml")]
+pub fn js_format_and_highlight_xml(
+    input: &str,
+    format_options: JsValue,
+    options: JsValue,
+) -> Result<String, JsValue> {
+    let format_options: XmlFormatOptions = from_js_options(&format_options)?;
+    let options: HighlightOptions = from_js_options(&options)?;
+    let result = xml_highlighter::format_and_highlight_xml(input, &format_options, &options)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    to_js_json(&result)
+}
+
+/// Validate XML and highlight it with every well-formedness error marked in place.
+///
+/// # Arguments
+/// * `input` - The XML string to check
+/// * `options` - Optional object as for `highlightXmlWithOptions`
+///
+/// # Returns
+/// * JSON string: `{ "html": string, "isValid": boolean, "errors":
+///   [{ "message": string, "line": number, "column": number }] }` where the
+///   text at each error is wrapped in `<span class="hl-error" title="message">`
+/// * Throws error string if the options are invalid
+#[wasm_bindgen(js_name = "highlightXmlWithErrors")]
+pub fn js_highlight_xml_with_errors(input: &str, options: JsValue) -> Result<String, JsValue> {
+    let options: HighlightOptions = from_js_options(&options)?;
+    to_js_json(&xml_highlighter::highlight_xml_with_errors(input, &options))
+}
+
+/// Highlight one chunk of a large XML document, for virtual scrolling.
+///
+/// # Arguments
+/// * `chunk` - The next part of the document, ending at a line break
+/// * `state` - The `state` returned for the previous chunk; omit for the first
+/// * `options` - Optional object as for `highlightXmlWithOptions`
+///
+/// # Returns
+/// * JSON string: `{ "html": string, "state": object }` where `html` has no
+///   `<pre>` wrapper and `state` is passed back with the next chunk
+/// * Throws error string if the state or options are invalid
+#[wasm_bindgen(js_name = "highlightXmlChunk")]
+pub fn js_highlight_xml_chunk(
+    chunk: &str,
+    state: JsValue,
+    options: JsValue,
+) -> Result<String, JsValue> {
+    let state: XmlHighlightState = from_js_options(&state)?;
+    let options: HighlightOptions = from_js_options(&options)?;
+    to_js_json(&xml_highlighter::highlight_xml_chunk(
+        chunk, &state, &options,
+    ))
+}
+
+/// Highlight XML as an array of segments, for inserting a huge document
+/// into the page progressively.
+///
+/// # Arguments
+/// * `input` - The XML string to highlight
+/// * `lines_per_segment` - Lines in each segment; at least one
+/// * `options` - Optional object as for `highlightXmlWithOptions`
+///
+/// # Returns
+/// * Array of output strings, one per segment, without a `<pre>` wrapper;
+///   line numbers continue across segments
+/// * Throws error string if the options are invalid
+#[wasm_bindgen(js_name = "highlightXmlSegments")]
+pub fn js_highlight_xml_segments(
+    input: &str,
+    lines_per_segment: usize,
+    options: JsValue,
+) -> Result<Vec<String>, JsValue> {
+    let options: HighlightOptions = from_js_options(&options)?;
+    Ok(xml_highlighter::highlight_xml_segments(
+        input,
+        lines_per_segment,
+        &options,
+    ))
+}
+
+/// Find foldable elements, comments, and CDATA sections in XML.
+///
+/// # Arguments
+/// * `input` - The XML string to scan
+///
+/// # Returns
+/// * JSON array string: `[{ "startLine": number, "endLine": number,
+///   "kind": "element" | "comment" | "cdata", "summary": string }]`,
+///   sorted by start line, with summaries like `"<item>…</item>"`
+#[wasm_bindgen(js_name = "xmlFoldRegions")]
+pub fn js_xml_fold_regions(input: &str) -> Result<String, JsValue> {
+    to_js_json(&xml_highlighter::xml_fold_regions(input))
+}
+
+/// Pair each start tag in XML with its end tag.
+///
+/// # Arguments
+/// * `input` - The XML string to scan
+///
+/// # Returns
+/// * JSON array string: `[{ "open": [start, end], "close": [start, end] }]`
+///   with UTF-8 byte ranges covering whole tags, sorted by the opening position
+#[wasm_bindgen(js_name = "xmlMatchingPairs")]
+pub fn js_xml_matching_pairs(input: &str) -> Result<String, JsValue> {
+    to_js_json(&xml_highlighter::xml_matching_pairs(input))
+}
+
+/// Split XML into highlighting tokens, for editors that do their own rendering.
+///
+/// # Arguments
+/// * `input` - The XML string to tokenize
+///
+/// # Returns
+/// * JSON array string:
+///   `[{ "kind": "tag" | "attrName" | "namespace" | "attrValue" | "text"
+///   | "comment" | "cdata" | "declaration" | "bracket" | "entity",
+///   "start": number, "end": number,
+///   "line": number, "column": number, "depth": number }]`, positions as for
+///   `tokenizeJson` and `depth` counting open elements
+#[wasm_bindgen(js_name = "tokenizeXml")]
+pub fn js_tokenize_xml(input: &str) -> Result<String, JsValue> {
+    to_js_json(&xml_highlighter::tokenize_xml(input))
+}
+
+/// Evaluate an XPath 1.0 expression against an XML document.
+///
+/// # Arguments
+/// * `input` - The XML string to query
+/// * `expression` - The XPath expression
+///
+/// # Returns
+/// * JSON string, one of:
+///   ```json
+///   { "type": "nodeset", "matches": [{ "kind": string, "name": string | null, "value": string, "line": number, "column": number }] }
+///   { "type": "string" | "number" | "boolean", "value": ... }
+///   ```
+///   where a NaN or infinite number is the string `"NaN"`, `"Infinity"`, or `"-Infinity"`
+/// * Throws error string if the XML is malformed or the expression is invalid
+#[wasm_bindgen(js_name = "xmlQuery")]
+pub fn js_xml_query(input: &str, expression: &str) -> Result<String, JsValue> {
+    let result =
+        xml_query::xml_query(input, expression).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    to_js_json(&result)
+}
+
+/// Validate an XML document against an XSD schema.
+///
+/// # Arguments
+/// * `input` - The XML string to validate
+/// * `schema` - The XSD schema string
+///
+/// # Returns
+/// * JSON string:
+///   ```json
+///   {
+///     "isValid": boolean,
+///     "violations": [{ "path": string, "message": string, "line": number, "column": number }]
+///   }
+///   ```
+/// * Throws error string if either document is malformed or the schema is invalid
+#[wasm_bindgen(js_name = "validateXmlSchema")]
+pub fn js_validate_xml_schema(input: &str, schema: &str) -> Result<String, JsValue> {
+    let result = xml_schema::validate_xml_schema(input, schema)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    to_js_json(&result)
+}
+
+/// Canonicalize XML with Exclusive XML Canonicalization (for XML-DSig).
+///
+/// # Arguments
+/// * `input` - The XML string to canonicalize
+/// * `options` - Optional object: `{ withComments, inclusivePrefixes }`
+///
+/// # Returns
+/// * Canonical XML string on success
+/// * Throws error string on failure
+#[wasm_bindgen(js_name = "canonicalizeXml")]
+pub fn js_canonicalize_xml(input: &str, options: JsValue) -> Result<String, JsValue> {
+    let options: xml_canonical::CanonicalizationOptions = from_js_options(&options)?;
+    xml_canonical::canonicalize_xml(input, &options).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Rewrite XML so each namespace has one prefix, declared on the root element.
+///
+/// # Arguments
+/// * `input` - The XML string to rewrite
+/// * `options` - Optional object: `{ prefixes: { [uri]: prefix }, generatePrefixes }`
+///
+/// # Returns
+/// * Rewritten XML string on success
+/// * Throws error string on failure
+#[wasm_bindgen(js_name = "normalizeXmlNamespaces")]
+pub fn js_normalize_xml_namespaces(input: &str, options: JsValue) -> Result<String, JsValue> {
+    let options: xml_namespaces::NamespaceOptions = from_js_options(&options)?;
+    xml_namespaces::normalize_namespaces(input, &options)
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Move attributes matching a name pattern into child elements.
+///
+/// # Arguments
+/// * `input` - The XML string to transform
+/// * `pattern` - Comma-separated attribute names; `*` matches any run of characters
+///
+/// # Returns
+/// * Transformed XML string on success
+/// * Throws error string on failure
+#[wasm_bindgen(js_name = "xmlAttributesToElements")]
+pub fn js_xml_attributes_to_elements(input: &str, pattern: &str) -> Result<String, JsValue> {
+    xml_transform::attributes_to_elements(input, pattern)
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Move simple child elements matching a name pattern into attributes.
+///
+/// # Arguments
+/// * `input` - The XML string to transform
+/// * `pattern` - Comma-separated element names; `*` matches any run of characters
+///
+/// # Returns
+/// * Transformed XML string on success
+/// * Throws error string on failure
+#[wasm_bindgen(js_name = "xmlElementsToAttributes")]
+pub fn js_xml_elements_to_attributes(input: &str, pattern: &str) -> Result<String, JsValue> {
+    xml_transform::elements_to_attributes(input, pattern)
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
@@ -0,0 +1,579 @@
+//! Sample data generation from a JSON Schema
+//!
+//! Produces a plausible example document satisfying a schema: `const`,
+//! `enum`, `default` and `examples` are used verbatim when present,
+//! otherwise values are synthesized from `type`, `format`, and numeric/
+//! length/count bounds. Local `$ref`s (`#/definitions/...`, `#/$defs/...`)
+//! are followed, except into a definition already being expanded; `pattern`
+//! is not interpreted.
+
+use crate::formatter::format_value_to_string;
+use crate::types::{FormatError, IndentStyle, DEFAULT_MAX_INPUT_BYTES};
+use serde_json::{Map, Number, Value};
+
+/// Recursion guard for deeply nested schemas.
+const MAX_DEPTH: usize = 32;
+
+/// Most values a sample may have, so that large `minItems` or schemas
+/// that reuse definitions many times over cannot exhaust memory.
+const MAX_VALUES: usize = 100_000;
+
+/// Generate an example document for a JSON Schema.
+///
+/// # Arguments
+/// * `schema` - The JSON Schema as a string
+/// * `indent` - Indentation style for the generated document
+///
+/// # Returns
+/// * `Ok(String)` - The formatted sample document
+/// * `Err(FormatError)` - If the schema is invalid JSON, contains an unresolvable `$ref`,
+///   has integer bounds no 64-bit integer satisfies, a `minLength` above 64 MiB, or
+///   would give a sample of more than 100,000 values
+pub fn generate_sample(schema: &str, indent: IndentStyle) -> Result<String, FormatError> {
+    let root: Value = serde_json::from_str(schema)
+        .map_err(|e| FormatError::new(e.to_string(), e.line(), e.column()))?;
+    let mut sampler = Sampler {
+        root: &root,
+        refs: Vec::new(),
+        values: 0,
+    };
+    let sample = sampler.sample_for(&root, 0, 0)?;
+    Ok(format_value_to_string(&sample, indent))
+}
+
+/// State carried through one sample.
+///
+/// A `$ref` already being expanded further up is not expanded again but
+/// sampled as an empty object or array, or `null`, so each recursive
+/// property appears once.
+struct Sampler<'a> {
+    root: &'a Value,
+    /// `$ref`s being expanded, outermost first
+    refs: Vec<String>,
+    /// Values generated so far, limited to `MAX_VALUES`
+    values: usize,
+}
+
+impl<'a> Sampler<'a> {
+    /// Generate a value for `schema`; `variant` distinguishes sibling array items.
+    fn sample_for(
+        &mut self,
+        schema: &Value,
+        depth: usize,
+        variant: usize,
+    ) -> Result<Value, FormatError> {
+        self.values += 1;
+        if self.values > MAX_VALUES {
+            return Err(FormatError::new(
+                format!("Sample would have more than {} values", MAX_VALUES),
+                1,
+                1,
+            ));
+        }
+        let Value::Object(obj) = schema else {
+            // Boolean schemas (`true` accepts anything) and malformed nodes
+            return Ok(Value::Null);
+        };
+        if depth > MAX_DEPTH {
+            return Ok(Value::Null);
+        }
+
+        if let Some(Value::String(reference)) = obj.get("$ref") {
+            let target = resolve_ref(self.root, reference)?;
+            if self.refs.contains(reference) {
+                // Already being expanded further up: stop with an empty value
+                return Ok(match target.as_object().map(schema_type) {
+                    Some("object") => Value::Object(Map::new()),
+                    Some("array") => Value::Array(Vec::new()),
+                    _ => Value::Null,
+                });
+            }
+            self.refs.push(reference.clone());
+            let sample = self.sample_for(target, depth + 1, variant);
+            self.refs.pop();
+            return sample;
+        }
+        if let Some(value) = obj.get("const") {
+            return Ok(value.clone());
+        }
+        if let Some(Value::Array(values)) = obj.get("enum") {
+            if !values.is_empty() {
+                return Ok(values[variant % values.len()].clone());
+            }
+        }
+        if let Some(value) = obj.get("default") {
+            return Ok(value.clone());
+        }
+        if let Some(Value::Array(examples)) = obj.get("examples") {
+            if let Some(first) = examples.first() {
+                return Ok(first.clone());
+            }
+        }
+        if let Some(Value::Array(parts)) = obj.get("allOf") {
+            return self.sample_all_of(obj, parts, depth, variant);
+        }
+        for key in ["oneOf", "anyOf"] {
+            if let Some(Value::Array(options)) = obj.get(key) {
+                if let Some(first) = options.first() {
+                    return self.sample_for(first, depth + 1, variant);
+                }
+            }
+        }
+
+        match schema_type(obj) {
+            "object" => self.sample_object(obj, depth),
+            "array" => self.sample_array(obj, depth),
+            "string" => sample_string(obj, variant).map(Value::String),
+            "integer" => sample_integer(obj, variant).map(Value::Number),
+            "number" => Ok(Value::Number(sample_number(obj, variant))),
+            "boolean" => Ok(Value::Bool(true)),
+            _ => Ok(Value::Null),
+        }
+    }
+
+    /// Merge the samples of every `allOf` branch (plus sibling keywords).
+    fn sample_all_of(
+        &mut self,
+        obj: &Map<String, Value>,
+        parts: &[Value],
+        depth: usize,
+        variant: usize,
+    ) -> Result<Value, FormatError> {
+        let mut siblings = obj.clone();
+        siblings.remove("allOf");
+        let mut merged: Option<Value> = None;
+        for part in parts
+            .iter()
+            .chain(std::iter::once(&Value::Object(siblings)))
+        {
+            let value = self.sample_for(part, depth + 1, variant)?;
+            merged = Some(match (merged, value) {
+                (Some(Value::Object(mut acc)), Value::Object(next)) => {
+                    acc.extend(next);
+                    Value::Object(acc)
+                }
+                (None, value) | (Some(Value::Null), value) => value,
+                (Some(acc), _) => acc,
+            });
+        }
+        Ok(merged.unwrap_or(Value::Null))
+    }
+
+    fn sample_object(
+        &mut self,
+        obj: &Map<String, Value>,
+        depth: usize,
+    ) -> Result<Value, FormatError> {
+        let mut out = Map::new();
+        if let Some(Value::Object(properties)) = obj.get("properties") {
+            for (name, prop_schema) in properties {
+                out.insert(name.clone(), self.sample_for(prop_schema, depth + 1, 0)?);
+            }
+        }
+        // Required names without a property schema still need a value
+        if let Some(Value::Array(required)) = obj.get("required") {
+            for name in required.iter().filter_map(Value::as_str) {
+                if !out.contains_key(name) {
+                    let fallback = obj
+                        .get("additionalProperties")
+                        .unwrap_or(&Value::Bool(true));
+                    out.insert(name.to_string(), self.sample_for(fallback, depth + 1, 0)?);
+                }
+            }
+        }
+        Ok(Value::Object(out))
+    }
+
+    fn sample_array(
+        &mut self,
+        obj: &Map<String, Value>,
+        depth: usize,
+    ) -> Result<Value, FormatError> {
+        let min_items = get_u64(obj, "minItems").unwrap_or(0) as usize;
+        let max_items = get_u64(obj, "maxItems").map(|n| n as usize);
+
+        // Tuple validation: `prefixItems` (2020-12) or an array-valued `items` (draft 4-7)
+        let tuple = match (obj.get("prefixItems"), obj.get("items")) {
+            (Some(Value::Array(prefix)), _) | (None, Some(Value::Array(prefix))) => Some(prefix),
+            _ => None,
+        };
+        let item_schema = match obj.get("items") {
+            Some(Value::Array(_)) | None => obj.get("additionalItems"),
+            Some(schema) => Some(schema),
+        };
+
+        let mut out = Vec::new();
+        if let Some(prefix) = tuple {
+            for (i, schema) in prefix.iter().enumerate() {
+                out.push(self.sample_for(schema, depth + 1, i)?);
+            }
+        }
+        let wanted = min_items.max(if tuple.is_some() { 0 } else { 1 });
+        let wanted = max_items.map_or(wanted, |max| wanted.min(max));
+        if let Some(schema) = item_schema {
+            while out.len() < wanted {
+                out.push(self.sample_for(schema, depth + 1, out.len())?);
+            }
+        }
+        if let Some(max) = max_items {
+            out.truncate(max);
+        }
+        Ok(Value::Array(out))
+    }
+}
+
+/// Determine the schema type, inferring it from keywords when `type` is absent.
+fn schema_type(obj: &Map<String, Value>) -> &str {
+    match obj.get("type") {
+        Some(Value::String(t)) => return t,
+        Some(Value::Array(types)) => {
+            let mut names = types.iter().filter_map(Value::as_str);
+            if let Some(t) = names
+                .clone()
+                .find(|t| *t != "null")
+                .or_else(|| names.next())
+            {
+                return t;
+            }
+        }
+        _ => {}
+    }
+    if obj.contains_key("properties") || obj.contains_key("required") {
+        "object"
+    } else if obj.contains_key("items") || obj.contains_key("prefixItems") {
+        "array"
+    } else if [
+        "minimum",
+        "maximum",
+        "exclusiveMinimum",
+        "exclusiveMaximum",
+        "multipleOf",
+    ]
+    .iter()
+    .any(|k| obj.contains_key(*k))
+    {
+        "number"
+    } else if ["minLength", "maxLength", "format", "pattern"]
+        .iter()
+        .any(|k| obj.contains_key(*k))
+    {
+        "string"
+    } else {
+        "null"
+    }
+}
+
+fn resolve_ref<'a>(root: &'a Value, reference: &str) -> Result<&'a Value, FormatError> {
+    reference
+        .strip_prefix('#')
+        .and_then(|pointer| {
+            if pointer.is_empty() {
+                Some(root)
+            } else {
+                root.pointer(pointer)
+            }
+        })
+        .ok_or_else(|| FormatError::new(format!("Unresolvable $ref: {}", reference), 1, 1))
+}
+
+fn sample_string(obj: &Map<String, Value>, variant: usize) -> Result<String, FormatError> {
+    let mut s = match obj.get("format").and_then(Value::as_str) {
+        Some("date-time") => "2024-01-01T00:00:00Z".to_string(),
+        Some("date") => "2024-01-01".to_string(),
+        Some("time") => "12:00:00".to_string(),
+        Some("email") | Some("idn-email") => "user@example.com".to_string(),
+        Some("uuid") => format!("00000000-0000-4000-8000-{:012x}", variant),
+        Some("uri") | Some("url") | Some("iri") => "https://example.com".to_string(),
+        Some("hostname") | Some("idn-hostname") => "example.com".to_string(),
+        Some("ipv4") => "192.0.2.1".to_string(),
+        Some("ipv6") => "2001:db8::1".to_string(),
+        _ if variant > 0 => format!("string{}", variant),
+        _ => "string".to_string(),
+    };
+    let min_len = get_u64(obj, "minLength").unwrap_or(0);
+    if min_len > DEFAULT_MAX_INPUT_BYTES as u64 {
+        return Err(FormatError::new(
+            format!(
+                "minLength {} exceeds the limit of {} characters",
+                min_len, DEFAULT_MAX_INPUT_BYTES
+            ),
+            1,
+            1,
+        ));
+    }
+    let len = s.chars().count();
+    s.push_str(&"x".repeat((min_len as usize).saturating_sub(len)));
+    if let Some(max) = get_u64(obj, "maxLength") {
+        s = s
+            .chars()
+            .take(max.try_into().unwrap_or(usize::MAX))
+            .collect();
+    }
+    Ok(s)
+}
+
+fn sample_integer(obj: &Map<String, Value>, variant: usize) -> Result<Number, FormatError> {
+    let (lo, hi) = numeric_bounds(obj, true);
+    // Bounds past the i64 range would saturate and look satisfied
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+    if lo.is_some_and(|v| v >= LIMIT) || hi.is_some_and(|v| v < -LIMIT) {
+        return Err(FormatError::new(
+            "Integer bounds are outside the 64-bit range",
+            1,
+            1,
+        ));
+    }
+    let lo = lo.map(|v| v.ceil() as i64);
+    let hi = hi.map(|v| v.floor() as i64);
+    let step = obj
+        .get("multipleOf")
+        .and_then(Value::as_f64)
+        .filter(|m| *m >= 1.0 && m.fract() == 0.0)
+        .map_or(1, |m| m as i64);
+
+    let offset = i64::try_from(variant)
+        .unwrap_or(i64::MAX)
+        .saturating_mul(step);
+    let mut value = lo
+        .unwrap_or(0)
+        .max(0)
+        .min(hi.unwrap_or(i64::MAX))
+        .saturating_add(offset);
+    if let Some(hi) = hi {
+        value = value.min(hi);
+    }
+    // Round up to the next multiple, falling back down if that overshoots
+    let rem = value.rem_euclid(step);
+    let value = match value.checked_add(step - rem) {
+        _ if rem == 0 => Some(value),
+        Some(up) if hi.is_none_or(|h| up <= h) => Some(up),
+        _ => value.checked_sub(rem),
+    };
+    match value {
+        Some(value) if lo.is_none_or(|l| value >= l) => Ok(Number::from(value)),
+        _ => Err(FormatError::new(
+            format!("No multiple of {} within the integer bounds", step),
+            1,
+            1,
+        )),
+    }
+}
+
+fn sample_number(obj: &Map<String, Value>, variant: usize) -> Number {
+    let (lo, hi) = numeric_bounds(obj, false);
+    let mut value = match (lo, hi) {
+        (Some(lo), Some(hi)) => lo + (hi - lo) / 2.0,
+        (Some(lo), None) => lo.max(0.0),
+        (None, Some(hi)) => hi.min(0.0),
+        (None, None) => 0.0,
+    } + variant as f64;
+    if let Some(hi) = hi {
+        value = value.min(hi);
+    }
+    if let Some(step) = obj
+        .get("multipleOf")
+        .and_then(Value::as_f64)
+        .filter(|m| *m > 0.0)
+    {
+        value = (value / step).ceil() * step;
+    }
+    Number::from_f64(value).unwrap_or_else(|| Number::from(0))
+}
+
+/// Inclusive bounds implied by minimum/maximum and their exclusive forms.
+///
+/// Handles both the draft 6+ numeric form and the draft 4 boolean flags.
+/// For integers an exclusive bound moves by one; for numbers by a small step.
+fn numeric_bounds(obj: &Map<String, Value>, integer: bool) -> (Option<f64>, Option<f64>) {
+    let nudge = if integer { 1.0 } else { 0.001 };
+    let mut lo = obj.get("minimum").and_then(Value::as_f64);
+    let mut hi = obj.get("maximum").and_then(Value::as_f64);
+    match obj.get("exclusiveMinimum") {
+        Some(Value::Bool(true)) => lo = lo.map(|v| v + nudge),
+        Some(v) => {
+            if let Some(v) = v.as_f64() {
+                lo = Some(lo.map_or(v + nudge, |l| l.max(v + nudge)));
+            }
+        }
+        None => {}
+    }
+    match obj.get("exclusiveMaximum") {
+        Some(Value::Bool(true)) => hi = hi.map(|v| v - nudge),
+        Some(v) => {
+            if let Some(v) = v.as_f64() {
+                hi = Some(hi.map_or(v - nudge, |h| h.min(v - nudge)));
+            }
+        }
+        None => {}
+    }
+    (lo, hi)
+}
+
+fn get_u64(obj: &Map<String, Value>, key: &str) -> Option<u64> {
+    obj.get(key).and_then(Value::as_u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(schema: &str) -> Value {
+        let out = generate_sample(schema, IndentStyle::Spaces(2)).unwrap();
+        serde_json::from_str(&out).unwrap()
+    }
+
+    #[test]
+    fn test_object_with_formats() {
+        let value = sample(
+            r#"{"type":"object","properties":{
+                "id":{"type":"string","format":"uuid"},
+                "email":{"type":"string","format":"email"},
+                "created":{"type":"string","format":"date-time"},
+                "active":{"type":"boolean"}
+            }}"#,
+        );
+        assert_eq!(value["email"], "user@example.com");
+        assert_eq!(value["created"], "2024-01-01T00:00:00Z");
+        assert_eq!(value["active"], true);
+        assert_eq!(value["id"].as_str().unwrap().len(), 36);
+    }
+
+    #[test]
+    fn test_enum_const_and_default() {
+        let value = sample(
+            r#"{"properties":{
+                "color":{"enum":["red","green"]},
+                "version":{"const":2},
+                "level":{"type":"string","default":"info"}
+            }}"#,
+        );
+        assert_eq!(value["color"], "red");
+        assert_eq!(value["version"], 2);
+        assert_eq!(value["level"], "info");
+    }
+
+    #[test]
+    fn test_numeric_bounds() {
+        let value = sample(
+            r#"{"properties":{
+                "age":{"type":"integer","minimum":18,"maximum":99},
+                "score":{"type":"number","exclusiveMinimum":0,"maximum":1},
+                "even":{"type":"integer","minimum":3,"multipleOf":2},
+                "neg":{"type":"integer","maximum":-5}
+            }}"#,
+        );
+        assert_eq!(value["age"], 18);
+        let score = value["score"].as_f64().unwrap();
+        assert!(score > 0.0 && score <= 1.0);
+        assert_eq!(value["even"], 4);
+        assert_eq!(value["neg"], -5);
+    }
+
+    #[test]
+    fn test_integer_bounds_near_limit() {
+        let value = sample(r#"{"type":"integer","minimum":9223372036854775000,"multipleOf":1000}"#);
+        assert_eq!(value, serde_json::json!(9223372036854775000i64));
+        let value = sample(r#"{"type":"integer","maximum":-9223372036854775000}"#);
+        assert_eq!(value, serde_json::json!(-9223372036854774784i64));
+        // Later array items step past i64::MAX and fall back to the last multiple
+        let value = sample(
+            r#"{"type":"array","minItems":3,"items":{"type":"integer","minimum":9223372036854775000,"multipleOf":1000}}"#,
+        );
+        assert_eq!(value[2], serde_json::json!(9223372036854775000i64));
+        for schema in [
+            r#"{"type":"integer","minimum":9223372036854775801,"multipleOf":10}"#,
+            r#"{"type":"integer","minimum":1e300}"#,
+            r#"{"type":"integer","maximum":-1e300}"#,
+            r#"{"type":"integer","minimum":9223372036854775000,"multipleOf":100000}"#,
+        ] {
+            assert!(
+                generate_sample(schema, IndentStyle::Spaces(2)).is_err(),
+                "{}",
+                schema
+            );
+        }
+    }
+
+    #[test]
+    fn test_string_lengths_and_arrays() {
+        let value = sample(
+            r#"{"properties":{
+                "code":{"type":"string","minLength":10,"maxLength":12},
+                "tags":{"type":"array","items":{"type":"string"},"minItems":3,"maxItems":5},
+                "none":{"type":"array","items":{"type":"integer"},"maxItems":0}
+            }}"#,
+        );
+        let code = value["code"].as_str().unwrap();
+        assert!(code.len() >= 10 && code.len() <= 12);
+        assert_eq!(value["tags"].as_array().unwrap().len(), 3);
+        assert_eq!(value["tags"][1], "string1");
+        assert!(value["none"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_long_and_oversized_strings() {
+        let value = sample(r#"{"type":"string","minLength":200000}"#);
+        assert_eq!(value.as_str().unwrap().len(), 200000);
+        let err =
+            generate_sample(r#"{"minLength":10000000000}"#, IndentStyle::Spaces(2)).unwrap_err();
+        assert!(err.message.contains("minLength"));
+    }
+
+    #[test]
+    fn test_refs_and_composition() {
+        let value = sample(
+            r##"{
+                "$defs":{"point":{"type":"object","properties":{"x":{"type":"integer"}}}},
+                "type":"object",
+                "properties":{
+                    "origin":{"$ref":"#/$defs/point"},
+                    "tagged":{"allOf":[{"$ref":"#/$defs/point"},{"properties":{"tag":{"type":"string"}}}]},
+                    "either":{"oneOf":[{"type":"null"},{"type":"string"}]}
+                }
+            }"##,
+        );
+        assert_eq!(value["origin"]["x"], 0);
+        assert_eq!(value["tagged"]["x"], 0);
+        assert_eq!(value["tagged"]["tag"], "string");
+        assert!(value["either"].is_null());
+    }
+
+    #[test]
+    fn test_recursive_schema_terminates() {
+        let value = sample(r##"{"type":"object","properties":{"child":{"$ref":"#"}}}"##);
+        assert!(value["child"]["child"].is_object());
+    }
+
+    #[test]
+    fn test_recursive_properties_expand_once() {
+        let schema = r##"{
+            "$defs":{"node":{"type":"object","properties":{
+                "left":{"$ref":"#/$defs/node"},
+                "right":{"$ref":"#/$defs/node"},
+                "parent":{"$ref":"#/$defs/node"},
+                "children":{"type":"array","items":{"$ref":"#/$defs/node"},"minItems":2},
+                "list":{"$ref":"#/$defs/list"}
+            }},
+            "list":{"type":"array","items":{"$ref":"#/$defs/list"}}},
+            "$ref":"#/$defs/node"
+        }"##;
+        let value = sample(schema);
+        assert_eq!(value["left"], serde_json::json!({}));
+        assert_eq!(value["children"], serde_json::json!([{}, {}]));
+        assert_eq!(value["list"], serde_json::json!([[]]));
+    }
+
+    #[test]
+    fn test_sample_size_limit() {
+        let schema = r#"{"type":"array","minItems":1000,"items":{"type":"array","minItems":1000,"items":{"type":"integer"}}}"#;
+        let err = generate_sample(schema, IndentStyle::Spaces(2)).unwrap_err();
+        assert!(err.message.contains("more than 100000 values"));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(generate_sample("{bad", IndentStyle::Spaces(2)).is_err());
+        let err = generate_sample(r##"{"$ref":"#/missing"}"##, IndentStyle::Spaces(2)).unwrap_err();
+        assert!(err.message.contains("#/missing"));
+    }
+}
@@ -0,0 +1,364 @@
+//! Semantic value detection
+//!
+//! Scans the string values of a JSON document for well-known formats
+//! (dates, UUIDs, emails, IP addresses, URLs, base64 blobs) and reports
+//! where they occur. Detection is heuristic and purely local.
+
+use crate::types::FormatError;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// Index into `SemanticReport::findings` by path and kind.
+type FindingIndex = HashMap<(String, SemanticKind), usize>;
+
+/// Well-known string formats recognized by the detector.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SemanticKind {
+    Iso8601,
+    Uuid,
+    Email,
+    Ipv4,
+    Ipv6,
+    Url,
+    Base64,
+}
+
+/// Occurrences of one semantic kind at one generalized path.
+///
+/// Array indices in `path` are replaced with `*`, so all elements of an
+/// array share a single finding.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SemanticFinding {
+    pub path: String,
+    pub kind: SemanticKind,
+    pub count: usize,
+}
+
+/// Result of a semantic detection pass.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticReport {
+    /// Findings ordered by first occurrence, visiting object members in
+    /// key order rather than as written
+    pub findings: Vec<SemanticFinding>,
+    /// Total number of string values inspected
+    pub strings_scanned: usize,
+}
+
+impl SemanticReport {
+    /// Total number of values detected as `kind` across all paths.
+    pub fn count_of(&self, kind: SemanticKind) -> usize {
+        self.findings
+            .iter()
+            .filter(|f| f.kind == kind)
+            .map(|f| f.count)
+            .sum()
+    }
+}
+
+/// Detect well-known formats among the string values of a JSON document.
+///
+/// # Arguments
+/// * `input` - The JSON string to analyze
+///
+/// # Returns
+/// * `Ok(SemanticReport)` - Findings grouped by path and kind
+/// * `Err(FormatError)` - Error with line/column position if JSON is invalid
+pub fn detect_semantic_values(input: &str) -> Result<SemanticReport, FormatError> {
+    let value: Value = serde_json::from_str(input)
+        .map_err(|e| FormatError::new(e.to_string(), e.line(), e.column()))?;
+
+    let mut report = SemanticReport::default();
+    let mut path = String::new();
+    scan_value(&value, &mut path, &mut report, &mut FindingIndex::new());
+    Ok(report)
+}
+
+/// Classify a single string value, returning `None` if no format matches.
+pub fn classify_string(s: &str) -> Option<SemanticKind> {
+    if is_uuid(s) {
+        Some(SemanticKind::Uuid)
+    } else if is_iso8601(s) {
+        Some(SemanticKind::Iso8601)
+    } else if s.parse::<Ipv4Addr>().is_ok() {
+        Some(SemanticKind::Ipv4)
+    } else if s.contains(':') && s.parse::<Ipv6Addr>().is_ok() {
+        Some(SemanticKind::Ipv6)
+    } else if is_url(s) {
+        Some(SemanticKind::Url)
+    } else if is_email(s) {
+        Some(SemanticKind::Email)
+    } else if is_base64(s) {
+        Some(SemanticKind::Base64)
+    } else {
+        None
+    }
+}
+
+/// Recursively walk the value tree, recording findings.
+fn scan_value(
+    value: &Value,
+    path: &mut String,
+    report: &mut SemanticReport,
+    index: &mut FindingIndex,
+) {
+    match value {
+        Value::String(s) => {
+            report.strings_scanned += 1;
+            if let Some(kind) = classify_string(s) {
+                record(report, index, path, kind);
+            }
+        }
+        Value::Array(arr) => {
+            let len = path.len();
+            path.push_str("/*");
+            for item in arr {
+                scan_value(item, path, report, index);
+            }
+            path.truncate(len);
+        }
+        Value::Object(map) => {
+            for (key, val) in map {
+                let len = path.len();
+                push_pointer_token(path, key);
+                scan_value(val, path, report, index);
+                path.truncate(len);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Increment the count for (path, kind), creating the finding if needed.
+fn record(report: &mut SemanticReport, index: &mut FindingIndex, path: &str, kind: SemanticKind) {
+    let findings = &mut report.findings;
+    let i = *index.entry((path.to_string(), kind)).or_insert_with(|| {
+        findings.push(SemanticFinding {
+            path: path.to_string(),
+            kind,
+            count: 0,
+        });
+        findings.len() - 1
+    });
+    findings[i].count += 1;
+}
+
+/// Append a JSON Pointer reference token (RFC 6901), escaping `~` and `/`.
+pub(crate) fn push_pointer_token(path: &mut String, token: &str) {
+    path.push('/');
+    for c in token.chars() {
+        match c {
+            '~' => path.push_str("~0"),
+            '/' => path.push_str("~1"),
+            c => path.push(c),
+        }
+    }
+}
+
+fn is_uuid(s: &str) -> bool {
+    let bytes = s.as_bytes();
+    if bytes.len() != 36 {
+        return false;
+    }
+    bytes.iter().enumerate().all(|(i, &b)| match i {
+        8 | 13 | 18 | 23 => b == b'-',
+        _ => b.is_ascii_hexdigit(),
+    })
+}
+
+/// Match `YYYY-MM-DD` optionally followed by `THH:MM[:SS[.fff]]` and a zone.
+fn is_iso8601(s: &str) -> bool {
+    let b = s.as_bytes();
+    if b.len() < 10 || !all_digits(&b[0..4]) || b[4] != b'-' || b[7] != b'-' {
+        return false;
+    }
+    if !in_range(&b[5..7], 1, 12) || !in_range(&b[8..10], 1, 31) {
+        return false;
+    }
+    if b.len() == 10 {
+        return true;
+    }
+    if b[10] != b'T' && b[10] != b't' && b[10] != b' ' {
+        return false;
+    }
+    let mut rest = &b[11..];
+    if rest.len() < 5
+        || !in_range(&rest[0..2], 0, 23)
+        || rest[2] != b':'
+        || !in_range(&rest[3..5], 0, 59)
+    {
+        return false;
+    }
+    rest = &rest[5..];
+    if rest.len() >= 3 && rest[0] == b':' {
+        if !in_range(&rest[1..3], 0, 60) {
+            return false;
+        }
+        rest = &rest[3..];
+        if rest.first() == Some(&b'.') {
+            let frac = rest[1..].iter().take_while(|c| c.is_ascii_digit()).count();
+            if frac == 0 {
+                return false;
+            }
+            rest = &rest[1 + frac..];
+        }
+    }
+    match rest {
+        [] | [b'Z'] | [b'z'] => true,
+        [b'+' | b'-', h1, h2, b':', m1, m2] => {
+            in_range(&[*h1, *h2], 0, 23) && in_range(&[*m1, *m2], 0, 59)
+        }
+        _ => false,
+    }
+}
+
+fn is_url(s: &str) -> bool {
+    let Some((scheme, rest)) = s.split_once("://") else {
+        return false;
+    };
+    let mut chars = scheme.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        && !rest.is_empty()
+        && !rest.starts_with('/')
+        && !s.chars().any(char::is_whitespace)
+}
+
+fn is_email(s: &str) -> bool {
+    let Some((local, domain)) = s.split_once('@') else {
+        return false;
+    };
+    if local.is_empty() || local.len() > 64 || domain.contains('@') {
+        return false;
+    }
+    let local_ok = local
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-/=?^_`{|}~.".contains(c))
+        && !local.starts_with('.')
+        && !local.ends_with('.');
+    let labels: Vec<&str> = domain.split('.').collect();
+    let domain_ok = labels.len() >= 2
+        && labels.iter().all(|l| {
+            !l.is_empty()
+                && !l.starts_with('-')
+                && !l.ends_with('-')
+                && l.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+        && labels
+            .last()
+            .is_some_and(|tld| tld.len() >= 2 && tld.chars().all(|c| c.is_ascii_alphabetic()));
+    local_ok && domain_ok
+}
+
+/// Heuristic base64 check: long enough, valid alphabet and padding, and
+/// mixing character classes so plain words are not reported.
+fn is_base64(s: &str) -> bool {
+    const MIN_LEN: usize = 16;
+    let body = s.trim_end_matches('=');
+    let padding = s.len() - body.len();
+    if s.len() < MIN_LEN || padding > 2 || (padding > 0 && !s.len().is_multiple_of(4)) {
+        return false;
+    }
+    let standard = body
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/');
+    let url_safe = body
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    if !standard && !url_safe {
+        return false;
+    }
+    if body.len() % 4 == 1 {
+        return false;
+    }
+    let has_upper = body.bytes().any(|b| b.is_ascii_uppercase());
+    let has_lower = body.bytes().any(|b| b.is_ascii_lowercase());
+    let has_digit = body.bytes().any(|b| b.is_ascii_digit());
+    has_upper && has_lower && (has_digit || padding > 0)
+}
+
+fn all_digits(b: &[u8]) -> bool {
+    b.iter().all(u8::is_ascii_digit)
+}
+
+fn in_range(b: &[u8], min: u32, max: u32) -> bool {
+    if !all_digits(b) {
+        return false;
+    }
+    let n = b.iter().fold(0u32, |acc, d| acc * 10 + (d - b'0') as u32);
+    (min..=max).contains(&n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_formats() {
+        assert_eq!(classify_string("2024-01-15"), Some(SemanticKind::Iso8601));
+        assert_eq!(
+            classify_string("2024-01-15T10:30:00.123+02:00"),
+            Some(SemanticKind::Iso8601)
+        );
+        assert_eq!(
+            classify_string("550e8400-e29b-41d4-a716-446655440000"),
+            Some(SemanticKind::Uuid)
+        );
+        assert_eq!(
+            classify_string("alice@example.com"),
+            Some(SemanticKind::Email)
+        );
+        assert_eq!(classify_string("192.168.0.1"), Some(SemanticKind::Ipv4));
+        assert_eq!(classify_string("fe80::1"), Some(SemanticKind::Ipv6));
+        assert_eq!(
+            classify_string("https://example.com/a?b=c"),
+            Some(SemanticKind::Url)
+        );
+        assert_eq!(
+            classify_string("U2VjcmV0S2V5VmFsdWUxMjM0NTY="),
+            Some(SemanticKind::Base64)
+        );
+    }
+
+    #[test]
+    fn test_classify_rejects_plain_values() {
+        assert_eq!(classify_string("hello world"), None);
+        assert_eq!(classify_string("2024-13-01"), None);
+        assert_eq!(classify_string("256.1.1.1"), None);
+        assert_eq!(classify_string("not@an@email"), None);
+        assert_eq!(classify_string("abcdefghijklmnopqrstuvwx"), None);
+        assert_eq!(classify_string("12:30"), None);
+    }
+
+    #[test]
+    fn test_report_groups_array_elements() {
+        let input = r#"{"users":[{"email":"a@x.io"},{"email":"b@x.io"}],"id":"550e8400-e29b-41d4-a716-446655440000"}"#;
+        let report = detect_semantic_values(input).unwrap();
+        assert_eq!(report.strings_scanned, 3);
+        let emails = report
+            .findings
+            .iter()
+            .find(|f| f.path == "/users/*/email")
+            .unwrap();
+        assert_eq!(emails.kind, SemanticKind::Email);
+        assert_eq!(emails.count, 2);
+        assert_eq!(report.count_of(SemanticKind::Uuid), 1);
+        // Members are visited in key order, so "id" comes before "users"
+        let paths: Vec<&str> = report.findings.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, ["/id", "/users/*/email"]);
+    }
+
+    #[test]
+    fn test_pointer_escaping() {
+        let report = detect_semantic_values(r#"{"a/b":{"c~d":"10.0.0.1"}}"#).unwrap();
+        assert_eq!(report.findings[0].path, "/a~1b/c~0d");
+    }
+
+    #[test]
+    fn test_invalid_json() {
+        assert!(detect_semantic_values("{invalid}").is_err());
+    }
+}
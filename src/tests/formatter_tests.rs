@@ -1,4 +1,7 @@
 use crate::formatter::format_json;
+use crate::highlight::HighlightOptions;
+use crate::highlighter::highlight_json_with_options;
+use crate::xml_highlighter::highlight_xml_with_options;
 use crate::types::IndentStyle;
 use crate::greet;
 use std::time::Instant;
@@ -186,6 +189,32 @@ fn test_deeply_nested_structure() {
     assert!(lines.len() > 5);
 }
 
+#[test]
+fn test_nesting_beyond_parser_limit_is_an_error() {
+    // serde_json stops at 128 levels, which bounds the recursive formatter
+    let input = format!("{}{}", "[".repeat(10_000), "]".repeat(10_000));
+    let err = format_json(&input, IndentStyle::Spaces(2)).unwrap_err();
+    assert!(err.message.contains("recursion limit"));
+}
+
+#[test]
+fn test_deep_xml_formats_without_recursion() {
+    use crate::xml_formatter::{format_xml, minify_xml, XmlFormatOptions, XmlLimits};
+
+    let depth = 20_000;
+    let input = format!("{}{}", "<a>".repeat(depth), "</a>".repeat(depth));
+    let options = XmlFormatOptions {
+        limits: XmlLimits {
+            max_depth: depth,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    assert_eq!(minify_xml(&input, &options).unwrap(), input);
+    // Past the configured depth limit the formatter reports an error
+    assert!(format_xml(&input, &XmlFormatOptions::default()).is_err());
+}
+
 #[test]
 #[ignore] // Run with: cargo test --release -- --ignored
 fn test_performance_1mb_json() {
@@ -221,3 +250,59 @@ fn test_performance_1mb_json() {
 
     println!("Performance test: {}KB formatted in {}ms", size_kb, duration_ms);
 }
+
+#[test]
+#[ignore] // Run with: cargo test --release -- --ignored
+fn test_performance_1mb_highlight() {
+    let mut json = Vec::new();
+    let mut xml = String::from("<items>\n");
+    for i in 0..10000 {
+        json.push(format!(
+            r#"{{"id":{},"name":"Item {}","description":"A longer description for item {} &amp; more","active":{}}}"#,
+            i, i, i, i % 2 == 0
+        ));
+        xml.push_str(&format!(
+            "  <item id=\"{}\" active=\"{}\">\n    <name>Item {}</name>\n    <!-- note -->\n    <description>A longer description for item {} &amp; more</description>\n  </item>\n",
+            i, i % 2 == 0, i, i
+        ));
+    }
+    let json = format!("[\n{}\n]", json.join(",\n"));
+    xml.push_str("</items>\n");
+    let options = HighlightOptions {
+        line_numbers: true,
+        ..Default::default()
+    };
+
+    for (name, input, highlight) in [
+        (
+            "JSON",
+            &json,
+            highlight_json_with_options as fn(&str, &HighlightOptions) -> String,
+        ),
+        ("XML", &xml, highlight_xml_with_options),
+    ] {
+        let size_kb = input.len() / 1024;
+        assert!(
+            size_kb >= 900,
+            "Generated {} should be at least 900KB, got {}KB",
+            name,
+            size_kb
+        );
+
+        let start = Instant::now();
+        let result = highlight(input, &options);
+        let duration_ms = start.elapsed().as_millis();
+
+        assert!(result.ends_with("</pre>"));
+        assert!(
+            duration_ms < 100,
+            "{} highlight should complete in <100ms, took {}ms",
+            name,
+            duration_ms
+        );
+        println!(
+            "Performance test: {}KB of {} highlighted in {}ms",
+            size_kb, name, duration_ms
+        );
+    }
+}
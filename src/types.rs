@@ -1,3 +1,5 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::fmt;
 
 /// Indentation style for JSON formatting.
@@ -25,8 +27,40 @@ impl IndentStyle {
     }
 }
 
+impl std::str::FromStr for IndentStyle {
+    type Err = String;
+
+    /// Parse `"spaces:N"` or `"tabs"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tabs" => Ok(IndentStyle::Tabs),
+            s if s.starts_with("spaces:") => s
+                .strip_prefix("spaces:")
+                .and_then(|n| n.parse::<u8>().ok())
+                .map(IndentStyle::Spaces)
+                .ok_or_else(|| "Invalid indent format. Use 'spaces:N' or 'tabs'".to_string()),
+            _ => Err("Invalid indent format. Use 'spaces:2', 'spaces:4', or 'tabs'".to_string()),
+        }
+    }
+}
+
+/// Largest input XML operations accept by default, in bytes.
+pub const DEFAULT_MAX_INPUT_BYTES: usize = 64 * 1024 * 1024;
+
+/// Fail fast when an input of `len` bytes is larger than `max`.
+pub fn check_input_size(len: usize, max: usize) -> Result<(), FormatError> {
+    if len > max {
+        return Err(FormatError::new(
+            format!("Input is {} bytes, more than the limit of {}", len, max),
+            1,
+            1,
+        ));
+    }
+    Ok(())
+}
+
 /// Error that occurs during JSON formatting or parsing.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub struct FormatError {
     pub message: String,
     pub line: usize,
@@ -41,6 +75,21 @@ impl FormatError {
             column,
         }
     }
+
+    /// Create an error positioned at a byte offset into `input`.
+    ///
+    /// Lines and columns are 1-based; columns count characters, not bytes.
+    pub fn at_offset(message: impl Into<String>, input: &str, offset: usize) -> Self {
+        let mut offset = offset.min(input.len());
+        while !input.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let before = &input[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let column = before[line_start..].chars().count() + 1;
+        Self::new(message, line, column)
+    }
 }
 
 impl fmt::Display for FormatError {
@@ -96,6 +145,62 @@ impl ValidationResult {
     }
 }
 
+/// Statistics about an XML document's structure.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct XmlStats {
+    pub element_count: usize,
+    pub attribute_count: usize,
+    pub max_depth: usize,
+    /// Distinct namespace URIs declared via `xmlns` / `xmlns:prefix`, in document order
+    pub namespaces: Vec<String>,
+    /// Bytes of text and CDATA content, excluding whitespace-only text between elements
+    pub text_bytes: usize,
+    pub comment_count: usize,
+    pub cdata_count: usize,
+    /// Number of elements with each qualified name
+    pub element_names: BTreeMap<String, usize>,
+}
+
+/// Result of checking an XML document for well-formedness.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct XmlValidationResult {
+    pub is_valid: bool,
+    /// The first error (same as `errors[0]`)
+    pub error: Option<FormatError>,
+    /// Every error found; more than one only in multi-error mode
+    pub errors: Vec<FormatError>,
+    pub stats: XmlStats,
+}
+
+impl XmlValidationResult {
+    /// Create a validation result for well-formed XML.
+    pub fn valid(stats: XmlStats) -> Self {
+        Self {
+            is_valid: true,
+            error: None,
+            errors: Vec::new(),
+            stats,
+        }
+    }
+
+    /// Create a validation result for malformed XML.
+    pub fn invalid(error: FormatError) -> Self {
+        Self::with_errors(vec![error])
+    }
+
+    /// Create a validation result for malformed XML with one or more errors.
+    pub fn with_errors(errors: Vec<FormatError>) -> Self {
+        Self {
+            is_valid: errors.is_empty(),
+            error: errors.first().cloned(),
+            errors,
+            stats: XmlStats::default(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -126,6 +231,15 @@ mod tests {
         assert_eq!(err.column, 2);
     }
 
+    #[test]
+    fn test_format_error_at_offset() {
+        let input = "<a>\n  <b>é</c>\n</a>";
+        let err = FormatError::at_offset("mismatch", input, input.find("</c>").unwrap());
+        assert_eq!(err.line, 2);
+        assert_eq!(err.column, 7);
+        assert_eq!(FormatError::at_offset("start", input, 0).column, 1);
+    }
+
     #[test]
     fn test_json_stats_default() {
         let stats = JsonStats::default();
@@ -1,4 +1,11 @@
-use crate::types::{FormatError, JsonStats, ValidationResult};
+use crate::types::{
+    check_input_size, FormatError, JsonStats, ValidationResult, XmlStats, XmlValidationResult,
+    DEFAULT_MAX_INPUT_BYTES,
+};
+use crate::xml_formatter::declared_entities;
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
 use serde_json::Value;
 
 /// Validate JSON and return statistics about its structure.
@@ -48,6 +55,410 @@ fn collect_stats(value: &Value, depth: usize, stats: &mut JsonStats) {
     }
 }
 
+/// Check XML well-formedness and return statistics about its structure.
+///
+/// Beyond what the parser reports, this checks that every element is
+/// closed and that there is exactly one root element with no text outside it.
+///
+/// # Arguments
+/// * `input` - The XML string to validate
+///
+/// # Returns
+/// * `XmlValidationResult` containing validity status, error info (if invalid), and statistics
+pub fn validate_xml(input: &str) -> XmlValidationResult {
+    XmlChecker::new(input, false).run()
+}
+
+/// Check XML well-formedness, continuing past recoverable errors.
+///
+/// Mismatched or unmatched end tags, bad attributes, invalid text, extra
+/// root elements and unclosed elements are all reported; only a syntax
+/// error that stops the parser ends the scan early.
+///
+/// # Arguments
+/// * `input` - The XML string to validate
+///
+/// # Returns
+/// * `XmlValidationResult` whose `errors` lists every issue in document order
+///   (`error` is the first of them)
+pub fn validate_xml_all(input: &str) -> XmlValidationResult {
+    XmlChecker::new(input, true).run()
+}
+
+/// Collect statistics about a well-formed XML document.
+///
+/// # Arguments
+/// * `input` - The XML string to analyze
+///
+/// # Returns
+/// * `Ok(XmlStats)` - Counts of elements, attributes, comments, and CDATA
+///   sections, nesting depth, text size, element name frequencies, and
+///   declared namespaces
+/// * `Err(FormatError)` - The first well-formedness error
+pub fn xml_stats(input: &str) -> Result<XmlStats, FormatError> {
+    let result = validate_xml(input);
+    match result.error {
+        Some(error) => Err(error),
+        None => Ok(result.stats),
+    }
+}
+
+/// Check namespace usage, reporting every problem found.
+///
+/// Flags what quick-xml accepts but namespace-aware parsers reject:
+/// element and attribute prefixes with no declaration in scope, a prefix
+/// declared twice on one element, misuse of the reserved `xml` and `xmlns`
+/// prefixes, prefix undeclarations (`xmlns:p=""`), and attributes whose
+/// names differ only in prefixes bound to the same namespace. A parse error
+/// ends the scan; other well-formedness problems are left to [`validate_xml`].
+///
+/// # Arguments
+/// * `input` - The XML string to validate
+///
+/// # Returns
+/// * `XmlValidationResult` whose `errors` lists every namespace problem in
+///   document order; statistics are included when there are none
+pub fn validate_xml_namespaces(input: &str) -> XmlValidationResult {
+    let errors = namespace_errors(input);
+    if errors.is_empty() {
+        XmlValidationResult::valid(validate_xml(input).stats)
+    } else {
+        XmlValidationResult::with_errors(errors)
+    }
+}
+
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+const XMLNS_NAMESPACE: &str = "http://www.w3.org/2000/xmlns/";
+
+fn namespace_errors(input: &str) -> Vec<FormatError> {
+    let mut errors = Vec::new();
+    if input.trim().is_empty() {
+        errors.push(FormatError::new("Empty input", 1, 1));
+        return errors;
+    }
+    if let Err(error) = check_input_size(input.len(), DEFAULT_MAX_INPUT_BYTES) {
+        errors.push(error);
+        return errors;
+    }
+
+    let mut reader = Reader::from_str(input);
+    reader.config_mut().check_end_names = false;
+    reader.config_mut().allow_unmatched_ends = true;
+    // Prefix bindings declared by each open element
+    let mut scopes: Vec<Vec<(String, String)>> = Vec::new();
+    loop {
+        let start = reader.buffer_position() as usize;
+        let event = match reader.read_event() {
+            Ok(event) => event,
+            Err(e) => {
+                let offset = reader.error_position() as usize;
+                errors.push(FormatError::at_offset(
+                    format!("XML parse error: {}", e),
+                    input,
+                    offset,
+                ));
+                break;
+            }
+        };
+        let mut report =
+            |message: String| errors.push(FormatError::at_offset(message, input, start));
+        match &event {
+            Event::Start(e) | Event::Empty(e) => {
+                let is_start = matches!(event, Event::Start(_));
+                let mut declared: Vec<(String, String)> = Vec::new();
+                let mut attributes = Vec::new();
+                for attr in e.attributes().with_checks(false).flatten() {
+                    let key = String::from_utf8_lossy(attr.key.as_ref()).into_owned();
+                    let value = String::from_utf8_lossy(&attr.value).into_owned();
+                    let prefix = match key.strip_prefix("xmlns") {
+                        Some("") => "",
+                        Some(rest) if rest.starts_with(':') => &rest[1..],
+                        _ => {
+                            attributes.push(key);
+                            continue;
+                        }
+                    };
+                    if declared.iter().any(|(p, _)| p == prefix) {
+                        report(format!("Duplicate namespace declaration '{}'", key));
+                        continue;
+                    }
+                    match prefix {
+                        "xml" if value != XML_NAMESPACE => {
+                            report(format!("Prefix 'xml' must be bound to '{}'", XML_NAMESPACE))
+                        }
+                        "xmlns" => report("Prefix 'xmlns' must not be declared".to_string()),
+                        _ if prefix != "xml"
+                            && (value == XML_NAMESPACE || value == XMLNS_NAMESPACE) =>
+                        {
+                            report(format!(
+                                "Namespace '{}' is reserved and cannot be bound to '{}'",
+                                value, key
+                            ))
+                        }
+                        _ if !prefix.is_empty() && value.is_empty() => {
+                            report(format!("Prefix '{}' cannot be undeclared", prefix))
+                        }
+                        _ => {}
+                    }
+                    declared.push((prefix.to_string(), value));
+                }
+
+                let lookup = |prefix: &str| -> Option<String> {
+                    if prefix == "xml" {
+                        return Some(XML_NAMESPACE.to_string());
+                    }
+                    declared
+                        .iter()
+                        .chain(scopes.iter().rev().flatten())
+                        .find(|(p, _)| p == prefix)
+                        .map(|(_, uri)| uri.clone())
+                };
+                let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+                if let Some((prefix, _)) = name.split_once(':') {
+                    if lookup(prefix).is_none_or(|uri| uri.is_empty()) {
+                        report(format!(
+                            "Undeclared namespace prefix '{}' on element <{}>",
+                            prefix, name
+                        ));
+                    }
+                }
+                let mut expanded: Vec<(String, &str, &str)> = Vec::new();
+                for key in &attributes {
+                    let Some((prefix, local)) = key.split_once(':') else {
+                        continue;
+                    };
+                    match lookup(prefix).filter(|uri| !uri.is_empty()) {
+                        None => report(format!(
+                            "Undeclared namespace prefix '{}' on attribute '{}'",
+                            prefix, key
+                        )),
+                        Some(uri) => {
+                            if let Some((_, _, other)) =
+                                expanded.iter().find(|(u, l, _)| *u == uri && *l == local)
+                            {
+                                report(format!(
+                                    "Attributes '{}' and '{}' have the same namespace and local name",
+                                    other, key
+                                ));
+                            }
+                            expanded.push((uri, local, key));
+                        }
+                    }
+                }
+                if is_start {
+                    scopes.push(declared);
+                }
+            }
+            Event::End(_) => {
+                scopes.pop();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    errors
+}
+
+/// Marker returned when checking must stop (first error in single-error mode).
+struct Stop;
+
+/// Event-driven well-formedness checker shared by both validation modes.
+struct XmlChecker<'a> {
+    input: &'a str,
+    collect_all: bool,
+    errors: Vec<FormatError>,
+    stats: XmlStats,
+    /// Open elements as (name, start offset)
+    stack: Vec<(String, usize)>,
+    root_seen: bool,
+    /// General entities declared in the DOCTYPE's internal subset
+    entities: Vec<String>,
+}
+
+impl<'a> XmlChecker<'a> {
+    fn new(input: &'a str, collect_all: bool) -> Self {
+        Self {
+            input,
+            collect_all,
+            errors: Vec::new(),
+            stats: XmlStats::default(),
+            stack: Vec::new(),
+            root_seen: false,
+            entities: Vec::new(),
+        }
+    }
+
+    fn run(mut self) -> XmlValidationResult {
+        // Stop only short-circuits the walk; errors are already recorded
+        let _ = self.walk();
+        if self.errors.is_empty() {
+            XmlValidationResult::valid(self.stats)
+        } else {
+            XmlValidationResult::with_errors(self.errors)
+        }
+    }
+
+    /// Record an error; in single-error mode this ends the walk.
+    fn report(&mut self, message: impl Into<String>, offset: usize) -> Result<(), Stop> {
+        self.errors
+            .push(FormatError::at_offset(message, self.input, offset));
+        if self.collect_all {
+            Ok(())
+        } else {
+            Err(Stop)
+        }
+    }
+
+    fn walk(&mut self) -> Result<(), Stop> {
+        if self.input.trim().is_empty() {
+            self.errors.push(FormatError::new("Empty input", 1, 1));
+            return Err(Stop);
+        }
+        if let Err(error) = check_input_size(self.input.len(), DEFAULT_MAX_INPUT_BYTES) {
+            self.errors.push(error);
+            return Err(Stop);
+        }
+
+        let mut reader = Reader::from_str(self.input);
+        if self.collect_all {
+            // End tags are matched against our own stack so we can recover
+            reader.config_mut().check_end_names = false;
+            reader.config_mut().allow_unmatched_ends = true;
+        }
+
+        loop {
+            let start = reader.buffer_position() as usize;
+            let event = match reader.read_event() {
+                Ok(event) => event,
+                Err(e) => {
+                    let offset = reader.error_position() as usize;
+                    self.errors.push(FormatError::at_offset(
+                        format!("XML parse error: {}", e),
+                        self.input,
+                        offset,
+                    ));
+                    // The parser cannot resume after a syntax error
+                    return Err(Stop);
+                }
+            };
+            match event {
+                Event::Start(e) => {
+                    self.open_element(&e, start)?;
+                    let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+                    self.stack.push((name, start));
+                    self.stats.max_depth = self.stats.max_depth.max(self.stack.len());
+                }
+                Event::Empty(e) => {
+                    self.open_element(&e, start)?;
+                    self.stats.max_depth = self.stats.max_depth.max(self.stack.len() + 1);
+                }
+                Event::End(e) => {
+                    let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+                    self.close_element(&name, start)?;
+                }
+                Event::DocType(e) => {
+                    self.entities = declared_entities(&String::from_utf8_lossy(&e))
+                }
+                // Declared entities are only checked for being declared, not expanded
+                Event::Text(e) => match e.unescape_with(|name| {
+                    resolve_predefined_entity(name)
+                        .or_else(|| self.entities.iter().any(|n| n == name).then_some(""))
+                }) {
+                    Ok(text) if !text.trim().is_empty() => {
+                        if self.stack.is_empty() {
+                            self.report("Text outside the root element", start)?;
+                        }
+                        self.stats.text_bytes += text.len();
+                    }
+                    Ok(_) => {}
+                    Err(err) => self.report(format!("Invalid text content: {}", err), start)?,
+                },
+                Event::CData(e) => {
+                    if self.stack.is_empty() {
+                        self.report("CDATA outside the root element", start)?;
+                    }
+                    self.stats.text_bytes += e.len();
+                    self.stats.cdata_count += 1;
+                }
+                Event::Comment(_) => self.stats.comment_count += 1,
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        let unclosed: Vec<(String, usize)> = self.stack.drain(..).rev().collect();
+        for (name, offset) in unclosed {
+            self.report(format!("Unclosed element <{}>", name), offset)?;
+        }
+        if !self.root_seen {
+            self.errors.push(FormatError::new("No root element", 1, 1));
+        }
+        Ok(())
+    }
+
+    /// Count an element and its attributes, recording namespace declarations.
+    fn open_element(&mut self, e: &BytesStart, start: usize) -> Result<(), Stop> {
+        if self.stack.is_empty() && self.root_seen {
+            let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+            self.report(
+                format!(
+                    "Multiple root elements: <{}> follows the root element",
+                    name
+                ),
+                start,
+            )?;
+        }
+        self.root_seen = true;
+        self.stats.element_count += 1;
+        let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+        *self.stats.element_names.entry(name).or_default() += 1;
+        for attr in e.attributes() {
+            let attr = match attr {
+                Ok(attr) => attr,
+                Err(err) => {
+                    self.report(format!("Invalid attribute: {}", err), start)?;
+                    continue;
+                }
+            };
+            self.stats.attribute_count += 1;
+            let key = attr.key.as_ref();
+            if key == b"xmlns" || key.starts_with(b"xmlns:") {
+                let uri = String::from_utf8_lossy(&attr.value).into_owned();
+                if !self.stats.namespaces.contains(&uri) {
+                    self.stats.namespaces.push(uri);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Match an end tag against the open elements (only reached with our own end-name checks).
+    fn close_element(&mut self, name: &str, start: usize) -> Result<(), Stop> {
+        match self.stack.iter().rposition(|(open, _)| open == name) {
+            Some(index) if index == self.stack.len() - 1 => {
+                self.stack.pop();
+            }
+            Some(index) => {
+                let expected = self.stack[self.stack.len() - 1].0.clone();
+                self.report(
+                    format!("Mismatched end tag </{}>, expected </{}>", name, expected),
+                    start,
+                )?;
+                self.stack.truncate(index);
+            }
+            None => match self.stack.last() {
+                Some((expected, _)) => {
+                    let message =
+                        format!("Mismatched end tag </{}>, expected </{}>", name, expected);
+                    self.report(message, start)?;
+                }
+                None => self.report(format!("Unmatched end tag </{}>", name), start)?,
+            },
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -110,4 +521,147 @@ mod tests {
         let result = validate_json(input);
         assert_eq!(result.stats.total_keys, 4);
     }
+
+    #[test]
+    fn test_validate_xml_stats() {
+        let input = r#"<?xml version="1.0"?>
+<root xmlns="urn:a" xmlns:b="urn:b">
+  <b:item id="1">hello</b:item>
+  <item id="2" flag="x"/>
+  <![CDATA[raw]]>
+</root>"#;
+        let result = validate_xml(input);
+        assert!(result.is_valid, "{:?}", result.error);
+        assert_eq!(result.stats.element_count, 3);
+        assert_eq!(result.stats.attribute_count, 5);
+        assert_eq!(result.stats.max_depth, 2);
+        assert_eq!(result.stats.namespaces, vec!["urn:a", "urn:b"]);
+        assert_eq!(result.stats.text_bytes, 8);
+    }
+
+    #[test]
+    fn test_xml_stats() {
+        let stats =
+            xml_stats("<r><!-- a --><x/><x><![CDATA[1]]><![CDATA[2]]></x><!-- b --><y/></r>")
+                .unwrap();
+        assert_eq!(stats.element_count, 4);
+        assert_eq!(stats.comment_count, 2);
+        assert_eq!(stats.cdata_count, 2);
+        let names: Vec<(&str, usize)> = stats
+            .element_names
+            .iter()
+            .map(|(k, v)| (k.as_str(), *v))
+            .collect();
+        assert_eq!(names, vec![("r", 1), ("x", 2), ("y", 1)]);
+        assert_eq!(xml_stats("<r><a></r>").unwrap_err().line, 1);
+    }
+
+    #[test]
+    fn test_validate_xml_mismatched_tag_position() {
+        let result = validate_xml("<root>\n  <a></b>\n</root>");
+        assert!(!result.is_valid);
+        let err = result.error.unwrap();
+        assert_eq!(err.line, 2);
+        assert!(err.message.contains("XML parse error"));
+    }
+
+    #[test]
+    fn test_validate_xml_structural_errors() {
+        let unclosed = validate_xml("<root><child>").error.unwrap();
+        assert!(unclosed.message.contains("<child>"));
+        assert_eq!(unclosed.column, 7);
+        assert!(!validate_xml("<a/><b/>").is_valid);
+        assert!(!validate_xml("<a/>text").is_valid);
+        assert!(!validate_xml("").is_valid);
+        assert!(!validate_xml(r#"<a x="1" x="2"/>"#).is_valid);
+    }
+
+    #[test]
+    fn test_validate_xml_declared_entities() {
+        assert!(validate_xml(r#"<!DOCTYPE r [<!ENTITY e "x">]><r>&e;</r>"#).is_valid);
+        assert!(validate_xml_all(r#"<!DOCTYPE r [<!ENTITY e "x">]><r>&e;</r>"#).is_valid);
+        assert!(!validate_xml(r#"<!DOCTYPE r [<!ENTITY e "x">]><r>&f;</r>"#).is_valid);
+        assert!(!validate_xml("<r>&e;</r>").is_valid);
+    }
+
+    #[test]
+    fn test_validate_xml_document_errors_at_start() {
+        for input in ["", "<?xml version=\"1.0\"?>"] {
+            let result = validate_xml(input);
+            let error = result.error.unwrap();
+            assert_eq!((error.line, error.column), (1, 1), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_validate_xml_predefined_entities() {
+        assert!(validate_xml("<r>&amp;</r>").is_valid);
+        assert!(validate_xml("<r>&lt;&gt;&quot;&apos;</r>").is_valid);
+        assert!(validate_xml_all("<r>a &amp; b</r>").is_valid);
+        let mixed = r#"<!DOCTYPE r [<!ENTITY e "x">]><r>&e; &amp; &lt;</r>"#;
+        assert!(validate_xml(mixed).is_valid);
+        assert!(validate_xml_all(mixed).is_valid);
+    }
+
+    #[test]
+    fn test_validate_xml_all_reports_every_error() {
+        let input = "<root>\n  <a></b>\n  <c x=\"1\" x=\"2\"/>\n  </zzz>\n  <d>&bogus;</d>\n  <open>\n</root>";
+        let result = validate_xml_all(input);
+        assert!(!result.is_valid);
+        let lines: Vec<usize> = result.errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![2, 3, 4, 5, 7]);
+        assert!(result.errors[0]
+            .message
+            .contains("Mismatched end tag </b>, expected </a>"));
+        assert!(result.errors[2].message.contains("</zzz>"));
+        assert!(result.errors[4].message.contains("expected </open>"));
+        assert_eq!(result.error, Some(result.errors[0].clone()));
+    }
+
+    #[test]
+    fn test_validate_xml_all_unmatched_end_tag() {
+        let result = validate_xml_all("<root/></extra>");
+        assert_eq!(result.errors.len(), 1);
+        assert!(result.errors[0]
+            .message
+            .contains("Unmatched end tag </extra>"));
+    }
+
+    #[test]
+    fn test_validate_xml_all_stops_at_syntax_error() {
+        let result = validate_xml_all("<root><a></b><!-- never closed");
+        assert_eq!(result.errors.len(), 2);
+        assert!(result.errors[1].message.contains("XML parse error"));
+        assert!(validate_xml_all("<root><a/></root>").is_valid);
+    }
+
+    #[test]
+    fn test_validate_xml_namespaces() {
+        let input = r#"<root xmlns:a="urn:x" xmlns:b="urn:x">
+  <p:child/>
+  <c a:k="1" b:k="2" q:z="3"/>
+  <d xmlns:e="urn:e" xmlns:e="urn:f"/>
+  <f xmlns:g="" xmlns:xml="urn:wrong"/>
+</root>"#;
+        let result = validate_xml_namespaces(input);
+        assert!(!result.is_valid);
+        let lines: Vec<usize> = result.errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![2, 3, 3, 4, 5, 5]);
+        assert!(result.errors[0]
+            .message
+            .contains("'p' on element <p:child>"));
+        assert!(result.errors[1].message.contains("'a:k' and 'b:k'"));
+        assert!(result.errors[2].message.contains("'q' on attribute 'q:z'"));
+        assert!(result.errors[3]
+            .message
+            .contains("Duplicate namespace declaration 'xmlns:e'"));
+
+        let valid = validate_xml_namespaces(
+            r#"<a:r xmlns:a="urn:a" xml:lang="en"><a:c a:k="1" k="2"/></a:r>"#,
+        );
+        assert!(valid.is_valid, "{:?}", valid.errors);
+        assert_eq!(valid.stats.element_count, 2);
+        // Scopes end with their element
+        assert!(!validate_xml_namespaces(r#"<r><a xmlns:p="urn:p"/><p:b/></r>"#).is_valid);
+    }
 }
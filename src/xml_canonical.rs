@@ -0,0 +1,357 @@
+//! XML canonicalization
+//!
+//! Implements Exclusive XML Canonicalization 1.0
+//! (<https://www.w3.org/TR/xml-exc-c14n/>), the form XML-DSig signs: the
+//! same logical document always produces the same bytes regardless of
+//! attribute order, quoting, empty-element style, entity references, or
+//! redundant namespace declarations.
+
+use crate::types::FormatError;
+use crate::xml_tree::{NodeId, NodeKind, XmlDocument};
+use quick_xml::escape::unescape;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Options for exclusive canonicalization.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CanonicalizationOptions {
+    /// Keep comments (the `#WithComments` variant)
+    pub with_comments: bool,
+    /// InclusiveNamespaces PrefixList: prefixes rendered wherever they are
+    /// in scope, as in inclusive C14N (`#default` for the default namespace)
+    pub inclusive_prefixes: Vec<String>,
+}
+
+/// Canonicalize an XML document with Exclusive XML Canonicalization.
+///
+/// The XML declaration and DOCTYPE are dropped, CDATA sections become
+/// escaped text, empty elements are written as start/end pairs, and each
+/// element carries only the namespace declarations it visibly uses.
+///
+/// # Arguments
+/// * `input` - The XML string to canonicalize
+/// * `options` - Comment handling and inclusive namespace prefixes
+///
+/// # Returns
+/// * `Ok(String)` - The canonical form
+/// * `Err(FormatError)` - If the XML is malformed, uses an undeclared prefix,
+///   or references entities other than the predefined ones
+pub fn canonicalize_xml(
+    input: &str,
+    options: &CanonicalizationOptions,
+) -> Result<String, FormatError> {
+    // Line ends are normalized before parsing, as an XML processor would
+    let normalized = input.replace("\r\n", "\n").replace('\r', "\n");
+    let doc = XmlDocument::parse(&normalized)?;
+    let mut c14n = Canonicalizer {
+        doc: &doc,
+        input: &normalized,
+        options,
+        out: String::with_capacity(normalized.len()),
+    };
+
+    let root = doc.document_element();
+    let mut after_root = false;
+    for &child in doc.children(XmlDocument::ROOT) {
+        if Some(child) == root {
+            let in_scope = HashMap::from([(String::new(), String::new())]);
+            let rendered = HashMap::from([(String::new(), String::new())]);
+            c14n.element(child, &in_scope, &rendered)?;
+            after_root = true;
+            continue;
+        }
+        let node = &doc.node(child).kind;
+        let keep = match node {
+            NodeKind::ProcessingInstruction(_) => true,
+            NodeKind::Comment(_) => options.with_comments,
+            _ => false,
+        };
+        if keep {
+            if after_root {
+                c14n.out.push('\n');
+            }
+            c14n.leaf(child)?;
+            if !after_root {
+                c14n.out.push('\n');
+            }
+        }
+    }
+    Ok(c14n.out)
+}
+
+struct Canonicalizer<'a> {
+    doc: &'a XmlDocument,
+    input: &'a str,
+    options: &'a CanonicalizationOptions,
+    out: String,
+}
+
+impl Canonicalizer<'_> {
+    fn error(&self, message: impl Into<String>, id: NodeId) -> FormatError {
+        FormatError::at_offset(message, self.input, self.doc.node(id).offset)
+    }
+
+    /// Write an element.
+    ///
+    /// `in_scope` maps prefixes to URIs declared in the input; `rendered`
+    /// holds the declarations already output by ancestors (`""` is the
+    /// default namespace, empty URI meaning none).
+    fn element(
+        &mut self,
+        id: NodeId,
+        in_scope: &HashMap<String, String>,
+        rendered: &HashMap<String, String>,
+    ) -> Result<(), FormatError> {
+        let name = self.doc.name(id).unwrap_or_default();
+        let mut in_scope = in_scope.clone();
+        let mut attributes = Vec::new();
+        for attr in self.doc.attributes(id) {
+            let value = attribute_value(&attr.raw_value).map_err(|e| self.error(e, id))?;
+            if attr.name == "xmlns" {
+                in_scope.insert(String::new(), value);
+            } else if let Some(prefix) = attr.name.strip_prefix("xmlns:") {
+                in_scope.insert(prefix.to_string(), value);
+            } else {
+                attributes.push((attr.name.as_str(), value));
+            }
+        }
+
+        // Prefixes visibly utilized by the element and its attributes
+        let mut utilized = vec![prefix_of(name).to_string()];
+        for (attr_name, _) in &attributes {
+            if let Some((prefix, _)) = attr_name.split_once(':') {
+                if prefix != "xml" && !utilized.iter().any(|p| p == prefix) {
+                    utilized.push(prefix.to_string());
+                }
+            }
+        }
+        for prefix in &self.options.inclusive_prefixes {
+            let prefix = if prefix == "#default" {
+                ""
+            } else {
+                prefix.as_str()
+            };
+            if in_scope.contains_key(prefix) && !utilized.iter().any(|p| p == prefix) {
+                utilized.push(prefix.to_string());
+            }
+        }
+
+        let mut declarations = Vec::new();
+        for prefix in utilized {
+            let uri = match in_scope.get(&prefix) {
+                Some(uri) => uri.clone(),
+                None => {
+                    return Err(self.error(format!("Undeclared namespace prefix '{}'", prefix), id))
+                }
+            };
+            if rendered.get(&prefix) != Some(&uri) {
+                declarations.push((prefix, uri));
+            }
+        }
+        declarations.sort();
+
+        let mut sorted = Vec::new();
+        for (attr_name, value) in attributes {
+            let (uri, local) = match attr_name.split_once(':') {
+                Some(("xml", local)) => ("http://www.w3.org/XML/1998/namespace".to_string(), local),
+                Some((prefix, local)) => match in_scope.get(prefix) {
+                    Some(uri) => (uri.clone(), local),
+                    None => {
+                        return Err(
+                            self.error(format!("Undeclared namespace prefix '{}'", prefix), id)
+                        )
+                    }
+                },
+                None => (String::new(), attr_name),
+            };
+            sorted.push((uri, local.to_string(), attr_name, value));
+        }
+        sorted.sort();
+
+        self.out.push('<');
+        self.out.push_str(name);
+        for (prefix, uri) in &declarations {
+            self.out.push_str(if prefix.is_empty() {
+                " xmlns"
+            } else {
+                " xmlns:"
+            });
+            self.out.push_str(prefix);
+            self.out.push_str("=\"");
+            escape_attribute(&mut self.out, uri);
+            self.out.push('"');
+        }
+        for (_, _, attr_name, value) in &sorted {
+            self.out.push(' ');
+            self.out.push_str(attr_name);
+            self.out.push_str("=\"");
+            escape_attribute(&mut self.out, value);
+            self.out.push('"');
+        }
+        self.out.push('>');
+
+        let mut child_rendered = rendered.clone();
+        child_rendered.extend(declarations);
+        for &child in self.doc.children(id) {
+            if self.doc.is_element(child) {
+                self.element(child, &in_scope, &child_rendered)?;
+            } else {
+                self.leaf(child)?;
+            }
+        }
+
+        self.out.push_str("</");
+        self.out.push_str(name);
+        self.out.push('>');
+        Ok(())
+    }
+
+    fn leaf(&mut self, id: NodeId) -> Result<(), FormatError> {
+        match &self.doc.node(id).kind {
+            NodeKind::Text(raw) => {
+                let text = unescape(raw)
+                    .map_err(|e| self.error(format!("Invalid text content: {}", e), id))?;
+                escape_text(&mut self.out, &text);
+            }
+            NodeKind::CData(text) => escape_text(&mut self.out, text),
+            NodeKind::Comment(text) if self.options.with_comments => {
+                self.out.push_str("<!--");
+                self.out.push_str(text);
+                self.out.push_str("-->");
+            }
+            NodeKind::ProcessingInstruction(content) => {
+                let content = content.trim_start();
+                let (target, data) = content
+                    .split_once(char::is_whitespace)
+                    .unwrap_or((content, ""));
+                self.out.push_str("<?");
+                self.out.push_str(target);
+                let data = data.trim_start();
+                if !data.is_empty() {
+                    self.out.push(' ');
+                    self.out.push_str(data);
+                }
+                self.out.push_str("?>");
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+fn prefix_of(name: &str) -> &str {
+    name.split_once(':').map_or("", |(prefix, _)| prefix)
+}
+
+/// Normalize an attribute value: literal whitespace becomes spaces, then references are resolved.
+fn attribute_value(raw: &str) -> Result<String, String> {
+    let spaced = raw.replace(['\t', '\n', '\r'], " ");
+    unescape(&spaced)
+        .map(|v| v.into_owned())
+        .map_err(|e| format!("Invalid attribute value: {}", e))
+}
+
+fn escape_text(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '\r' => out.push_str("&#xD;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+fn escape_attribute(out: &mut String, value: &str) {
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '"' => out.push_str("&quot;"),
+            '\t' => out.push_str("&#x9;"),
+            '\n' => out.push_str("&#xA;"),
+            '\r' => out.push_str("&#xD;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c14n(input: &str) -> String {
+        canonicalize_xml(input, &CanonicalizationOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn test_document_level_normalization() {
+        let input = "<?xml version=\"1.0\"?>\r\n<!DOCTYPE doc>\n<?pi  data?>\n<!--c-->\n<doc/>\n<!--after-->";
+        assert_eq!(c14n(input), "<?pi data?>\n<doc></doc>");
+        let with_comments = CanonicalizationOptions {
+            with_comments: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            canonicalize_xml(input, &with_comments).unwrap(),
+            "<?pi data?>\n<!--c-->\n<doc></doc>\n<!--after-->"
+        );
+    }
+
+    #[test]
+    fn test_attributes_and_text() {
+        let input = "<e b='2' a=\"x&#xA;y\" c='&lt;\"&gt;\t'>1 &lt; 2 &gt; 0 &amp; <![CDATA[<raw> & ]]>&#xD;</e>";
+        assert_eq!(
+            c14n(input),
+            "<e a=\"x&#xA;y\" b=\"2\" c=\"&lt;&quot;> \">1 &lt; 2 &gt; 0 &amp; &lt;raw&gt; &amp; &#xD;</e>"
+        );
+    }
+
+    #[test]
+    fn test_namespaces_rendered_where_used() {
+        let input = r#"<a:root xmlns:a="urn:a" xmlns:b="urn:b" xmlns="urn:d"><a:child b:x="1" z="2"><plain/></a:child></a:root>"#;
+        assert_eq!(
+            c14n(input),
+            r#"<a:root xmlns:a="urn:a"><a:child xmlns:b="urn:b" z="2" b:x="1"><plain xmlns="urn:d"></plain></a:child></a:root>"#
+        );
+    }
+
+    #[test]
+    fn test_attribute_order_by_namespace() {
+        let input = r#"<r xmlns:y="urn:a" xmlns:x="urn:b" x:k="1" y:k="2" k="3"/>"#;
+        assert_eq!(
+            c14n(input),
+            r#"<r xmlns:x="urn:b" xmlns:y="urn:a" k="3" y:k="2" x:k="1"></r>"#
+        );
+    }
+
+    #[test]
+    fn test_default_namespace_undeclaration() {
+        let input = r#"<r xmlns="urn:d"><c xmlns=""><d/></c></r>"#;
+        assert_eq!(
+            c14n(input),
+            r#"<r xmlns="urn:d"><c xmlns=""><d></d></c></r>"#
+        );
+    }
+
+    #[test]
+    fn test_inclusive_prefixes() {
+        let input = r#"<r xmlns:p="urn:p" xmlns:q="urn:q"><c/></r>"#;
+        let options = CanonicalizationOptions {
+            inclusive_prefixes: vec!["q".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            canonicalize_xml(input, &options).unwrap(),
+            r#"<r xmlns:q="urn:q"><c></c></r>"#
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(canonicalize_xml("<p:r/>", &CanonicalizationOptions::default()).is_err());
+        assert!(canonicalize_xml("<r>&custom;</r>", &CanonicalizationOptions::default()).is_err());
+    }
+}
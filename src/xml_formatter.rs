@@ -1,206 +1,766 @@
-//! XML Formatting Module - Spike Investigation
+//! XML Formatting Module
 //!
-//! This module evaluates quick-xml for WASM compatibility and basic formatting capabilities.
+//! Formats and minifies XML on top of quick-xml's reader. Events are read
+//! up front and then written by a small indenting writer, which lets the
+//! formatter look at an element's whole content (for example to detect
+//! mixed content) before deciding how to lay it out.
 
-use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
-use quick_xml::{Reader, Writer};
-use std::io::Cursor;
+use quick_xml::escape::{escape, unescape};
+use quick_xml::events::{BytesStart, BytesText, Event};
+use quick_xml::Reader;
+use serde::{Deserialize, Deserializer};
 
-use crate::types::{FormatError, IndentStyle};
+use crate::encoding::decode_xml;
+use crate::types::{check_input_size, FormatError, IndentStyle, DEFAULT_MAX_INPUT_BYTES};
+use crate::xml_html::html_to_xml;
 
-/// Format XML with specified indentation.
+/// How elements without content are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EmptyElementStyle {
+    /// Keep each element as written
+    #[default]
+    Preserve,
+    /// Write `<a></a>` as `<a/>`
+    SelfClosing,
+    /// Write `<a/>` as `<a></a>`
+    Expanded,
+}
+
+/// What happens to comments.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CommentHandling {
+    #[default]
+    Preserve,
+    Strip,
+}
+
+/// How CDATA sections and text relate.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CdataHandling {
+    /// Keep CDATA sections and text as written
+    #[default]
+    Preserve,
+    /// Write CDATA sections as escaped text
+    Unwrap,
+    /// Write text with many markup characters as a CDATA section
+    Wrap,
+}
+
+/// With `CdataHandling::Wrap`, text needs at least this many `<`, `>`, or
+/// `&` characters to become a CDATA section.
+const CDATA_WRAP_THRESHOLD: usize = 3;
+
+/// Line break written between formatted lines.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NewlineStyle {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+impl NewlineStyle {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NewlineStyle::Lf => "\n",
+            NewlineStyle::Crlf => "\r\n",
+        }
+    }
+}
+
+/// Options for XML formatting and minification.
+///
+/// Minification only uses the options that affect content rather than
+/// layout: `sort_attributes`, `empty_elements`, `comments`, `cdata`,
+/// `strip_processing_instructions`, `limits`, and `html`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct XmlFormatOptions {
+    /// Indentation style; `"spaces:N"` or `"tabs"` from JavaScript
+    #[serde(deserialize_with = "deserialize_indent")]
+    pub indent: IndentStyle,
+    /// Keep text verbatim in elements that contain non-whitespace text,
+    /// indenting only element-only content
+    pub preserve_mixed_content: bool,
+    /// Sort attributes by name, namespace declarations first
+    pub sort_attributes: bool,
+    /// Put each attribute on its own line when a start tag with several
+    /// attributes would extend past this many characters
+    pub wrap_attributes_width: Option<usize>,
+    pub empty_elements: EmptyElementStyle,
+    pub comments: CommentHandling,
+    pub cdata: CdataHandling,
+    /// Drop processing instructions (the XML declaration is kept)
+    pub strip_processing_instructions: bool,
+    pub newline: NewlineStyle,
+    pub limits: XmlLimits,
+    /// Accept HTML-ish input (void elements, unquoted attributes, omitted
+    /// end tags) by converting it to XML first
+    pub html: bool,
+}
+
+/// Resource limits that make hostile or enormous documents fail with a
+/// `FormatError` before they exhaust memory.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct XmlLimits {
+    /// Largest accepted input, in bytes
+    pub max_input_bytes: usize,
+    /// Deepest accepted element nesting
+    pub max_depth: usize,
+    /// Most attributes accepted on a single element
+    pub max_attributes: usize,
+    /// Most entity and character references accepted in the whole document
+    pub max_entity_expansions: usize,
+}
+
+impl Default for XmlLimits {
+    fn default() -> Self {
+        XmlLimits {
+            max_input_bytes: DEFAULT_MAX_INPUT_BYTES,
+            max_depth: 1024,
+            max_attributes: 1024,
+            max_entity_expansions: 1_000_000,
+        }
+    }
+}
+
+fn deserialize_indent<'de, D: Deserializer<'de>>(deserializer: D) -> Result<IndentStyle, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    value.parse().map_err(serde::de::Error::custom)
+}
+
+/// Format XML.
+///
+/// Elements marked `xml:space="preserve"` are written exactly as in the
+/// input, including their whitespace; the rest of the document is indented.
+///
+/// The input may be a fragment: sibling elements without a single root are
+/// formatted one after another rather than rejected.
 ///
 /// # Arguments
 /// * `input` - The XML string to format
-/// * `indent` - Indentation style (spaces or tabs)
+/// * `options` - Indentation and layout options
 ///
 /// # Returns
 /// * Formatted XML string on success
 /// * FormatError on failure
-pub fn format_xml(input: &str, indent: IndentStyle) -> Result<String, FormatError> {
-    if input.trim().is_empty() {
-        return Err(FormatError::new("Empty input", 0, 0));
-    }
-
-    let indent_char = match indent {
-        IndentStyle::Spaces(_) => b' ',
-        IndentStyle::Tabs => b'\t',
+pub fn format_xml(input: &str, options: &XmlFormatOptions) -> Result<String, FormatError> {
+    check_input_size(input.len(), options.limits.max_input_bytes)?;
+    let converted;
+    let input = if options.html {
+        converted = html_to_xml(input)?;
+        converted.as_str()
+    } else {
+        input
     };
-    let indent_size = match indent {
-        IndentStyle::Spaces(n) => n as usize,
-        IndentStyle::Tabs => 1,
+    let events = read_events(input, &options.limits)?;
+    let mixed = if options.preserve_mixed_content {
+        mixed_elements(&events)
+    } else {
+        Vec::new()
     };
+    let (events, verbatim) = apply_xml_space(events, &mixed);
 
-    let mut reader = Reader::from_str(input);
-    reader.config_mut().trim_text_start = true;
-    reader.config_mut().trim_text_end = true;
+    let mut writer = XmlWriter::new(input, Some(options.indent.as_str()), options);
+    writer.write_all(&events, &verbatim)?;
+    Ok(writer.out)
+}
 
-    let mut writer = Writer::new_with_indent(Cursor::new(Vec::new()), indent_char, indent_size);
-    let mut buf = Vec::new();
+/// Minify XML by removing unnecessary whitespace.
+///
+/// Layout options (indent, wrapping, newline style, mixed content) are ignored.
+/// Whitespace inside `xml:space="preserve"` elements is kept.
+pub fn minify_xml(input: &str, options: &XmlFormatOptions) -> Result<String, FormatError> {
+    check_input_size(input.len(), options.limits.max_input_bytes)?;
+    let converted;
+    let input = if options.html {
+        converted = html_to_xml(input)?;
+        converted.as_str()
+    } else {
+        input
+    };
+    let (events, verbatim) = apply_xml_space(read_events(input, &options.limits)?, &[]);
+    let mut writer = XmlWriter::new(input, None, options);
+    writer.write_all(&events, &verbatim)?;
+    Ok(writer.out)
+}
 
+/// Format XML given as raw bytes in any encoding [`decode_xml`] supports.
+///
+/// The output is UTF-8; the XML declaration is kept as written.
+pub fn format_xml_bytes(input: &[u8], options: &XmlFormatOptions) -> Result<String, FormatError> {
+    check_input_size(input.len(), options.limits.max_input_bytes)?;
+    format_xml(&decode_xml(input)?, options)
+}
+
+/// Minify XML given as raw bytes in any encoding [`decode_xml`] supports.
+pub fn minify_xml_bytes(input: &[u8], options: &XmlFormatOptions) -> Result<String, FormatError> {
+    check_input_size(input.len(), options.limits.max_input_bytes)?;
+    minify_xml(&decode_xml(input)?, options)
+}
+
+/// Read every event with its byte offset, failing on the first parse error
+/// or the first exceeded limit.
+fn read_events<'a>(
+    input: &'a str,
+    limits: &XmlLimits,
+) -> Result<Vec<(usize, Event<'a>)>, FormatError> {
+    if input.trim().is_empty() {
+        return Err(FormatError::new("Empty input", 1, 1));
+    }
+
+    let mut reader = Reader::from_str(input);
+    // Offset of the reader's input within `input`; the reader restarts after a DOCTYPE
+    let mut base = 0;
+
+    let mut events = Vec::new();
+    let mut depth = 0;
+    let mut references = 0;
     loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) => {
-                let name = String::from_utf8(e.name().as_ref().to_vec())
-                    .map_err(|_| FormatError::new("Invalid UTF-8 in tag name", 0, 0))?;
-                let mut new_elem = BytesStart::new(name);
-                for attr in e.attributes() {
-                    let attr = attr.map_err(|_| FormatError::new("Invalid attribute", 0, 0))?;
-                    new_elem.push_attribute(attr);
+        let offset = base + reader.buffer_position() as usize;
+        if input[offset..].starts_with("<!DOCTYPE") {
+            // quick-xml ends a DOCTYPE at the first unbalanced `>`, even one
+            // inside a quoted entity value or comment in the internal subset
+            let end = doctype_end(input, offset).ok_or_else(|| {
+                FormatError::at_offset("XML parse error: unclosed DOCTYPE", input, offset)
+            })?;
+            let content = &input[offset + "<!DOCTYPE".len()..end - 1];
+            events.push((offset, Event::DocType(BytesText::from_escaped(content))));
+            base = end;
+            reader = Reader::from_str(&input[end..]);
+            continue;
+        }
+        match reader.read_event() {
+            Ok(Event::Eof) => break,
+            Ok(event) => {
+                match &event {
+                    Event::Start(e) | Event::Empty(e) => {
+                        if matches!(event, Event::Start(_)) {
+                            depth += 1;
+                            if depth > limits.max_depth {
+                                return Err(FormatError::at_offset(
+                                    format!("Elements nested more than {} deep", limits.max_depth),
+                                    input,
+                                    offset,
+                                ));
+                            }
+                        }
+                        let mut count = 0;
+                        for attr in e.attributes().flatten() {
+                            count += 1;
+                            references += attr.value.iter().filter(|&&b| b == b'&').count();
+                        }
+                        if count > limits.max_attributes {
+                            return Err(FormatError::at_offset(
+                                format!(
+                                    "Element has {} attributes, more than the limit of {}",
+                                    count, limits.max_attributes
+                                ),
+                                input,
+                                offset,
+                            ));
+                        }
+                    }
+                    Event::End(_) => depth -= 1,
+                    Event::Text(e) => references += e.iter().filter(|&&b| b == b'&').count(),
+                    _ => {}
+                }
+                if references > limits.max_entity_expansions {
+                    return Err(FormatError::at_offset(
+                        format!(
+                            "More than {} entity references",
+                            limits.max_entity_expansions
+                        ),
+                        input,
+                        offset,
+                    ));
                 }
-                writer
-                    .write_event(Event::Start(new_elem))
-                    .map_err(|e| FormatError::new(&format!("Write error: {}", e), 0, 0))?;
+                events.push((offset, event));
             }
-            Ok(Event::End(e)) => {
-                let name = String::from_utf8(e.name().as_ref().to_vec())
-                    .map_err(|_| FormatError::new("Invalid UTF-8 in tag name", 0, 0))?;
-                let end = BytesEnd::new(name);
-                writer
-                    .write_event(Event::End(end))
-                    .map_err(|e| FormatError::new(&format!("Write error: {}", e), 0, 0))?;
+            Err(e) => {
+                let position = base + reader.error_position() as usize;
+                return Err(FormatError::at_offset(
+                    format!("XML parse error: {}", e),
+                    input,
+                    position,
+                ));
             }
-            Ok(Event::Empty(e)) => {
-                let name = String::from_utf8(e.name().as_ref().to_vec())
-                    .map_err(|_| FormatError::new("Invalid UTF-8 in tag name", 0, 0))?;
-                let mut new_elem = BytesStart::new(name);
-                for attr in e.attributes() {
-                    let attr = attr.map_err(|_| FormatError::new("Invalid attribute", 0, 0))?;
-                    new_elem.push_attribute(attr);
-                }
-                writer
-                    .write_event(Event::Empty(new_elem))
-                    .map_err(|e| FormatError::new(&format!("Write error: {}", e), 0, 0))?;
+        }
+    }
+    Ok(events)
+}
+
+/// Byte offset just past the `>` ending the DOCTYPE that starts at `start`.
+///
+/// Quoted strings, comments, and processing instructions in the internal
+/// subset may contain `>` and `]`.
+fn doctype_end(input: &str, start: usize) -> Option<usize> {
+    let bytes = input.as_bytes();
+    let mut i = start + "<!DOCTYPE".len();
+    let mut in_subset = false;
+    while i < bytes.len() {
+        let rest = &input[i..];
+        match bytes[i] {
+            quote @ (b'"' | b'\'') => i += 1 + input[i + 1..].find(char::from(quote))? + 1,
+            b'<' if in_subset && rest.starts_with("<!--") => i += rest.find("-->")? + 3,
+            b'<' if in_subset && rest.starts_with("<?") => i += rest.find("?>")? + 2,
+            b'[' => {
+                in_subset = true;
+                i += 1;
             }
-            Ok(Event::Text(e)) => {
-                let text = e
-                    .unescape()
-                    .map_err(|_| FormatError::new("Invalid text content", 0, 0))?;
-                if !text.trim().is_empty() {
-                    writer
-                        .write_event(Event::Text(BytesText::new(&text)))
-                        .map_err(|e| FormatError::new(&format!("Write error: {}", e), 0, 0))?;
-                }
+            b']' => {
+                in_subset = false;
+                i += 1;
             }
-            Ok(Event::CData(e)) => {
-                writer
-                    .write_event(Event::CData(e))
-                    .map_err(|e| FormatError::new(&format!("Write error: {}", e), 0, 0))?;
+            b'>' if !in_subset => return Some(i + 1),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// Names of the general entities declared in a DOCTYPE's internal subset.
+pub(crate) fn declared_entities(doctype: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut rest = doctype;
+    while let Some(i) = rest.find(['<', '"', '\'']) {
+        rest = &rest[i..];
+        if rest.starts_with("<!--") {
+            rest = rest.find("-->").map_or("", |end| &rest[end + 3..]);
+        } else if let Some(decl) = rest.strip_prefix("<!ENTITY") {
+            let decl = decl.trim_start();
+            // Parameter entities (`<!ENTITY % name ...>`) are only used inside the DTD
+            if !decl.starts_with('%') {
+                let name: String = decl.chars().take_while(|c| !c.is_whitespace()).collect();
+                names.push(name);
             }
-            Ok(Event::Comment(e)) => {
-                writer
-                    .write_event(Event::Comment(e))
-                    .map_err(|e| FormatError::new(&format!("Write error: {}", e), 0, 0))?;
+            rest = decl;
+        } else if rest.starts_with('<') {
+            rest = &rest[1..];
+        } else {
+            let quote = &rest[..1];
+            rest = rest[1..].find(quote).map_or("", |end| &rest[end + 2..]);
+        }
+    }
+    names
+}
+
+/// Drop whitespace-only text outside verbatim subtrees and flag the start
+/// events that open such a subtree.
+///
+/// A subtree is verbatim if its element has `xml:space="preserve"` or is
+/// flagged in `mixed` (indexed like `events`). A nested `xml:space="default"`
+/// is still written verbatim: the writer cannot re-indent part of a subtree
+/// whose surrounding whitespace it keeps.
+fn apply_xml_space<'a>(
+    events: Vec<(usize, Event<'a>)>,
+    mixed: &[bool],
+) -> (Vec<(usize, Event<'a>)>, Vec<bool>) {
+    let mut kept = Vec::with_capacity(events.len());
+    let mut preserved = Vec::with_capacity(events.len());
+    let mut open: Vec<bool> = Vec::new();
+    for (i, (offset, event)) in events.into_iter().enumerate() {
+        let inherited = open.last().copied().unwrap_or(false);
+        let mut opens_preserved = false;
+        match &event {
+            Event::Start(e) => {
+                let preserve = inherited
+                    || mixed.get(i).copied().unwrap_or(false)
+                    || matches!(e.try_get_attribute("xml:space"), Ok(Some(attr)) if &*attr.value == b"preserve");
+                opens_preserved = preserve && !inherited;
+                open.push(preserve);
             }
-            Ok(Event::Decl(e)) => {
-                writer
-                    .write_event(Event::Decl(e))
-                    .map_err(|e| FormatError::new(&format!("Write error: {}", e), 0, 0))?;
+            Event::End(_) => {
+                open.pop();
             }
-            Ok(Event::PI(e)) => {
-                writer
-                    .write_event(Event::PI(e))
-                    .map_err(|e| FormatError::new(&format!("Write error: {}", e), 0, 0))?;
+            Event::Text(e) if !inherited && e.iter().all(u8::is_ascii_whitespace) => continue,
+            _ => {}
+        }
+        kept.push((offset, event));
+        preserved.push(opens_preserved);
+    }
+    (kept, preserved)
+}
+
+/// Flag the start events of elements whose direct content includes
+/// non-whitespace text or CDATA.
+fn mixed_elements(events: &[(usize, Event)]) -> Vec<bool> {
+    let mut mixed = vec![false; events.len()];
+    let mut open = Vec::new();
+    for (i, (_, event)) in events.iter().enumerate() {
+        match event {
+            Event::Start(_) => open.push(i),
+            Event::End(_) => {
+                open.pop();
             }
-            Ok(Event::DocType(e)) => {
-                writer
-                    .write_event(Event::DocType(e))
-                    .map_err(|e| FormatError::new(&format!("Write error: {}", e), 0, 0))?;
+            Event::Text(e) if !e.iter().all(u8::is_ascii_whitespace) => {
+                if let Some(&start) = open.last() {
+                    mixed[start] = true;
+                }
             }
-            Ok(Event::Eof) => break,
-            Err(e) => {
-                return Err(FormatError::new(&format!("XML parse error: {}", e), 0, 0));
+            Event::CData(_) => {
+                if let Some(&start) = open.last() {
+                    mixed[start] = true;
+                }
             }
+            _ => {}
         }
-        buf.clear();
     }
+    mixed
+}
 
-    let result = writer.into_inner().into_inner();
-    String::from_utf8(result).map_err(|_| FormatError::new("Invalid UTF-8 in output", 0, 0))
+/// Indenting XML writer.
+///
+/// Line breaks follow quick-xml's indenting writer: every markup event
+/// starts on a new line except directly after text or CDATA. Unlike that
+/// writer, an empty `<a></a>` pair is kept on one line.
+struct XmlWriter<'a> {
+    input: &'a str,
+    out: String,
+    /// One level of indentation; `None` writes everything on one line
+    indent: Option<String>,
+    level: usize,
+    line_break: bool,
+    /// Depth inside an element whose content is written verbatim
+    verbatim: usize,
+    options: &'a XmlFormatOptions,
+    /// General entities declared in the DOCTYPE; references to them are kept as written
+    entities: Vec<String>,
 }
 
-/// Minify XML by removing unnecessary whitespace.
-pub fn minify_xml(input: &str) -> Result<String, FormatError> {
-    if input.trim().is_empty() {
-        return Err(FormatError::new("Empty input", 0, 0));
+impl<'a> XmlWriter<'a> {
+    fn new(input: &'a str, indent: Option<String>, options: &'a XmlFormatOptions) -> Self {
+        Self {
+            input,
+            out: String::with_capacity(input.len() + input.len() / 4),
+            indent,
+            level: 0,
+            line_break: false,
+            verbatim: 0,
+            options,
+            entities: Vec::new(),
+        }
     }
 
-    let mut reader = Reader::from_str(input);
-    reader.config_mut().trim_text_start = true;
-    reader.config_mut().trim_text_end = true;
+    /// Write all events; `verbatim` flags start tags whose content is kept as is.
+    fn write_all(
+        &mut self,
+        events: &[(usize, Event)],
+        verbatim: &[bool],
+    ) -> Result<(), FormatError> {
+        let mut i = 0;
+        while i < events.len() {
+            let (offset, event) = &events[i];
+            // A start tag immediately followed by its end tag
+            let pair = matches!(
+                (event, events.get(i + 1)),
+                (Event::Start(_), Some((_, Event::End(_))))
+            );
+            match (event, self.options.empty_elements) {
+                (Event::Start(e), EmptyElementStyle::SelfClosing) if pair => {
+                    self.empty_element(e, *offset, false)?;
+                    i += 2;
+                }
+                // Empty pairs stay on one line rather than splitting around an indent
+                (Event::Start(e), EmptyElementStyle::Expanded | EmptyElementStyle::Preserve)
+                    if pair =>
+                {
+                    self.empty_element(e, *offset, true)?;
+                    i += 2;
+                }
+                (Event::Empty(e), EmptyElementStyle::Expanded) => {
+                    self.empty_element(e, *offset, true)?;
+                    i += 1;
+                }
+                _ => {
+                    self.write(event, *offset, verbatim.get(i).copied().unwrap_or(false))?;
+                    i += 1;
+                }
+            }
+        }
+        Ok(())
+    }
 
-    let mut writer = Writer::new(Cursor::new(Vec::new()));
-    let mut buf = Vec::new();
+    fn break_line(&mut self) {
+        if let Some(unit) = &self.indent {
+            if self.line_break && self.verbatim == 0 {
+                self.out.push_str(self.options.newline.as_str());
+                for _ in 0..self.level {
+                    self.out.push_str(unit);
+                }
+            }
+        }
+    }
 
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) => {
-                let name = String::from_utf8(e.name().as_ref().to_vec())
-                    .map_err(|_| FormatError::new("Invalid UTF-8", 0, 0))?;
-                let mut new_elem = BytesStart::new(name);
-                for attr in e.attributes() {
-                    let attr = attr.map_err(|_| FormatError::new("Invalid attribute", 0, 0))?;
-                    new_elem.push_attribute(attr);
+    /// Write one event; `mixed` marks a start tag whose content is kept verbatim.
+    fn write(&mut self, event: &Event, offset: usize, mixed: bool) -> Result<(), FormatError> {
+        let mut line_break = true;
+        match event {
+            Event::Start(e) => {
+                if self.verbatim > 0 {
+                    self.verbatim += 1;
+                    return self.start_tag(e, offset, ">");
+                }
+                self.break_line();
+                self.start_tag(e, offset, ">")?;
+                if mixed {
+                    self.verbatim = 1;
+                } else {
+                    self.level += 1;
                 }
-                writer
-                    .write_event(Event::Start(new_elem))
-                    .map_err(|e| FormatError::new(&format!("Write error: {}", e), 0, 0))?;
             }
-            Ok(Event::End(e)) => {
-                let name = String::from_utf8(e.name().as_ref().to_vec())
-                    .map_err(|_| FormatError::new("Invalid UTF-8", 0, 0))?;
-                let end = BytesEnd::new(name);
-                writer
-                    .write_event(Event::End(end))
-                    .map_err(|e| FormatError::new(&format!("Write error: {}", e), 0, 0))?;
+            Event::End(e) => {
+                let name = self.utf8(e.name().into_inner(), offset)?;
+                if self.verbatim > 0 {
+                    self.verbatim -= 1;
+                } else {
+                    self.level = self.level.saturating_sub(1);
+                    self.break_line();
+                }
+                self.out.push_str("</");
+                self.out.push_str(name);
+                self.out.push('>');
             }
-            Ok(Event::Empty(e)) => {
-                let name = String::from_utf8(e.name().as_ref().to_vec())
-                    .map_err(|_| FormatError::new("Invalid UTF-8", 0, 0))?;
-                let mut new_elem = BytesStart::new(name);
-                for attr in e.attributes() {
-                    let attr = attr.map_err(|_| FormatError::new("Invalid attribute", 0, 0))?;
-                    new_elem.push_attribute(attr);
+            Event::Empty(e) => self.empty_element(e, offset, false)?,
+            Event::Text(e) => {
+                if self.verbatim > 0 && self.options.cdata != CdataHandling::Wrap {
+                    let raw = self.utf8(e, offset)?;
+                    self.out.push_str(raw);
+                    return Ok(());
+                }
+                if !self.entities.is_empty() {
+                    let raw = self.utf8(e, offset)?;
+                    if self
+                        .entities
+                        .iter()
+                        .any(|name| raw.contains(&format!("&{};", name)))
+                    {
+                        return self.text_with_entities(raw, offset);
+                    }
+                }
+                let text = e.unescape().map_err(|_| {
+                    FormatError::at_offset("Invalid text content", self.input, offset)
+                })?;
+                let text = if self.verbatim > 0 {
+                    &text
+                } else {
+                    text.trim()
+                };
+                if text.is_empty() {
+                    return Ok(());
+                }
+                let wrap = self.options.cdata == CdataHandling::Wrap
+                    && !text.contains("]]>")
+                    && text
+                        .chars()
+                        .filter(|c| matches!(c, '<' | '>' | '&'))
+                        .count()
+                        >= CDATA_WRAP_THRESHOLD;
+                if wrap {
+                    self.out.push_str("<![CDATA[");
+                    self.out.push_str(text);
+                    self.out.push_str("]]>");
+                } else if self.verbatim > 0 {
+                    // Verbatim text keeps its original escaping
+                    let raw = self.utf8(e, offset)?;
+                    self.out.push_str(raw);
+                } else {
+                    self.out.push_str(&escape(text));
                 }
-                writer
-                    .write_event(Event::Empty(new_elem))
-                    .map_err(|e| FormatError::new(&format!("Write error: {}", e), 0, 0))?;
+                line_break = false;
             }
-            Ok(Event::Text(e)) => {
-                let text = e
-                    .unescape()
-                    .map_err(|_| FormatError::new("Invalid text", 0, 0))?;
-                if !text.trim().is_empty() {
-                    writer
-                        .write_event(Event::Text(BytesText::new(&text)))
-                        .map_err(|e| FormatError::new(&format!("Write error: {}", e), 0, 0))?;
+            Event::CData(e) => {
+                let raw = self.utf8(e, offset)?;
+                if self.options.cdata == CdataHandling::Unwrap {
+                    self.out.push_str(&escape(raw));
+                } else {
+                    self.out.push_str("<![CDATA[");
+                    self.out.push_str(raw);
+                    self.out.push_str("]]>");
                 }
+                line_break = false;
             }
-            Ok(Event::Eof) => break,
-            Ok(event) => {
-                writer
-                    .write_event(event)
-                    .map_err(|e| FormatError::new(&format!("Write error: {}", e), 0, 0))?;
+            Event::Comment(_) if self.options.comments == CommentHandling::Strip => return Ok(()),
+            Event::Comment(e) => self.wrapped("<!--", e, "-->", offset)?,
+            Event::Decl(e) => self.wrapped("<?", e, "?>", offset)?,
+            Event::PI(_) if self.options.strip_processing_instructions => return Ok(()),
+            Event::PI(e) => self.wrapped("<?", e, "?>", offset)?,
+            Event::DocType(e) => {
+                let raw = self.utf8(e, offset)?.trim_start();
+                self.entities = declared_entities(raw);
+                self.break_line();
+                self.out.push_str("<!DOCTYPE ");
+                self.out.push_str(raw);
+                self.out.push('>');
             }
-            Err(e) => {
-                return Err(FormatError::new(&format!("XML parse error: {}", e), 0, 0));
+            Event::Eof => {}
+        }
+        if self.verbatim == 0 {
+            self.line_break = line_break;
+        }
+        Ok(())
+    }
+
+    /// Write text that references declared entities, keeping those
+    /// references as written and normalizing the escaping around them.
+    fn text_with_entities(&mut self, raw: &str, offset: usize) -> Result<(), FormatError> {
+        let mut rest = if self.verbatim > 0 { raw } else { raw.trim() };
+        while !rest.is_empty() {
+            let reference = rest.match_indices('&').find_map(|(i, _)| {
+                let name = &rest[i + 1..i + 1 + rest[i + 1..].find(';')?];
+                self.entities
+                    .iter()
+                    .any(|e| e == name)
+                    .then_some((i, name.len() + 2))
+            });
+            let (segment, after) = match reference {
+                Some((i, len)) => (&rest[..i], Some(&rest[i..i + len])),
+                None => (rest, None),
+            };
+            if self.verbatim > 0 {
+                self.out.push_str(segment);
+            } else {
+                let text = unescape(segment).map_err(|_| {
+                    FormatError::at_offset("Invalid text content", self.input, offset)
+                })?;
+                self.out.push_str(&escape(text));
+            }
+            if let Some(reference) = after {
+                self.out.push_str(reference);
+            }
+            rest = &rest[segment.len() + after.map_or(0, str::len)..];
+        }
+        if self.verbatim == 0 {
+            self.line_break = false;
+        }
+        Ok(())
+    }
+
+    /// Write an element without content as `<a/>`, or `<a></a>` when `expanded`.
+    fn empty_element(
+        &mut self,
+        e: &BytesStart,
+        offset: usize,
+        expanded: bool,
+    ) -> Result<(), FormatError> {
+        self.break_line();
+        if expanded {
+            self.start_tag(e, offset, ">")?;
+            let name = self.utf8(e.name().into_inner(), offset)?;
+            self.out.push_str("</");
+            self.out.push_str(name);
+            self.out.push('>');
+        } else {
+            self.start_tag(e, offset, "/>")?;
+        }
+        if self.verbatim == 0 {
+            self.line_break = true;
+        }
+        Ok(())
+    }
+
+    fn wrapped(
+        &mut self,
+        before: &str,
+        content: &[u8],
+        after: &str,
+        offset: usize,
+    ) -> Result<(), FormatError> {
+        let raw = self.utf8(content, offset)?;
+        self.break_line();
+        self.out.push_str(before);
+        self.out.push_str(raw);
+        self.out.push_str(after);
+        Ok(())
+    }
+
+    /// Write `<name attr="value"...` followed by `close` (`>` or `/>`).
+    fn start_tag(&mut self, e: &BytesStart, offset: usize, close: &str) -> Result<(), FormatError> {
+        let name = self.utf8(e.name().into_inner(), offset)?;
+        let mut attributes = Vec::new();
+        for attr in e.attributes() {
+            let attr =
+                attr.map_err(|_| FormatError::at_offset("Invalid attribute", self.input, offset))?;
+            let key = self.utf8(attr.key.into_inner(), offset)?;
+            let value = self.utf8(&attr.value, offset)?.to_string();
+            attributes.push((key, value));
+        }
+        if self.options.sort_attributes {
+            attributes.sort_by_key(|&(key, _)| (key != "xmlns" && !key.starts_with("xmlns:"), key));
+        }
+
+        let attributes: Vec<(&str, String)> = attributes
+            .into_iter()
+            // Values from single-quoted attributes may contain `"`
+            .map(|(key, value)| (key, value.replace('"', "&quot;")))
+            .collect();
+        let separator = match (&self.indent, self.options.wrap_attributes_width) {
+            (Some(unit), Some(width)) if self.verbatim == 0 && attributes.len() > 1 => {
+                let column = self
+                    .out
+                    .rsplit('\n')
+                    .next()
+                    .unwrap_or_default()
+                    .chars()
+                    .count();
+                let tag_len = 1
+                    + name.chars().count()
+                    + attributes
+                        .iter()
+                        .map(|(k, v)| 4 + k.chars().count() + v.chars().count())
+                        .sum::<usize>()
+                    + close.len();
+                if column + tag_len > width {
+                    Some(format!(
+                        "{}{}",
+                        self.options.newline.as_str(),
+                        unit.repeat(self.level + 1)
+                    ))
+                } else {
+                    None
+                }
             }
+            _ => None,
+        };
+
+        self.out.push('<');
+        self.out.push_str(name);
+        for (key, value) in attributes {
+            self.out.push_str(separator.as_deref().unwrap_or(" "));
+            self.out.push_str(key);
+            self.out.push_str("=\"");
+            self.out.push_str(&value);
+            self.out.push('"');
         }
-        buf.clear();
+        self.out.push_str(close);
+        Ok(())
     }
 
-    let result = writer.into_inner().into_inner();
-    String::from_utf8(result).map_err(|_| FormatError::new("Invalid UTF-8 in output", 0, 0))
+    fn utf8<'b>(&self, bytes: &'b [u8], offset: usize) -> Result<&'b str, FormatError> {
+        std::str::from_utf8(bytes)
+            .map_err(|_| FormatError::at_offset("Invalid UTF-8", self.input, offset))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn spaces(n: u8) -> XmlFormatOptions {
+        XmlFormatOptions {
+            indent: IndentStyle::Spaces(n),
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn test_format_xml_basic() {
         let input = "<root><child>text</child></root>";
-        let result = format_xml(input, IndentStyle::Spaces(2)).unwrap();
+        let result = format_xml(input, &spaces(2)).unwrap();
         assert!(result.contains("<root>"));
         assert!(result.contains("<child>"));
         assert!(result.contains("text"));
@@ -209,7 +769,7 @@ mod tests {
     #[test]
     fn test_format_xml_with_attributes() {
         let input = r#"<root attr="value"><child id="1"/></root>"#;
-        let result = format_xml(input, IndentStyle::Spaces(2)).unwrap();
+        let result = format_xml(input, &spaces(2)).unwrap();
         assert!(result.contains(r#"attr="value""#));
         assert!(result.contains(r#"id="1""#));
     }
@@ -217,7 +777,7 @@ mod tests {
     #[test]
     fn test_format_xml_with_declaration() {
         let input = r#"<?xml version="1.0" encoding="UTF-8"?><root/>"#;
-        let result = format_xml(input, IndentStyle::Spaces(2)).unwrap();
+        let result = format_xml(input, &spaces(2)).unwrap();
         assert!(result.contains("<?xml"));
         assert!(result.contains("<root"));
     }
@@ -225,7 +785,7 @@ mod tests {
     #[test]
     fn test_minify_xml() {
         let input = "<root>\n  <child>\n    text\n  </child>\n</root>";
-        let result = minify_xml(input).unwrap();
+        let result = minify_xml(input, &XmlFormatOptions::default()).unwrap();
         assert!(!result.contains('\n'));
         assert!(result.contains("<root><child>"));
     }
@@ -233,8 +793,8 @@ mod tests {
     #[test]
     fn test_roundtrip() {
         let input = r#"<root><a>1</a><b attr="x">2</b></root>"#;
-        let formatted = format_xml(input, IndentStyle::Spaces(2)).unwrap();
-        let minified = minify_xml(&formatted).unwrap();
+        let formatted = format_xml(input, &spaces(2)).unwrap();
+        let minified = minify_xml(&formatted, &XmlFormatOptions::default()).unwrap();
         // Content should be preserved
         assert!(minified.contains("<root>"));
         assert!(minified.contains("<a>1</a>"));
@@ -243,29 +803,464 @@ mod tests {
 
     #[test]
     fn test_empty_input() {
-        let result = format_xml("", IndentStyle::Spaces(2));
-        assert!(result.is_err());
+        let err = format_xml("", &spaces(2)).unwrap_err();
+        // Whole-document errors point at the start, like positioned ones
+        assert_eq!((err.line, err.column), (1, 1));
     }
 
     #[test]
     fn test_cdata() {
         let input = "<root><![CDATA[<not xml>]]></root>";
-        let result = format_xml(input, IndentStyle::Spaces(2)).unwrap();
+        let result = format_xml(input, &spaces(2)).unwrap();
         assert!(result.contains("<![CDATA[<not xml>]]>"));
     }
 
     #[test]
     fn test_comments() {
         let input = "<root><!-- comment --><child/></root>";
-        let result = format_xml(input, IndentStyle::Spaces(2)).unwrap();
+        let result = format_xml(input, &spaces(2)).unwrap();
         assert!(result.contains("<!-- comment -->"));
     }
 
     #[test]
     fn test_namespace_prefix() {
         let input = r#"<ns:root xmlns:ns="http://example.com"><ns:child/></ns:root>"#;
-        let result = format_xml(input, IndentStyle::Spaces(2)).unwrap();
+        let result = format_xml(input, &spaces(2)).unwrap();
         assert!(result.contains("ns:root"));
         assert!(result.contains("ns:child"));
     }
+
+    #[test]
+    fn test_format_xml_matches_indent_writer_layout() {
+        let input = "<?xml version=\"1.0\"?>\n<root a='x\"y'><!-- c --><a></a><b/><c>it's <d>x</d> tail</c><![CDATA[raw]]></root>";
+        let result = format_xml(input, &spaces(2)).unwrap();
+        assert_eq!(
+            result,
+            "<?xml version=\"1.0\"?>\n<root a=\"x&quot;y\">\n  <!-- c -->\n  <a></a>\n  <b/>\n  <c>it&apos;s<d>x</d>tail</c><![CDATA[raw]]></root>"
+        );
+    }
+
+    #[test]
+    fn test_preserve_mixed_content() {
+        let input = "<doc>\n<p>Hello <b>bold</b>  world <i>!</i></p>\n<list><item> a </item><empty/></list></doc>";
+        let result = format_xml(
+            input,
+            &XmlFormatOptions {
+                preserve_mixed_content: true,
+                ..spaces(2)
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            result,
+            "<doc>\n  <p>Hello <b>bold</b>  world <i>!</i></p>\n  <list>\n    <item> a </item>\n    <empty/>\n  </list>\n</doc>"
+        );
+        // Whitespace between inline children of mixed content is kept
+        let options = XmlFormatOptions {
+            preserve_mixed_content: true,
+            ..spaces(2)
+        };
+        let result = format_xml("<p>Hello <b>bold</b> <i>it</i></p>", &options).unwrap();
+        assert_eq!(result, "<p>Hello <b>bold</b> <i>it</i></p>");
+        let result = format_xml("<r>a<![CDATA[x]]> <b/></r>", &options).unwrap();
+        assert_eq!(result, "<r>a<![CDATA[x]]> <b/></r>");
+        // Text keeps its original escaping
+        let result = format_xml(
+            "<p>a &amp; 'b'</p>",
+            &XmlFormatOptions {
+                indent: IndentStyle::Tabs,
+                preserve_mixed_content: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(result, "<p>a &amp; 'b'</p>");
+    }
+
+    #[test]
+    fn test_parse_error_position() {
+        let err = format_xml("<root>\n  <a></b>\n</root>", &spaces(2)).unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn test_sort_attributes() {
+        let input =
+            r#"<root z="1" xmlns:b="urn:b" a="2" xmlns="urn:d"><c b:y="3" b:x="4"/></root>"#;
+        let result = minify_xml(
+            input,
+            &XmlFormatOptions {
+                sort_attributes: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            result,
+            r#"<root xmlns="urn:d" xmlns:b="urn:b" a="2" z="1"><c b:x="4" b:y="3"/></root>"#
+        );
+        let result = format_xml(
+            input,
+            &XmlFormatOptions {
+                sort_attributes: true,
+                ..spaces(2)
+            },
+        )
+        .unwrap();
+        assert!(result.starts_with(r#"<root xmlns="urn:d" xmlns:b="urn:b" a="2" z="1">"#));
+        // Source order is kept by default
+        assert_eq!(
+            minify_xml(input, &XmlFormatOptions::default()).unwrap(),
+            input
+        );
+    }
+
+    #[test]
+    fn test_wrap_attributes() {
+        let input = r#"<manifest><LinearLayout android:layout_width="match_parent" android:layout_height="wrap_content"><TextView a="1" b="2"/></LinearLayout></manifest>"#;
+        let result = format_xml(
+            input,
+            &XmlFormatOptions {
+                wrap_attributes_width: Some(40),
+                ..spaces(4)
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            result,
+            concat!(
+                "<manifest>\n    <LinearLayout\n        android:layout_width=\"match_parent\"\n",
+                "        android:layout_height=\"wrap_content\">\n        <TextView a=\"1\" b=\"2\"/>\n",
+                "    </LinearLayout>\n</manifest>"
+            )
+        );
+        // A single attribute is never wrapped
+        let result = format_xml(
+            r#"<a long="xxxxxxxxxxxxxxxxxxxx"/>"#,
+            &XmlFormatOptions {
+                wrap_attributes_width: Some(10),
+                ..spaces(2)
+            },
+        )
+        .unwrap();
+        assert_eq!(result, r#"<a long="xxxxxxxxxxxxxxxxxxxx"/>"#);
+    }
+
+    #[test]
+    fn test_empty_element_styles() {
+        let input = r#"<root><a x="1"></a><b/><c>text</c></root>"#;
+        assert_eq!(
+            minify_xml(
+                input,
+                &XmlFormatOptions {
+                    empty_elements: EmptyElementStyle::SelfClosing,
+                    ..Default::default()
+                }
+            )
+            .unwrap(),
+            r#"<root><a x="1"/><b/><c>text</c></root>"#
+        );
+        assert_eq!(
+            minify_xml(
+                input,
+                &XmlFormatOptions {
+                    empty_elements: EmptyElementStyle::Expanded,
+                    ..Default::default()
+                }
+            )
+            .unwrap(),
+            r#"<root><a x="1"></a><b></b><c>text</c></root>"#
+        );
+        let result = format_xml(
+            input,
+            &XmlFormatOptions {
+                empty_elements: EmptyElementStyle::SelfClosing,
+                ..spaces(2)
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            result,
+            "<root>\n  <a x=\"1\"/>\n  <b/>\n  <c>text</c>\n</root>"
+        );
+        let result = format_xml(
+            input,
+            &XmlFormatOptions {
+                empty_elements: EmptyElementStyle::Expanded,
+                ..spaces(2)
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            result,
+            "<root>\n  <a x=\"1\"></a>\n  <b></b>\n  <c>text</c>\n</root>"
+        );
+    }
+
+    #[test]
+    fn test_comments_and_newlines() {
+        let input = "<root><!-- note --><a/></root>";
+        let options = XmlFormatOptions {
+            comments: CommentHandling::Strip,
+            newline: NewlineStyle::Crlf,
+            ..spaces(2)
+        };
+        assert_eq!(
+            format_xml(input, &options).unwrap(),
+            "<root>\r\n  <a/>\r\n</root>"
+        );
+        assert_eq!(minify_xml(input, &options).unwrap(), "<root><a/></root>");
+    }
+
+    #[test]
+    fn test_options_deserialize() {
+        let options: XmlFormatOptions = serde_json::from_str(
+            r#"{"indent":"tabs","sortAttributes":true,"emptyElements":"selfClosing","comments":"strip","newline":"crlf"}"#,
+        )
+        .unwrap();
+        assert_eq!(options.indent, IndentStyle::Tabs);
+        assert!(options.sort_attributes);
+        assert_eq!(options.empty_elements, EmptyElementStyle::SelfClosing);
+        assert_eq!(options.newline, NewlineStyle::Crlf);
+        assert!(serde_json::from_str::<XmlFormatOptions>(r#"{"indent":"spaces:x"}"#).is_err());
+    }
+
+    #[test]
+    fn test_xml_space_preserve() {
+        let input = "<doc>\n <pre xml:space=\"preserve\">  a\n  <b> x </b>\n</pre>\n <p>  text  </p>\n</doc>";
+        assert_eq!(
+            format_xml(input, &spaces(2)).unwrap(),
+            "<doc>\n  <pre xml:space=\"preserve\">  a\n  <b> x </b>\n</pre>\n  <p>text</p>\n</doc>"
+        );
+        assert_eq!(
+            minify_xml(input, &XmlFormatOptions::default()).unwrap(),
+            "<doc><pre xml:space=\"preserve\">  a\n  <b> x </b>\n</pre><p>text</p></doc>"
+        );
+        // A nested xml:space="default" stays verbatim; elsewhere it changes nothing
+        let input = "<a xml:space=\"preserve\"><b xml:space=\"default\">\n<c/> </b></a>";
+        assert_eq!(format_xml(input, &spaces(2)).unwrap(), input);
+        let input = "<r><a xml:space=\"default\">\n<c/> </a></r>";
+        assert_eq!(
+            format_xml(input, &spaces(2)).unwrap(),
+            "<r>\n  <a xml:space=\"default\">\n    <c/>\n  </a>\n</r>"
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let limited = |limits: XmlLimits| XmlFormatOptions {
+            limits,
+            ..spaces(2)
+        };
+        let err = format_xml(
+            "<a/>",
+            &limited(XmlLimits {
+                max_input_bytes: 3,
+                ..Default::default()
+            }),
+        )
+        .unwrap_err();
+        assert!(err.message.contains("limit of 3"));
+        assert_eq!((err.line, err.column), (1, 1));
+        // The guard runs before HTML conversion and decoding
+        let tiny = XmlFormatOptions {
+            html: true,
+            ..limited(XmlLimits {
+                max_input_bytes: 3,
+                ..Default::default()
+            })
+        };
+        assert!(minify_xml("<br>", &tiny)
+            .unwrap_err()
+            .message
+            .contains("limit of 3"));
+        assert!(format_xml_bytes(b"<a/>", &tiny)
+            .unwrap_err()
+            .message
+            .contains("limit of 3"));
+
+        let err = format_xml(
+            "<a>\n<b><c/></b></a>",
+            &limited(XmlLimits {
+                max_depth: 1,
+                ..Default::default()
+            }),
+        )
+        .unwrap_err();
+        assert_eq!((err.line, err.column), (2, 1));
+
+        let input = r#"<a x="1" y="2" z="3"/>"#;
+        assert!(format_xml(
+            input,
+            &limited(XmlLimits {
+                max_attributes: 2,
+                ..Default::default()
+            })
+        )
+        .is_err());
+        assert!(format_xml(
+            input,
+            &limited(XmlLimits {
+                max_attributes: 3,
+                ..Default::default()
+            })
+        )
+        .is_ok());
+
+        let input = r#"<a t="&amp;">&lt;&gt;</a>"#;
+        assert!(minify_xml(
+            input,
+            &limited(XmlLimits {
+                max_entity_expansions: 2,
+                ..Default::default()
+            })
+        )
+        .is_err());
+        assert!(minify_xml(
+            input,
+            &limited(XmlLimits {
+                max_entity_expansions: 3,
+                ..Default::default()
+            })
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_html_mode() {
+        let input = "<ul><li>one<li>two<br></ul>";
+        assert!(format_xml(input, &spaces(2)).is_err());
+        let options = XmlFormatOptions {
+            html: true,
+            ..spaces(2)
+        };
+        assert_eq!(
+            format_xml(input, &options).unwrap(),
+            "<ul>\n  <li>one</li>\n  <li>two<br/>\n  </li>\n</ul>"
+        );
+        assert_eq!(
+            minify_xml(input, &options).unwrap(),
+            "<ul><li>one</li><li>two<br/></li></ul>"
+        );
+    }
+
+    #[test]
+    fn test_byte_input() {
+        let utf16: Vec<u8> = [0xFF, 0xFE]
+            .into_iter()
+            .chain("<a><b>é</b></a>".encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        assert_eq!(
+            format_xml_bytes(&utf16, &spaces(2)).unwrap(),
+            "<a>\n  <b>é</b>\n</a>"
+        );
+        let latin1 = b"<?xml version=\"1.0\" encoding=\"latin1\"?>\n<a> \xE9 </a>";
+        assert_eq!(
+            minify_xml_bytes(latin1, &XmlFormatOptions::default()).unwrap(),
+            "<?xml version=\"1.0\" encoding=\"latin1\"?><a>é</a>"
+        );
+    }
+
+    #[test]
+    fn test_strip_processing_instructions() {
+        let input = "<?xml version=\"1.0\"?><?xml-stylesheet href=\"a.xsl\"?><r><?app data?><!-- c --><a/></r>";
+        let options = XmlFormatOptions {
+            comments: CommentHandling::Strip,
+            strip_processing_instructions: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            minify_xml(input, &options).unwrap(),
+            "<?xml version=\"1.0\"?><r><a/></r>"
+        );
+        assert_eq!(
+            format_xml(
+                input,
+                &XmlFormatOptions {
+                    indent: IndentStyle::Spaces(2),
+                    ..options
+                }
+            )
+            .unwrap(),
+            "<?xml version=\"1.0\"?>\n<r>\n  <a/>\n</r>"
+        );
+    }
+
+    #[test]
+    fn test_cdata_handling() {
+        let input = "<r><a><![CDATA[x < y]]></a><b>if a &lt; b &amp;&amp; c</b><c>1 &lt; 2</c></r>";
+        let unwrap = XmlFormatOptions {
+            cdata: CdataHandling::Unwrap,
+            ..Default::default()
+        };
+        assert_eq!(
+            minify_xml(input, &unwrap).unwrap(),
+            "<r><a>x &lt; y</a><b>if a &lt; b &amp;&amp; c</b><c>1 &lt; 2</c></r>"
+        );
+        let wrap = XmlFormatOptions {
+            cdata: CdataHandling::Wrap,
+            ..Default::default()
+        };
+        assert_eq!(
+            minify_xml(input, &wrap).unwrap(),
+            "<r><a><![CDATA[x < y]]></a><b><![CDATA[if a < b && c]]></b><c>1 &lt; 2</c></r>"
+        );
+        // Text that would end a CDATA section early stays escaped
+        assert_eq!(
+            minify_xml("<r>]]&gt; &lt;&lt;</r>", &wrap).unwrap(),
+            "<r>]]&gt; &lt;&lt;</r>"
+        );
+    }
+
+    #[test]
+    fn test_doctype_internal_subset() {
+        let subset = concat!(
+            "<!DOCTYPE root [\n  <!ENTITY e \"x > y\">\n  <!ENTITY % p \"z\">\n",
+            "  <!-- ] > -->\n  <!ATTLIST root a CDATA \"]>\">\n]>"
+        );
+        let input = format!(
+            "<?xml version=\"1.0\"?>\n{}\n<root><a>&e; &amp; 'q'</a></root>",
+            subset
+        );
+        assert_eq!(
+            format_xml(&input, &spaces(2)).unwrap(),
+            format!(
+                "<?xml version=\"1.0\"?>\n{}\n<root>\n  <a>&e; &amp; &apos;q&apos;</a>\n</root>",
+                subset
+            )
+        );
+        assert_eq!(
+            minify_xml(&input, &XmlFormatOptions::default()).unwrap(),
+            format!(
+                "<?xml version=\"1.0\"?>{}<root><a>&e; &amp; &apos;q&apos;</a></root>",
+                subset
+            )
+        );
+        assert_eq!(declared_entities(subset), vec!["e"]);
+        // Undeclared entities are still rejected
+        assert!(format_xml("<!DOCTYPE r [<!ENTITY e \"x\">]><r>&f;</r>", &spaces(2)).is_err());
+        assert!(format_xml("<!DOCTYPE r [<!ENTITY e \"x>", &spaces(2)).is_err());
+    }
+
+    #[test]
+    fn test_fragments() {
+        let input = "<a>1</a><b><c/></b>\n<!-- tail -->";
+        assert_eq!(
+            format_xml(input, &spaces(2)).unwrap(),
+            "<a>1</a>\n<b>\n  <c/>\n</b>\n<!-- tail -->"
+        );
+        assert_eq!(
+            minify_xml(input, &XmlFormatOptions::default()).unwrap(),
+            "<a>1</a><b><c/></b><!-- tail -->"
+        );
+    }
+
+    #[test]
+    fn test_empty_pairs_and_short_text_on_one_line() {
+        let input = "<root><another></another><name>short text</name><list><item/></list></root>";
+        assert_eq!(
+            format_xml(input, &spaces(2)).unwrap(),
+            "<root>\n  <another></another>\n  <name>short text</name>\n  <list>\n    <item/>\n  </list>\n</root>"
+        );
+    }
 }
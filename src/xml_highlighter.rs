@@ -3,378 +3,607 @@
 //! Provides syntax highlighting for XML using a simple state machine parser.
 //! Mirrors the pattern from highlighter.rs for JSON.
 
-/// Color palette (VS Code dark theme inspired)
-mod colors {
-    pub const TAG: &str = "#569cd6";           // Blue for tags
-    pub const ATTR_NAME: &str = "#9cdcfe";     // Light blue for attribute names
-    pub const ATTR_VALUE: &str = "#ce9178";    // Orange for attribute values
-    pub const TEXT: &str = "#d4d4d4";          // Gray for text content
-    pub const COMMENT: &str = "#6a9955";       // Green for comments
-    pub const CDATA: &str = "#dcdcaa";         // Yellow for CDATA
-    pub const DECLARATION: &str = "#c586c0";   // Purple for XML declarations
-    pub const BRACKET: &str = "#808080";       // Gray for < > brackets
-    pub const ENTITY: &str = "#d7ba7d";        // Gold for entities like &amp;
+use crate::highlight::{
+    sort_regions, split_segments, FoldKind, FoldRegion, FormattedHighlight, HighlightOptions,
+    HighlightedChunk, HighlightedValidation, Language, MatchingPair, Output, Token, TokenCollector,
+    TokenKind,
+};
+use crate::types::FormatError;
+use crate::validator::validate_xml_all;
+use crate::xml_formatter::{format_xml, XmlFormatOptions};
+use serde::{Deserialize, Serialize};
+
+/// Where XML highlighting stopped, to continue with the next chunk.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct XmlHighlightState {
+    /// Lines highlighted before the next chunk
+    pub lines: usize,
+    /// Construct the previous chunk ended in
+    pub context: XmlContext,
 }
 
-/// Parser state
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum State {
+/// Multi-line constructs a chunk can end inside.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum XmlContext {
+    #[default]
     Text,
-    TagOpen,        // Just saw <
-    TagName,        // Reading tag name
-    TagClose,       // Reading </tagname
-    InTag,          // Inside tag, between attributes
-    AttrName,       // Reading attribute name
-    AttrEquals,     // After = before value
-    AttrValue,      // Reading attribute value
-    Comment,        // Inside <!-- -->
-    Cdata,          // Inside <![CDATA[ ]]>
-    Declaration,    // Inside <?xml ?>
-    Doctype,        // Inside <!DOCTYPE >
+    Comment,
+    Cdata,
+    /// XML declaration or processing instruction
+    ProcessingInstruction,
+    Doctype,
+    /// Inside a tag, after its name
+    Tag,
+    /// Inside an attribute value delimited by the given quote
+    AttrValue(char),
 }
 
 /// Highlights XML string and returns HTML with inline styles.
+///
+/// Never fails: fragments with several top-level elements and malformed
+/// input are highlighted as far as they go.
 pub fn highlight_xml(input: &str) -> String {
+    highlight_xml_with_options(input, &HighlightOptions::default())
+}
+
+/// Highlights XML with the given theme and options.
+///
+/// # Arguments
+/// * `input` - The XML string to highlight
+/// * `options` - Theme and output options
+///
+/// # Returns
+/// * Highlighted output in the requested target, HTML with inline styles by default
+/// * Empty string if input is empty
+pub fn highlight_xml_with_options(input: &str, options: &HighlightOptions) -> String {
     if input.is_empty() {
         return String::new();
     }
+    let mut output = Output::new(input, options, Language::Xml);
+    if options.mark_errors {
+        output.mark_errors(&validate_xml_all(input).errors);
+    }
+    scan_xml(output.input(), &mut XmlContext::Text, |kind, start, end| {
+        output.push(start, end, kind)
+    });
+    output.finish()
+}
 
-    let mut output = String::with_capacity(input.len() * 3);
-    output.push_str("<pre style=\"margin:0;font-family:inherit;\">");
+/// Formats XML and highlights the result in one call.
+///
+/// The input is parsed once, by the formatter; the formatted text is then
+/// highlighted without leaving Rust, so a page that shows both does not
+/// pass a large document across the JavaScript boundary twice.
+///
+/// # Arguments
+/// * `input` - The XML string to format
+/// * `format_options` - Indentation and layout options for the formatter
+/// * `options` - Theme and output options for the highlighting
+///
+/// # Returns
+/// * `Ok(FormattedHighlight)` - The formatted text and its highlighted output
+/// * `Err(FormatError)` - If the input cannot be formatted
+pub fn format_and_highlight_xml(
+    input: &str,
+    format_options: &XmlFormatOptions,
+    options: &HighlightOptions,
+) -> Result<FormattedHighlight, FormatError> {
+    let formatted = format_xml(input, format_options)?;
+    let html = highlight_xml_with_options(&formatted, options);
+    Ok(FormattedHighlight { formatted, html })
+}
 
-    let chars: Vec<char> = input.chars().collect();
-    let len = chars.len();
-    let mut i = 0;
-    let mut state = State::Text;
-    let mut buffer = String::new();
-    let mut quote_char: Option<char> = None;
+/// Validates XML and highlights it with the errors marked in place.
+///
+/// Marks every well-formedness error [`validate_xml_all`] finds. The text
+/// at an error is wrapped in `<span class="hl-error">` with the message as its
+/// tooltip, so problems show up in context rather than only as a message.
+///
+/// # Arguments
+/// * `input` - The XML string to validate and highlight
+/// * `options` - Theme and output options
+///
+/// # Returns
+/// * The highlighted output, whether the input is valid, and its errors
+pub fn highlight_xml_with_errors(input: &str, options: &HighlightOptions) -> HighlightedValidation {
+    let result = validate_xml_all(input);
+    let errors: Vec<_> = result.errors;
+    let html = if input.is_empty() {
+        String::new()
+    } else {
+        let mut output = Output::new(input, options, Language::Xml);
+        output.mark_errors(&errors);
+        scan_xml(output.input(), &mut XmlContext::Text, |kind, start, end| {
+            output.push(start, end, kind)
+        });
+        output.finish()
+    };
+    HighlightedValidation {
+        html,
+        is_valid: result.is_valid,
+        errors,
+    }
+}
 
-    while i < len {
-        let c = chars[i];
+/// Highlights one chunk of a larger XML document.
+///
+/// Feed the chunks in order, each with the state returned for the one
+/// before (the default state for the first). Chunks should end at line
+/// breaks; comments, CDATA sections, declarations, tags, and attribute
+/// values continue across chunks, names and entity references do not.
+/// HTML output has no `<pre>` wrapper, and with `line_numbers` the line spans
+/// continue the numbering of earlier chunks.
+///
+/// # Arguments
+/// * `chunk` - The next part of the XML document
+/// * `state` - State returned for the previous chunk
+/// * `options` - Theme and output options
+///
+/// # Returns
+/// * The chunk's output and the state to continue from
+pub fn highlight_xml_chunk(
+    chunk: &str,
+    state: &XmlHighlightState,
+    options: &HighlightOptions,
+) -> HighlightedChunk<XmlHighlightState> {
+    let mut context = state.context;
+    let mut output = Output::fragment(chunk, options, Language::Xml, state.lines);
+    scan_xml(chunk, &mut context, |kind, start, end| {
+        output.push(start, end, kind)
+    });
+    HighlightedChunk {
+        html: output.finish(),
+        state: XmlHighlightState {
+            lines: state.lines + chunk.matches('\n').count(),
+            context,
+        },
+    }
+}
 
-        match state {
-            State::Text => {
-                if c == '<' {
-                    // Flush text buffer
-                    if !buffer.is_empty() {
-                        push_colored_escaped(&mut output, &buffer, colors::TEXT);
-                        buffer.clear();
-                    }
-                    state = State::TagOpen;
-                    i += 1;
-                } else if c == '&' {
-                    // Entity reference
-                    if !buffer.is_empty() {
-                        push_colored_escaped(&mut output, &buffer, colors::TEXT);
-                        buffer.clear();
-                    }
-                    let (entity, end) = parse_entity(&chars, i);
-                    push_colored_escaped(&mut output, &entity, colors::ENTITY);
-                    i = end;
-                } else {
-                    buffer.push(c);
-                    i += 1;
-                }
+/// Highlights an XML document as separate segments of `lines_per_segment`
+/// lines each.
+///
+/// Segments are highlighted as consecutive chunks (see
+/// [`highlight_xml_chunk`]), so constructs spanning segments are colored as
+/// in the whole document and line numbers continue across them. The host
+/// can insert them into the page one at a time instead of building a
+/// single huge string.
+///
+/// # Arguments
+/// * `input` - The XML string to highlight
+/// * `lines_per_segment` - Lines in each segment; at least one
+/// * `options` - Theme and output options
+///
+/// # Returns
+/// * One output string per segment, without a `<pre>` wrapper; none for empty input
+pub fn highlight_xml_segments(
+    input: &str,
+    lines_per_segment: usize,
+    options: &HighlightOptions,
+) -> Vec<String> {
+    let mut state = XmlHighlightState::default();
+    split_segments(input, lines_per_segment, options)
+        .into_iter()
+        .map(|(segment, options)| {
+            let chunk = highlight_xml_chunk(segment, &state, &options);
+            state = chunk.state;
+            chunk.html
+        })
+        .collect()
+}
+
+/// Split XML into highlighting tokens.
+///
+/// Never fails: fragments and malformed input are tokenized as far as they
+/// go. Whitespace inside tags, `=`, and stray characters are not reported.
+/// Each token has its element nesting depth; tags are at the depth of the
+/// element they belong to.
+///
+/// # Arguments
+/// * `input` - The XML string to tokenize
+///
+/// # Returns
+/// * Tokens in input order
+pub fn tokenize_xml(input: &str) -> Vec<Token> {
+    let mut tokens = TokenCollector::new(input);
+    // Whether the tag being read is a start tag, whose content is one level deeper
+    let mut start_tag = false;
+    scan_xml(input, &mut XmlContext::Text, |kind, start, end| {
+        let bracket = if kind == TokenKind::Bracket {
+            &input[start..end]
+        } else {
+            ""
+        };
+        if bracket == "</" {
+            tokens.leave();
+        }
+        tokens.push(kind, start, end);
+        match bracket {
+            "<" => start_tag = true,
+            ">" if start_tag => {
+                tokens.enter();
+                start_tag = false;
             }
+            "" => {}
+            _ => start_tag = false,
+        }
+    });
+    tokens.finish()
+}
 
-            State::TagOpen => {
-                if c == '!' {
-                    // Could be comment, CDATA, or DOCTYPE
-                    if matches_str(&chars, i, "!--") {
-                        push_colored(&mut output, "&lt;!--", colors::COMMENT);
-                        state = State::Comment;
-                        i += 3;
-                    } else if matches_str(&chars, i, "![CDATA[") {
-                        push_colored(&mut output, "&lt;![CDATA[", colors::CDATA);
-                        state = State::Cdata;
-                        i += 8;
-                    } else if matches_str(&chars, i, "!DOCTYPE") {
-                        push_colored(&mut output, "&lt;!DOCTYPE", colors::DECLARATION);
-                        state = State::Doctype;
-                        i += 8;
-                    } else {
-                        push_colored(&mut output, "&lt;!", colors::BRACKET);
-                        state = State::Text;
-                        i += 1;
-                    }
-                } else if c == '?' {
-                    push_colored(&mut output, "&lt;?", colors::DECLARATION);
-                    state = State::Declaration;
-                    i += 1;
-                } else if c == '/' {
-                    push_colored(&mut output, "&lt;/", colors::BRACKET);
-                    state = State::TagClose;
-                    i += 1;
-                } else if c.is_alphabetic() || c == '_' || c == ':' {
-                    push_colored(&mut output, "&lt;", colors::BRACKET);
-                    buffer.push(c);
-                    state = State::TagName;
-                    i += 1;
-                } else {
-                    push_colored(&mut output, "&lt;", colors::BRACKET);
-                    state = State::Text;
+/// Find the elements, comments, and CDATA sections that span several lines,
+/// for code folding.
+///
+/// Works on fragments and malformed XML; an end tag closes the nearest open
+/// element of the same name.
+///
+/// # Arguments
+/// * `input` - The XML string to scan
+///
+/// # Returns
+/// * Regions sorted by start line, with summaries like `<item>…</item>`
+pub fn xml_fold_regions(input: &str) -> Vec<FoldRegion> {
+    let mut regions = Vec::new();
+    // Open elements with the line of their start tag
+    let mut open: Vec<(&str, usize)> = Vec::new();
+    // Bracket before the current tag name, and the start tag being read
+    let mut bracket = "";
+    let mut start_tag: Option<(&str, usize)> = None;
+    for token in tokenize_xml(input) {
+        let text = &input[token.start..token.end];
+        let end_line = token.line + text.matches('\n').count();
+        match token.kind {
+            TokenKind::Bracket => {
+                if text == ">" {
+                    open.extend(start_tag.take());
                 }
+                start_tag = None;
+                bracket = text;
             }
-
-            State::TagName => {
-                if c.is_alphanumeric() || c == '_' || c == '-' || c == '.' || c == ':' {
-                    buffer.push(c);
-                    i += 1;
-                } else {
-                    // Flush tag name
-                    push_colored_escaped(&mut output, &buffer, colors::TAG);
-                    buffer.clear();
-                    if c == '>' {
-                        push_colored(&mut output, "&gt;", colors::BRACKET);
-                        state = State::Text;
-                        i += 1;
-                    } else if c == '/' {
-                        if i + 1 < len && chars[i + 1] == '>' {
-                            push_colored(&mut output, "/&gt;", colors::BRACKET);
-                            state = State::Text;
-                            i += 2;
-                        } else {
-                            output.push('/');
-                            i += 1;
-                        }
-                    } else {
-                        state = State::InTag;
+            TokenKind::Tag if bracket == "<" => start_tag = Some((text, token.line)),
+            TokenKind::Tag if bracket == "</" => {
+                if let Some(index) = open.iter().rposition(|(name, _)| *name == text) {
+                    let (name, start_line) = open[index];
+                    open.truncate(index);
+                    if token.line > start_line {
+                        regions.push(FoldRegion {
+                            start_line,
+                            end_line: token.line,
+                            kind: FoldKind::Element,
+                            summary: format!("<{}>…</{}>", name, name),
+                        });
                     }
                 }
             }
-
-            State::TagClose => {
-                if c.is_alphanumeric() || c == '_' || c == '-' || c == '.' || c == ':' {
-                    buffer.push(c);
-                    i += 1;
-                } else if c == '>' {
-                    push_colored_escaped(&mut output, &buffer, colors::TAG);
-                    buffer.clear();
-                    push_colored(&mut output, "&gt;", colors::BRACKET);
-                    state = State::Text;
-                    i += 1;
+            TokenKind::Comment | TokenKind::Cdata if end_line > token.line => {
+                let (kind, summary) = if token.kind == TokenKind::Comment {
+                    (FoldKind::Comment, "<!--…-->")
                 } else {
-                    push_colored_escaped(&mut output, &buffer, colors::TAG);
-                    buffer.clear();
-                    state = State::InTag;
-                }
+                    (FoldKind::Cdata, "<![CDATA[…]]>")
+                };
+                regions.push(FoldRegion {
+                    start_line: token.line,
+                    end_line,
+                    kind,
+                    summary: summary.to_string(),
+                });
             }
+            _ => {}
+        }
+    }
+    sort_regions(&mut regions);
+    regions
+}
 
-            State::InTag => {
-                if c.is_whitespace() {
-                    output.push(c);
-                    i += 1;
-                } else if c == '>' {
-                    push_colored(&mut output, "&gt;", colors::BRACKET);
-                    state = State::Text;
-                    i += 1;
-                } else if c == '/' {
-                    if i + 1 < len && chars[i + 1] == '>' {
-                        push_colored(&mut output, "/&gt;", colors::BRACKET);
-                        state = State::Text;
-                        i += 2;
-                    } else {
-                        output.push('/');
-                        i += 1;
+/// Pair each start tag with its end tag, so the page can highlight the
+/// matching tag under the cursor.
+///
+/// Like [`xml_fold_regions`], an end tag closes the nearest open element of
+/// the same name; unclosed elements and stray end tags are left out.
+///
+/// # Arguments
+/// * `input` - The XML string to scan
+///
+/// # Returns
+/// * Matched pairs of whole tags, sorted by the position of the start tag
+pub fn xml_matching_pairs(input: &str) -> Vec<MatchingPair> {
+    let mut pairs = Vec::new();
+    // Open elements with the range of their start tag
+    let mut open: Vec<(&str, (usize, usize))> = Vec::new();
+    // Bracket before the current tag name, and where it starts
+    let mut bracket = ("", 0);
+    // Start tag being read, or the open element an end tag being read closes
+    let mut start_tag: Option<(&str, usize)> = None;
+    let mut end_tag: Option<(usize, usize)> = None;
+    for token in tokenize_xml(input) {
+        let text = &input[token.start..token.end];
+        match token.kind {
+            TokenKind::Bracket => {
+                if text == ">" {
+                    if let Some((name, start)) = start_tag {
+                        open.push((name, (start, token.end)));
                     }
-                } else if c.is_alphabetic() || c == '_' || c == ':' {
-                    buffer.push(c);
-                    state = State::AttrName;
-                    i += 1;
-                } else {
-                    output.push(c);
-                    i += 1;
-                }
-            }
-
-            State::AttrName => {
-                if c.is_alphanumeric() || c == '_' || c == '-' || c == '.' || c == ':' {
-                    buffer.push(c);
-                    i += 1;
-                } else {
-                    push_colored_escaped(&mut output, &buffer, colors::ATTR_NAME);
-                    buffer.clear();
-                    if c == '=' {
-                        output.push('=');
-                        state = State::AttrEquals;
-                        i += 1;
-                    } else {
-                        state = State::InTag;
+                    if let Some((index, start)) = end_tag {
+                        pairs.push(MatchingPair {
+                            open: open[index].1,
+                            close: (start, token.end),
+                        });
+                        open.truncate(index);
                     }
                 }
+                start_tag = None;
+                end_tag = None;
+                bracket = (text, token.start);
             }
-
-            State::AttrEquals => {
-                if c == '"' || c == '\'' {
-                    quote_char = Some(c);
-                    buffer.push(c);
-                    state = State::AttrValue;
-                    i += 1;
-                } else if c.is_whitespace() {
-                    output.push(c);
-                    i += 1;
-                } else {
-                    state = State::InTag;
-                }
+            TokenKind::Tag if bracket.0 == "<" => start_tag = Some((text, bracket.1)),
+            TokenKind::Tag if bracket.0 == "</" => {
+                end_tag = open
+                    .iter()
+                    .rposition(|(name, _)| *name == text)
+                    .map(|index| (index, bracket.1));
             }
+            _ => {}
+        }
+    }
+    pairs.sort_by_key(|pair| pair.open.0);
+    pairs
+}
 
-            State::AttrValue => {
-                if Some(c) == quote_char {
-                    buffer.push(c);
-                    push_colored_escaped(&mut output, &buffer, colors::ATTR_VALUE);
-                    buffer.clear();
-                    quote_char = None;
-                    state = State::InTag;
-                    i += 1;
-                } else {
-                    buffer.push(c);
-                    i += 1;
+/// Run the highlighting state machine, reporting each token's kind and byte range.
+///
+/// Starts inside `context` and leaves it set to the construct the input
+/// ended in, or `Text`.
+fn scan_xml(input: &str, context: &mut XmlContext, mut emit: impl FnMut(TokenKind, usize, usize)) {
+    let bytes = input.as_bytes();
+    let mut i = resume(input, context, &mut emit);
+    let mut text_start = i;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'<' | b'&' => {
+                // Flush text
+                if text_start < i {
+                    emit(TokenKind::Text, text_start, i);
                 }
-            }
-
-            State::Comment => {
-                if matches_str(&chars, i, "-->") {
-                    if !buffer.is_empty() {
-                        push_colored_escaped(&mut output, &buffer, colors::COMMENT);
-                        buffer.clear();
-                    }
-                    push_colored(&mut output, "--&gt;", colors::COMMENT);
-                    state = State::Text;
-                    i += 3;
+                i = if bytes[i] == b'<' {
+                    scan_markup(input, i, context, &mut emit)
                 } else {
-                    buffer.push(c);
-                    i += 1;
-                }
+                    let end = entity_end(bytes, i);
+                    emit(TokenKind::Entity, i, end);
+                    end
+                };
+                text_start = i;
             }
+            _ => i += 1,
+        }
+    }
+    if text_start < bytes.len() {
+        emit(TokenKind::Text, text_start, bytes.len());
+    }
+}
 
-            State::Cdata => {
-                if matches_str(&chars, i, "]]>") {
-                    if !buffer.is_empty() {
-                        push_colored_escaped(&mut output, &buffer, colors::CDATA);
-                        buffer.clear();
-                    }
-                    push_colored(&mut output, "]]&gt;", colors::CDATA);
-                    state = State::Text;
-                    i += 3;
-                } else {
-                    buffer.push(c);
-                    i += 1;
-                }
+/// Finish the construct a previous chunk ended in, returning where text resumes.
+fn resume(
+    input: &str,
+    context: &mut XmlContext,
+    emit: &mut impl FnMut(TokenKind, usize, usize),
+) -> usize {
+    let open = std::mem::take(context);
+    match open {
+        XmlContext::Text => 0,
+        XmlContext::Comment => scan_until(input, 0, 0, TokenKind::Comment, context, emit),
+        XmlContext::Cdata => scan_until(input, 0, 0, TokenKind::Cdata, context, emit),
+        XmlContext::ProcessingInstruction => {
+            scan_until(input, 0, 0, TokenKind::Declaration, context, emit)
+        }
+        XmlContext::Doctype => scan_doctype(input, 0, context, emit),
+        XmlContext::Tag => scan_attributes(input, 0, context, emit),
+        XmlContext::AttrValue(quote) => match input.find(quote) {
+            Some(i) => {
+                emit(TokenKind::AttrValue, 0, i + 1);
+                scan_attributes(input, i + 1, context, emit)
             }
-
-            State::Declaration => {
-                if matches_str(&chars, i, "?>") {
-                    if !buffer.is_empty() {
-                        push_colored_escaped(&mut output, &buffer, colors::DECLARATION);
-                        buffer.clear();
-                    }
-                    push_colored(&mut output, "?&gt;", colors::DECLARATION);
-                    state = State::Text;
-                    i += 2;
-                } else {
-                    buffer.push(c);
-                    i += 1;
-                }
+            None => {
+                emit(TokenKind::AttrValue, 0, input.len());
+                *context = open;
+                input.len()
             }
+        },
+    }
+}
 
-            State::Doctype => {
-                if c == '>' {
-                    if !buffer.is_empty() {
-                        push_colored_escaped(&mut output, &buffer, colors::DECLARATION);
-                        buffer.clear();
-                    }
-                    push_colored(&mut output, "&gt;", colors::BRACKET);
-                    state = State::Text;
-                    i += 1;
-                } else {
-                    buffer.push(c);
-                    i += 1;
-                }
-            }
-        }
+/// Scan markup starting with `<` at `start`, returning where text resumes.
+fn scan_markup(
+    input: &str,
+    start: usize,
+    context: &mut XmlContext,
+    emit: &mut impl FnMut(TokenKind, usize, usize),
+) -> usize {
+    let rest = &input[start..];
+    if rest.starts_with("<!--") {
+        scan_until(input, start, start + 4, TokenKind::Comment, context, emit)
+    } else if rest.starts_with("<![CDATA[") {
+        scan_until(input, start, start + 9, TokenKind::Cdata, context, emit)
+    } else if rest.starts_with("<!DOCTYPE") {
+        scan_doctype(input, start, context, emit)
+    } else if rest.starts_with("<?") {
+        scan_until(
+            input,
+            start,
+            start + 2,
+            TokenKind::Declaration,
+            context,
+            emit,
+        )
+    } else if rest.starts_with("<!") {
+        emit(TokenKind::Bracket, start, start + 2);
+        start + 2
+    } else if rest.starts_with("</") {
+        emit(TokenKind::Bracket, start, start + 2);
+        scan_tag(input, start + 2, context, emit)
+    } else if rest[1..].starts_with(is_name_start) {
+        emit(TokenKind::Bracket, start, start + 1);
+        scan_tag(input, start + 1, context, emit)
+    } else {
+        emit(TokenKind::Bracket, start, start + 1);
+        start + 1
     }
+}
 
-    // Flush remaining buffer
-    if !buffer.is_empty() {
-        let color = match state {
-            State::Text => colors::TEXT,
-            State::Comment => colors::COMMENT,
-            State::Cdata => colors::CDATA,
-            State::Declaration | State::Doctype => colors::DECLARATION,
-            _ => colors::TEXT,
-        };
-        push_colored_escaped(&mut output, &buffer, color);
+/// Emit a comment, CDATA, or declaration token from `start` through its
+/// terminator, searched for from `from`. If the terminator is missing the
+/// token runs to the end of input and `context` records the open construct.
+fn scan_until(
+    input: &str,
+    start: usize,
+    from: usize,
+    kind: TokenKind,
+    context: &mut XmlContext,
+    emit: &mut impl FnMut(TokenKind, usize, usize),
+) -> usize {
+    let (terminator, open) = match kind {
+        TokenKind::Comment => ("-->", XmlContext::Comment),
+        TokenKind::Cdata => ("]]>", XmlContext::Cdata),
+        _ => ("?>", XmlContext::ProcessingInstruction),
+    };
+    let end = match input[from.min(input.len())..].find(terminator) {
+        Some(i) => from + i + terminator.len(),
+        None => {
+            *context = open;
+            input.len()
+        }
+    };
+    if start < end {
+        emit(kind, start, end);
     }
+    end
+}
 
-    output.push_str("</pre>");
-    output
+/// Scan a DOCTYPE up to and including the first `>`.
+fn scan_doctype(
+    input: &str,
+    start: usize,
+    context: &mut XmlContext,
+    emit: &mut impl FnMut(TokenKind, usize, usize),
+) -> usize {
+    let Some(gt) = input[start..].find('>').map(|i| start + i) else {
+        if start < input.len() {
+            emit(TokenKind::Declaration, start, input.len());
+        }
+        *context = XmlContext::Doctype;
+        return input.len();
+    };
+    if start < gt {
+        emit(TokenKind::Declaration, start, gt);
+    }
+    emit(TokenKind::Bracket, gt, gt + 1);
+    gt + 1
 }
 
-/// Check if a substring matches at position i
-fn matches_str(chars: &[char], start: usize, pattern: &str) -> bool {
-    let pattern_chars: Vec<char> = pattern.chars().collect();
-    if start + pattern_chars.len() > chars.len() {
-        return false;
+/// Scan a tag name and attributes up to and including `>` or `/>`.
+fn scan_tag(
+    input: &str,
+    start: usize,
+    context: &mut XmlContext,
+    emit: &mut impl FnMut(TokenKind, usize, usize),
+) -> usize {
+    let end = name_end(input, start);
+    if end > start {
+        emit(TokenKind::Tag, start, end);
     }
-    for (j, pc) in pattern_chars.iter().enumerate() {
-        if chars[start + j] != *pc {
-            return false;
+    scan_attributes(input, end, context, emit)
+}
+
+/// Scan attributes up to and including `>` or `/>`.
+fn scan_attributes(
+    input: &str,
+    start: usize,
+    context: &mut XmlContext,
+    emit: &mut impl FnMut(TokenKind, usize, usize),
+) -> usize {
+    let bytes = input.as_bytes();
+    let mut i = start;
+    while i < bytes.len() {
+        let rest = &input[i..];
+        if rest.starts_with('>') {
+            emit(TokenKind::Bracket, i, i + 1);
+            return i + 1;
+        } else if rest.starts_with("/>") {
+            emit(TokenKind::Bracket, i, i + 2);
+            return i + 2;
+        } else if rest.starts_with(is_name_start) {
+            let end = name_end(input, i);
+            let name = &input[i..end];
+            let kind = if name == "xmlns" || name.starts_with("xmlns:") {
+                TokenKind::Namespace
+            } else {
+                TokenKind::AttrName
+            };
+            emit(kind, i, end);
+            i = skip_whitespace(input, end);
+            if bytes.get(i) == Some(&b'=') {
+                i = skip_whitespace(input, i + 1);
+                if let Some(&quote @ (b'"' | b'\'')) = bytes.get(i) {
+                    let end = match input[i + 1..].find(quote as char) {
+                        Some(j) => i + j + 2,
+                        None => {
+                            emit(TokenKind::AttrValue, i, input.len());
+                            *context = XmlContext::AttrValue(quote as char);
+                            return input.len();
+                        }
+                    };
+                    emit(TokenKind::AttrValue, i, end);
+                    i = end;
+                }
+            }
+        } else {
+            // Whitespace, `/`, and stray characters stay uncolored
+            i += rest.chars().next().map_or(1, char::len_utf8);
         }
     }
-    true
+    *context = XmlContext::Tag;
+    i
 }
 
-/// Parse entity reference starting with &
-fn parse_entity(chars: &[char], start: usize) -> (String, usize) {
-    let mut result = String::new();
-    result.push('&');
+/// End of the entity reference starting with `&` at `start`: just past the
+/// `;`, or before the first character that cannot be part of the name.
+fn entity_end(bytes: &[u8], start: usize) -> usize {
     let mut i = start + 1;
-    let len = chars.len();
-
-    while i < len {
-        let c = chars[i];
-        result.push(c);
-        if c == ';' {
-            return (result, i + 1);
-        }
-        if !c.is_alphanumeric() && c != '#' {
-            break;
+    while i < bytes.len() {
+        match bytes[i] {
+            b';' => return i + 1,
+            b if b.is_ascii_alphanumeric() || b == b'#' => i += 1,
+            _ => break,
         }
-        i += 1;
     }
+    i
+}
 
-    (result, i)
+fn is_name_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == ':'
 }
 
-/// Push colored HTML span with HTML escaping
-fn push_colored_escaped(output: &mut String, text: &str, color: &str) {
-    output.push_str("<span style=\"color:");
-    output.push_str(color);
-    output.push_str("\">");
-    for c in text.chars() {
-        match c {
-            '<' => output.push_str("&lt;"),
-            '>' => output.push_str("&gt;"),
-            '&' => output.push_str("&amp;"),
-            '"' => output.push_str("&quot;"),
-            _ => output.push(c),
-        }
-    }
-    output.push_str("</span>");
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.' || c == ':'
+}
+
+fn name_end(input: &str, start: usize) -> usize {
+    input[start..]
+        .find(|c| !is_name_char(c))
+        .map_or(input.len(), |i| start + i)
 }
 
-/// Push colored HTML span (text already escaped)
-fn push_colored(output: &mut String, text: &str, color: &str) {
-    output.push_str("<span style=\"color:");
-    output.push_str(color);
-    output.push_str("\">");
-    output.push_str(text);
-    output.push_str("</span>");
+fn skip_whitespace(input: &str, start: usize) -> usize {
+    input[start..]
+        .find(|c: char| !c.is_whitespace())
+        .map_or(input.len(), |i| start + i)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::highlight::HighlightTheme;
 
     #[test]
     fn test_highlight_empty() {
@@ -401,21 +630,21 @@ mod tests {
     fn test_highlight_comment() {
         let result = highlight_xml("<!-- comment -->");
         assert!(result.contains("comment"));
-        assert!(result.contains(colors::COMMENT));
+        assert!(result.contains(&HighlightTheme::dark().xml.comment));
     }
 
     #[test]
     fn test_highlight_cdata() {
         let result = highlight_xml("<![CDATA[raw data]]>");
         assert!(result.contains("raw data"));
-        assert!(result.contains(colors::CDATA));
+        assert!(result.contains(&HighlightTheme::dark().xml.cdata));
     }
 
     #[test]
     fn test_highlight_declaration() {
         let result = highlight_xml(r#"<?xml version="1.0"?>"#);
         assert!(result.contains("xml"));
-        assert!(result.contains(colors::DECLARATION));
+        assert!(result.contains(&HighlightTheme::dark().xml.declaration));
     }
 
     #[test]
@@ -431,4 +660,284 @@ mod tests {
         assert!(!result.contains("<script>"));
         assert!(result.contains("&lt;script&gt;"));
     }
+
+    #[test]
+    fn test_highlight_fragment() {
+        let result = highlight_xml("<a>1</a><b/>");
+        assert!(result.contains(">a<") && result.contains(">b<"));
+        assert!(result.contains(">1<"));
+    }
+
+    #[test]
+    fn test_highlight_with_theme() {
+        let options = HighlightOptions {
+            theme: HighlightTheme::light(),
+            ..Default::default()
+        };
+        let result = highlight_xml_with_options("<a x=\"1\">t</a>", &options);
+        assert!(result.contains(&format!("color:{}\">a<", options.theme.xml.tag)));
+        assert!(result.contains(&format!("color:{}\">x<", options.theme.xml.attr_name)));
+        assert!(!result.contains(&HighlightTheme::dark().xml.tag));
+    }
+
+    #[test]
+    fn test_namespace_declarations() {
+        let input =
+            r#"<s:Envelope xmlns:s="urn:s" xmlns="urn:d" s:mustUnderstand="1" xmlnsx="2"/>"#;
+        let kinds: Vec<_> = tokenize_xml(input)
+            .into_iter()
+            .filter(|t| matches!(t.kind, TokenKind::AttrName | TokenKind::Namespace))
+            .map(|t| (t.kind, &input[t.start..t.end]))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (TokenKind::Namespace, "xmlns:s"),
+                (TokenKind::Namespace, "xmlns"),
+                (TokenKind::AttrName, "s:mustUnderstand"),
+                (TokenKind::AttrName, "xmlnsx"),
+            ]
+        );
+        let colors = HighlightTheme::dark().xml;
+        assert!(highlight_xml(input).contains(&format!("color:{}\">xmlns:s<", colors.namespace)));
+    }
+
+    #[test]
+    fn test_line_numbers() {
+        let options = HighlightOptions {
+            line_numbers: true,
+            ..Default::default()
+        };
+        let result = highlight_xml_with_options("<a>\n<!-- x\ny -->\n</a>", &options);
+        assert_eq!(result.matches("<span class=\"line\"").count(), 4);
+        assert!(result.contains("data-line=\"4\""));
+        // The comment is split across lines but each half keeps its color
+        let comment = format!("color:{}", options.theme.xml.comment);
+        assert!(result.contains(&format!("data-line=\"3\"><span style=\"{}\">y", comment)));
+        assert_eq!(
+            result.matches("<span").count(),
+            result.matches("</span>").count()
+        );
+    }
+
+    #[test]
+    fn test_tokenize_xml() {
+        let input = "<?xml version=\"1.0\"?>\n<a x = '1'>&amp;t<!--c--></a>";
+        let tokens = tokenize_xml(input);
+        let kinds: Vec<(TokenKind, &str)> = tokens
+            .iter()
+            .map(|t| (t.kind, &input[t.start..t.end]))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (TokenKind::Declaration, "<?xml version=\"1.0\"?>"),
+                (TokenKind::Text, "\n"),
+                (TokenKind::Bracket, "<"),
+                (TokenKind::Tag, "a"),
+                (TokenKind::AttrName, "x"),
+                (TokenKind::AttrValue, "'1'"),
+                (TokenKind::Bracket, ">"),
+                (TokenKind::Entity, "&amp;"),
+                (TokenKind::Text, "t"),
+                (TokenKind::Comment, "<!--c-->"),
+                (TokenKind::Bracket, "</"),
+                (TokenKind::Tag, "a"),
+                (TokenKind::Bracket, ">"),
+            ]
+        );
+        assert_eq!((tokens[4].line, tokens[4].column), (2, 4));
+        let depths: Vec<usize> = tokens.iter().map(|t| t.depth).collect();
+        assert_eq!(depths, [0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 0, 0, 0]);
+        // Empty elements and declarations open nothing
+        let depths: Vec<usize> = tokenize_xml("<a><b/><c>x</c></a>")
+            .iter()
+            .map(|t| t.depth)
+            .collect();
+        assert_eq!(depths, [0, 0, 0, 1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_tokenize_unterminated() {
+        let tokens = tokenize_xml("<a b=\"x");
+        assert_eq!(
+            tokens.last().map(|t| (t.kind, t.end)),
+            Some((TokenKind::AttrValue, 7))
+        );
+        let tokens = tokenize_xml("x & y");
+        assert_eq!(
+            tokens.iter().map(|t| t.kind).collect::<Vec<_>>(),
+            [TokenKind::Text, TokenKind::Entity, TokenKind::Text]
+        );
+    }
+
+    /// Each output character with the color it is shown in.
+    fn colored_chars(html: &str) -> Vec<(char, &str)> {
+        let mut chars = Vec::new();
+        let mut color = "";
+        let mut rest = html;
+        while let Some(c) = rest.chars().next() {
+            if c == '<' {
+                let end = rest.find('>').unwrap() + 1;
+                color = rest[..end]
+                    .strip_prefix("<span style=\"color:")
+                    .map_or("", |c| c.trim_end_matches("\">"));
+                rest = &rest[end..];
+            } else {
+                chars.push((c, color));
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+        chars
+    }
+
+    #[test]
+    fn test_format_and_highlight() {
+        let format_options = XmlFormatOptions::default();
+        let options = HighlightOptions::default();
+        let result =
+            format_and_highlight_xml("<a><b>t</b></a>", &format_options, &options).unwrap();
+        assert_eq!(
+            result.formatted,
+            format_xml("<a><b>t</b></a>", &format_options).unwrap()
+        );
+        assert_eq!(
+            result.html,
+            highlight_xml_with_options(&result.formatted, &options)
+        );
+        assert!(format_and_highlight_xml("<a></b>", &format_options, &options).is_err());
+    }
+
+    #[test]
+    fn test_highlight_with_errors() {
+        let result =
+            highlight_xml_with_errors("<a>\n  <b></c>\n</a>", &HighlightOptions::default());
+        assert!(!result.is_valid);
+        assert_eq!(result.errors[0].line, 2);
+        // An error spanning several tokens is marked in each of them
+        for error in &result.errors {
+            let title = format!(
+                "title=\"{}\"",
+                error.message.replace('<', "&lt;").replace('>', "&gt;")
+            );
+            assert_eq!(result.html.matches(&title).count(), 3);
+        }
+        assert!(result.html.contains("text-decoration:underline wavy"));
+
+        let result = highlight_xml_with_errors("<a/>", &HighlightOptions::default());
+        assert!(result.is_valid && result.errors.is_empty());
+        assert_eq!(result.html, highlight_xml("<a/>"));
+    }
+
+    #[test]
+    fn test_highlight_with_errors_entities() {
+        let options = HighlightOptions::default();
+        assert!(highlight_xml_with_errors("<r>a &amp; b</r>", &options).is_valid);
+        assert!(highlight_xml_with_errors("<r>&lt;&gt;</r>", &options).is_valid);
+        let mixed = r#"<!DOCTYPE r [<!ENTITY e "x">]><r>&e; &amp;</r>"#;
+        assert!(highlight_xml_with_errors(mixed, &options).is_valid);
+        assert!(!highlight_xml_with_errors("<r>&e;</r>", &options).is_valid);
+    }
+
+    #[test]
+    fn test_matching_pairs() {
+        let input = r#"<a x="1"><b/><c>t</c><!-- </a> --></a></d>"#;
+        let pairs: Vec<_> = xml_matching_pairs(input)
+            .iter()
+            .map(|p| {
+                (
+                    &input[p.open.0..p.open.1],
+                    &input[p.close.0..p.close.1],
+                    p.open.0,
+                )
+            })
+            .collect();
+        assert_eq!(pairs, [(r#"<a x="1">"#, "</a>", 0), ("<c>", "</c>", 13)]);
+        // An end tag closes the nearest open element of its name
+        let input = "<a><b><c></b></a>";
+        let pairs: Vec<_> = xml_matching_pairs(input)
+            .iter()
+            .map(|p| (p.open.0, p.close.0))
+            .collect();
+        assert_eq!(pairs, [(0, 13), (3, 9)]);
+    }
+
+    #[test]
+    fn test_fold_regions() {
+        let input = "<root>\n  <a x=\"1\"\n     y=\"2\"/>\n  <b><c>\n  </c></b>\n  <!-- one\n  two -->\n  <d>t</d>\n</root>";
+        let regions: Vec<(usize, usize, FoldKind, String)> = xml_fold_regions(input)
+            .into_iter()
+            .map(|r| (r.start_line, r.end_line, r.kind, r.summary))
+            .collect();
+        assert_eq!(
+            regions,
+            [
+                (1, 9, FoldKind::Element, "<root>…</root>".to_string()),
+                (4, 5, FoldKind::Element, "<b>…</b>".to_string()),
+                (4, 5, FoldKind::Element, "<c>…</c>".to_string()),
+                (6, 7, FoldKind::Comment, "<!--…-->".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_segments() {
+        let input = "<a>\n<!-- one\ntwo -->\n<b x=\"1\"/>\n</a>";
+        let options = HighlightOptions::default();
+        let segments = highlight_xml_segments(input, 2, &options);
+        assert_eq!(segments.len(), 3);
+        // The comment continues into the second segment
+        let colors = &options.theme.xml;
+        assert!(segments[1].starts_with(&format!(
+            "<span style=\"color:{}\">two --&gt;",
+            colors.comment
+        )));
+        let whole = highlight_xml_chunk(input, &XmlHighlightState::default(), &options).html;
+        let split = format!("one\n</span><span style=\"color:{}\">", colors.comment);
+        assert_eq!(segments.concat().replace(&split, "one\n"), whole);
+    }
+
+    #[test]
+    fn test_chunks_continue_constructs() {
+        let options = HighlightOptions {
+            line_numbers: true,
+            ..Default::default()
+        };
+        let colors = &options.theme.xml;
+        let first = highlight_xml_chunk("<a x=\"1\n", &XmlHighlightState::default(), &options);
+        assert_eq!(first.state.context, XmlContext::AttrValue('"'));
+        assert!(!first.html.starts_with("<pre"));
+        let second = highlight_xml_chunk("2\" y='3'\n>t<!-- c\n", &first.state, &options);
+        assert_eq!(second.state.context, XmlContext::Comment);
+        assert_eq!(second.state.lines, 3);
+        assert!(second.html.starts_with(&format!(
+            "<span class=\"line\" data-line=\"2\"><span style=\"color:{}\">2\"",
+            colors.attr_value
+        )));
+        assert!(second
+            .html
+            .contains(&format!("color:{}\">y<", colors.attr_name)));
+        let third = highlight_xml_chunk("-->\n", &second.state, &options);
+        assert_eq!(third.state.context, XmlContext::Text);
+        assert!(third.html.contains(&format!(
+            "data-line=\"4\"><span style=\"color:{}\">--&gt;",
+            colors.comment
+        )));
+    }
+
+    #[test]
+    fn test_chunks_match_whole_document() {
+        let input = "<?xml version=\"1.0\"?>\n<r a=\"1\"\n   b=\"2\">\n<![CDATA[x\ny]]>&amp;</r>\n";
+        let options = HighlightOptions::default();
+        let mut state = XmlHighlightState::default();
+        let mut html = String::new();
+        for line in input.split_inclusive('\n') {
+            let chunk = highlight_xml_chunk(line, &state, &options);
+            html.push_str(&chunk.html);
+            state = chunk.state;
+        }
+        // Same colors and text as the whole document, though spans split at chunk ends
+        let whole = highlight_xml_with_options(input, &options);
+        assert_eq!(colored_chars(&html), colored_chars(&whole));
+    }
 }
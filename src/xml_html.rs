@@ -0,0 +1,453 @@
+//! HTML-tolerant input
+//!
+//! Users often paste HTML fragments into the XML tab. This module rewrites
+//! HTML-ish markup into well-formed XML so the regular formatter can lay it
+//! out: void elements (`<br>`) become self-closing, unquoted and valueless
+//! attributes are quoted, optional end tags (`<li>`, `<p>`, table cells)
+//! are inserted, stray end tags are dropped, and script and style bodies
+//! are wrapped in CDATA. A `<` that cannot start a tag, as in `a < b`, is
+//! escaped, and of repeated attributes only the first is kept.
+
+use crate::types::FormatError;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::parser::{ElementParser, Parser};
+use quick_xml::Reader;
+use std::borrow::Cow;
+
+/// Elements that never have content.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Elements whose content is raw text rather than markup.
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style"];
+
+/// Elements that implicitly close an open `<p>`.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "div",
+    "dl",
+    "fieldset",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "ul",
+];
+
+/// Named HTML entities outside XML's predefined five, as code points.
+const HTML_ENTITIES: &[(&str, u32)] = &[
+    ("nbsp", 160),
+    ("copy", 169),
+    ("reg", 174),
+    ("deg", 176),
+    ("middot", 183),
+    ("laquo", 171),
+    ("raquo", 187),
+    ("ndash", 8211),
+    ("mdash", 8212),
+    ("lsquo", 8216),
+    ("rsquo", 8217),
+    ("ldquo", 8220),
+    ("rdquo", 8221),
+    ("bull", 8226),
+    ("hellip", 8230),
+    ("euro", 8364),
+    ("trade", 8482),
+];
+
+/// Convert HTML-ish markup into well-formed XML.
+///
+/// # Arguments
+/// * `input` - The HTML or XML string to convert
+///
+/// # Returns
+/// * `Ok(String)` - Well-formed XML with the same content
+/// * `Err(FormatError)` - If the markup cannot be tokenized at all
+///   (for example an unterminated tag or comment)
+pub fn html_to_xml(input: &str) -> Result<String, FormatError> {
+    let escaped = escape_stray_lt(input);
+    let input: &str = &escaped;
+    let mut reader = lenient_reader(input);
+    // Offset of the reader's input within `input`; the reader restarts after raw text
+    let mut base = 0;
+
+    let mut out = String::with_capacity(input.len() + input.len() / 8);
+    // Open elements as written, compared case-insensitively
+    let mut open: Vec<String> = Vec::new();
+    loop {
+        let offset = base + reader.buffer_position() as usize;
+        let event = reader.read_event().map_err(|e| {
+            FormatError::at_offset(format!("HTML parse error: {}", e), input, offset)
+        })?;
+        match event {
+            Event::Eof => break,
+            Event::Start(e) | Event::Empty(e) if is_void(&e) => {
+                push_tag(&mut out, &e, "/>", input, offset)?
+            }
+            Event::Start(e) => {
+                let name = tag_name(&e, input, offset)?;
+                close_implied(&mut out, &mut open, &name.to_ascii_lowercase());
+                push_tag(&mut out, &e, ">", input, offset)?;
+                if RAW_TEXT_ELEMENTS.contains(&name.to_ascii_lowercase().as_str()) {
+                    // Raw text may contain `<`, so find the end tag by hand
+                    let start = base + reader.buffer_position() as usize;
+                    let end = find_end_tag(&input[start..], &name)
+                        .map(|i| start + i)
+                        .ok_or_else(|| {
+                            FormatError::at_offset(format!("Unclosed <{}>", name), input, offset)
+                        })?;
+                    push_raw_text(&mut out, &input[start..end]);
+                    push_end(&mut out, &name);
+                    base = input[end..].find('>').map_or(input.len(), |i| end + i + 1);
+                    reader = lenient_reader(&input[base..]);
+                } else {
+                    open.push(name);
+                }
+            }
+            Event::Empty(e) => {
+                let name = tag_name(&e, input, offset)?;
+                close_implied(&mut out, &mut open, &name.to_ascii_lowercase());
+                push_tag(&mut out, &e, "/>", input, offset)?;
+            }
+            Event::End(e) => {
+                let name = std::str::from_utf8(e.name().into_inner())
+                    .map_err(|_| FormatError::at_offset("Invalid UTF-8", input, offset))?;
+                // End tags with no open element, including void ones like `</br>`, are dropped
+                if let Some(index) = open.iter().rposition(|n| n.eq_ignore_ascii_case(name)) {
+                    while open.len() > index {
+                        push_end(&mut out, &open.pop().unwrap_or_default());
+                    }
+                }
+            }
+            Event::Text(e) => {
+                let text = std::str::from_utf8(&e)
+                    .map_err(|_| FormatError::at_offset("Invalid UTF-8", input, offset))?;
+                push_escaped(&mut out, text, false);
+            }
+            Event::CData(e) => {
+                out.push_str("<![CDATA[");
+                out.push_str(&String::from_utf8_lossy(&e));
+                out.push_str("]]>");
+            }
+            Event::Comment(e) => {
+                out.push_str("<!--");
+                out.push_str(&String::from_utf8_lossy(&e));
+                out.push_str("-->");
+            }
+            Event::Decl(e) => {
+                out.push_str("<?");
+                out.push_str(&String::from_utf8_lossy(&e));
+                out.push_str("?>");
+            }
+            Event::PI(e) => {
+                out.push_str("<?");
+                out.push_str(&String::from_utf8_lossy(&e));
+                out.push_str("?>");
+            }
+            Event::DocType(e) => {
+                out.push_str("<!DOCTYPE ");
+                out.push_str(String::from_utf8_lossy(&e).trim_start());
+                out.push('>');
+            }
+        }
+    }
+    while let Some(name) = open.pop() {
+        push_end(&mut out, &name);
+    }
+    Ok(out)
+}
+
+/// Escape each `<` that cannot start markup, so the tokenizer does not
+/// read text such as `a < b` as a tag.
+///
+/// Tags, comments, CDATA sections, and script and style bodies are skipped
+/// as the tokenizer will read them. Line numbers are unchanged; columns
+/// after an escaped `<` on the same line move by three.
+fn escape_stray_lt(input: &str) -> Cow<'_, str> {
+    let mut out = String::new();
+    let mut copied = 0;
+    let mut pos = 0;
+    while let Some(i) = input[pos..].find('<') {
+        let at = pos + i;
+        let rest = &input[at..];
+        let next = rest[1..].chars().next();
+        let markup_end = if rest.starts_with("<!--") {
+            rest.find("-->").map(|i| i + 3)
+        } else if rest.starts_with("<![CDATA[") {
+            rest.find("]]>").map(|i| i + 3)
+        } else if next.is_some_and(|c| {
+            matches!(c, '/' | '!' | '?') || c.is_alphabetic() || c == '_' || c == ':'
+        }) {
+            let tag_end = ElementParser::default()
+                .feed(rest.as_bytes())
+                .map(|i| i + 1);
+            let name_end = rest[1..]
+                .find(|c: char| c.is_whitespace() || matches!(c, '/' | '>'))
+                .map_or(rest.len(), |i| i + 1);
+            let name = &rest[1..name_end];
+            let raw_text = RAW_TEXT_ELEMENTS
+                .iter()
+                .any(|r| r.eq_ignore_ascii_case(name));
+            match tag_end {
+                // Skip a script or style body up to its end tag
+                Some(end) if raw_text && !rest[..end].ends_with("/>") => {
+                    Some(find_end_tag(&rest[end..], name).map_or(rest.len(), |i| end + i))
+                }
+                tag_end => tag_end,
+            }
+        } else {
+            out.push_str(&input[copied..at]);
+            out.push_str("&lt;");
+            copied = at + 1;
+            pos = at + 1;
+            continue;
+        };
+        // Unterminated markup is left for the tokenizer to report
+        pos = markup_end.map_or(input.len(), |end| at + end);
+    }
+    if copied == 0 {
+        return Cow::Borrowed(input);
+    }
+    out.push_str(&input[copied..]);
+    Cow::Owned(out)
+}
+
+/// Offset of the `</name` that ends a raw text element, ignoring case.
+fn find_end_tag(text: &str, name: &str) -> Option<usize> {
+    let len = name.len() + 2;
+    text.as_bytes()
+        .windows(len)
+        .position(|w| w.starts_with(b"</") && w[2..].eq_ignore_ascii_case(name.as_bytes()))
+}
+
+fn lenient_reader(input: &str) -> Reader<&[u8]> {
+    let mut reader = Reader::from_str(input);
+    reader.config_mut().check_end_names = false;
+    reader.config_mut().allow_unmatched_ends = true;
+    reader
+}
+
+fn tag_name(e: &BytesStart, input: &str, offset: usize) -> Result<String, FormatError> {
+    std::str::from_utf8(e.name().into_inner())
+        .map(str::to_string)
+        .map_err(|_| FormatError::at_offset("Invalid UTF-8", input, offset))
+}
+
+fn is_void(e: &BytesStart) -> bool {
+    let name = e.name().into_inner().to_ascii_lowercase();
+    VOID_ELEMENTS
+        .iter()
+        .any(|v| v.as_bytes() == name.as_slice())
+}
+
+/// Close elements whose end tag is implied by the start of `name`.
+fn close_implied(out: &mut String, open: &mut Vec<String>, name: &str) {
+    // Elements closed by `name`, and the elements that bound the search
+    let (closes, scope): (&[&str], &[&str]) = match name {
+        "li" => (&["li"], &["ul", "ol", "menu"]),
+        "dt" | "dd" => (&["dt", "dd"], &["dl"]),
+        "tr" => (&["tr", "td", "th"], &["table", "thead", "tbody", "tfoot"]),
+        "td" | "th" => (&["td", "th"], &["tr", "table"]),
+        "thead" | "tbody" | "tfoot" => (&["thead", "tbody", "tfoot", "tr", "td", "th"], &["table"]),
+        "option" => (&["option"], &["select", "datalist", "optgroup"]),
+        _ if BLOCK_ELEMENTS.contains(&name) => (&["p"], &[]),
+        _ => return,
+    };
+    let mut index = None;
+    for (i, open_name) in open.iter().enumerate().rev() {
+        let lower = open_name.to_ascii_lowercase();
+        if closes.contains(&lower.as_str()) {
+            index = Some(i);
+        } else if scope.contains(&lower.as_str()) || closes == ["p"] {
+            break;
+        }
+    }
+    if let Some(index) = index {
+        while open.len() > index {
+            push_end(out, &open.pop().unwrap_or_default());
+        }
+    }
+}
+
+/// Write a start or empty tag with its attributes quoted.
+fn push_tag(
+    out: &mut String,
+    e: &BytesStart,
+    close: &str,
+    input: &str,
+    offset: usize,
+) -> Result<(), FormatError> {
+    out.push('<');
+    out.push_str(&tag_name(e, input, offset)?);
+    let mut attributes = e.html_attributes();
+    // HTML keeps the first of repeated attributes; XML rejects the repeat
+    attributes.with_checks(false);
+    let mut seen: Vec<Vec<u8>> = Vec::new();
+    for attr in attributes {
+        let attr = attr.map_err(|_| FormatError::at_offset("Invalid attribute", input, offset))?;
+        let key = attr.key.into_inner().to_ascii_lowercase();
+        if seen.contains(&key) {
+            continue;
+        }
+        seen.push(key);
+        out.push(' ');
+        out.push_str(&String::from_utf8_lossy(attr.key.into_inner()));
+        out.push_str("=\"");
+        push_escaped(out, &String::from_utf8_lossy(&attr.value), true);
+        out.push('"');
+    }
+    out.push_str(close);
+    Ok(())
+}
+
+fn push_end(out: &mut String, name: &str) {
+    out.push_str("</");
+    out.push_str(name);
+    out.push('>');
+}
+
+/// Write script or style content, as CDATA when it contains markup characters.
+fn push_raw_text(out: &mut String, body: &str) {
+    if body.contains(['<', '&']) {
+        out.push_str("<![CDATA[");
+        out.push_str(&body.replace("]]>", "]]]]><![CDATA[>"));
+        out.push_str("]]>");
+    } else {
+        out.push_str(body);
+    }
+}
+
+/// Write text with bare `&` and `<` escaped and HTML entities made numeric.
+fn push_escaped(out: &mut String, text: &str, attribute: bool) {
+    let mut rest = text;
+    while let Some(i) = rest.find(['&', '<', '"']) {
+        out.push_str(&rest[..i]);
+        let c = rest.as_bytes()[i];
+        rest = &rest[i + 1..];
+        match c {
+            b'<' => out.push_str("&lt;"),
+            b'"' if attribute => out.push_str("&quot;"),
+            b'"' => out.push('"'),
+            _ => {
+                let entity = rest.find(';').map(|end| &rest[..end]).filter(|name| {
+                    !name.is_empty()
+                        && name.len() <= 32
+                        && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'#')
+                });
+                match entity {
+                    Some(name)
+                        if matches!(name, "lt" | "gt" | "amp" | "quot" | "apos")
+                            || name.starts_with('#') =>
+                    {
+                        out.push('&');
+                    }
+                    Some(name) => match HTML_ENTITIES.iter().find(|(n, _)| *n == name) {
+                        Some((_, code)) => {
+                            out.push_str(&format!("&#{};", code));
+                            rest = &rest[name.len() + 1..];
+                        }
+                        None => out.push_str("&amp;"),
+                    },
+                    None => out.push_str("&amp;"),
+                }
+            }
+        }
+    }
+    out.push_str(rest);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_void_elements_and_attributes() {
+        let input = r#"<p class=intro hidden>Line<br>next <img src='a.png' alt="x"></p>"#;
+        assert_eq!(
+            html_to_xml(input).unwrap(),
+            r#"<p class="intro" hidden="">Line<br/>next <img src="a.png" alt="x"/></p>"#
+        );
+    }
+
+    #[test]
+    fn test_implied_end_tags() {
+        let input = "<ul><li>one<li>two<ul><li>nested</ul><li>three</ul><p>a<p>b<div>c</div>";
+        assert_eq!(
+            html_to_xml(input).unwrap(),
+            "<ul><li>one</li><li>two<ul><li>nested</li></ul></li><li>three</li></ul><p>a</p><p>b</p><div>c</div>"
+        );
+        let input = "<table><tr><td>1<td>2<tr><th>3</table>";
+        assert_eq!(
+            html_to_xml(input).unwrap(),
+            "<table><tr><td>1</td><td>2</td></tr><tr><th>3</th></tr></table>"
+        );
+    }
+
+    #[test]
+    fn test_stray_end_tags_and_entities() {
+        let input = "<div>Tom &amp; Jerry & co&nbsp;&copy; &unknown;</span></br></div>";
+        assert_eq!(
+            html_to_xml(input).unwrap(),
+            "<div>Tom &amp; Jerry &amp; co&#160;&#169; &amp;unknown;</div>"
+        );
+    }
+
+    #[test]
+    fn test_script_body() {
+        let input = "<script>if (a < b && c) {}</script><style>p { color: red }</style>";
+        assert_eq!(
+            html_to_xml(input).unwrap(),
+            "<script><![CDATA[if (a < b && c) {}]]></script><style>p { color: red }</style>"
+        );
+    }
+
+    #[test]
+    fn test_stray_less_than() {
+        assert_eq!(
+            html_to_xml("<p>if a < b then</p>").unwrap(),
+            "<p>if a &lt; b then</p>"
+        );
+        assert_eq!(
+            html_to_xml("<p>1 <2 <= 3 <</p>").unwrap(),
+            "<p>1 &lt;2 &lt;= 3 &lt;</p>"
+        );
+        // Markup keeps its `<` as written
+        let input = "<p title=\"a < b\"><!-- x < y --><script>a < b</script></p>";
+        assert_eq!(
+            html_to_xml(input).unwrap(),
+            "<p title=\"a &lt; b\"><!-- x < y --><script><![CDATA[a < b]]></script></p>"
+        );
+    }
+
+    #[test]
+    fn test_repeated_attributes() {
+        assert_eq!(
+            html_to_xml("<p class=a CLASS=b id=x class=c>t</p>").unwrap(),
+            r#"<p class="a" id="x">t</p>"#
+        );
+    }
+
+    #[test]
+    fn test_well_formed_xml_unchanged() {
+        let input = r#"<?xml version="1.0"?><root a="1"><!-- c --><b/><![CDATA[x]]></root>"#;
+        assert_eq!(html_to_xml(input).unwrap(), input);
+    }
+}
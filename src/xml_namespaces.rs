@@ -0,0 +1,430 @@
+//! XML namespace normalization
+//!
+//! Rewrites a document so every namespace has exactly one prefix, chosen by
+//! the caller or generated, with all declarations hoisted to the document
+//! element. Documents assembled from several sources often bind the same
+//! namespace to different prefixes, or the same prefix to different
+//! namespaces; after normalization each prefix means one thing everywhere.
+
+use crate::types::FormatError;
+use crate::xml_tree::{NodeId, NodeKind, XmlAttribute, XmlDocument};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+const XSI_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema-instance";
+
+/// Options for namespace normalization.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct NamespaceOptions {
+    /// Prefix to use for a namespace URI; `""` makes it the default namespace
+    pub prefixes: HashMap<String, String>,
+    /// Give unmapped namespaces generated prefixes (`ns1`, `ns2`, ...)
+    /// instead of reusing the first prefix the document used for them
+    pub generate_prefixes: bool,
+}
+
+/// Rewrite an XML document with consistent namespace prefixes.
+///
+/// Element and attribute names keep their namespace and local name; only
+/// prefixes change. Every `xmlns` declaration is removed and the namespaces
+/// are declared once on the document element, in order of first appearance.
+/// The QName in an `xsi:type` value is rewritten like an element name; other
+/// attribute values, text, and comments are kept as written, so a QName in
+/// them may refer to a prefix that is no longer declared.
+///
+/// # Arguments
+/// * `input` - The XML string to rewrite
+/// * `options` - Explicit prefix mapping and prefix generation
+///
+/// # Returns
+/// * `Ok(String)` - The rewritten document
+/// * `Err(FormatError)` - If the XML is malformed, uses an undeclared prefix,
+///   or the mapping cannot be applied (duplicate or reserved prefixes, or a
+///   default namespace that would capture unqualified elements or attributes)
+pub fn normalize_namespaces(
+    input: &str,
+    options: &NamespaceOptions,
+) -> Result<String, FormatError> {
+    let mut doc = XmlDocument::parse(input)?;
+    let root = match doc.document_element() {
+        Some(root) => root,
+        None => return Err(FormatError::new("No root element", 1, 1)),
+    };
+
+    let mut collector = Collector {
+        doc: &doc,
+        input,
+        namespaces: Vec::new(),
+        resolved: Vec::new(),
+        attribute_namespaces: HashSet::new(),
+        type_names: Vec::new(),
+        unqualified_names: false,
+    };
+    let scope = HashMap::from([(String::new(), String::new())]);
+    collector.element(root, &scope)?;
+    let Collector {
+        namespaces,
+        resolved,
+        attribute_namespaces,
+        type_names,
+        unqualified_names,
+        ..
+    } = collector;
+
+    let prefixes = assign_prefixes(
+        &namespaces,
+        &attribute_namespaces,
+        unqualified_names,
+        options,
+    )
+    .map_err(|message| FormatError::at_offset(message, input, doc.node(root).offset))?;
+    let prefix_of: HashMap<&str, &str> = prefixes
+        .iter()
+        .map(|(uri, p)| (uri.as_str(), p.as_str()))
+        .collect();
+
+    for (id, index, uri) in type_names {
+        if let NodeKind::Element { attributes, .. } = &mut doc.node_mut(id).kind {
+            let attr = &mut attributes[index];
+            let prefix = uri.as_deref().map(|uri| prefix_of[uri]);
+            attr.raw_value = escape_uri(&qualify(attr.value().trim(), prefix));
+        }
+    }
+
+    for (id, element_uri, attribute_uris) in resolved {
+        let node = doc.node_mut(id);
+        if let NodeKind::Element {
+            name, attributes, ..
+        } = &mut node.kind
+        {
+            *name = qualify(name, element_uri.as_deref().map(|uri| prefix_of[uri]));
+            let mut kept = Vec::with_capacity(attributes.len());
+            for (mut attr, uri) in attributes.drain(..).zip(attribute_uris) {
+                if is_declaration(&attr.name) {
+                    continue;
+                }
+                if let Some(uri) = uri {
+                    let prefix = if uri == XML_NAMESPACE {
+                        "xml"
+                    } else {
+                        prefix_of[uri.as_str()]
+                    };
+                    attr.name = qualify(&attr.name, Some(prefix));
+                }
+                kept.push(attr);
+            }
+            *attributes = kept;
+        }
+    }
+
+    let mut declarations: Vec<XmlAttribute> = prefixes
+        .iter()
+        .map(|(uri, prefix)| XmlAttribute {
+            name: if prefix.is_empty() {
+                "xmlns".to_string()
+            } else {
+                format!("xmlns:{}", prefix)
+            },
+            raw_value: escape_uri(uri),
+        })
+        .collect();
+    declarations.sort_by_key(|d| d.name != "xmlns");
+    if let NodeKind::Element { attributes, .. } = &mut doc.node_mut(root).kind {
+        declarations.append(attributes);
+        *attributes = declarations;
+    }
+
+    Ok(doc.to_xml(XmlDocument::ROOT))
+}
+
+/// Namespace URIs resolved for an element and each of its attributes.
+type Resolved = (NodeId, Option<String>, Vec<Option<String>>);
+
+struct Collector<'a> {
+    doc: &'a XmlDocument,
+    input: &'a str,
+    /// Namespace URIs in order of first appearance, with the first prefix used
+    namespaces: Vec<(String, String)>,
+    resolved: Vec<Resolved>,
+    /// Namespaces used by attributes, which cannot become the default
+    attribute_namespaces: HashSet<String>,
+    /// `xsi:type` attributes by element and attribute index, with the
+    /// namespace URI of the QName in their value
+    type_names: Vec<(NodeId, usize, Option<String>)>,
+    /// Whether some element or `xsi:type` value is in no namespace
+    unqualified_names: bool,
+}
+
+impl Collector<'_> {
+    fn error(&self, message: impl Into<String>, id: NodeId) -> FormatError {
+        FormatError::at_offset(message, self.input, self.doc.node(id).offset)
+    }
+
+    /// Resolve the prefix of an element name or `xsi:type` value in `scope`.
+    fn resolve(
+        &mut self,
+        prefix: &str,
+        scope: &HashMap<String, String>,
+        id: NodeId,
+    ) -> Result<Option<String>, FormatError> {
+        match scope.get(prefix) {
+            Some(uri) if uri.is_empty() => {
+                self.unqualified_names = true;
+                Ok(None)
+            }
+            Some(uri) => Ok(Some(uri.clone())),
+            None => Err(self.error(format!("Undeclared namespace prefix '{}'", prefix), id)),
+        }
+    }
+
+    fn note(&mut self, uri: &str, prefix: &str) {
+        if !uri.is_empty() && uri != XML_NAMESPACE && !self.namespaces.iter().any(|(u, _)| u == uri)
+        {
+            self.namespaces.push((uri.to_string(), prefix.to_string()));
+        }
+    }
+
+    /// Resolve names in an element's subtree; `scope` maps prefixes to URIs
+    /// (`""` is the default namespace, an empty URI meaning none).
+    fn element(&mut self, id: NodeId, scope: &HashMap<String, String>) -> Result<(), FormatError> {
+        let mut scope = scope.clone();
+        for attr in self.doc.attributes(id) {
+            let prefix = if attr.name == "xmlns" {
+                ""
+            } else if let Some(prefix) = attr.name.strip_prefix("xmlns:") {
+                prefix
+            } else {
+                continue;
+            };
+            let uri = attr.value();
+            self.note(&uri, prefix);
+            scope.insert(prefix.to_string(), uri);
+        }
+
+        let name = self.doc.name(id).unwrap_or_default();
+        let (prefix, _) = split_name(name);
+        let element_uri = self.resolve(prefix, &scope, id)?;
+
+        let mut attribute_uris = Vec::new();
+        for (index, attr) in self.doc.attributes(id).iter().enumerate() {
+            let uri = match split_name(&attr.name) {
+                _ if is_declaration(&attr.name) => None,
+                ("", _) => None,
+                ("xml", _) => Some(XML_NAMESPACE.to_string()),
+                (prefix, _) => match scope.get(prefix) {
+                    Some(uri) if !uri.is_empty() => Some(uri.clone()),
+                    _ => {
+                        return Err(
+                            self.error(format!("Undeclared namespace prefix '{}'", prefix), id)
+                        )
+                    }
+                },
+            };
+            if let Some(uri) = &uri {
+                self.attribute_namespaces.insert(uri.clone());
+                if uri == XSI_NAMESPACE && split_name(&attr.name).1 == "type" {
+                    let value = attr.value();
+                    let (prefix, _) = split_name(value.trim());
+                    let type_uri = self.resolve(prefix, &scope, id)?;
+                    self.type_names.push((id, index, type_uri));
+                }
+            }
+            attribute_uris.push(uri);
+        }
+        self.resolved.push((id, element_uri, attribute_uris));
+
+        for &child in self.doc.children(id) {
+            if self.doc.is_element(child) {
+                self.element(child, &scope)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Choose one prefix per namespace, in order of first appearance.
+fn assign_prefixes(
+    namespaces: &[(String, String)],
+    attribute_namespaces: &HashSet<String>,
+    unqualified_names: bool,
+    options: &NamespaceOptions,
+) -> Result<Vec<(String, String)>, String> {
+    let can_be_default = |uri: &str| !unqualified_names && !attribute_namespaces.contains(uri);
+    let mut taken = HashSet::new();
+    for (uri, _) in namespaces {
+        if let Some(prefix) = options.prefixes.get(uri) {
+            if prefix == "xml" || prefix == "xmlns" || prefix.contains(':') {
+                return Err(format!(
+                    "Invalid prefix '{}' for namespace '{}'",
+                    prefix, uri
+                ));
+            }
+            if prefix.is_empty() && !can_be_default(uri) {
+                return Err(format!(
+                    "Namespace '{}' cannot be the default namespace",
+                    uri
+                ));
+            }
+            if !taken.insert(prefix.clone()) {
+                return Err(format!(
+                    "Prefix '{}' is mapped to more than one namespace",
+                    prefix
+                ));
+            }
+        }
+    }
+
+    let mut generated = 0;
+    let mut assigned = Vec::with_capacity(namespaces.len());
+    for (uri, original) in namespaces {
+        let prefix = match options.prefixes.get(uri) {
+            Some(prefix) => prefix.clone(),
+            None if !options.generate_prefixes
+                && !taken.contains(original)
+                && (!original.is_empty() || can_be_default(uri)) =>
+            {
+                taken.insert(original.clone());
+                original.clone()
+            }
+            None => loop {
+                generated += 1;
+                let candidate = format!("ns{}", generated);
+                if taken.insert(candidate.clone()) {
+                    break candidate;
+                }
+            },
+        };
+        assigned.push((uri.clone(), prefix));
+    }
+    Ok(assigned)
+}
+
+fn split_name(name: &str) -> (&str, &str) {
+    name.split_once(':').unwrap_or(("", name))
+}
+
+fn qualify(name: &str, prefix: Option<&str>) -> String {
+    let (_, local) = split_name(name);
+    match prefix {
+        Some(prefix) if !prefix.is_empty() => format!("{}:{}", prefix, local),
+        _ => local.to_string(),
+    }
+}
+
+fn is_declaration(name: &str) -> bool {
+    name == "xmlns" || name.starts_with("xmlns:")
+}
+
+fn escape_uri(uri: &str) -> String {
+    uri.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(input: &str) -> String {
+        normalize_namespaces(input, &NamespaceOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn test_hoists_and_unifies_prefixes() {
+        let input = r#"<a:root xmlns:a="urn:a"><b:x xmlns:b="urn:b"><c:y xmlns:c="urn:a" c:k="1"/></b:x><b:z xmlns:b="urn:c"/></a:root>"#;
+        assert_eq!(
+            normalize(input),
+            r#"<a:root xmlns:a="urn:a" xmlns:b="urn:b" xmlns:ns1="urn:c"><b:x><a:y a:k="1"/></b:x><ns1:z/></a:root>"#
+        );
+    }
+
+    #[test]
+    fn test_default_namespace() {
+        let input = r#"<root xmlns="urn:d"><x:item xmlns:x="urn:d">t</x:item></root>"#;
+        assert_eq!(
+            normalize(input),
+            r#"<root xmlns="urn:d"><item>t</item></root>"#
+        );
+        // Unqualified elements keep the default namespace from being hoisted
+        let input = r#"<root><child xmlns="urn:d"/></root>"#;
+        assert_eq!(
+            normalize(input),
+            r#"<root xmlns:ns1="urn:d"><ns1:child/></root>"#
+        );
+    }
+
+    #[test]
+    fn test_explicit_and_generated_prefixes() {
+        let input = r#"<p:root xmlns:p="urn:p" xmlns:q="urn:q"><q:a/></p:root>"#;
+        let options = NamespaceOptions {
+            prefixes: HashMap::from([
+                ("urn:q".to_string(), "p".to_string()),
+                ("urn:p".to_string(), String::new()),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(
+            normalize_namespaces(input, &options).unwrap(),
+            r#"<root xmlns="urn:p" xmlns:p="urn:q"><p:a/></root>"#
+        );
+        let options = NamespaceOptions {
+            generate_prefixes: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            normalize_namespaces(input, &options).unwrap(),
+            r#"<ns1:root xmlns:ns1="urn:p" xmlns:ns2="urn:q"><ns2:a/></ns1:root>"#
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let options = NamespaceOptions::default();
+        assert!(normalize_namespaces("<p:r/>", &options).is_err());
+        let input = r#"<r xmlns:a="urn:a" xmlns:b="urn:b" a:x="1"/>"#;
+        let clash = NamespaceOptions {
+            prefixes: HashMap::from([
+                ("urn:a".to_string(), "p".to_string()),
+                ("urn:b".to_string(), "p".to_string()),
+            ]),
+            ..Default::default()
+        };
+        assert!(normalize_namespaces(input, &clash).is_err());
+        let default_for_attribute = NamespaceOptions {
+            prefixes: HashMap::from([("urn:a".to_string(), String::new())]),
+            ..Default::default()
+        };
+        assert!(normalize_namespaces(input, &default_for_attribute).is_err());
+        let undeclared_type =
+            r#"<r xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:type="a:T"/>"#;
+        assert!(normalize_namespaces(undeclared_type, &options).is_err());
+    }
+
+    #[test]
+    fn test_rewrites_xsi_type() {
+        let input = r#"<c xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xmlns:a="urn:a" xsi:type="a:T"/>"#;
+        let options = NamespaceOptions {
+            generate_prefixes: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            normalize_namespaces(input, &options).unwrap(),
+            r#"<c xmlns:ns1="http://www.w3.org/2001/XMLSchema-instance" xmlns:ns2="urn:a" ns1:type="ns2:T"/>"#
+        );
+        // An unprefixed type name is in the default namespace
+        let input = r#"<r xmlns="urn:d"><x:e xmlns:x="urn:d" xmlns:i="http://www.w3.org/2001/XMLSchema-instance" i:type="T"/></r>"#;
+        assert_eq!(
+            normalize(input),
+            r#"<r xmlns="urn:d" xmlns:i="http://www.w3.org/2001/XMLSchema-instance"><e i:type="T"/></r>"#
+        );
+        // A type name in no namespace keeps others from becoming the default
+        let input = r#"<a:r xmlns:a="urn:a" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:type="T"/>"#;
+        let options = NamespaceOptions {
+            prefixes: HashMap::from([("urn:a".to_string(), String::new())]),
+            ..Default::default()
+        };
+        assert!(normalize_namespaces(input, &options).is_err());
+    }
+}
@@ -0,0 +1,1473 @@
+//! XPath 1.0 queries over XML documents
+//!
+//! Evaluates XPath expressions against an [`XmlDocument`] and returns either
+//! the matched nodes (serialized, with their source positions) or the
+//! scalar result of the expression. Supports all axes except `namespace`,
+//! the node tests, predicates, the core function library except `id()`
+//! (there are no DTD attribute types to tell IDs apart), and union.
+//! Names are matched by their qualified form as written (`ns:item`); there
+//! is no prefix-to-URI resolution and variables are not supported.
+
+use crate::types::FormatError;
+use crate::xml_tree::{NodeId, NodeKind, XmlDocument};
+use serde::{Serialize, Serializer};
+
+/// A node matched by a query.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct QueryMatch {
+    /// Node type: "element", "attribute", "text", "comment", "processing-instruction", or "document"
+    pub kind: String,
+    /// Element or attribute name, if any
+    pub name: Option<String>,
+    /// Serialized XML for elements, the value for attributes and other nodes
+    pub value: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Result of evaluating an XPath expression.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum QueryResult {
+    NodeSet {
+        matches: Vec<QueryMatch>,
+    },
+    String {
+        value: String,
+    },
+    /// NaN and infinities serialize as `"NaN"`, `"Infinity"`, and `"-Infinity"`
+    Number {
+        #[serde(serialize_with = "serialize_number")]
+        value: f64,
+    },
+    Boolean {
+        value: bool,
+    },
+}
+
+fn serialize_number<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+    if value.is_finite() {
+        serializer.serialize_f64(*value)
+    } else {
+        serializer.serialize_str(&number_to_string(*value))
+    }
+}
+
+/// Evaluate an XPath 1.0 expression against an XML document.
+///
+/// # Arguments
+/// * `input` - The XML string to query
+/// * `expression` - The XPath expression
+///
+/// # Returns
+/// * `Ok(QueryResult)` - Matched nodes in document order, or a scalar value
+/// * `Err(FormatError)` - If the XML is malformed or the expression is invalid
+pub fn xml_query(input: &str, expression: &str) -> Result<QueryResult, FormatError> {
+    let doc = XmlDocument::parse(input)?;
+    let expr = parse_expression(expression)?;
+    let ctx = Context {
+        node: NodeRef::Node(XmlDocument::ROOT),
+        position: 1,
+        size: 1,
+    };
+    let value = Evaluator { doc: &doc }.eval(&expr, &ctx)?;
+    Ok(match value {
+        XValue::Nodes(nodes) => QueryResult::NodeSet {
+            matches: nodes.iter().map(|n| describe(&doc, input, *n)).collect(),
+        },
+        XValue::Str(value) => QueryResult::String { value },
+        XValue::Num(value) => QueryResult::Number { value },
+        XValue::Bool(value) => QueryResult::Boolean { value },
+    })
+}
+
+// ============================================================================
+// Data model
+// ============================================================================
+
+/// A node in the XPath data model: a tree node or an element's attribute.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum NodeRef {
+    Node(NodeId),
+    Attr(NodeId, usize),
+}
+
+impl NodeRef {
+    /// Sort key giving document order (attributes follow their element).
+    fn order_key(self) -> (NodeId, usize) {
+        match self {
+            NodeRef::Node(id) => (id, 0),
+            NodeRef::Attr(id, i) => (id, i + 1),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum XValue {
+    Nodes(Vec<NodeRef>),
+    Str(String),
+    Num(f64),
+    Bool(bool),
+}
+
+struct Context {
+    node: NodeRef,
+    position: usize,
+    size: usize,
+}
+
+fn describe(doc: &XmlDocument, input: &str, node: NodeRef) -> QueryMatch {
+    let (kind, name, value, offset) = match node {
+        NodeRef::Attr(id, i) => {
+            let attr = &doc.attributes(id)[i];
+            (
+                "attribute",
+                Some(attr.name.clone()),
+                attr.value(),
+                doc.node(id).offset,
+            )
+        }
+        NodeRef::Node(id) => {
+            let n = doc.node(id);
+            match &n.kind {
+                NodeKind::Element { name, .. } => {
+                    ("element", Some(name.clone()), doc.to_xml(id), n.offset)
+                }
+                NodeKind::Text(_) | NodeKind::CData(_) => {
+                    ("text", None, doc.text_content(id), n.offset)
+                }
+                NodeKind::Comment(c) => ("comment", None, c.clone(), n.offset),
+                NodeKind::ProcessingInstruction(p) => {
+                    ("processing-instruction", None, p.clone(), n.offset)
+                }
+                _ => ("document", None, doc.to_xml(id), 0),
+            }
+        }
+    };
+    let pos = FormatError::at_offset("", input, offset);
+    QueryMatch {
+        kind: kind.to_string(),
+        name,
+        value,
+        line: pos.line,
+        column: pos.column,
+    }
+}
+
+// ============================================================================
+// Lexer
+// ============================================================================
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Slash,
+    DoubleSlash,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Dot,
+    DotDot,
+    At,
+    Comma,
+    Pipe,
+    Star,
+    ColonColon,
+    Op(&'static str),
+    Number(f64),
+    Literal(String),
+    Name(String),
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Tok>, FormatError> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let tok = match c {
+            ' ' | '\t' | '\n' | '\r' => {
+                i += 1;
+                continue;
+            }
+            '/' if next == Some('/') => Tok::DoubleSlash,
+            '/' => Tok::Slash,
+            '(' => Tok::LParen,
+            ')' => Tok::RParen,
+            '[' => Tok::LBracket,
+            ']' => Tok::RBracket,
+            '.' if next == Some('.') => Tok::DotDot,
+            '.' if !next.is_some_and(|n| n.is_ascii_digit()) => Tok::Dot,
+            '@' => Tok::At,
+            ',' => Tok::Comma,
+            '|' => Tok::Pipe,
+            '*' => Tok::Star,
+            ':' if next == Some(':') => Tok::ColonColon,
+            '=' => Tok::Op("="),
+            '!' if next == Some('=') => Tok::Op("!="),
+            '<' if next == Some('=') => Tok::Op("<="),
+            '>' if next == Some('=') => Tok::Op(">="),
+            '<' => Tok::Op("<"),
+            '>' => Tok::Op(">"),
+            '+' => Tok::Op("+"),
+            '-' => Tok::Op("-"),
+            '"' | '\'' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&q| q == c)
+                    .ok_or_else(|| query_error("Unterminated string literal"))?;
+                tokens.push(Tok::Literal(chars[i + 1..i + 1 + end].iter().collect()));
+                i += end + 2;
+                continue;
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|d| d.is_ascii_digit() || **d == '.')
+                    .count();
+                let text: String = chars[i..i + len].iter().collect();
+                let n = text
+                    .parse()
+                    .map_err(|_| query_error(format!("Invalid number '{}'", text)))?;
+                tokens.push(Tok::Number(n));
+                i += len;
+                continue;
+            }
+            c if is_name_start(c) => {
+                let mut len = chars[i..].iter().take_while(|d| is_name_char(**d)).count();
+                // QName prefix (`ns:name`) or `ns:*`, but not the `::` axis separator
+                if chars.get(i + len) == Some(&':') && chars.get(i + len + 1) != Some(&':') {
+                    match chars.get(i + len + 1) {
+                        Some('*') => len += 2,
+                        Some(&n) if is_name_start(n) => {
+                            len += 1 + chars[i + len + 1..]
+                                .iter()
+                                .take_while(|d| is_name_char(**d))
+                                .count();
+                        }
+                        _ => {}
+                    }
+                }
+                tokens.push(Tok::Name(chars[i..i + len].iter().collect()));
+                i += len;
+                continue;
+            }
+            '$' => return Err(query_error("XPath variables are not supported")),
+            other => return Err(query_error(format!("Unexpected character '{}'", other))),
+        };
+        i += match tok {
+            Tok::DoubleSlash | Tok::DotDot | Tok::ColonColon => 2,
+            Tok::Op(op) => op.len(),
+            _ => 1,
+        };
+        tokens.push(tok);
+    }
+    Ok(tokens)
+}
+
+fn is_name_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+fn query_error(message: impl Into<String>) -> FormatError {
+    FormatError::new(format!("XPath error: {}", message.into()), 0, 0)
+}
+
+// ============================================================================
+// Parser
+// ============================================================================
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Axis {
+    Child,
+    Descendant,
+    DescendantOrSelf,
+    Parent,
+    Ancestor,
+    AncestorOrSelf,
+    FollowingSibling,
+    PrecedingSibling,
+    Following,
+    Preceding,
+    Attribute,
+    /// The `self` axis
+    Current,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum NodeTest {
+    /// `*`
+    Any,
+    /// `prefix:*`
+    Prefix(String),
+    Name(String),
+    Text,
+    Comment,
+    ProcessingInstruction,
+    Node,
+}
+
+#[derive(Clone, Debug)]
+struct Step {
+    axis: Axis,
+    test: NodeTest,
+    predicates: Vec<Expr>,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Compare(&'static str, Box<Expr>, Box<Expr>),
+    Arith(&'static str, Box<Expr>, Box<Expr>),
+    Neg(Box<Expr>),
+    Union(Box<Expr>, Box<Expr>),
+    /// A location path; `absolute` paths start at the document node
+    Path {
+        absolute: bool,
+        steps: Vec<Step>,
+    },
+    /// A primary expression with predicates, optionally followed by a relative path
+    Filter {
+        primary: Box<Expr>,
+        predicates: Vec<Expr>,
+        steps: Vec<Step>,
+    },
+    Literal(String),
+    Number(f64),
+    Call(String, Vec<Expr>),
+}
+
+fn parse_expression(expression: &str) -> Result<Expr, FormatError> {
+    let tokens = tokenize(expression)?;
+    if tokens.is_empty() {
+        return Err(query_error("Empty expression"));
+    }
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.or_expr()?;
+    if parser.pos < parser.tokens.len() {
+        return Err(query_error(format!(
+            "Unexpected {}",
+            token_text(parser.peek())
+        )));
+    }
+    Ok(expr)
+}
+
+struct Parser {
+    tokens: Vec<Tok>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Tok> {
+        self.tokens.get(self.pos + offset)
+    }
+
+    fn eat(&mut self, tok: &Tok) -> bool {
+        if self.peek() == Some(tok) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, tok: &Tok) -> Result<(), FormatError> {
+        if self.eat(tok) {
+            Ok(())
+        } else {
+            Err(query_error(format!(
+                "Expected {}, found {}",
+                token_text(Some(tok)),
+                token_text(self.peek())
+            )))
+        }
+    }
+
+    /// Whether the next `Name` token is an operator keyword (`and`, `or`, `div`, `mod`).
+    ///
+    /// Per the XPath lexical rules a name is an operator when it follows
+    /// something that can end an operand.
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Tok::Name(n)) if n == keyword) && self.pos > 0
+    }
+
+    fn or_expr(&mut self) -> Result<Expr, FormatError> {
+        let mut left = self.and_expr()?;
+        while self.at_keyword("or") {
+            self.pos += 1;
+            left = Expr::Or(Box::new(left), Box::new(self.and_expr()?));
+        }
+        Ok(left)
+    }
+
+    fn and_expr(&mut self) -> Result<Expr, FormatError> {
+        let mut left = self.equality_expr()?;
+        while self.at_keyword("and") {
+            self.pos += 1;
+            left = Expr::And(Box::new(left), Box::new(self.equality_expr()?));
+        }
+        Ok(left)
+    }
+
+    fn equality_expr(&mut self) -> Result<Expr, FormatError> {
+        let mut left = self.relational_expr()?;
+        while let Some(Tok::Op(op @ ("=" | "!="))) = self.peek() {
+            let op = *op;
+            self.pos += 1;
+            left = Expr::Compare(op, Box::new(left), Box::new(self.relational_expr()?));
+        }
+        Ok(left)
+    }
+
+    fn relational_expr(&mut self) -> Result<Expr, FormatError> {
+        let mut left = self.additive_expr()?;
+        while let Some(Tok::Op(op @ ("<" | "<=" | ">" | ">="))) = self.peek() {
+            let op = *op;
+            self.pos += 1;
+            left = Expr::Compare(op, Box::new(left), Box::new(self.additive_expr()?));
+        }
+        Ok(left)
+    }
+
+    fn additive_expr(&mut self) -> Result<Expr, FormatError> {
+        let mut left = self.multiplicative_expr()?;
+        while let Some(Tok::Op(op @ ("+" | "-"))) = self.peek() {
+            let op = *op;
+            self.pos += 1;
+            left = Expr::Arith(op, Box::new(left), Box::new(self.multiplicative_expr()?));
+        }
+        Ok(left)
+    }
+
+    fn multiplicative_expr(&mut self) -> Result<Expr, FormatError> {
+        let mut left = self.unary_expr()?;
+        loop {
+            let op = if self.peek() == Some(&Tok::Star) {
+                "*"
+            } else if self.at_keyword("div") {
+                "div"
+            } else if self.at_keyword("mod") {
+                "mod"
+            } else {
+                break;
+            };
+            self.pos += 1;
+            left = Expr::Arith(op, Box::new(left), Box::new(self.unary_expr()?));
+        }
+        Ok(left)
+    }
+
+    fn unary_expr(&mut self) -> Result<Expr, FormatError> {
+        if self.eat(&Tok::Op("-")) {
+            return Ok(Expr::Neg(Box::new(self.unary_expr()?)));
+        }
+        self.union_expr()
+    }
+
+    fn union_expr(&mut self) -> Result<Expr, FormatError> {
+        let mut left = self.path_expr()?;
+        while self.eat(&Tok::Pipe) {
+            left = Expr::Union(Box::new(left), Box::new(self.path_expr()?));
+        }
+        Ok(left)
+    }
+
+    fn path_expr(&mut self) -> Result<Expr, FormatError> {
+        match self.peek() {
+            Some(Tok::Slash) => {
+                self.pos += 1;
+                let steps = if self.starts_step() {
+                    self.relative_steps()?
+                } else {
+                    Vec::new()
+                };
+                Ok(Expr::Path {
+                    absolute: true,
+                    steps,
+                })
+            }
+            Some(Tok::DoubleSlash) => {
+                self.pos += 1;
+                let mut steps = vec![descendant_or_self_step()];
+                steps.extend(self.relative_steps()?);
+                Ok(Expr::Path {
+                    absolute: true,
+                    steps,
+                })
+            }
+            Some(Tok::LParen) | Some(Tok::Literal(_)) | Some(Tok::Number(_)) => self.filter_expr(),
+            Some(Tok::Name(name))
+                if self.peek_at(1) == Some(&Tok::LParen) && !is_node_type(name) =>
+            {
+                self.filter_expr()
+            }
+            _ => Ok(Expr::Path {
+                absolute: false,
+                steps: self.relative_steps()?,
+            }),
+        }
+    }
+
+    fn filter_expr(&mut self) -> Result<Expr, FormatError> {
+        let primary = self.primary_expr()?;
+        let mut predicates = Vec::new();
+        while self.peek() == Some(&Tok::LBracket) {
+            predicates.push(self.predicate()?);
+        }
+        let mut steps = Vec::new();
+        match self.peek() {
+            Some(Tok::Slash) => {
+                self.pos += 1;
+                steps = self.relative_steps()?;
+            }
+            Some(Tok::DoubleSlash) => {
+                self.pos += 1;
+                steps.push(descendant_or_self_step());
+                steps.extend(self.relative_steps()?);
+            }
+            _ => {}
+        }
+        if predicates.is_empty() && steps.is_empty() {
+            return Ok(primary);
+        }
+        Ok(Expr::Filter {
+            primary: Box::new(primary),
+            predicates,
+            steps,
+        })
+    }
+
+    fn primary_expr(&mut self) -> Result<Expr, FormatError> {
+        match self.peek().cloned() {
+            Some(Tok::LParen) => {
+                self.pos += 1;
+                let expr = self.or_expr()?;
+                self.expect(&Tok::RParen)?;
+                Ok(expr)
+            }
+            Some(Tok::Literal(s)) => {
+                self.pos += 1;
+                Ok(Expr::Literal(s))
+            }
+            Some(Tok::Number(n)) => {
+                self.pos += 1;
+                Ok(Expr::Number(n))
+            }
+            Some(Tok::Name(name)) => {
+                self.pos += 2; // name and '('
+                let mut args = Vec::new();
+                if !self.eat(&Tok::RParen) {
+                    loop {
+                        args.push(self.or_expr()?);
+                        if self.eat(&Tok::RParen) {
+                            break;
+                        }
+                        self.expect(&Tok::Comma)?;
+                    }
+                }
+                Ok(Expr::Call(name, args))
+            }
+            other => Err(query_error(format!(
+                "Unexpected {}",
+                token_text(other.as_ref())
+            ))),
+        }
+    }
+
+    fn starts_step(&self) -> bool {
+        matches!(
+            self.peek(),
+            Some(Tok::Name(_))
+                | Some(Tok::Star)
+                | Some(Tok::At)
+                | Some(Tok::Dot)
+                | Some(Tok::DotDot)
+        )
+    }
+
+    fn relative_steps(&mut self) -> Result<Vec<Step>, FormatError> {
+        let mut steps = vec![self.step()?];
+        loop {
+            if self.eat(&Tok::Slash) {
+                steps.push(self.step()?);
+            } else if self.eat(&Tok::DoubleSlash) {
+                steps.push(descendant_or_self_step());
+                steps.push(self.step()?);
+            } else {
+                return Ok(steps);
+            }
+        }
+    }
+
+    fn step(&mut self) -> Result<Step, FormatError> {
+        if self.eat(&Tok::Dot) {
+            return Ok(Step {
+                axis: Axis::Current,
+                test: NodeTest::Node,
+                predicates: Vec::new(),
+            });
+        }
+        if self.eat(&Tok::DotDot) {
+            return Ok(Step {
+                axis: Axis::Parent,
+                test: NodeTest::Node,
+                predicates: Vec::new(),
+            });
+        }
+
+        let mut axis = Axis::Child;
+        if self.eat(&Tok::At) {
+            axis = Axis::Attribute;
+        } else if let (Some(Tok::Name(name)), Some(Tok::ColonColon)) =
+            (self.peek(), self.peek_at(1))
+        {
+            axis = parse_axis(name)?;
+            self.pos += 2;
+        }
+
+        let test = match self.peek().cloned() {
+            Some(Tok::Star) => {
+                self.pos += 1;
+                NodeTest::Any
+            }
+            Some(Tok::Name(name)) => {
+                self.pos += 1;
+                if is_node_type(&name) && self.peek() == Some(&Tok::LParen) {
+                    self.pos += 1;
+                    // processing-instruction('target') literal is accepted but not matched on
+                    if let Some(Tok::Literal(_)) = self.peek() {
+                        self.pos += 1;
+                    }
+                    self.expect(&Tok::RParen)?;
+                    match name.as_str() {
+                        "text" => NodeTest::Text,
+                        "comment" => NodeTest::Comment,
+                        "processing-instruction" => NodeTest::ProcessingInstruction,
+                        _ => NodeTest::Node,
+                    }
+                } else if let Some(prefix) = name.strip_suffix(":*") {
+                    NodeTest::Prefix(prefix.to_string())
+                } else {
+                    NodeTest::Name(name)
+                }
+            }
+            other => {
+                return Err(query_error(format!(
+                    "Expected a node test, found {}",
+                    token_text(other.as_ref())
+                )))
+            }
+        };
+
+        let mut predicates = Vec::new();
+        while self.peek() == Some(&Tok::LBracket) {
+            predicates.push(self.predicate()?);
+        }
+        Ok(Step {
+            axis,
+            test,
+            predicates,
+        })
+    }
+
+    fn predicate(&mut self) -> Result<Expr, FormatError> {
+        self.expect(&Tok::LBracket)?;
+        let expr = self.or_expr()?;
+        self.expect(&Tok::RBracket)?;
+        Ok(expr)
+    }
+}
+
+fn descendant_or_self_step() -> Step {
+    Step {
+        axis: Axis::DescendantOrSelf,
+        test: NodeTest::Node,
+        predicates: Vec::new(),
+    }
+}
+
+fn is_node_type(name: &str) -> bool {
+    matches!(name, "text" | "comment" | "processing-instruction" | "node")
+}
+
+fn parse_axis(name: &str) -> Result<Axis, FormatError> {
+    Ok(match name {
+        "child" => Axis::Child,
+        "descendant" => Axis::Descendant,
+        "descendant-or-self" => Axis::DescendantOrSelf,
+        "parent" => Axis::Parent,
+        "ancestor" => Axis::Ancestor,
+        "ancestor-or-self" => Axis::AncestorOrSelf,
+        "following-sibling" => Axis::FollowingSibling,
+        "preceding-sibling" => Axis::PrecedingSibling,
+        "following" => Axis::Following,
+        "preceding" => Axis::Preceding,
+        "attribute" => Axis::Attribute,
+        "self" => Axis::Current,
+        "namespace" => return Err(query_error("The namespace axis is not supported")),
+        other => return Err(query_error(format!("Unknown axis '{}'", other))),
+    })
+}
+
+// ============================================================================
+// Evaluator
+// ============================================================================
+
+struct Evaluator<'a> {
+    doc: &'a XmlDocument,
+}
+
+impl Evaluator<'_> {
+    fn eval(&self, expr: &Expr, ctx: &Context) -> Result<XValue, FormatError> {
+        Ok(match expr {
+            Expr::Or(a, b) => XValue::Bool(self.boolean(a, ctx)? || self.boolean(b, ctx)?),
+            Expr::And(a, b) => XValue::Bool(self.boolean(a, ctx)? && self.boolean(b, ctx)?),
+            Expr::Compare(op, a, b) => {
+                let left = self.eval(a, ctx)?;
+                let right = self.eval(b, ctx)?;
+                XValue::Bool(self.compare(op, &left, &right))
+            }
+            Expr::Arith(op, a, b) => {
+                let x = self.number(&self.eval(a, ctx)?);
+                let y = self.number(&self.eval(b, ctx)?);
+                XValue::Num(match *op {
+                    "+" => x + y,
+                    "-" => x - y,
+                    "*" => x * y,
+                    "div" => x / y,
+                    _ => x % y,
+                })
+            }
+            Expr::Neg(a) => XValue::Num(-self.number(&self.eval(a, ctx)?)),
+            Expr::Union(a, b) => {
+                let mut nodes = self.node_set(a, ctx)?;
+                nodes.extend(self.node_set(b, ctx)?);
+                XValue::Nodes(sort_document_order(nodes))
+            }
+            Expr::Path { absolute, steps } => {
+                let start = if *absolute {
+                    NodeRef::Node(XmlDocument::ROOT)
+                } else {
+                    ctx.node
+                };
+                XValue::Nodes(self.apply_steps(vec![start], steps)?)
+            }
+            Expr::Filter {
+                primary,
+                predicates,
+                steps,
+            } => {
+                let mut nodes = match self.eval(primary, ctx)? {
+                    XValue::Nodes(nodes) => nodes,
+                    _ => return Err(query_error("Predicates and paths require a node-set")),
+                };
+                for predicate in predicates {
+                    nodes = self.filter(nodes, predicate)?;
+                }
+                XValue::Nodes(self.apply_steps(nodes, steps)?)
+            }
+            Expr::Literal(s) => XValue::Str(s.clone()),
+            Expr::Number(n) => XValue::Num(*n),
+            Expr::Call(name, args) => self.call(name, args, ctx)?,
+        })
+    }
+
+    fn node_set(&self, expr: &Expr, ctx: &Context) -> Result<Vec<NodeRef>, FormatError> {
+        match self.eval(expr, ctx)? {
+            XValue::Nodes(nodes) => Ok(nodes),
+            _ => Err(query_error("Expected a node-set")),
+        }
+    }
+
+    fn boolean(&self, expr: &Expr, ctx: &Context) -> Result<bool, FormatError> {
+        Ok(to_boolean(&self.eval(expr, ctx)?))
+    }
+
+    fn apply_steps(
+        &self,
+        mut nodes: Vec<NodeRef>,
+        steps: &[Step],
+    ) -> Result<Vec<NodeRef>, FormatError> {
+        for step in steps {
+            let mut next = Vec::new();
+            for &node in &nodes {
+                let mut candidates: Vec<NodeRef> = self
+                    .axis_nodes(node, step.axis)
+                    .into_iter()
+                    .filter(|n| self.matches_test(*n, &step.test, step.axis))
+                    .collect();
+                // Predicates see positions in axis order (reverse axes count backwards)
+                for predicate in &step.predicates {
+                    candidates = self.filter(candidates, predicate)?;
+                }
+                next.extend(candidates);
+            }
+            nodes = sort_document_order(next);
+        }
+        Ok(nodes)
+    }
+
+    fn filter(&self, nodes: Vec<NodeRef>, predicate: &Expr) -> Result<Vec<NodeRef>, FormatError> {
+        let size = nodes.len();
+        let mut kept = Vec::new();
+        for (i, node) in nodes.into_iter().enumerate() {
+            let ctx = Context {
+                node,
+                position: i + 1,
+                size,
+            };
+            let keep = match self.eval(predicate, &ctx)? {
+                XValue::Num(n) => n == (i + 1) as f64,
+                other => to_boolean(&other),
+            };
+            if keep {
+                kept.push(node);
+            }
+        }
+        Ok(kept)
+    }
+
+    /// Nodes along an axis, in axis order.
+    fn axis_nodes(&self, node: NodeRef, axis: Axis) -> Vec<NodeRef> {
+        let doc = self.doc;
+        let id = match node {
+            NodeRef::Node(id) => id,
+            NodeRef::Attr(owner, _) => {
+                // Attributes have no children or siblings; parent is the owner element
+                return match axis {
+                    Axis::Current | Axis::DescendantOrSelf => vec![node],
+                    Axis::Parent => vec![NodeRef::Node(owner)],
+                    Axis::Ancestor | Axis::AncestorOrSelf => {
+                        let mut out = if axis == Axis::AncestorOrSelf {
+                            vec![node]
+                        } else {
+                            Vec::new()
+                        };
+                        out.push(NodeRef::Node(owner));
+                        out.extend(self.ancestors(owner).into_iter().map(NodeRef::Node));
+                        out
+                    }
+                    _ => Vec::new(),
+                };
+            }
+        };
+        let nodes = |ids: Vec<NodeId>| ids.into_iter().map(NodeRef::Node).collect::<Vec<_>>();
+        match axis {
+            Axis::Child => nodes(doc.children(id).to_vec()),
+            Axis::Descendant => nodes(self.descendants(id)),
+            Axis::DescendantOrSelf => {
+                let mut out = vec![id];
+                out.extend(self.descendants(id));
+                nodes(out)
+            }
+            Axis::Parent => nodes(doc.node(id).parent.into_iter().collect()),
+            Axis::Ancestor => nodes(self.ancestors(id)),
+            Axis::AncestorOrSelf => {
+                let mut out = vec![id];
+                out.extend(self.ancestors(id));
+                nodes(out)
+            }
+            Axis::FollowingSibling | Axis::PrecedingSibling => {
+                let Some(parent) = doc.node(id).parent else {
+                    return Vec::new();
+                };
+                let siblings = doc.children(parent);
+                let index = siblings.iter().position(|&s| s == id).unwrap_or(0);
+                if axis == Axis::FollowingSibling {
+                    nodes(siblings[index + 1..].to_vec())
+                } else {
+                    nodes(siblings[..index].iter().rev().copied().collect())
+                }
+            }
+            Axis::Following => {
+                // Everything after this node's subtree in document order
+                let end = self.descendants(id).last().copied().unwrap_or(id);
+                nodes((end + 1..doc.len()).collect())
+            }
+            Axis::Preceding => {
+                let ancestors = self.ancestors(id);
+                nodes((1..id).rev().filter(|n| !ancestors.contains(n)).collect())
+            }
+            Axis::Attribute => (0..doc.attributes(id).len())
+                .map(|i| NodeRef::Attr(id, i))
+                .collect(),
+            Axis::Current => vec![node],
+        }
+    }
+
+    /// Descendants in document order (ids are assigned in document order).
+    fn descendants(&self, id: NodeId) -> Vec<NodeId> {
+        let mut out = Vec::new();
+        let mut stack: Vec<NodeId> = self.doc.children(id).iter().rev().copied().collect();
+        while let Some(n) = stack.pop() {
+            out.push(n);
+            stack.extend(self.doc.children(n).iter().rev());
+        }
+        out
+    }
+
+    /// Ancestors from nearest to farthest.
+    fn ancestors(&self, id: NodeId) -> Vec<NodeId> {
+        let mut out = Vec::new();
+        let mut current = self.doc.node(id).parent;
+        while let Some(p) = current {
+            out.push(p);
+            current = self.doc.node(p).parent;
+        }
+        out
+    }
+
+    fn matches_test(&self, node: NodeRef, test: &NodeTest, axis: Axis) -> bool {
+        let name = match node {
+            NodeRef::Attr(id, i) => Some(self.doc.attributes(id)[i].name.as_str()),
+            NodeRef::Node(id) => self.doc.name(id),
+        };
+        // Principal node type is attribute on the attribute axis, element elsewhere
+        let principal = match node {
+            NodeRef::Attr(..) => axis == Axis::Attribute,
+            NodeRef::Node(id) => axis != Axis::Attribute && self.doc.is_element(id),
+        };
+        let kind = match node {
+            NodeRef::Node(id) => Some(&self.doc.node(id).kind),
+            NodeRef::Attr(..) => None,
+        };
+        match test {
+            NodeTest::Node => true,
+            NodeTest::Any => principal,
+            NodeTest::Prefix(prefix) => {
+                principal
+                    && name.is_some_and(|n| n.split_once(':').is_some_and(|(p, _)| p == prefix))
+            }
+            NodeTest::Name(expected) => principal && name == Some(expected.as_str()),
+            NodeTest::Text => matches!(kind, Some(NodeKind::Text(_)) | Some(NodeKind::CData(_))),
+            NodeTest::Comment => matches!(kind, Some(NodeKind::Comment(_))),
+            NodeTest::ProcessingInstruction => {
+                matches!(kind, Some(NodeKind::ProcessingInstruction(_)))
+            }
+        }
+    }
+
+    fn string_value(&self, node: NodeRef) -> String {
+        match node {
+            NodeRef::Attr(id, i) => self.doc.attributes(id)[i].value(),
+            NodeRef::Node(id) => match &self.doc.node(id).kind {
+                NodeKind::Comment(text) | NodeKind::ProcessingInstruction(text) => text.clone(),
+                _ => self.doc.text_content(id),
+            },
+        }
+    }
+
+    fn string(&self, value: &XValue) -> String {
+        match value {
+            XValue::Nodes(nodes) => nodes
+                .first()
+                .map(|n| self.string_value(*n))
+                .unwrap_or_default(),
+            XValue::Str(s) => s.clone(),
+            XValue::Num(n) => number_to_string(*n),
+            XValue::Bool(b) => b.to_string(),
+        }
+    }
+
+    fn number(&self, value: &XValue) -> f64 {
+        match value {
+            XValue::Num(n) => *n,
+            XValue::Bool(b) => f64::from(u8::from(*b)),
+            other => string_to_number(&self.string(other)),
+        }
+    }
+
+    /// XPath comparison semantics, including existential node-set comparison.
+    fn compare(&self, op: &str, left: &XValue, right: &XValue) -> bool {
+        match (left, right) {
+            (XValue::Nodes(a), XValue::Nodes(b)) => a.iter().any(|x| {
+                let sx = self.string_value(*x);
+                b.iter().any(|y| {
+                    self.compare_atoms(
+                        op,
+                        &XValue::Str(sx.clone()),
+                        &XValue::Str(self.string_value(*y)),
+                    )
+                })
+            }),
+            (XValue::Nodes(nodes), other) => nodes
+                .iter()
+                .any(|n| self.compare_atoms(op, &self.node_as(other, *n), other)),
+            (other, XValue::Nodes(nodes)) => nodes
+                .iter()
+                .any(|n| self.compare_atoms(op, other, &self.node_as(other, *n))),
+            _ => self.compare_atoms(op, left, right),
+        }
+    }
+
+    /// Convert a node to the type of the value it is compared against.
+    fn node_as(&self, other: &XValue, node: NodeRef) -> XValue {
+        let s = self.string_value(node);
+        match other {
+            XValue::Num(_) => XValue::Num(string_to_number(&s)),
+            XValue::Bool(_) => XValue::Bool(true),
+            _ => XValue::Str(s),
+        }
+    }
+
+    fn compare_atoms(&self, op: &str, a: &XValue, b: &XValue) -> bool {
+        match op {
+            "=" | "!=" => {
+                let equal = match (a, b) {
+                    (XValue::Bool(_), _) | (_, XValue::Bool(_)) => to_boolean(a) == to_boolean(b),
+                    (XValue::Num(_), _) | (_, XValue::Num(_)) => self.number(a) == self.number(b),
+                    _ => self.string(a) == self.string(b),
+                };
+                equal == (op == "=")
+            }
+            _ => {
+                let (x, y) = (self.number(a), self.number(b));
+                match op {
+                    "<" => x < y,
+                    "<=" => x <= y,
+                    ">" => x > y,
+                    _ => x >= y,
+                }
+            }
+        }
+    }
+
+    fn call(&self, name: &str, args: &[Expr], ctx: &Context) -> Result<XValue, FormatError> {
+        let arity = |min: usize, max: usize| -> Result<(), FormatError> {
+            if args.len() < min || args.len() > max {
+                Err(query_error(format!(
+                    "Wrong number of arguments to {}()",
+                    name
+                )))
+            } else {
+                Ok(())
+            }
+        };
+        let arg_string = |i: usize| -> Result<String, FormatError> {
+            match args.get(i) {
+                Some(expr) => Ok(self.string(&self.eval(expr, ctx)?)),
+                None => Ok(self.string_value(ctx.node)),
+            }
+        };
+        let arg_number =
+            |i: usize| -> Result<f64, FormatError> { Ok(self.number(&self.eval(&args[i], ctx)?)) };
+
+        Ok(match name {
+            "last" => {
+                arity(0, 0)?;
+                XValue::Num(ctx.size as f64)
+            }
+            "position" => {
+                arity(0, 0)?;
+                XValue::Num(ctx.position as f64)
+            }
+            "count" => {
+                arity(1, 1)?;
+                XValue::Num(self.node_set(&args[0], ctx)?.len() as f64)
+            }
+            "name" | "local-name" => {
+                arity(0, 1)?;
+                let node = match args.first() {
+                    Some(expr) => self.node_set(expr, ctx)?.first().copied(),
+                    None => Some(ctx.node),
+                };
+                let qname = node
+                    .and_then(|n| match n {
+                        NodeRef::Attr(id, i) => Some(self.doc.attributes(id)[i].name.clone()),
+                        NodeRef::Node(id) => self.doc.name(id).map(str::to_string),
+                    })
+                    .unwrap_or_default();
+                let result = if name == "local-name" {
+                    qname.rsplit(':').next().unwrap_or_default().to_string()
+                } else {
+                    qname
+                };
+                XValue::Str(result)
+            }
+            "string" => {
+                arity(0, 1)?;
+                XValue::Str(arg_string(0)?)
+            }
+            "concat" => {
+                if args.len() < 2 {
+                    return Err(query_error("concat() requires at least two arguments"));
+                }
+                let mut s = String::new();
+                for i in 0..args.len() {
+                    s.push_str(&arg_string(i)?);
+                }
+                XValue::Str(s)
+            }
+            "starts-with" => {
+                arity(2, 2)?;
+                XValue::Bool(arg_string(0)?.starts_with(&arg_string(1)?))
+            }
+            "contains" => {
+                arity(2, 2)?;
+                XValue::Bool(arg_string(0)?.contains(&arg_string(1)?))
+            }
+            "substring-before" => {
+                arity(2, 2)?;
+                let (s, pat) = (arg_string(0)?, arg_string(1)?);
+                XValue::Str(
+                    s.split_once(&pat)
+                        .map(|(before, _)| before.to_string())
+                        .unwrap_or_default(),
+                )
+            }
+            "substring-after" => {
+                arity(2, 2)?;
+                let (s, pat) = (arg_string(0)?, arg_string(1)?);
+                XValue::Str(
+                    s.split_once(&pat)
+                        .map(|(_, after)| after.to_string())
+                        .unwrap_or_default(),
+                )
+            }
+            "substring" => {
+                arity(2, 3)?;
+                let s: Vec<char> = arg_string(0)?.chars().collect();
+                let start = arg_number(1)?.round();
+                let end = if args.len() == 3 {
+                    start + arg_number(2)?.round()
+                } else {
+                    f64::INFINITY
+                };
+                let out: String = s
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| {
+                        let p = (*i + 1) as f64;
+                        p >= start && p < end
+                    })
+                    .map(|(_, c)| *c)
+                    .collect();
+                XValue::Str(out)
+            }
+            "string-length" => {
+                arity(0, 1)?;
+                XValue::Num(arg_string(0)?.chars().count() as f64)
+            }
+            "normalize-space" => {
+                arity(0, 1)?;
+                XValue::Str(
+                    arg_string(0)?
+                        .split_whitespace()
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                )
+            }
+            "translate" => {
+                arity(3, 3)?;
+                let from: Vec<char> = arg_string(1)?.chars().collect();
+                let to: Vec<char> = arg_string(2)?.chars().collect();
+                let out = arg_string(0)?
+                    .chars()
+                    .filter_map(|c| match from.iter().position(|f| *f == c) {
+                        Some(i) => to.get(i).copied(),
+                        None => Some(c),
+                    })
+                    .collect();
+                XValue::Str(out)
+            }
+            "not" => {
+                arity(1, 1)?;
+                XValue::Bool(!self.boolean(&args[0], ctx)?)
+            }
+            "true" => {
+                arity(0, 0)?;
+                XValue::Bool(true)
+            }
+            "false" => {
+                arity(0, 0)?;
+                XValue::Bool(false)
+            }
+            "lang" => {
+                arity(1, 1)?;
+                let wanted = arg_string(0)?.to_lowercase();
+                let (NodeRef::Node(id) | NodeRef::Attr(id, _)) = ctx.node;
+                let mut current = Some(id);
+                let mut lang = None;
+                while let (Some(id), None) = (current, &lang) {
+                    lang = self.doc.attribute(id, "xml:lang");
+                    current = self.doc.node(id).parent;
+                }
+                // Matches the language or a sublanguage of it: "en" matches "EN-us"
+                XValue::Bool(lang.is_some_and(|lang| {
+                    let lang = lang.to_lowercase();
+                    lang.strip_prefix(&wanted)
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with('-'))
+                }))
+            }
+            "boolean" => {
+                arity(1, 1)?;
+                XValue::Bool(self.boolean(&args[0], ctx)?)
+            }
+            "number" => {
+                arity(0, 1)?;
+                match args.first() {
+                    Some(expr) => XValue::Num(self.number(&self.eval(expr, ctx)?)),
+                    None => XValue::Num(string_to_number(&self.string_value(ctx.node))),
+                }
+            }
+            "sum" => {
+                arity(1, 1)?;
+                let nodes = self.node_set(&args[0], ctx)?;
+                XValue::Num(
+                    nodes
+                        .iter()
+                        .map(|n| string_to_number(&self.string_value(*n)))
+                        .sum(),
+                )
+            }
+            "floor" => {
+                arity(1, 1)?;
+                XValue::Num(arg_number(0)?.floor())
+            }
+            "ceiling" => {
+                arity(1, 1)?;
+                XValue::Num(arg_number(0)?.ceil())
+            }
+            "round" => {
+                arity(1, 1)?;
+                XValue::Num((arg_number(0)? + 0.5).floor())
+            }
+            other => return Err(query_error(format!("Unknown function {}()", other))),
+        })
+    }
+}
+
+fn sort_document_order(mut nodes: Vec<NodeRef>) -> Vec<NodeRef> {
+    nodes.sort_by_key(|n| n.order_key());
+    nodes.dedup();
+    nodes
+}
+
+fn to_boolean(value: &XValue) -> bool {
+    match value {
+        XValue::Nodes(nodes) => !nodes.is_empty(),
+        XValue::Str(s) => !s.is_empty(),
+        XValue::Num(n) => *n != 0.0 && !n.is_nan(),
+        XValue::Bool(b) => *b,
+    }
+}
+
+fn string_to_number(s: &str) -> f64 {
+    let t = s.trim();
+    // XPath numbers have no exponent, sign prefix `+`, or hex/inf forms
+    if t.is_empty() || t.contains(['e', 'E', '+']) || t.chars().any(char::is_alphabetic) {
+        return f64::NAN;
+    }
+    t.parse().unwrap_or(f64::NAN)
+}
+
+/// A token as quoted in error messages.
+fn token_text(tok: Option<&Tok>) -> String {
+    let text = match tok {
+        None => return "end of expression".to_string(),
+        Some(Tok::Literal(s)) => return format!("string \"{}\"", s),
+        Some(Tok::Number(n)) => return format!("number {}", number_to_string(*n)),
+        Some(Tok::Name(name)) => name.as_str(),
+        Some(Tok::Op(op)) => op,
+        Some(Tok::Slash) => "/",
+        Some(Tok::DoubleSlash) => "//",
+        Some(Tok::LParen) => "(",
+        Some(Tok::RParen) => ")",
+        Some(Tok::LBracket) => "[",
+        Some(Tok::RBracket) => "]",
+        Some(Tok::Dot) => ".",
+        Some(Tok::DotDot) => "..",
+        Some(Tok::At) => "@",
+        Some(Tok::Comma) => ",",
+        Some(Tok::Pipe) => "|",
+        Some(Tok::Star) => "*",
+        Some(Tok::ColonColon) => "::",
+    };
+    format!("'{}'", text)
+}
+
+fn number_to_string(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else if n == n.trunc() && n.abs() < 1e15 {
+        format!("{}", n as i64)
+    } else {
+        n.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &str = r#"<library>
+  <book id="b1" lang="en"><title>Rust</title><price>30</price></book>
+  <book id="b2" lang="fr"><title>XML</title><price>20</price></book>
+  <!-- note -->
+  <magazine id="m1"><title>Weekly</title></magazine>
+</library>"#;
+
+    fn nodes(expr: &str) -> Vec<QueryMatch> {
+        match xml_query(DOC, expr).unwrap() {
+            QueryResult::NodeSet { matches } => matches,
+            other => panic!("expected node-set, got {:?}", other),
+        }
+    }
+
+    fn values(expr: &str) -> Vec<String> {
+        nodes(expr).into_iter().map(|m| m.value).collect()
+    }
+
+    #[test]
+    fn test_paths_and_positions() {
+        let matches = nodes("/library/book");
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].line, 2);
+        assert_eq!(matches[0].column, 3);
+        assert!(matches[0].value.starts_with(r#"<book id="b1""#));
+        assert_eq!(values("//title/text()"), vec!["Rust", "XML", "Weekly"]);
+        assert_eq!(values("//book/@id"), vec!["b1", "b2"]);
+        assert_eq!(nodes("/*/*").len(), 3);
+        assert_eq!(values("//comment()"), vec![" note "]);
+    }
+
+    #[test]
+    fn test_predicates() {
+        assert_eq!(values("//book[2]/title/text()"), vec!["XML"]);
+        assert_eq!(values("//book[last()]/@id"), vec!["b2"]);
+        assert_eq!(values("//book[@lang='fr']/@id"), vec!["b2"]);
+        assert_eq!(values("//book[price > 25]/title/text()"), vec!["Rust"]);
+        assert_eq!(values("//*[title='Weekly']/@id"), vec!["m1"]);
+        assert_eq!(
+            values("//book[not(@lang='en') and price < 100]/@id"),
+            vec!["b2"]
+        );
+    }
+
+    #[test]
+    fn test_axes_and_union() {
+        assert_eq!(values("//title[.='XML']/../@id"), vec!["b2"]);
+        assert_eq!(
+            values("//book[1]/following-sibling::*/@id"),
+            vec!["b2", "m1"]
+        );
+        assert_eq!(
+            values("//magazine/preceding-sibling::book[1]/@id"),
+            vec!["b2"]
+        );
+        assert_eq!(values("//price/ancestor::*[@id][1]/@id"), vec!["b1", "b2"]);
+        assert_eq!(
+            values("//magazine/@id | //book/@id"),
+            vec!["b1", "b2", "m1"]
+        );
+    }
+
+    #[test]
+    fn test_scalar_results() {
+        assert_eq!(
+            xml_query(DOC, "count(//book)").unwrap(),
+            QueryResult::Number { value: 2.0 }
+        );
+        assert_eq!(
+            xml_query(DOC, "sum(//price) div 2").unwrap(),
+            QueryResult::Number { value: 25.0 }
+        );
+        assert_eq!(
+            xml_query(DOC, "concat(name(/*), '-', string(//book[2]/@lang))").unwrap(),
+            QueryResult::String {
+                value: "library-fr".to_string()
+            }
+        );
+        assert_eq!(
+            xml_query(DOC, "starts-with(//title, 'Ru')").unwrap(),
+            QueryResult::Boolean { value: true }
+        );
+    }
+
+    #[test]
+    fn test_non_finite_numbers_serialize_as_strings() {
+        let json = |query| serde_json::to_string(&xml_query(DOC, query).unwrap()).unwrap();
+        assert_eq!(json("1 div 0"), r#"{"type":"number","value":"Infinity"}"#);
+        assert_eq!(json("-1 div 0"), r#"{"type":"number","value":"-Infinity"}"#);
+        assert_eq!(json("number('x')"), r#"{"type":"number","value":"NaN"}"#);
+        assert_eq!(json("count(//book)"), r#"{"type":"number","value":2.0}"#);
+    }
+
+    #[test]
+    fn test_lang() {
+        let doc = r#"<r xml:lang="en-US"><a/><b xml:lang="fr"><c/></b></r>"#;
+        let names = |query| match xml_query(doc, query).unwrap() {
+            QueryResult::NodeSet { matches } => matches
+                .into_iter()
+                .filter_map(|m| m.name)
+                .collect::<Vec<_>>(),
+            other => panic!("expected node-set, got {:?}", other),
+        };
+        assert_eq!(names("//*[lang('en')]"), vec!["r", "a"]);
+        assert_eq!(names("//*[lang('EN-us')]"), vec!["r", "a"]);
+        assert_eq!(names("//*[lang('fr')]"), vec!["b", "c"]);
+        assert!(names("//*[lang('e')]").is_empty());
+        assert_eq!(
+            xml_query("<r/>", "lang('en')").unwrap(),
+            QueryResult::Boolean { value: false }
+        );
+    }
+
+    #[test]
+    fn test_qualified_names() {
+        let doc =
+            r#"<s:Envelope xmlns:s="urn:s"><s:Body><m:Op xmlns:m="urn:m"/></s:Body></s:Envelope>"#;
+        let QueryResult::NodeSet { matches } = xml_query(doc, "//s:*").unwrap() else {
+            panic!()
+        };
+        assert_eq!(matches.len(), 2);
+        let QueryResult::NodeSet { matches } = xml_query(doc, "//*[local-name()='Op']").unwrap()
+        else {
+            panic!()
+        };
+        assert_eq!(matches[0].name.as_deref(), Some("m:Op"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = xml_query(DOC, "//i[").unwrap_err();
+        assert_eq!(
+            err.message,
+            "XPath error: Expected a node test, found end of expression"
+        );
+        let err = xml_query(DOC, "(//book").unwrap_err();
+        assert_eq!(
+            err.message,
+            "XPath error: Expected ')', found end of expression"
+        );
+        let err = xml_query(DOC, "//book]").unwrap_err();
+        assert_eq!(err.message, "XPath error: Unexpected ']'");
+        assert!(xml_query(DOC, "unknown()").is_err());
+        assert!(xml_query(DOC, "$var").is_err());
+        assert!(xml_query("<broken>", "/").is_err());
+        // Wrong argument counts are errors, not panics
+        for query in [
+            "floor()",
+            "ceiling()",
+            "round()",
+            "floor(1, 2)",
+            "true(1)",
+            "false(1)",
+        ] {
+            assert!(xml_query(DOC, query).is_err(), "{}", query);
+        }
+    }
+}
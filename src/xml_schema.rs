@@ -0,0 +1,1996 @@
+//! XML Schema (XSD) validation
+//!
+//! Validates an XML document against a user-supplied XSD 1.0 schema. The
+//! schema is compiled into element and type tables, then the document is
+//! checked top-down: content models (sequence, choice, all, groups and
+//! wildcards with occurrence bounds), attributes, and simple-type values
+//! for the built-in types and the common facets. Every violation is
+//! reported with its element path and position rather than stopping at the
+//! first one.
+//!
+//! Element and attribute names are matched by local name and
+//! `targetNamespace` is not enforced. There is nothing to fetch offline, so
+//! `xs:import`/`xs:include` are ignored, as are `pattern` facets and
+//! identity constraints (`xs:key`, `xs:unique`).
+
+use crate::types::FormatError;
+use crate::xml_tree::{NodeId, NodeKind, XmlDocument};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+const XSD_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema";
+const XSI_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema-instance";
+
+/// Built-in simple types understood by the validator (local names).
+const BUILTIN_TYPES: &[&str] = &[
+    "anyType",
+    "anySimpleType",
+    "string",
+    "normalizedString",
+    "token",
+    "language",
+    "Name",
+    "NCName",
+    "NMTOKEN",
+    "NMTOKENS",
+    "ID",
+    "IDREF",
+    "IDREFS",
+    "ENTITY",
+    "ENTITIES",
+    "QName",
+    "NOTATION",
+    "anyURI",
+    "boolean",
+    "decimal",
+    "integer",
+    "long",
+    "int",
+    "short",
+    "byte",
+    "nonNegativeInteger",
+    "positiveInteger",
+    "nonPositiveInteger",
+    "negativeInteger",
+    "unsignedLong",
+    "unsignedInt",
+    "unsignedShort",
+    "unsignedByte",
+    "float",
+    "double",
+    "duration",
+    "dateTime",
+    "date",
+    "time",
+    "gYear",
+    "gYearMonth",
+    "gMonth",
+    "gMonthDay",
+    "gDay",
+    "hexBinary",
+    "base64Binary",
+];
+
+/// A single schema violation.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SchemaViolation {
+    /// Element path, e.g. `/order/item[2]/price`
+    pub path: String,
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Result of validating a document against a schema.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaValidationResult {
+    pub is_valid: bool,
+    /// Every violation found, in document order
+    pub violations: Vec<SchemaViolation>,
+}
+
+/// Validate an XML document against an XSD schema.
+///
+/// # Arguments
+/// * `input` - The XML string to validate
+/// * `schema` - The XSD schema string
+///
+/// # Returns
+/// * `Ok(SchemaValidationResult)` - Validity plus every violation found
+/// * `Err(FormatError)` - If either document is malformed or the schema is invalid
+///   (schema errors are prefixed with `Schema:`)
+pub fn validate_xml_schema(
+    input: &str,
+    schema: &str,
+) -> Result<SchemaValidationResult, FormatError> {
+    let schema_doc = XmlDocument::parse(schema).map_err(schema_error_from)?;
+    let schema = Compiler::new(&schema_doc, schema).compile()?;
+    let doc = XmlDocument::parse(input)?;
+
+    let mut validator = Validator {
+        schema: &schema,
+        doc: &doc,
+        input,
+        violations: Vec::new(),
+    };
+    validator.validate_document();
+    Ok(SchemaValidationResult {
+        is_valid: validator.violations.is_empty(),
+        violations: validator.violations,
+    })
+}
+
+// ============================================================================
+// Schema model
+// ============================================================================
+
+/// Reference to a type: built-in, named in the schema, or defined inline.
+#[derive(Clone, Debug)]
+enum TypeRef {
+    Builtin(String),
+    Named(String),
+    Anonymous(usize),
+}
+
+#[derive(Clone, Debug)]
+struct ElementDecl {
+    name: String,
+    type_ref: TypeRef,
+    fixed: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+enum Term {
+    /// Index into `Schema::elements`
+    Element(usize),
+    ElementRef(String),
+    GroupRef(String),
+    Any,
+    Sequence(Vec<Particle>),
+    Choice(Vec<Particle>),
+    All(Vec<Particle>),
+}
+
+#[derive(Clone, Debug)]
+struct Particle {
+    term: Term,
+    min: usize,
+    /// `None` for `maxOccurs="unbounded"`
+    max: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+struct AttributeDecl {
+    name: String,
+    type_ref: TypeRef,
+    required: bool,
+    prohibited: bool,
+    fixed: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+enum AttributeUse {
+    Decl(AttributeDecl),
+    Ref(String, bool),
+    GroupRef(String),
+}
+
+#[derive(Clone, Debug)]
+enum Content {
+    Empty,
+    Simple(TypeRef),
+    Elements(Particle),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Derivation {
+    Extension,
+    Restriction,
+}
+
+#[derive(Clone, Debug)]
+struct ComplexType {
+    mixed: bool,
+    base: Option<(TypeRef, Derivation)>,
+    content: Content,
+    attributes: Vec<AttributeUse>,
+    any_attribute: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Variety {
+    Atomic(TypeRef),
+    List(TypeRef),
+    Union(Vec<TypeRef>),
+}
+
+#[derive(Clone, Debug, Default)]
+struct Facets {
+    enumeration: Vec<String>,
+    length: Option<usize>,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    min_inclusive: Option<String>,
+    max_inclusive: Option<String>,
+    min_exclusive: Option<String>,
+    max_exclusive: Option<String>,
+    total_digits: Option<usize>,
+    fraction_digits: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+struct SimpleType {
+    variety: Variety,
+    facets: Facets,
+}
+
+#[derive(Clone, Debug)]
+enum TypeDef {
+    Simple(SimpleType),
+    Complex(ComplexType),
+}
+
+#[derive(Debug, Default)]
+struct Schema {
+    elements: Vec<ElementDecl>,
+    global_elements: HashMap<String, usize>,
+    types: Vec<TypeDef>,
+    named_types: HashMap<String, usize>,
+    groups: HashMap<String, Particle>,
+    attributes: HashMap<String, AttributeDecl>,
+    attribute_groups: HashMap<String, (Vec<AttributeUse>, bool)>,
+}
+
+impl Schema {
+    fn type_def(&self, type_ref: &TypeRef) -> Option<&TypeDef> {
+        match type_ref {
+            TypeRef::Builtin(_) => None,
+            TypeRef::Named(name) => self.named_types.get(name).map(|&i| &self.types[i]),
+            TypeRef::Anonymous(i) => Some(&self.types[*i]),
+        }
+    }
+}
+
+// ============================================================================
+// Schema compiler
+// ============================================================================
+
+struct Compiler<'a> {
+    doc: &'a XmlDocument,
+    source: &'a str,
+    schema: Schema,
+    /// Prefixes bound to the XSD namespace (`""` for a default namespace)
+    xsd_prefixes: Vec<String>,
+    /// Named references to verify once every definition is known: (kind, name, offset)
+    pending: Vec<(&'static str, String, usize)>,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(doc: &'a XmlDocument, source: &'a str) -> Self {
+        Self {
+            doc,
+            source,
+            schema: Schema::default(),
+            xsd_prefixes: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    fn compile(mut self) -> Result<Schema, FormatError> {
+        let root = self
+            .doc
+            .document_element()
+            .ok_or_else(|| schema_error("No root element", 1, 1))?;
+        for attr in self.doc.attributes(root) {
+            if attr.value() == XSD_NAMESPACE {
+                if let Some(prefix) = attr.name.strip_prefix("xmlns:") {
+                    self.xsd_prefixes.push(prefix.to_string());
+                } else if attr.name == "xmlns" {
+                    self.xsd_prefixes.push(String::new());
+                }
+            }
+        }
+        if self.local(root) != "schema" {
+            return Err(self.error("Root element must be xs:schema", root));
+        }
+
+        for child in self.xsd_children(root) {
+            match self.local(child) {
+                "element" => {
+                    let decl = self.element_decl(child)?;
+                    let name = self.schema.elements[decl].name.clone();
+                    self.schema.global_elements.insert(name, decl);
+                }
+                "complexType" | "simpleType" => {
+                    let name = self.required_attr(child, "name")?;
+                    let id = self.type_def(child)?;
+                    self.schema.named_types.insert(name, id);
+                }
+                "group" => {
+                    let name = self.required_attr(child, "name")?;
+                    let model = self
+                        .xsd_children(child)
+                        .into_iter()
+                        .find(|&c| matches!(self.local(c), "sequence" | "choice" | "all"))
+                        .ok_or_else(|| {
+                            self.error("xs:group must contain a sequence, choice, or all", child)
+                        })?;
+                    let particle = self.particle(model)?;
+                    self.schema.groups.insert(name, particle);
+                }
+                "attribute" => {
+                    let decl = self.attribute_decl(child)?;
+                    self.schema.attributes.insert(decl.name.clone(), decl);
+                }
+                "attributeGroup" => {
+                    let name = self.required_attr(child, "name")?;
+                    let mut uses = Vec::new();
+                    let any = self.attribute_uses(child, &mut uses)?;
+                    self.schema.attribute_groups.insert(name, (uses, any));
+                }
+                _ => {}
+            }
+        }
+
+        for (kind, name, offset) in std::mem::take(&mut self.pending) {
+            let known = match kind {
+                "type" => self.schema.named_types.contains_key(&name),
+                "element" => self.schema.global_elements.contains_key(&name),
+                "group" => self.schema.groups.contains_key(&name),
+                "attribute" => self.schema.attributes.contains_key(&name),
+                _ => self.schema.attribute_groups.contains_key(&name),
+            };
+            if !known {
+                let pos = FormatError::at_offset("", self.source, offset);
+                return Err(schema_error(
+                    format!("Unknown {} '{}'", kind, name),
+                    pos.line,
+                    pos.column,
+                ));
+            }
+        }
+        self.check_cycles(root)?;
+        Ok(self.schema)
+    }
+
+    /// Reject definitions that refer back to themselves without an element
+    /// in between: type derivations, group references, and attribute group
+    /// references. Validating against them would never terminate.
+    fn check_cycles(&self, root: NodeId) -> Result<(), FormatError> {
+        let schema = &self.schema;
+        // Named top-level definitions of a kind, in document order
+        let named = |kinds: &[&str]| -> Vec<(String, NodeId)> {
+            self.xsd_children(root)
+                .into_iter()
+                .filter(|&c| kinds.contains(&self.local(c)))
+                .filter_map(|c| Some((self.doc.attribute(c, "name")?, c)))
+                .collect()
+        };
+        let circular =
+            |name: &str, id: NodeId| self.error(format!("circular definition of '{}'", name), id);
+
+        let types = named(&["complexType", "simpleType"]);
+        let type_index = |type_ref: &TypeRef| match type_ref {
+            TypeRef::Builtin(_) => None,
+            TypeRef::Named(name) => schema.named_types.get(name).copied(),
+            TypeRef::Anonymous(i) => Some(*i),
+        };
+        let type_edges = |i: usize| -> Vec<usize> {
+            let refs: Vec<&TypeRef> = match &schema.types[i] {
+                TypeDef::Simple(simple) => match &simple.variety {
+                    Variety::Atomic(base) | Variety::List(base) => vec![base],
+                    Variety::Union(members) => members.iter().collect(),
+                },
+                TypeDef::Complex(ct) => {
+                    let content = match &ct.content {
+                        Content::Simple(inner) => Some(inner),
+                        _ => None,
+                    };
+                    ct.base
+                        .iter()
+                        .map(|(base, _)| base)
+                        .chain(content)
+                        .collect()
+                }
+            };
+            refs.into_iter().filter_map(type_index).collect()
+        };
+        if let Some(cycle) = find_cycle(schema.types.len(), type_edges) {
+            // Inline types cannot be referenced, so every cycle has a named type
+            let (name, id) = cycle
+                .iter()
+                .find_map(|&i| {
+                    types
+                        .iter()
+                        .find(|(name, _)| schema.named_types.get(name) == Some(&i))
+                })
+                .map_or(("anonymous type", root), |(name, id)| (name.as_str(), *id));
+            return Err(circular(name, id));
+        }
+
+        let groups = named(&["group"]);
+        let group_index = |name: &String| groups.iter().rposition(|(n, _)| n == name);
+        let group_edges = |i: usize| -> Vec<usize> {
+            let mut refs = Vec::new();
+            if let Some(particle) = schema.groups.get(&groups[i].0) {
+                group_refs(particle, &mut refs);
+            }
+            refs.into_iter().filter_map(group_index).collect()
+        };
+        if let Some(cycle) = find_cycle(groups.len(), group_edges) {
+            let (name, id) = &groups[cycle[0]];
+            return Err(circular(name, *id));
+        }
+
+        let attribute_groups = named(&["attributeGroup"]);
+        let attribute_group_index =
+            |name: &String| attribute_groups.iter().rposition(|(n, _)| n == name);
+        let attribute_group_edges = |i: usize| -> Vec<usize> {
+            let uses = schema
+                .attribute_groups
+                .get(&attribute_groups[i].0)
+                .map_or(&[][..], |(uses, _)| uses);
+            uses.iter()
+                .filter_map(|u| match u {
+                    AttributeUse::GroupRef(name) => attribute_group_index(name),
+                    _ => None,
+                })
+                .collect()
+        };
+        if let Some(cycle) = find_cycle(attribute_groups.len(), attribute_group_edges) {
+            let (name, id) = &attribute_groups[cycle[0]];
+            return Err(circular(name, *id));
+        }
+        Ok(())
+    }
+
+    fn local(&self, id: NodeId) -> &'a str {
+        local_name(self.doc.name(id).unwrap_or_default())
+    }
+
+    /// Element children that are XSD components (annotations skipped).
+    fn xsd_children(&self, id: NodeId) -> Vec<NodeId> {
+        self.doc
+            .children(id)
+            .iter()
+            .copied()
+            .filter(|&c| self.doc.is_element(c) && self.local(c) != "annotation")
+            .collect()
+    }
+
+    fn error(&self, message: impl Into<String>, id: NodeId) -> FormatError {
+        let pos = FormatError::at_offset("", self.source, self.doc.node(id).offset);
+        schema_error(message, pos.line, pos.column)
+    }
+
+    fn required_attr(&self, id: NodeId, name: &str) -> Result<String, FormatError> {
+        self.doc.attribute(id, name).ok_or_else(|| {
+            self.error(
+                format!("xs:{} requires a '{}' attribute", self.local(id), name),
+                id,
+            )
+        })
+    }
+
+    /// Resolve a QName naming a type to a built-in or schema-defined type.
+    fn type_ref(&mut self, qname: &str, id: NodeId) -> Result<TypeRef, FormatError> {
+        let (prefix, local) = match qname.split_once(':') {
+            Some((prefix, local)) => (prefix, local),
+            None => ("", qname),
+        };
+        // Unknown names in the XSD namespace fall through to schema types, which
+        // tolerates schemas that use XSD as the default namespace for everything
+        if self.xsd_prefixes.iter().any(|p| p == prefix) && BUILTIN_TYPES.contains(&local) {
+            return Ok(TypeRef::Builtin(local.to_string()));
+        }
+        self.reference("type", local, id);
+        Ok(TypeRef::Named(local.to_string()))
+    }
+
+    fn reference(&mut self, kind: &'static str, qname: &str, id: NodeId) -> String {
+        let name = local_name(qname).to_string();
+        self.pending
+            .push((kind, name.clone(), self.doc.node(id).offset));
+        name
+    }
+
+    /// Type given by a `type`/`base` attribute or an inline definition, if any.
+    fn declared_type(&mut self, id: NodeId, attr: &str) -> Result<Option<TypeRef>, FormatError> {
+        if let Some(qname) = self.doc.attribute(id, attr) {
+            return self.type_ref(&qname, id).map(Some);
+        }
+        let inline = self
+            .xsd_children(id)
+            .into_iter()
+            .find(|&c| matches!(self.local(c), "complexType" | "simpleType"));
+        match inline {
+            Some(child) => Ok(Some(TypeRef::Anonymous(self.type_def(child)?))),
+            None => Ok(None),
+        }
+    }
+
+    fn push_type(&mut self, def: TypeDef) -> usize {
+        self.schema.types.push(def);
+        self.schema.types.len() - 1
+    }
+
+    fn element_decl(&mut self, id: NodeId) -> Result<usize, FormatError> {
+        let name = self.required_attr(id, "name")?;
+        let type_ref = self
+            .declared_type(id, "type")?
+            .unwrap_or_else(|| TypeRef::Builtin("anyType".to_string()));
+        self.schema.elements.push(ElementDecl {
+            name,
+            type_ref,
+            fixed: self.doc.attribute(id, "fixed"),
+        });
+        Ok(self.schema.elements.len() - 1)
+    }
+
+    fn attribute_decl(&mut self, id: NodeId) -> Result<AttributeDecl, FormatError> {
+        let name = self.required_attr(id, "name")?;
+        let type_ref = self
+            .declared_type(id, "type")?
+            .unwrap_or_else(|| TypeRef::Builtin("anySimpleType".to_string()));
+        let usage = self.doc.attribute(id, "use");
+        Ok(AttributeDecl {
+            name,
+            type_ref,
+            required: usage.as_deref() == Some("required"),
+            prohibited: usage.as_deref() == Some("prohibited"),
+            fixed: self.doc.attribute(id, "fixed"),
+        })
+    }
+
+    /// Collect attribute declarations under `id`; returns whether `xs:anyAttribute` is present.
+    fn attribute_uses(
+        &mut self,
+        id: NodeId,
+        uses: &mut Vec<AttributeUse>,
+    ) -> Result<bool, FormatError> {
+        let mut any = false;
+        for child in self.xsd_children(id) {
+            match self.local(child) {
+                "attribute" => match self.doc.attribute(child, "ref") {
+                    Some(qname) => {
+                        let name = self.reference("attribute", &qname, child);
+                        let required =
+                            self.doc.attribute(child, "use").as_deref() == Some("required");
+                        uses.push(AttributeUse::Ref(name, required));
+                    }
+                    None => uses.push(AttributeUse::Decl(self.attribute_decl(child)?)),
+                },
+                "attributeGroup" => {
+                    let qname = self.required_attr(child, "ref")?;
+                    uses.push(AttributeUse::GroupRef(self.reference(
+                        "attributeGroup",
+                        &qname,
+                        child,
+                    )));
+                }
+                "anyAttribute" => any = true,
+                _ => {}
+            }
+        }
+        Ok(any)
+    }
+
+    fn type_def(&mut self, id: NodeId) -> Result<usize, FormatError> {
+        let def = match self.local(id) {
+            "simpleType" => TypeDef::Simple(self.simple_type(id)?),
+            _ => TypeDef::Complex(self.complex_type(id)?),
+        };
+        Ok(self.push_type(def))
+    }
+
+    fn simple_type(&mut self, id: NodeId) -> Result<SimpleType, FormatError> {
+        let child = self.xsd_children(id).into_iter().next().ok_or_else(|| {
+            self.error(
+                "xs:simpleType must contain a restriction, list, or union",
+                id,
+            )
+        })?;
+        match self.local(child) {
+            "restriction" => {
+                let base = self
+                    .declared_type(child, "base")?
+                    .ok_or_else(|| self.error("xs:restriction requires a base type", child))?;
+                Ok(SimpleType {
+                    variety: Variety::Atomic(base),
+                    facets: self.facets(child)?,
+                })
+            }
+            "list" => {
+                let item = self
+                    .declared_type(child, "itemType")?
+                    .ok_or_else(|| self.error("xs:list requires an item type", child))?;
+                Ok(SimpleType {
+                    variety: Variety::List(item),
+                    facets: Facets::default(),
+                })
+            }
+            "union" => {
+                let mut members = Vec::new();
+                for qname in self
+                    .doc
+                    .attribute(child, "memberTypes")
+                    .unwrap_or_default()
+                    .split_whitespace()
+                {
+                    members.push(self.type_ref(qname, child)?);
+                }
+                for inline in self.xsd_children(child) {
+                    members.push(TypeRef::Anonymous(self.type_def(inline)?));
+                }
+                Ok(SimpleType {
+                    variety: Variety::Union(members),
+                    facets: Facets::default(),
+                })
+            }
+            other => Err(self.error(format!("Unsupported xs:{} in xs:simpleType", other), child)),
+        }
+    }
+
+    fn facets(&self, id: NodeId) -> Result<Facets, FormatError> {
+        let mut facets = Facets::default();
+        for child in self.xsd_children(id) {
+            let value = || self.required_attr(child, "value");
+            let count = || {
+                value()?.trim().parse::<usize>().map_err(|_| {
+                    self.error(
+                        format!(
+                            "xs:{} value must be a non-negative integer",
+                            self.local(child)
+                        ),
+                        child,
+                    )
+                })
+            };
+            match self.local(child) {
+                "enumeration" => facets.enumeration.push(value()?),
+                "length" => facets.length = Some(count()?),
+                "minLength" => facets.min_length = Some(count()?),
+                "maxLength" => facets.max_length = Some(count()?),
+                "minInclusive" => facets.min_inclusive = Some(value()?.trim().to_string()),
+                "maxInclusive" => facets.max_inclusive = Some(value()?.trim().to_string()),
+                "minExclusive" => facets.min_exclusive = Some(value()?.trim().to_string()),
+                "maxExclusive" => facets.max_exclusive = Some(value()?.trim().to_string()),
+                "totalDigits" => facets.total_digits = Some(count()?),
+                "fractionDigits" => facets.fraction_digits = Some(count()?),
+                _ => {}
+            }
+        }
+        Ok(facets)
+    }
+
+    fn complex_type(&mut self, id: NodeId) -> Result<ComplexType, FormatError> {
+        let mut ct = ComplexType {
+            mixed: self.doc.attribute(id, "mixed").as_deref() == Some("true"),
+            base: None,
+            content: Content::Empty,
+            attributes: Vec::new(),
+            any_attribute: false,
+        };
+        let mut body = id;
+        for child in self.xsd_children(id) {
+            let kind = self.local(child);
+            if kind != "simpleContent" && kind != "complexContent" {
+                continue;
+            }
+            if self.doc.attribute(child, "mixed").as_deref() == Some("true") {
+                ct.mixed = true;
+            }
+            let derivation = self.xsd_children(child).into_iter().next().ok_or_else(|| {
+                self.error(
+                    format!("xs:{} must contain an extension or restriction", kind),
+                    child,
+                )
+            })?;
+            let base_name = self.required_attr(derivation, "base")?;
+            let base = self.type_ref(&base_name, derivation)?;
+            let how = match self.local(derivation) {
+                "extension" => Derivation::Extension,
+                _ => Derivation::Restriction,
+            };
+            if kind == "simpleContent" {
+                ct.content = match how {
+                    Derivation::Extension => Content::Simple(base.clone()),
+                    Derivation::Restriction => {
+                        let restricted = SimpleType {
+                            variety: Variety::Atomic(base.clone()),
+                            facets: self.facets(derivation)?,
+                        };
+                        Content::Simple(TypeRef::Anonymous(
+                            self.push_type(TypeDef::Simple(restricted)),
+                        ))
+                    }
+                };
+            }
+            ct.base = Some((base, how));
+            body = derivation;
+        }
+
+        for child in self.xsd_children(body) {
+            if matches!(self.local(child), "sequence" | "choice" | "all" | "group") {
+                ct.content = Content::Elements(self.particle(child)?);
+            }
+        }
+        ct.any_attribute = self.attribute_uses(body, &mut ct.attributes)?;
+        Ok(ct)
+    }
+
+    fn particle(&mut self, id: NodeId) -> Result<Particle, FormatError> {
+        let min = match self.doc.attribute(id, "minOccurs") {
+            Some(v) => v
+                .trim()
+                .parse()
+                .map_err(|_| self.error("Invalid minOccurs", id))?,
+            None => 1,
+        };
+        let max = match self
+            .doc
+            .attribute(id, "maxOccurs")
+            .as_deref()
+            .map(str::trim)
+        {
+            Some("unbounded") => None,
+            Some(v) => Some(v.parse().map_err(|_| self.error("Invalid maxOccurs", id))?),
+            None => Some(1),
+        };
+        let term = match self.local(id) {
+            "element" => match self.doc.attribute(id, "ref") {
+                Some(qname) => Term::ElementRef(self.reference("element", &qname, id)),
+                None => Term::Element(self.element_decl(id)?),
+            },
+            "group" => {
+                let qname = self.required_attr(id, "ref")?;
+                Term::GroupRef(self.reference("group", &qname, id))
+            }
+            "any" => Term::Any,
+            kind @ ("sequence" | "choice" | "all") => {
+                let mut particles = Vec::new();
+                for child in self.xsd_children(id) {
+                    particles.push(self.particle(child)?);
+                }
+                match kind {
+                    "sequence" => Term::Sequence(particles),
+                    "choice" => Term::Choice(particles),
+                    _ => Term::All(particles),
+                }
+            }
+            other => {
+                return Err(self.error(format!("Unsupported xs:{} in content model", other), id))
+            }
+        };
+        Ok(Particle { term, min, max })
+    }
+}
+
+/// Group references in a content model, outside any element declaration.
+fn group_refs<'p>(particle: &'p Particle, out: &mut Vec<&'p String>) {
+    match &particle.term {
+        Term::GroupRef(name) => out.push(name),
+        Term::Sequence(particles) | Term::Choice(particles) | Term::All(particles) => {
+            for p in particles {
+                group_refs(p, out);
+            }
+        }
+        Term::Element(_) | Term::ElementRef(_) | Term::Any => {}
+    }
+}
+
+/// The nodes of a cycle in the graph with nodes `0..count`, if there is
+/// one, starting with the node the cycle was entered at.
+///
+/// Depth-first with an explicit stack, so long reference chains cannot
+/// overflow the call stack.
+fn find_cycle(count: usize, edges: impl Fn(usize) -> Vec<usize>) -> Option<Vec<usize>> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Visit {
+        New,
+        OnPath,
+        Done,
+    }
+    let mut visits = vec![Visit::New; count];
+    for root in 0..count {
+        if visits[root] != Visit::New {
+            continue;
+        }
+        visits[root] = Visit::OnPath;
+        let mut stack = vec![(root, edges(root))];
+        while let Some((node, targets)) = stack.last_mut() {
+            match targets.pop() {
+                Some(next) => match visits[next] {
+                    Visit::OnPath => {
+                        let start = stack.iter().position(|&(n, _)| n == next).unwrap_or(0);
+                        return Some(stack[start..].iter().map(|&(n, _)| n).collect());
+                    }
+                    Visit::New => {
+                        visits[next] = Visit::OnPath;
+                        stack.push((next, edges(next)));
+                    }
+                    Visit::Done => {}
+                },
+                None => {
+                    visits[*node] = Visit::Done;
+                    stack.pop();
+                }
+            }
+        }
+    }
+    None
+}
+
+fn schema_error(message: impl Into<String>, line: usize, column: usize) -> FormatError {
+    FormatError::new(format!("Schema: {}", message.into()), line, column)
+}
+
+fn schema_error_from(e: FormatError) -> FormatError {
+    schema_error(e.message, e.line, e.column)
+}
+
+fn local_name(qname: &str) -> &str {
+    qname.rsplit_once(':').map_or(qname, |(_, local)| local)
+}
+
+// ============================================================================
+// Content model matching
+// ============================================================================
+
+/// Matches a sequence of child element names against a content model.
+///
+/// Works on sets of reachable positions, so ambiguous models (optional
+/// elements followed by the same name, nested repetitions) need no
+/// backtracking.
+struct Matcher<'s> {
+    schema: &'s Schema,
+    names: Vec<&'s str>,
+    /// Declaration chosen for each child name (`None` for wildcard matches)
+    decls: HashMap<String, Option<usize>>,
+    /// Furthest position any partial match reached
+    furthest: usize,
+    /// Element names that would have been accepted at `furthest`
+    expected: Vec<String>,
+}
+
+impl<'s> Matcher<'s> {
+    fn advance(&mut self, pos: usize) {
+        if pos > self.furthest {
+            self.furthest = pos;
+            self.expected.clear();
+        }
+    }
+
+    fn expect(&mut self, pos: usize, name: &str) {
+        self.advance(pos);
+        if pos == self.furthest && !self.expected.iter().any(|e| e == name) {
+            self.expected.push(name.to_string());
+        }
+    }
+
+    fn particle(&mut self, particle: &Particle, start: Vec<usize>) -> Vec<usize> {
+        let mut result = Vec::new();
+        let mut seen = start.clone();
+        let mut current = start;
+        let mut reps = 0;
+        loop {
+            if reps >= particle.min {
+                merge(&mut result, &current);
+            }
+            if particle.max == Some(reps) || current.is_empty() {
+                break;
+            }
+            let next = self.term(&particle.term, &current);
+            reps += 1;
+            let progressed = next.iter().any(|p| !seen.contains(p));
+            merge(&mut seen, &next);
+            if !progressed && reps >= particle.min {
+                merge(&mut result, &next);
+                break;
+            }
+            current = next;
+        }
+        result
+    }
+
+    fn term(&mut self, term: &Term, start: &[usize]) -> Vec<usize> {
+        let schema = self.schema;
+        match term {
+            Term::Element(decl) => self.element(*decl, start),
+            Term::ElementRef(name) => match schema.global_elements.get(name) {
+                Some(&decl) => self.element(decl, start),
+                None => Vec::new(),
+            },
+            Term::GroupRef(name) => match schema.groups.get(name) {
+                Some(group) => self.particle(group, start.to_vec()),
+                None => Vec::new(),
+            },
+            Term::Any => {
+                let mut out = Vec::new();
+                for &pos in start {
+                    match self.names.get(pos) {
+                        Some(&name) => {
+                            self.decls.entry(name.to_string()).or_insert(None);
+                            self.advance(pos + 1);
+                            merge(&mut out, &[pos + 1]);
+                        }
+                        None => self.expect(pos, "any element"),
+                    }
+                }
+                out
+            }
+            Term::Sequence(particles) => {
+                let mut positions = start.to_vec();
+                for p in particles {
+                    positions = self.particle(p, positions);
+                }
+                positions
+            }
+            Term::Choice(particles) => {
+                let mut out = Vec::new();
+                for p in particles {
+                    let reached = self.particle(p, start.to_vec());
+                    merge(&mut out, &reached);
+                }
+                out
+            }
+            Term::All(particles) => {
+                let mut out = Vec::new();
+                for &pos in start {
+                    if let Some(end) = self.all(particles, pos) {
+                        merge(&mut out, &[end]);
+                    }
+                }
+                out
+            }
+        }
+    }
+
+    fn element(&mut self, decl: usize, start: &[usize]) -> Vec<usize> {
+        let name = &self.schema.elements[decl].name;
+        let mut out = Vec::new();
+        for &pos in start {
+            if self.names.get(pos) == Some(&name.as_str()) {
+                self.decls.insert(name.clone(), Some(decl));
+                self.advance(pos + 1);
+                merge(&mut out, &[pos + 1]);
+            } else {
+                self.expect(pos, name);
+            }
+        }
+        out
+    }
+
+    /// `xs:all`: each member at most once, in any order.
+    fn all(&mut self, particles: &[Particle], start: usize) -> Option<usize> {
+        let mut used = vec![false; particles.len()];
+        let mut pos = start;
+        'children: while pos < self.names.len() {
+            for (i, p) in particles.iter().enumerate() {
+                if used[i] {
+                    continue;
+                }
+                if let Some(&end) = self.particle(p, vec![pos]).iter().max() {
+                    if end > pos {
+                        used[i] = true;
+                        pos = end;
+                        continue 'children;
+                    }
+                }
+            }
+            break;
+        }
+        let mut complete = true;
+        for (i, p) in particles.iter().enumerate() {
+            if !used[i] && p.min > 0 {
+                complete = false;
+                if let Term::Element(decl) = p.term {
+                    let name = self.schema.elements[decl].name.clone();
+                    self.expect(pos, &name);
+                }
+            }
+        }
+        complete.then_some(pos)
+    }
+}
+
+/// Add positions to a sorted set.
+fn merge(set: &mut Vec<usize>, positions: &[usize]) {
+    for &p in positions {
+        if let Err(i) = set.binary_search(&p) {
+            set.insert(i, p);
+        }
+    }
+}
+
+// ============================================================================
+// Document validation
+// ============================================================================
+
+struct Validator<'a> {
+    schema: &'a Schema,
+    doc: &'a XmlDocument,
+    input: &'a str,
+    violations: Vec<SchemaViolation>,
+}
+
+impl<'a> Validator<'a> {
+    fn report(&mut self, id: NodeId, path: &str, message: impl Into<String>) {
+        let pos = FormatError::at_offset("", self.input, self.doc.node(id).offset);
+        self.violations.push(SchemaViolation {
+            path: path.to_string(),
+            message: message.into(),
+            line: pos.line,
+            column: pos.column,
+        });
+    }
+
+    fn validate_document(&mut self) {
+        let Some(root) = self.doc.document_element() else {
+            return;
+        };
+        let name = self.doc.name(root).unwrap_or_default();
+        let path = format!("/{}", name);
+        match self.schema.global_elements.get(local_name(name)) {
+            Some(&decl) => self.validate_element(root, decl, &path),
+            None => self.report(
+                root,
+                &path,
+                format!("No global element declaration for <{}>", name),
+            ),
+        }
+    }
+
+    fn validate_element(&mut self, id: NodeId, decl: usize, path: &str) {
+        let decl = &self.schema.elements[decl];
+        match self.schema.type_def(&decl.type_ref) {
+            Some(TypeDef::Complex(ct)) => self.validate_complex(id, ct, path),
+            _ if matches!(&decl.type_ref, TypeRef::Builtin(name) if name == "anyType") => {}
+            _ => {
+                self.check_attributes(id, path, &[], false);
+                if self.has_child_elements(id) {
+                    self.report(id, path, "Child elements are not allowed in simple content");
+                } else {
+                    let text = self.doc.text_content(id);
+                    if let Err(message) = self.check_value(&decl.type_ref, &text) {
+                        self.report(id, path, message);
+                    }
+                }
+            }
+        }
+        if let Some(fixed) = &decl.fixed {
+            let text = self.doc.text_content(id);
+            if text.trim() != fixed.trim() {
+                self.report(
+                    id,
+                    path,
+                    format!(
+                        "Value '{}' must equal the fixed value '{}'",
+                        text.trim(),
+                        fixed
+                    ),
+                );
+            }
+        }
+    }
+
+    fn validate_complex(&mut self, id: NodeId, ct: &ComplexType, path: &str) {
+        let mut attributes = Vec::new();
+        let any_attribute = self.collect_attributes(ct, &mut attributes);
+        self.check_attributes(id, path, &attributes, any_attribute);
+
+        match self.effective_content(ct) {
+            Content::Simple(type_ref) => {
+                if self.has_child_elements(id) {
+                    self.report(id, path, "Child elements are not allowed in simple content");
+                } else if let Err(message) = self.check_value(&type_ref, &self.doc.text_content(id))
+                {
+                    self.report(id, path, message);
+                }
+            }
+            Content::Empty => {
+                let mixed = self.is_mixed(ct);
+                for &child in self.doc.children(id) {
+                    if self.doc.is_element(child) {
+                        let name = self.doc.name(child).unwrap_or_default();
+                        self.report(
+                            child,
+                            path,
+                            format!("Unexpected element <{}>; content must be empty", name),
+                        );
+                        break;
+                    }
+                    if !mixed && self.is_significant_text(child) {
+                        self.report(child, path, "Text is not allowed; content must be empty");
+                        break;
+                    }
+                }
+            }
+            Content::Elements(particle) => {
+                if !self.is_mixed(ct) {
+                    if let Some(&text) = self
+                        .doc
+                        .children(id)
+                        .iter()
+                        .find(|&&c| self.is_significant_text(c))
+                    {
+                        self.report(text, path, "Text is not allowed in element-only content");
+                    }
+                }
+                self.validate_children(id, &particle, path);
+            }
+        }
+    }
+
+    fn validate_children(&mut self, id: NodeId, particle: &Particle, path: &str) {
+        let children: Vec<NodeId> = self
+            .doc
+            .children(id)
+            .iter()
+            .copied()
+            .filter(|&c| self.doc.is_element(c))
+            .collect();
+        let mut matcher = Matcher {
+            schema: self.schema,
+            names: children
+                .iter()
+                .map(|&c| local_name(self.doc.name(c).unwrap_or_default()))
+                .collect(),
+            decls: HashMap::new(),
+            furthest: 0,
+            expected: Vec::new(),
+        };
+        let ends = matcher.particle(particle, vec![0]);
+        if !ends.contains(&children.len()) {
+            let expected = matcher
+                .expected
+                .iter()
+                .map(|e| {
+                    if e == "any element" {
+                        e.clone()
+                    } else {
+                        format!("<{}>", e)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            match children.get(matcher.furthest) {
+                Some(&child) => {
+                    let name = self.doc.name(child).unwrap_or_default();
+                    let child_path = self.child_path(id, child, path);
+                    let message = if expected.is_empty() {
+                        format!("Unexpected element <{}>", name)
+                    } else {
+                        format!("Unexpected element <{}>; expected {}", name, expected)
+                    };
+                    self.report(child, &child_path, message);
+                }
+                None => self.report(
+                    id,
+                    path,
+                    format!("Missing required element; expected {}", expected),
+                ),
+            }
+        }
+
+        for child in children {
+            let local = local_name(self.doc.name(child).unwrap_or_default());
+            let decl = match matcher.decls.get(local) {
+                Some(Some(decl)) => Some(*decl),
+                // Wildcard matches are validated laxly against global declarations
+                Some(None) => self.schema.global_elements.get(local).copied(),
+                None => None,
+            };
+            if let Some(decl) = decl {
+                let child_path = self.child_path(id, child, path);
+                self.validate_element(child, decl, &child_path);
+            }
+        }
+    }
+
+    /// Attribute declarations of a type, including those inherited from its base.
+    fn collect_attributes(&self, ct: &ComplexType, out: &mut Vec<AttributeDecl>) -> bool {
+        let mut any = ct.any_attribute;
+        if let Some((base, _)) = &ct.base {
+            if let Some(TypeDef::Complex(base)) = self.schema.type_def(base) {
+                any |= self.collect_attributes(base, out);
+            }
+        }
+        self.expand_attribute_uses(&ct.attributes, out, &mut any);
+        out.retain(|a| !a.prohibited);
+        any
+    }
+
+    fn expand_attribute_uses(
+        &self,
+        uses: &[AttributeUse],
+        out: &mut Vec<AttributeDecl>,
+        any: &mut bool,
+    ) {
+        for attr_use in uses {
+            let decl = match attr_use {
+                AttributeUse::Decl(decl) => decl.clone(),
+                AttributeUse::Ref(name, required) => match self.schema.attributes.get(name) {
+                    Some(decl) => AttributeDecl {
+                        required: *required,
+                        ..decl.clone()
+                    },
+                    None => continue,
+                },
+                AttributeUse::GroupRef(name) => {
+                    if let Some((uses, group_any)) = self.schema.attribute_groups.get(name) {
+                        *any |= group_any;
+                        self.expand_attribute_uses(uses, out, any);
+                    }
+                    continue;
+                }
+            };
+            out.retain(|a| a.name != decl.name);
+            out.push(decl);
+        }
+    }
+
+    fn check_attributes(
+        &mut self,
+        id: NodeId,
+        path: &str,
+        decls: &[AttributeDecl],
+        any_attribute: bool,
+    ) {
+        let element = self.doc.name(id).unwrap_or_default().to_string();
+        for attr in self.doc.attributes(id) {
+            if attr.name == "xmlns"
+                || attr.name.starts_with("xmlns:")
+                || self.is_xsi(id, &attr.name)
+            {
+                continue;
+            }
+            let local = local_name(&attr.name);
+            let value = attr.value();
+            match decls.iter().find(|d| d.name == local) {
+                Some(decl) => {
+                    if let Err(message) = self.check_value(&decl.type_ref, &value) {
+                        self.report(id, path, format!("Attribute '{}': {}", attr.name, message));
+                    } else if decl
+                        .fixed
+                        .as_ref()
+                        .is_some_and(|f| f.trim() != value.trim())
+                    {
+                        let fixed = decl.fixed.clone().unwrap_or_default();
+                        self.report(
+                            id,
+                            path,
+                            format!(
+                                "Attribute '{}' must equal the fixed value '{}'",
+                                attr.name, fixed
+                            ),
+                        );
+                    }
+                }
+                None if any_attribute => {}
+                None => self.report(
+                    id,
+                    path,
+                    format!("Attribute '{}' is not allowed on <{}>", attr.name, element),
+                ),
+            }
+        }
+        for decl in decls.iter().filter(|d| d.required) {
+            let present = self
+                .doc
+                .attributes(id)
+                .iter()
+                .any(|a| local_name(&a.name) == decl.name && !a.name.starts_with("xmlns"));
+            if !present {
+                self.report(
+                    id,
+                    path,
+                    format!(
+                        "Missing required attribute '{}' on <{}>",
+                        decl.name, element
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Whether an attribute name uses a prefix bound to the XSI namespace.
+    fn is_xsi(&self, id: NodeId, name: &str) -> bool {
+        let Some((prefix, _)) = name.split_once(':') else {
+            return false;
+        };
+        let decl = format!("xmlns:{}", prefix);
+        let mut node = Some(id);
+        while let Some(n) = node {
+            if let Some(uri) = self.doc.attribute(n, &decl) {
+                return uri == XSI_NAMESPACE;
+            }
+            node = self.doc.node(n).parent;
+        }
+        prefix == "xsi"
+    }
+
+    /// Content model after applying complexContent extension.
+    fn effective_content(&self, ct: &ComplexType) -> Content {
+        let base = match &ct.base {
+            Some((base, Derivation::Extension)) => match self.schema.type_def(base) {
+                Some(TypeDef::Complex(base)) => Some(self.effective_content(base)),
+                _ => None,
+            },
+            _ => None,
+        };
+        match (base, &ct.content) {
+            (Some(Content::Elements(base)), Content::Elements(own)) => {
+                Content::Elements(Particle {
+                    term: Term::Sequence(vec![base, own.clone()]),
+                    min: 1,
+                    max: Some(1),
+                })
+            }
+            (Some(base), Content::Empty) => base,
+            (_, own) => own.clone(),
+        }
+    }
+
+    fn is_mixed(&self, ct: &ComplexType) -> bool {
+        ct.mixed
+            || match &ct.base {
+                Some((base, Derivation::Extension)) => {
+                    matches!(self.schema.type_def(base), Some(TypeDef::Complex(b)) if self.is_mixed(b))
+                }
+                _ => false,
+            }
+    }
+
+    fn has_child_elements(&self, id: NodeId) -> bool {
+        self.doc
+            .children(id)
+            .iter()
+            .any(|&c| self.doc.is_element(c))
+    }
+
+    fn is_significant_text(&self, id: NodeId) -> bool {
+        match &self.doc.node(id).kind {
+            NodeKind::Text(raw) => !raw.trim().is_empty(),
+            NodeKind::CData(text) => !text.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// Path of a child element, with a 1-based index when it has same-named siblings.
+    fn child_path(&self, parent: NodeId, child: NodeId, path: &str) -> String {
+        let name = self.doc.name(child).unwrap_or_default();
+        let same: Vec<NodeId> = self
+            .doc
+            .children(parent)
+            .iter()
+            .copied()
+            .filter(|&c| self.doc.name(c) == Some(name))
+            .collect();
+        if same.len() > 1 {
+            let index = same.iter().position(|&c| c == child).unwrap_or(0) + 1;
+            format!("{}/{}[{}]", path, name, index)
+        } else {
+            format!("{}/{}", path, name)
+        }
+    }
+
+    // ------------------------------------------------------------------------
+    // Simple values
+    // ------------------------------------------------------------------------
+
+    fn check_value(&self, type_ref: &TypeRef, raw: &str) -> Result<(), String> {
+        let simple = match (type_ref, self.schema.type_def(type_ref)) {
+            (TypeRef::Builtin(name), _) => return check_builtin(name, raw),
+            (_, Some(TypeDef::Simple(simple))) => simple,
+            (_, Some(TypeDef::Complex(ct))) => {
+                return match self.effective_content(ct) {
+                    Content::Simple(inner) => self.check_value(&inner, raw),
+                    _ => Ok(()),
+                };
+            }
+            (_, None) => return Ok(()),
+        };
+        match &simple.variety {
+            Variety::Atomic(base) => {
+                self.check_value(base, raw)?;
+                let value = if self.is_string_based(base) {
+                    raw.to_string()
+                } else {
+                    collapse(raw)
+                };
+                check_facets(&simple.facets, &value, value.chars().count())
+            }
+            Variety::List(item) => {
+                for token in raw.split_whitespace() {
+                    self.check_value(item, token)?;
+                }
+                let value = collapse(raw);
+                check_facets(&simple.facets, &value, raw.split_whitespace().count())
+            }
+            Variety::Union(members) => {
+                if members.iter().any(|m| self.check_value(m, raw).is_ok()) {
+                    check_facets(&simple.facets, &collapse(raw), raw.chars().count())
+                } else {
+                    Err(format!(
+                        "Value '{}' does not match any member type of the union",
+                        raw.trim()
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Whether whitespace in values of this type is significant.
+    fn is_string_based(&self, type_ref: &TypeRef) -> bool {
+        match (type_ref, self.schema.type_def(type_ref)) {
+            (TypeRef::Builtin(name), _) => matches!(
+                name.as_str(),
+                "string" | "normalizedString" | "anySimpleType"
+            ),
+            (
+                _,
+                Some(TypeDef::Simple(SimpleType {
+                    variety: Variety::Atomic(base),
+                    ..
+                })),
+            ) => self.is_string_based(base),
+            _ => false,
+        }
+    }
+}
+
+fn collapse(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn check_facets(facets: &Facets, value: &str, length: usize) -> Result<(), String> {
+    if !facets.enumeration.is_empty() && !facets.enumeration.iter().any(|e| e == value) {
+        return Err(format!(
+            "Value '{}' is not one of: {}",
+            value,
+            facets.enumeration.join(", ")
+        ));
+    }
+    if let Some(n) = facets.length.filter(|&n| n != length) {
+        return Err(format!("Value '{}' must have length {}", value, n));
+    }
+    if let Some(n) = facets.min_length.filter(|&n| length < n) {
+        return Err(format!("Value '{}' is shorter than minLength {}", value, n));
+    }
+    if let Some(n) = facets.max_length.filter(|&n| length > n) {
+        return Err(format!("Value '{}' is longer than maxLength {}", value, n));
+    }
+    let bounds = [
+        (
+            &facets.min_inclusive,
+            "less than minInclusive",
+            Ordering::Less,
+            true,
+        ),
+        (
+            &facets.max_inclusive,
+            "greater than maxInclusive",
+            Ordering::Greater,
+            true,
+        ),
+        (
+            &facets.min_exclusive,
+            "not greater than minExclusive",
+            Ordering::Greater,
+            false,
+        ),
+        (
+            &facets.max_exclusive,
+            "not less than maxExclusive",
+            Ordering::Less,
+            false,
+        ),
+    ];
+    for (bound, description, ordering, inclusive) in bounds {
+        if let Some(bound) = bound {
+            let cmp = compare_values(value, bound);
+            // Inclusive bounds reject `ordering`; exclusive bounds require it
+            let violated = if inclusive {
+                cmp == ordering
+            } else {
+                cmp != ordering
+            };
+            if violated {
+                return Err(format!("Value '{}' is {} {}", value, description, bound));
+            }
+        }
+    }
+    if facets.total_digits.is_some() || facets.fraction_digits.is_some() {
+        let digits = value.trim_start_matches(['+', '-']);
+        let (int_part, frac_part) = digits.split_once('.').unwrap_or((digits, ""));
+        let int_part = int_part.trim_start_matches('0');
+        let frac_part = frac_part.trim_end_matches('0');
+        if let Some(n) = facets
+            .total_digits
+            .filter(|&n| int_part.len() + frac_part.len() > n)
+        {
+            return Err(format!(
+                "Value '{}' has more than {} total digits",
+                value, n
+            ));
+        }
+        if let Some(n) = facets.fraction_digits.filter(|&n| frac_part.len() > n) {
+            return Err(format!(
+                "Value '{}' has more than {} fraction digits",
+                value, n
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Compare numerically when both values are numbers, otherwise lexically
+/// (which orders ISO 8601 dates and times correctly).
+fn compare_values(a: &str, b: &str) -> Ordering {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(x), Ok(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+        _ => a.cmp(b),
+    }
+}
+
+fn check_builtin(name: &str, raw: &str) -> Result<(), String> {
+    let value = raw.trim();
+    let valid = match name {
+        "anyType" | "anySimpleType" | "string" | "normalizedString" | "token" | "anyURI" => true,
+        "boolean" => matches!(value, "true" | "false" | "1" | "0"),
+        "decimal" => is_decimal(value),
+        "float" | "double" => is_float(value),
+        "integer" | "long" | "int" | "short" | "byte" | "nonNegativeInteger"
+        | "positiveInteger" | "nonPositiveInteger" | "negativeInteger" | "unsignedLong"
+        | "unsignedInt" | "unsignedShort" | "unsignedByte" => return check_integer(name, value),
+        "date" => is_date(value),
+        "dateTime" => value
+            .split_once('T')
+            .is_some_and(|(date, time)| is_date_part(date) && is_time(time)),
+        "time" => is_time(value),
+        "gYear" => is_year(strip_timezone(value)),
+        "gYearMonth" => strip_timezone(value)
+            .rsplit_once('-')
+            .is_some_and(|(y, m)| is_year(y) && is_in_range(m, 1, 12)),
+        "gMonth" => strip_timezone(value)
+            .strip_prefix("--")
+            .is_some_and(|m| is_in_range(m, 1, 12)),
+        "gMonthDay" => strip_timezone(value)
+            .strip_prefix("--")
+            .and_then(|md| md.split_once('-'))
+            .is_some_and(|(m, d)| is_in_range(m, 1, 12) && is_in_range(d, 1, 31)),
+        "gDay" => strip_timezone(value)
+            .strip_prefix("---")
+            .is_some_and(|d| is_in_range(d, 1, 31)),
+        "duration" => is_duration(value),
+        "language" => {
+            !value.is_empty()
+                && value.split('-').all(|part| {
+                    (1..=8).contains(&part.len()) && part.chars().all(|c| c.is_ascii_alphanumeric())
+                })
+        }
+        "Name" => is_name(value),
+        "NCName" | "ID" | "IDREF" | "ENTITY" => is_name(value) && !value.contains(':'),
+        "QName" | "NOTATION" => value.split(':').count() <= 2 && value.split(':').all(is_name),
+        "NMTOKEN" => !value.is_empty() && value.chars().all(is_name_char),
+        "NMTOKENS" => {
+            value.split_whitespace().next().is_some()
+                && value
+                    .split_whitespace()
+                    .all(|t| t.chars().all(is_name_char))
+        }
+        "IDREFS" | "ENTITIES" => {
+            value.split_whitespace().next().is_some()
+                && value
+                    .split_whitespace()
+                    .all(|t| is_name(t) && !t.contains(':'))
+        }
+        "hexBinary" => {
+            value.len().is_multiple_of(2) && value.chars().all(|c| c.is_ascii_hexdigit())
+        }
+        "base64Binary" => {
+            let compact: String = value.chars().filter(|c| !c.is_whitespace()).collect();
+            compact.len().is_multiple_of(4)
+                && compact
+                    .trim_end_matches('=')
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/')
+                && compact.len() - compact.trim_end_matches('=').len() <= 2
+        }
+        _ => true,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("'{}' is not a valid {}", value, name))
+    }
+}
+
+fn check_integer(name: &str, value: &str) -> Result<(), String> {
+    let digits = value.strip_prefix(['+', '-']).unwrap_or(value);
+    let parsed = if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+        value.parse::<i128>().ok()
+    } else {
+        None
+    };
+    let Some(n) = parsed else {
+        return Err(format!("'{}' is not a valid {}", value, name));
+    };
+    let (min, max): (i128, i128) = match name {
+        "long" => (i64::MIN.into(), i64::MAX.into()),
+        "int" => (i32::MIN.into(), i32::MAX.into()),
+        "short" => (i16::MIN.into(), i16::MAX.into()),
+        "byte" => (i8::MIN.into(), i8::MAX.into()),
+        "nonNegativeInteger" => (0, i128::MAX),
+        "positiveInteger" => (1, i128::MAX),
+        "nonPositiveInteger" => (i128::MIN, 0),
+        "negativeInteger" => (i128::MIN, -1),
+        "unsignedLong" => (0, u64::MAX.into()),
+        "unsignedInt" => (0, u32::MAX.into()),
+        "unsignedShort" => (0, u16::MAX.into()),
+        "unsignedByte" => (0, u8::MAX.into()),
+        _ => (i128::MIN, i128::MAX),
+    };
+    if n < min || n > max {
+        return Err(format!("{} is out of range for {}", value, name));
+    }
+    Ok(())
+}
+
+fn is_decimal(value: &str) -> bool {
+    let digits = value.strip_prefix(['+', '-']).unwrap_or(value);
+    let (int_part, frac_part) = digits.split_once('.').unwrap_or((digits, ""));
+    !(int_part.is_empty() && frac_part.is_empty())
+        && int_part.chars().all(|c| c.is_ascii_digit())
+        && frac_part.chars().all(|c| c.is_ascii_digit())
+}
+
+fn is_float(value: &str) -> bool {
+    if matches!(value, "INF" | "-INF" | "+INF" | "NaN") {
+        return true;
+    }
+    let (mantissa, exponent) = match value.split_once(['e', 'E']) {
+        Some((m, e)) => (m, Some(e)),
+        None => (value, None),
+    };
+    is_decimal(mantissa)
+        && exponent.is_none_or(|e| {
+            let digits = e.strip_prefix(['+', '-']).unwrap_or(e);
+            !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+        })
+}
+
+fn strip_timezone(value: &str) -> &str {
+    if let Some(v) = value.strip_suffix('Z') {
+        return v;
+    }
+    // A trailing `+hh:mm` / `-hh:mm` offset
+    if value.len() > 6 {
+        let (head, tz) = value.split_at(value.len() - 6);
+        let tz = tz.as_bytes();
+        if (tz[0] == b'+' || tz[0] == b'-')
+            && tz[3] == b':'
+            && is_in_range(&value[value.len() - 5..value.len() - 3], 0, 14)
+        {
+            return head;
+        }
+    }
+    value
+}
+
+fn is_year(value: &str) -> bool {
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    digits.len() >= 4 && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+fn is_in_range(value: &str, min: u32, max: u32) -> bool {
+    value.len() == 2 && value.parse::<u32>().is_ok_and(|n| (min..=max).contains(&n))
+}
+
+fn is_date_part(value: &str) -> bool {
+    let mut parts = value.rsplitn(3, '-');
+    let (Some(day), Some(month), Some(year)) = (parts.next(), parts.next(), parts.next()) else {
+        return false;
+    };
+    is_year(year) && is_in_range(month, 1, 12) && is_in_range(day, 1, 31)
+}
+
+fn is_date(value: &str) -> bool {
+    is_date_part(strip_timezone(value))
+}
+
+fn is_time(value: &str) -> bool {
+    let time = strip_timezone(value);
+    let mut parts = time.splitn(3, ':');
+    let (Some(h), Some(m), Some(s)) = (parts.next(), parts.next(), parts.next()) else {
+        return false;
+    };
+    let (secs, frac) = s.split_once('.').unwrap_or((s, "0"));
+    is_in_range(h, 0, 24)
+        && is_in_range(m, 0, 59)
+        && is_in_range(secs, 0, 60)
+        && !frac.is_empty()
+        && frac.chars().all(|c| c.is_ascii_digit())
+}
+
+fn is_duration(value: &str) -> bool {
+    let Some(body) = value.strip_prefix('-').unwrap_or(value).strip_prefix('P') else {
+        return false;
+    };
+    let (date, time) = body
+        .split_once('T')
+        .map_or((body, None), |(d, t)| (d, Some(t)));
+    let components_ok = |part: &str, units: &str| {
+        let mut rest = part;
+        let mut last = 0;
+        while !rest.is_empty() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(rest.len());
+            let Some(unit) = rest[len..].chars().next() else {
+                return false;
+            };
+            let Some(index) = units.find(unit).map(|i| i + 1) else {
+                return false;
+            };
+            if len == 0 || index <= last {
+                return false;
+            }
+            last = index;
+            rest = &rest[len + 1..];
+        }
+        true
+    };
+    components_ok(date, "YMD")
+        && time.is_none_or(|t| !t.is_empty() && components_ok(t, "HMS"))
+        && (!date.is_empty() || time.is_some())
+}
+
+fn is_name(value: &str) -> bool {
+    let mut chars = value.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_' || c == ':')
+        && chars.all(is_name_char)
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | ':' | '-' | '.' | '\u{B7}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = r#"<?xml version="1.0"?>
+<xs:schema xmlns:xs="http://www.w3.org/2001/XMLSchema">
+  <xs:element name="order">
+    <xs:complexType>
+      <xs:sequence>
+        <xs:element name="customer" type="xs:string"/>
+        <xs:element name="item" type="ItemType" maxOccurs="unbounded"/>
+        <xs:element name="note" type="xs:string" minOccurs="0"/>
+      </xs:sequence>
+      <xs:attribute name="id" type="xs:positiveInteger" use="required"/>
+      <xs:attribute name="status" type="Status"/>
+    </xs:complexType>
+  </xs:element>
+  <xs:complexType name="ItemType">
+    <xs:sequence>
+      <xs:element name="sku" type="Sku"/>
+      <xs:element name="qty" type="xs:int"/>
+      <xs:element name="price" type="Price"/>
+    </xs:sequence>
+  </xs:complexType>
+  <xs:simpleType name="Status">
+    <xs:restriction base="xs:string">
+      <xs:enumeration value="open"/>
+      <xs:enumeration value="shipped"/>
+    </xs:restriction>
+  </xs:simpleType>
+  <xs:simpleType name="Sku">
+    <xs:restriction base="xs:string">
+      <xs:length value="6"/>
+    </xs:restriction>
+  </xs:simpleType>
+  <xs:simpleType name="Price">
+    <xs:restriction base="xs:decimal">
+      <xs:minInclusive value="0"/>
+      <xs:fractionDigits value="2"/>
+    </xs:restriction>
+  </xs:simpleType>
+</xs:schema>"#;
+
+    fn violations(xml: &str) -> Vec<SchemaViolation> {
+        validate_xml_schema(xml, SCHEMA).unwrap().violations
+    }
+
+    #[test]
+    fn test_valid_document() {
+        let xml = r#"<order id="7" status="open">
+  <customer>Ada</customer>
+  <item><sku>ABC123</sku><qty>2</qty><price>9.99</price></item>
+  <item><sku>XYZ789</sku><qty>1</qty><price>100</price></item>
+</order>"#;
+        let result = validate_xml_schema(xml, SCHEMA).unwrap();
+        assert!(result.is_valid, "{:?}", result.violations);
+    }
+
+    #[test]
+    fn test_value_and_attribute_violations() {
+        let xml = r#"<order id="0" status="lost" extra="1">
+  <customer>Ada</customer>
+  <item><sku>ABC</sku><qty>two</qty><price>-1.005</price></item>
+  <item><sku>XYZ789</sku><qty>1</qty><price>1</price></item>
+</order>"#;
+        let found = violations(xml);
+        let messages: Vec<&str> = found.iter().map(|v| v.message.as_str()).collect();
+        assert_eq!(found.len(), 6, "{:?}", messages);
+        assert!(messages[0].contains("'id'") && messages[0].contains("positiveInteger"));
+        assert!(messages[1].contains("not one of: open, shipped"));
+        assert!(messages[2].contains("'extra' is not allowed"));
+        assert_eq!(found[3].path, "/order/item[1]/sku");
+        assert!(messages[3].contains("length 6"));
+        assert_eq!(found[4].path, "/order/item[1]/qty");
+        assert_eq!(found[4].line, 3);
+        assert!(messages[5].contains("minInclusive"));
+    }
+
+    #[test]
+    fn test_content_model_violations() {
+        let found = violations(
+            r#"<order id="1"><item><sku>ABC123</sku><qty>1</qty><price>1</price></item></order>"#,
+        );
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].path, "/order/item");
+        assert_eq!(
+            found[0].message,
+            "Unexpected element <item>; expected <customer>"
+        );
+
+        let found = violations(r#"<order id="1"><customer>A</customer></order>"#);
+        assert_eq!(found[0].path, "/order");
+        assert!(found[0]
+            .message
+            .contains("Missing required element; expected <item>"));
+
+        let found = violations(
+            r#"<order><customer>A<b/></customer><item><sku>ABC123</sku><qty>1</qty><price>1</price></item><bogus/></order>"#,
+        );
+        let messages: Vec<&str> = found.iter().map(|v| v.message.as_str()).collect();
+        assert!(messages
+            .iter()
+            .any(|m| m.contains("Missing required attribute 'id'")));
+        assert!(messages
+            .iter()
+            .any(|m| m.contains("Unexpected element <bogus>; expected <item>, <note>")));
+        assert!(messages
+            .iter()
+            .any(|m| m.contains("Child elements are not allowed")));
+    }
+
+    #[test]
+    fn test_choice_all_extension_and_refs() {
+        let schema = r#"<schema xmlns="http://www.w3.org/2001/XMLSchema">
+  <element name="shape" type="Shape"/>
+  <element name="label" type="string"/>
+  <complexType name="Base">
+    <all>
+      <element name="x" type="int"/>
+      <element name="y" type="int" minOccurs="0"/>
+    </all>
+    <attribute name="id" type="ID"/>
+  </complexType>
+  <complexType name="Shape">
+    <complexContent>
+      <extension base="Base">
+        <sequence>
+          <choice><element name="r" type="double"/><element name="w" type="double"/></choice>
+          <element ref="label" minOccurs="0"/>
+          <any minOccurs="0" maxOccurs="unbounded"/>
+        </sequence>
+      </extension>
+    </complexContent>
+  </complexType>
+</schema>"#;
+        let ok = r#"<shape id="s1"><y>2</y><x>1</x><w>1e3</w><label>L</label><anything/></shape>"#;
+        assert!(validate_xml_schema(ok, schema).unwrap().is_valid);
+
+        let bad = r#"<shape id="1"><x>1</x><r>x</r></shape>"#;
+        let found = validate_xml_schema(bad, schema).unwrap().violations;
+        assert_eq!(found.len(), 2, "{:?}", found);
+        assert!(found[0].message.contains("not a valid ID"));
+        assert_eq!(found[1].path, "/shape/r");
+    }
+
+    #[test]
+    fn test_schema_errors() {
+        assert!(validate_xml_schema("<a/>", "<notschema/>")
+            .unwrap_err()
+            .message
+            .starts_with("Schema:"));
+        let unknown = r#"<xs:schema xmlns:xs="http://www.w3.org/2001/XMLSchema"><xs:element name="a" type="Missing"/></xs:schema>"#;
+        assert!(validate_xml_schema("<a/>", unknown)
+            .unwrap_err()
+            .message
+            .contains("Unknown type 'Missing'"));
+        let found = validate_xml_schema("<b/>", SCHEMA).unwrap().violations;
+        assert!(found[0]
+            .message
+            .contains("No global element declaration for <b>"));
+        assert!(validate_xml_schema("<order", SCHEMA).is_err());
+    }
+
+    fn circular(body: &str) -> String {
+        let schema = format!(
+            r#"<xs:schema xmlns:xs="http://www.w3.org/2001/XMLSchema">{}</xs:schema>"#,
+            body
+        );
+        validate_xml_schema("<a/>", &schema).unwrap_err().message
+    }
+
+    #[test]
+    fn test_circular_complex_type() {
+        let body = r#"<xs:element name="a" type="T"/>
+<xs:complexType name="T"><xs:complexContent><xs:extension base="T"/></xs:complexContent></xs:complexType>"#;
+        assert_eq!(circular(body), "Schema: circular definition of 'T'");
+        let body = r#"<xs:element name="a" type="A"/>
+<xs:complexType name="A"><xs:complexContent><xs:extension base="B"/></xs:complexContent></xs:complexType>
+<xs:complexType name="B"><xs:complexContent><xs:restriction base="A"/></xs:complexContent></xs:complexType>"#;
+        assert!(circular(body).contains("circular definition of 'A'"));
+    }
+
+    #[test]
+    fn test_circular_group() {
+        let body = r#"<xs:element name="a"><xs:complexType><xs:group ref="g"/></xs:complexType></xs:element>
+<xs:group name="g"><xs:sequence><xs:element name="b" minOccurs="0"/><xs:group ref="g"/></xs:sequence></xs:group>"#;
+        assert_eq!(circular(body), "Schema: circular definition of 'g'");
+        // Recursion through an element declaration is fine
+        let schema = r#"<xs:schema xmlns:xs="http://www.w3.org/2001/XMLSchema">
+<xs:element name="a"><xs:complexType><xs:group ref="g"/></xs:complexType></xs:element>
+<xs:group name="g"><xs:sequence><xs:element name="b" minOccurs="0"><xs:complexType><xs:group ref="g"/></xs:complexType></xs:element></xs:sequence></xs:group>
+</xs:schema>"#;
+        assert!(
+            validate_xml_schema("<a><b><b/></b></a>", schema)
+                .unwrap()
+                .is_valid
+        );
+    }
+
+    #[test]
+    fn test_circular_simple_type() {
+        let body = r#"<xs:element name="a" type="S"/>
+<xs:simpleType name="S"><xs:restriction base="S"><xs:maxLength value="3"/></xs:restriction></xs:simpleType>"#;
+        assert_eq!(circular(body), "Schema: circular definition of 'S'");
+        let body = r#"<xs:element name="a" type="S"/>
+<xs:simpleType name="S"><xs:restriction><xs:simpleType><xs:list itemType="S"/></xs:simpleType></xs:restriction></xs:simpleType>"#;
+        assert!(circular(body).contains("circular definition of 'S'"));
+    }
+
+    #[test]
+    fn test_circular_attribute_group() {
+        let body = r#"<xs:element name="a"><xs:complexType><xs:attributeGroup ref="g"/></xs:complexType></xs:element>
+<xs:attributeGroup name="g"><xs:attribute name="x"/><xs:attributeGroup ref="g"/></xs:attributeGroup>"#;
+        assert_eq!(circular(body), "Schema: circular definition of 'g'");
+    }
+
+    #[test]
+    fn test_builtin_types() {
+        assert!(check_builtin("date", "2024-02-29").is_ok());
+        assert!(check_builtin("date", "2024-13-01").is_err());
+        assert!(check_builtin("dateTime", "2024-02-29T12:30:00.5+02:00").is_ok());
+        assert!(check_builtin("time", "25:00:00").is_err());
+        assert!(check_builtin("boolean", "yes").is_err());
+        assert!(check_builtin("byte", "128").is_err());
+        assert!(check_builtin("unsignedInt", "-1").is_err());
+        assert!(check_builtin("duration", "P1Y2M3DT4H").is_ok());
+        assert!(check_builtin("duration", "P").is_err());
+        assert!(check_builtin("double", "inf").is_err());
+        assert!(check_builtin("double", "-INF").is_ok());
+        assert!(check_builtin("base64Binary", "aGk=").is_ok());
+        assert!(check_builtin("hexBinary", "abc").is_err());
+    }
+}
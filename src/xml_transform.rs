@@ -0,0 +1,240 @@
+//! XML shape transforms
+//!
+//! Converts attributes into child elements and simple child elements into
+//! attributes, selected by name pattern. Useful when massaging a document
+//! into the shape another system expects. Everything not selected is kept
+//! as written.
+
+use crate::types::FormatError;
+use crate::xml_tree::{NodeId, NodeKind, XmlAttribute, XmlDocument};
+
+/// Move matching attributes into child elements.
+///
+/// `<item id="1" name="a"/>` with pattern `id` becomes
+/// `<item name="a"><id>1</id></item>`. New elements come before the
+/// element's existing content, in attribute order. Namespace declarations
+/// are never moved. Names are copied as written, so an unprefixed attribute
+/// (in no namespace) becomes an element in the default namespace, if one is
+/// in scope.
+///
+/// # Arguments
+/// * `input` - The XML string to transform
+/// * `pattern` - Comma-separated attribute names; `*` matches any run of characters
+///
+/// # Returns
+/// * `Ok(String)` - The transformed document
+/// * `Err(FormatError)` - If the XML is malformed
+pub fn attributes_to_elements(input: &str, pattern: &str) -> Result<String, FormatError> {
+    let patterns = parse_patterns(pattern);
+    let mut doc = XmlDocument::parse(input)?;
+    for id in element_ids(&doc) {
+        let offset = doc.node(id).offset;
+        let moved: Vec<XmlAttribute> = match &mut doc.node_mut(id).kind {
+            NodeKind::Element { attributes, .. } => {
+                let (moved, kept) = attributes
+                    .drain(..)
+                    .partition(|a| !is_declaration(&a.name) && matches_any(&patterns, &a.name));
+                *attributes = kept;
+                moved
+            }
+            _ => continue,
+        };
+        if moved.is_empty() {
+            continue;
+        }
+
+        let existing = std::mem::take(&mut doc.node_mut(id).children);
+        for attr in moved {
+            let child = doc.push(
+                id,
+                NodeKind::Element {
+                    name: attr.name,
+                    attributes: Vec::new(),
+                    self_closing: true,
+                },
+                offset,
+            );
+            if !attr.raw_value.is_empty() {
+                // Attribute values may hold `>` literally; text is escaped the same way otherwise
+                doc.push(
+                    child,
+                    NodeKind::Text(attr.raw_value.replace('>', "&gt;")),
+                    offset,
+                );
+            }
+        }
+        doc.node_mut(id).children.extend(existing);
+    }
+    Ok(doc.to_xml(XmlDocument::ROOT))
+}
+
+/// Move matching simple child elements into attributes of their parent.
+///
+/// A child is converted only if it has no attributes, holds nothing but
+/// text and CDATA, and its parent does not already have an attribute of
+/// that name; later duplicates stay elements. The text is trimmed. Names
+/// are copied as written, so an unprefixed child in a default namespace
+/// becomes an attribute in no namespace.
+///
+/// # Arguments
+/// * `input` - The XML string to transform
+/// * `pattern` - Comma-separated element names; `*` matches any run of characters
+///
+/// # Returns
+/// * `Ok(String)` - The transformed document
+/// * `Err(FormatError)` - If the XML is malformed
+pub fn elements_to_attributes(input: &str, pattern: &str) -> Result<String, FormatError> {
+    let patterns = parse_patterns(pattern);
+    let mut doc = XmlDocument::parse(input)?;
+    for id in element_ids(&doc) {
+        let mut converted = Vec::new();
+        let mut names: Vec<String> = doc.attributes(id).iter().map(|a| a.name.clone()).collect();
+        for &child in doc.children(id) {
+            let name = match doc.name(child) {
+                Some(name) if matches_any(&patterns, name) && !names.iter().any(|n| n == name) => {
+                    name
+                }
+                _ => continue,
+            };
+            if let Some(value) = simple_value(&doc, child) {
+                names.push(name.to_string());
+                converted.push((
+                    child,
+                    XmlAttribute {
+                        name: name.to_string(),
+                        raw_value: value,
+                    },
+                ));
+            }
+        }
+        if converted.is_empty() {
+            continue;
+        }
+
+        let node = doc.node_mut(id);
+        node.children
+            .retain(|c| !converted.iter().any(|(id, _)| id == c));
+        if let NodeKind::Element {
+            attributes,
+            self_closing,
+            ..
+        } = &mut node.kind
+        {
+            attributes.extend(converted.into_iter().map(|(_, attr)| attr));
+            *self_closing |= node.children.is_empty();
+        }
+    }
+    Ok(doc.to_xml(XmlDocument::ROOT))
+}
+
+/// Escaped attribute value for an element that holds only text and CDATA.
+fn simple_value(doc: &XmlDocument, id: NodeId) -> Option<String> {
+    if !doc.attributes(id).is_empty() {
+        return None;
+    }
+    let mut value = String::new();
+    for &child in doc.children(id) {
+        match &doc.node(child).kind {
+            NodeKind::Text(raw) => value.push_str(raw),
+            NodeKind::CData(text) => {
+                value.push_str(&text.replace('&', "&amp;").replace('<', "&lt;"))
+            }
+            _ => return None,
+        }
+    }
+    Some(value.trim().to_string())
+}
+
+/// All element ids, in document order.
+fn element_ids(doc: &XmlDocument) -> Vec<NodeId> {
+    (0..doc.len()).filter(|&id| doc.is_element(id)).collect()
+}
+
+fn is_declaration(name: &str) -> bool {
+    name == "xmlns" || name.starts_with("xmlns:")
+}
+
+fn parse_patterns(pattern: &str) -> Vec<&str> {
+    pattern
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+fn matches_any(patterns: &[&str], name: &str) -> bool {
+    patterns.iter().any(|p| glob_match(p, name))
+}
+
+/// Match `name` against a pattern where `*` matches any run of characters.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("id", "id"));
+        assert!(!glob_match("id", "ids"));
+        assert!(glob_match("data-*", "data-x"));
+        assert!(glob_match("*:id", "x:id"));
+        assert!(glob_match("a*b*c", "abxbc"));
+        assert!(!glob_match("a*bc", "abc_"));
+        assert!(glob_match("*", ""));
+    }
+
+    #[test]
+    fn test_attributes_to_elements() {
+        let input = r#"<items xmlns:x="urn:x"><item id="1" name="a &amp; b" x:note="n"><sub/></item></items>"#;
+        assert_eq!(
+            attributes_to_elements(input, "id, name").unwrap(),
+            r#"<items xmlns:x="urn:x"><item x:note="n"><id>1</id><name>a &amp; b</name><sub/></item></items>"#
+        );
+        assert_eq!(
+            attributes_to_elements(input, "*").unwrap(),
+            r#"<items xmlns:x="urn:x"><item><id>1</id><name>a &amp; b</name><x:note>n</x:note><sub/></item></items>"#
+        );
+    }
+
+    #[test]
+    fn test_elements_to_attributes() {
+        let input = "<item id=\"1\"><id>2</id><name> a \"b\" </name><name>dup</name><code><![CDATA[<x>]]></code><nested><c/></nested></item>";
+        assert_eq!(
+            elements_to_attributes(input, "id,name,code,nested").unwrap(),
+            r#"<item id="1" name="a &quot;b&quot;" code="&lt;x>"><id>2</id><name>dup</name><nested><c/></nested></item>"#
+        );
+        assert_eq!(
+            elements_to_attributes("<a><b>1</b></a>", "b").unwrap(),
+            r#"<a b="1"/>"#
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let input = r#"<p id="7" lang="en">text</p>"#;
+        let elements = attributes_to_elements(input, "*").unwrap();
+        assert_eq!(elements, "<p><id>7</id><lang>en</lang>text</p>");
+        assert_eq!(
+            elements_to_attributes(&elements, "id,lang").unwrap(),
+            r#"<p id="7" lang="en">text</p>"#
+        );
+    }
+}
@@ -0,0 +1,454 @@
+//! XML document tree
+//!
+//! An arena-based DOM built on quick-xml events, for XML features that need
+//! more than a single streaming pass. Nodes live in a flat vector in
+//! document order and refer to each other by index, so walking parents and
+//! siblings is cheap. Text and attribute values are kept exactly as written
+//! (still escaped) so that serializing a tree reproduces entity references.
+
+use quick_xml::escape::unescape;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use crate::types::{check_input_size, FormatError, DEFAULT_MAX_INPUT_BYTES};
+
+/// Index of a node within an [`XmlDocument`].
+pub type NodeId = usize;
+
+/// An attribute with its value as written in the source (escaped).
+#[derive(Clone, Debug, PartialEq)]
+pub struct XmlAttribute {
+    pub name: String,
+    pub raw_value: String,
+}
+
+impl XmlAttribute {
+    /// The attribute value with entity and character references resolved.
+    ///
+    /// Falls back to the raw value when it contains unknown entities.
+    pub fn value(&self) -> String {
+        unescape(&self.raw_value)
+            .map(|v| v.into_owned())
+            .unwrap_or_else(|_| self.raw_value.clone())
+    }
+}
+
+/// The kind-specific payload of a node.
+#[derive(Clone, Debug, PartialEq)]
+pub enum NodeKind {
+    /// The document node (always id 0)
+    Document,
+    Element {
+        name: String,
+        attributes: Vec<XmlAttribute>,
+        /// Written as `<name/>` rather than `<name></name>`
+        self_closing: bool,
+    },
+    /// Character data as written in the source (escaped)
+    Text(String),
+    CData(String),
+    Comment(String),
+    /// Processing instruction content between `<?` and `?>`
+    ProcessingInstruction(String),
+    /// XML declaration content between `<?` and `?>` (starts with `xml`)
+    Declaration(String),
+    /// DOCTYPE content between `<!DOCTYPE` and `>`
+    DocType(String),
+}
+
+/// A single node in the tree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct XmlNode {
+    pub kind: NodeKind,
+    pub parent: Option<NodeId>,
+    pub children: Vec<NodeId>,
+    /// Byte offset of the node in the source input
+    pub offset: usize,
+}
+
+/// A parsed XML document.
+#[derive(Clone, Debug)]
+pub struct XmlDocument {
+    nodes: Vec<XmlNode>,
+}
+
+impl XmlDocument {
+    /// Id of the document node.
+    pub const ROOT: NodeId = 0;
+
+    /// Deepest element nesting `parse` accepts. Tree consumers such as
+    /// canonicalization and schema validation recurse once per level, so
+    /// deeper documents are rejected up front rather than risking a stack
+    /// overflow in WASM.
+    pub const MAX_DEPTH: usize = 1024;
+
+    /// Parse a well-formed XML document into a tree.
+    ///
+    /// Requires exactly one root element and no text outside it;
+    /// whitespace between top-level nodes is dropped.
+    pub fn parse(input: &str) -> Result<Self, FormatError> {
+        if input.trim().is_empty() {
+            return Err(FormatError::new("Empty input", 1, 1));
+        }
+        check_input_size(input.len(), DEFAULT_MAX_INPUT_BYTES)?;
+
+        let mut doc = XmlDocument {
+            nodes: vec![XmlNode {
+                kind: NodeKind::Document,
+                parent: None,
+                children: Vec::new(),
+                offset: 0,
+            }],
+        };
+        let mut reader = Reader::from_str(input);
+        let mut current = Self::ROOT;
+        let mut depth = 0;
+        let mut root_seen = false;
+
+        loop {
+            let start = reader.buffer_position() as usize;
+            let event = reader.read_event().map_err(|e| {
+                FormatError::at_offset(
+                    format!("XML parse error: {}", e),
+                    input,
+                    reader.error_position() as usize,
+                )
+            })?;
+            let kind = match event {
+                Event::Start(e) | Event::Empty(e) if current == Self::ROOT && root_seen => {
+                    let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+                    return Err(FormatError::at_offset(
+                        format!(
+                            "Multiple root elements: <{}> follows the root element",
+                            name
+                        ),
+                        input,
+                        start,
+                    ));
+                }
+                Event::Start(e) => {
+                    root_seen = true;
+                    depth += 1;
+                    if depth > Self::MAX_DEPTH {
+                        return Err(FormatError::at_offset(
+                            format!("Elements nested more than {} deep", Self::MAX_DEPTH),
+                            input,
+                            start,
+                        ));
+                    }
+                    let kind = element_kind(&e, false, input, start)?;
+                    current = doc.push(current, kind, start);
+                    continue;
+                }
+                Event::Empty(e) => {
+                    root_seen = true;
+                    element_kind(&e, true, input, start)?
+                }
+                Event::End(_) => {
+                    depth -= 1;
+                    current = doc.nodes[current].parent.unwrap_or(Self::ROOT);
+                    continue;
+                }
+                Event::Text(e) => {
+                    let raw = decode(&e, input, start)?;
+                    if current == Self::ROOT {
+                        if raw.trim().is_empty() {
+                            continue;
+                        }
+                        return Err(FormatError::at_offset(
+                            "Text outside the root element",
+                            input,
+                            start,
+                        ));
+                    }
+                    NodeKind::Text(raw)
+                }
+                Event::CData(e) => {
+                    if current == Self::ROOT {
+                        return Err(FormatError::at_offset(
+                            "CDATA outside the root element",
+                            input,
+                            start,
+                        ));
+                    }
+                    NodeKind::CData(decode(&e, input, start)?)
+                }
+                Event::Comment(e) => NodeKind::Comment(decode(&e, input, start)?),
+                Event::PI(e) => NodeKind::ProcessingInstruction(decode(&e, input, start)?),
+                Event::Decl(e) => NodeKind::Declaration(decode(&e, input, start)?),
+                Event::DocType(e) => NodeKind::DocType(decode(&e, input, start)?),
+                Event::Eof => break,
+            };
+            doc.push(current, kind, start);
+        }
+
+        if current != Self::ROOT {
+            let offset = doc.nodes[current].offset;
+            let name = doc.name(current).unwrap_or_default().to_string();
+            return Err(FormatError::at_offset(
+                format!("Unclosed element <{}>", name),
+                input,
+                offset,
+            ));
+        }
+        if !root_seen {
+            return Err(FormatError::new("No root element", 1, 1));
+        }
+        Ok(doc)
+    }
+
+    /// Append a new node as the last child of `parent`, returning its id.
+    pub fn push(&mut self, parent: NodeId, kind: NodeKind, offset: usize) -> NodeId {
+        let id = self.nodes.len();
+        self.nodes.push(XmlNode {
+            kind,
+            parent: Some(parent),
+            children: Vec::new(),
+            offset,
+        });
+        self.nodes[parent].children.push(id);
+        id
+    }
+
+    /// Access a node by id.
+    pub fn node(&self, id: NodeId) -> &XmlNode {
+        &self.nodes[id]
+    }
+
+    /// Mutable access to a node by id.
+    pub fn node_mut(&mut self, id: NodeId) -> &mut XmlNode {
+        &mut self.nodes[id]
+    }
+
+    /// Total number of nodes, including the document node.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Whether the document holds only the document node.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.len() <= 1
+    }
+
+    /// Child ids of a node, in document order.
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.nodes[id].children
+    }
+
+    /// The single root element.
+    pub fn document_element(&self) -> Option<NodeId> {
+        self.children(Self::ROOT)
+            .iter()
+            .copied()
+            .find(|&c| self.is_element(c))
+    }
+
+    pub fn is_element(&self, id: NodeId) -> bool {
+        matches!(self.nodes[id].kind, NodeKind::Element { .. })
+    }
+
+    /// Element name (qualified, as written), or `None` for non-elements.
+    pub fn name(&self, id: NodeId) -> Option<&str> {
+        match &self.nodes[id].kind {
+            NodeKind::Element { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Element attributes (empty for non-elements).
+    pub fn attributes(&self, id: NodeId) -> &[XmlAttribute] {
+        match &self.nodes[id].kind {
+            NodeKind::Element { attributes, .. } => attributes,
+            _ => &[],
+        }
+    }
+
+    /// Value of the named attribute, unescaped.
+    pub fn attribute(&self, id: NodeId, name: &str) -> Option<String> {
+        self.attributes(id)
+            .iter()
+            .find(|a| a.name == name)
+            .map(XmlAttribute::value)
+    }
+
+    /// Concatenated unescaped text of all descendant text and CDATA nodes.
+    pub fn text_content(&self, id: NodeId) -> String {
+        let mut out = String::new();
+        let mut stack = vec![id];
+        while let Some(n) = stack.pop() {
+            match &self.nodes[n].kind {
+                NodeKind::Text(raw) => out.push_str(&unescape_text(raw)),
+                NodeKind::CData(text) => out.push_str(text),
+                NodeKind::Document | NodeKind::Element { .. } => {
+                    stack.extend(self.nodes[n].children.iter().rev());
+                }
+                _ => {}
+            }
+        }
+        out
+    }
+
+    /// Serialize a node and its subtree as compact XML, preserving text verbatim.
+    pub fn to_xml(&self, id: NodeId) -> String {
+        let mut out = String::new();
+        // (node, whether its children have been written); an explicit stack
+        // keeps deep documents off the call stack
+        let mut stack = vec![(id, false)];
+        while let Some((id, closing)) = stack.pop() {
+            let node = &self.nodes[id];
+            match &node.kind {
+                NodeKind::Element { name, .. } if closing => {
+                    out.push_str("</");
+                    out.push_str(name);
+                    out.push('>');
+                }
+                NodeKind::Document => stack.extend(node.children.iter().rev().map(|&c| (c, false))),
+                NodeKind::Element {
+                    name,
+                    attributes,
+                    self_closing,
+                } => {
+                    out.push('<');
+                    out.push_str(name);
+                    for attr in attributes {
+                        push_attribute(&mut out, attr);
+                    }
+                    if node.children.is_empty() && *self_closing {
+                        out.push_str("/>");
+                        continue;
+                    }
+                    out.push('>');
+                    stack.push((id, true));
+                    stack.extend(node.children.iter().rev().map(|&c| (c, false)));
+                }
+                _ => push_leaf(&mut out, &node.kind),
+            }
+        }
+        out
+    }
+}
+
+/// Write ` name="value"`, converting the value to double-quote form.
+pub(crate) fn push_attribute(out: &mut String, attr: &XmlAttribute) {
+    out.push(' ');
+    out.push_str(&attr.name);
+    out.push_str("=\"");
+    out.push_str(&attr.raw_value.replace('"', "&quot;"));
+    out.push('"');
+}
+
+/// Write a non-container node in its source form.
+pub(crate) fn push_leaf(out: &mut String, kind: &NodeKind) {
+    match kind {
+        NodeKind::Text(raw) => out.push_str(raw),
+        NodeKind::CData(text) => {
+            out.push_str("<![CDATA[");
+            out.push_str(text);
+            out.push_str("]]>");
+        }
+        NodeKind::Comment(text) => {
+            out.push_str("<!--");
+            out.push_str(text);
+            out.push_str("-->");
+        }
+        NodeKind::ProcessingInstruction(text) | NodeKind::Declaration(text) => {
+            out.push_str("<?");
+            out.push_str(text);
+            out.push_str("?>");
+        }
+        NodeKind::DocType(text) => {
+            out.push_str("<!DOCTYPE ");
+            out.push_str(text.trim_start());
+            out.push('>');
+        }
+        NodeKind::Document | NodeKind::Element { .. } => {}
+    }
+}
+
+/// Unescape text content, keeping it raw if it contains unknown entities.
+pub(crate) fn unescape_text(raw: &str) -> String {
+    unescape(raw)
+        .map(|v| v.into_owned())
+        .unwrap_or_else(|_| raw.to_string())
+}
+
+fn element_kind(
+    e: &BytesStart,
+    self_closing: bool,
+    input: &str,
+    start: usize,
+) -> Result<NodeKind, FormatError> {
+    let name = String::from_utf8(e.name().as_ref().to_vec())
+        .map_err(|_| FormatError::at_offset("Invalid UTF-8 in tag name", input, start))?;
+    let mut attributes = Vec::new();
+    for attr in e.attributes() {
+        let attr = attr.map_err(|err| {
+            FormatError::at_offset(format!("Invalid attribute: {}", err), input, start)
+        })?;
+        attributes.push(XmlAttribute {
+            name: String::from_utf8_lossy(attr.key.as_ref()).into_owned(),
+            raw_value: String::from_utf8_lossy(&attr.value).into_owned(),
+        });
+    }
+    Ok(NodeKind::Element {
+        name,
+        attributes,
+        self_closing,
+    })
+}
+
+fn decode(bytes: &[u8], input: &str, start: usize) -> Result<String, FormatError> {
+    String::from_utf8(bytes.to_vec())
+        .map_err(|_| FormatError::at_offset("Invalid UTF-8", input, start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_structure() {
+        let doc = XmlDocument::parse(
+            r#"<?xml version="1.0"?><root a="1"><child>t &amp; u</child><e/></root>"#,
+        )
+        .unwrap();
+        let root = doc.document_element().unwrap();
+        assert_eq!(doc.name(root), Some("root"));
+        assert_eq!(doc.attribute(root, "a").as_deref(), Some("1"));
+        assert_eq!(doc.children(root).len(), 2);
+        let child = doc.children(root)[0];
+        assert_eq!(doc.text_content(child), "t & u");
+        assert_eq!(doc.node(child).parent, Some(root));
+        assert_eq!(doc.node(child).offset, 33);
+    }
+
+    #[test]
+    fn test_to_xml_preserves_source_forms() {
+        let input = r#"<r x='say "hi"'><a></a><b/>&lt;text&gt;<![CDATA[<raw>]]><!--c--></r>"#;
+        let doc = XmlDocument::parse(input).unwrap();
+        assert_eq!(
+            doc.to_xml(XmlDocument::ROOT),
+            r#"<r x="say &quot;hi&quot;"><a></a><b/>&lt;text&gt;<![CDATA[<raw>]]><!--c--></r>"#
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(XmlDocument::parse("").is_err());
+        assert!(XmlDocument::parse("<a/><b/>").is_err());
+        assert!(XmlDocument::parse("<a/>text").is_err());
+        let err = XmlDocument::parse("<a>\n<b>").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(err.message.contains("<b>"));
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let depth = XmlDocument::MAX_DEPTH;
+        let nested = |n: usize| format!("{}{}", "<a>".repeat(n), "</a>".repeat(n));
+        let doc = XmlDocument::parse(&nested(depth)).unwrap();
+        assert_eq!(doc.to_xml(XmlDocument::ROOT), nested(depth));
+        let err = XmlDocument::parse(&nested(depth + 1)).unwrap_err();
+        assert!(err.message.contains("nested more than"));
+    }
+}